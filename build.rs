@@ -1,4 +1,43 @@
+#[cfg(feature = "bindgen")]
+extern crate bindgen;
+
+/// Generates the liblsf and LSBLIB bindings from `lsf.h` and `lsbatch.h` of
+/// the installed LSF, found under `LSF_INCLUDE_DIR` (e.g.
+/// `$LSF_TOP/10.1/include`).
+#[cfg(feature = "bindgen")]
+fn generate_bindings() {
+    use std::env;
+    use std::path::PathBuf;
+
+    println!("cargo:rerun-if-env-changed=LSF_INCLUDE_DIR");
+
+    let include_dir = env::var("LSF_INCLUDE_DIR")
+        .expect("LSF_INCLUDE_DIR must point to the LSF include directory to generate bindings");
+
+    let bindings = bindgen::Builder::default()
+        .header(format!("{}/lsf/lsf.h", include_dir))
+        .header(format!("{}/lsf/lsbatch.h", include_dir))
+        .clang_arg(format!("-I{}", include_dir))
+        .allowlist_function("ls_load|ls_loadinfo|ls_getclustername|ls_sharedresourceinfo|ls_info|ls_gethostinfo|lsb_init|lsb_hostinfo|lsb_sysmsg|lsb_openjobinfo|lsb_readjobinfo|lsb_closejobinfo")
+        .allowlist_type("hostLoad|hostInfo|hostInfoEnt|jobInfoEnt|lsInfo|resItem|lsSharedResourceInfo|lsSharedResourceInstance")
+        .allowlist_var("LIM_.*|RESF_.*|HOST_STAT_.*|JOB_STAT_.*|CUR_JOB|ALL_JOB|DONE_JOB|PEND_JOB|RUN_JOB|EXACT|OK_ONLY|NORMALIZE|LOCALITY|ALL_CLUSTERS|EFFECTIVE")
+        .generate()
+        .expect("Unable to generate bindings from lsf.h");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is not set"));
+
+    bindings.write_to_file(out_dir.join("lsf_sys.rs"))
+        .expect("Unable to write generated bindings");
+}
+
+#[cfg(not(feature = "bindgen"))]
+fn generate_bindings() {
+}
+
 fn main() {
+    generate_bindings();
+
     println!("cargo:rustc-link-lib=lsf");
+    println!("cargo:rustc-link-lib=bat");
     println!("cargo:rustc-link-lib=nsl");
 }
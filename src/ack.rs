@@ -0,0 +1,43 @@
+use common::StatusStorageInfo;
+use glob;
+use {ALERT, DOWNTIME, FAILED, PASSED};
+
+/// Hosts with known issues, matched by record name with `*` and `?`
+/// wildcards.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AckConfig {
+    pub hosts: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// Downgrades failures of acknowledged hosts to ALERT and marks them, so that
+/// they are left out of the exit code.
+pub fn apply(records: &mut [StatusStorageInfo], acks: &[AckConfig]) {
+    for record in records.iter_mut().filter(|record| record.status == FAILED || record.status == ALERT) {
+        let ack = acks.iter()
+            .find(|ack| ack.hosts.iter().any(|pattern| glob::matches(pattern, &record.name)));
+
+        if let Some(ack) = ack {
+            let remark = match ack.comment {
+                Some(ref comment) => format!("Known issue: {}", comment),
+                None => "Known issue".to_owned(),
+            };
+
+            record.status = ALERT;
+            record.acknowledged = true;
+
+            record.remarks = Some(match record.remarks {
+                Some(ref remarks) => format!("{} ({})", remarks, remark),
+                None => remark,
+            });
+        }
+    }
+}
+
+/// Whether the record counts as healthy for the exit code.
+pub fn is_ok(record: &StatusStorageInfo) -> bool {
+    record.status == PASSED || record.acknowledged || record.status == DOWNTIME
+}
@@ -0,0 +1,84 @@
+use common::{BatchInfo, StatusStorageInfo};
+use errors::*;
+use lsf::{self, HOST_STAT_FULL, HOST_STAT_OK};
+use std::collections::HashMap;
+use Config;
+use {ALERT, FAILED, PASSED};
+
+/// Batch layer checks, merging the mbatchd view of every host into its record.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchConfig {
+    /// Raise passing hosts that are closed for any reason but being full
+    #[serde(default)]
+    pub alert_closed: bool,
+
+    #[serde(default)]
+    pub alert_full: bool,
+}
+
+fn to_batch_info(host: &lsf::BatchHost) -> BatchInfo {
+    BatchInfo {
+        status: lsf::to_batch_status_str(host.status).to_owned(),
+        max_jobs: host.max_jobs,
+        num_jobs: host.num_jobs,
+        num_run: host.num_run,
+        num_ssusp: host.num_ssusp,
+        num_ususp: host.num_ususp,
+        closed: host.status != HOST_STAT_OK,
+        full: host.status & HOST_STAT_FULL != 0,
+        control_message: host.control_message.clone(),
+    }
+}
+
+/// Attaches the batch status of the hosts to their records, raising them to
+/// ALERT if closed or full as configured. Adds a `{prefix}batch` record that
+/// is FAILED while mbatchd cannot be queried.
+pub fn apply(records: &mut Vec<StatusStorageInfo>, batch: &BatchConfig, config: &Config) -> Result<()> {
+    let hosts = match lsf::batch_hosts() {
+        Ok(hosts) => hosts,
+
+        Err(Error(ErrorKind::QueryFailed(reason), _)) => {
+            records.push(StatusStorageInfo::new(
+                format!("{}batch", config.prefix),
+                FAILED,
+                None,
+                Some(config.critical_group_name.clone()),
+                Some(format!("Batch query failed: {}", reason))));
+
+            return Ok(());
+        },
+
+        Err(e) => return Err(e),
+    };
+
+    let by_name: HashMap<String, BatchInfo> = hosts.iter()
+        .map(|host| (config.record_name(&host.host_name), to_batch_info(host)))
+        .collect();
+
+    for record in records.iter_mut() {
+        let batch_info = match by_name.get(&record.name) {
+            Some(batch_info) => batch_info.clone(),
+            None => continue,
+        };
+
+        let flagged = (batch.alert_full && batch_info.full) || (batch.alert_closed && batch_info.closed && !batch_info.full);
+
+        if flagged {
+            if record.status == PASSED {
+                record.status = ALERT;
+            }
+
+            let reason = format!("batch {}, {} of {} jobs", batch_info.status, batch_info.num_jobs, batch_info.max_jobs);
+
+            record.remarks = Some(match record.remarks {
+                Some(ref remarks) => format!("{} ({})", remarks, reason),
+                None => reason,
+            });
+        }
+
+        record.batch = Some(batch_info);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use common::StatusStorageInfo;
+use errors::*;
+use libc;
+use logging;
+use outfile::OutputFile;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_runtime_secs: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_memory_bytes: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_payload_bytes: Option<usize>,
+}
+
+// how often the watchdog samples the resident memory of the agent
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Resident memory of the agent process, as opposed to its address space,
+/// which also counts reservations such as thread stacks and malloc arenas.
+fn resident_bytes() -> Option<u64> {
+    let mut statm = String::new();
+
+    File::open("/proc/self/statm")
+        .and_then(|mut f| f.read_to_string(&mut statm))
+        .ok()?;
+
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+    Some(pages * page_size as u64)
+}
+
+/// Payloads emitted in place of the one of the poll, depending on the budget
+/// that was exceeded.
+#[derive(Clone, Debug, Default)]
+pub struct FallbackPayloads {
+    pub runtime: String,
+    pub memory: String,
+}
+
+struct GuardState {
+    deadline: Option<Instant>,
+    fallback_payloads: Option<FallbackPayloads>,
+}
+
+/// Serializes writes of payloads to stdout, or the output file if given, with
+/// a watchdog that emits a fallback payload and exits once the runtime budget
+/// of a poll is exhausted, or the memory budget at any time.
+#[derive(Clone)]
+pub struct RuntimeGuard {
+    state: Arc<(Mutex<GuardState>, Condvar)>,
+    output: Option<OutputFile>,
+}
+
+fn write_payload(output: Option<&OutputFile>, payload: &str) -> Result<()> {
+    match output {
+        Some(output) => output.write(payload),
+        None => {
+            println!("{}", payload);
+            Ok(())
+        },
+    }
+}
+
+impl RuntimeGuard {
+    pub fn spawn(exit_code: i32, output: Option<OutputFile>, max_memory_bytes: Option<u64>) -> RuntimeGuard {
+        let guard = RuntimeGuard {
+            state: Arc::new((
+                Mutex::new(GuardState { deadline: None, fallback_payloads: None }),
+                Condvar::new())),
+            output,
+        };
+
+        let state = guard.state.clone();
+        let output = guard.output.clone();
+
+        thread::spawn(move || {
+            let (ref lock, ref cvar) = *state;
+            let mut guard_state = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+            let exceed = |guard_state: &GuardState, payload: fn(&FallbackPayloads) -> &String| -> ! {
+                if let Some(ref fallback_payloads) = guard_state.fallback_payloads {
+                    if let Err(e) = write_payload(output.as_ref(), payload(fallback_payloads)) {
+                        error!(error = %logging::chain(&e), "Unable to write fallback payload");
+                    }
+                }
+
+                process::exit(exit_code);
+            };
+
+            loop {
+                let now = Instant::now();
+
+                if let Some(max_memory_bytes) = max_memory_bytes {
+                    if resident_bytes().is_some_and(|resident_bytes| resident_bytes > max_memory_bytes) {
+                        error!(max_memory_bytes, "Agent exceeded memory budget");
+                        exceed(&guard_state, |fallback_payloads| &fallback_payloads.memory);
+                    }
+                }
+
+                let timeout = match guard_state.deadline {
+                    Some(deadline) if now >= deadline => exceed(&guard_state, |fallback_payloads| &fallback_payloads.runtime),
+                    Some(deadline) => Some(deadline - now),
+                    None => None,
+                };
+
+                let timeout = match (timeout, max_memory_bytes) {
+                    (Some(timeout), Some(_)) => Some(timeout.min(MEMORY_CHECK_INTERVAL)),
+                    (None, Some(_)) => Some(MEMORY_CHECK_INTERVAL),
+                    (timeout, None) => timeout,
+                };
+
+                guard_state = match timeout {
+                    Some(timeout) => cvar.wait_timeout(guard_state, timeout)
+                        .unwrap_or_else(|e| e.into_inner()).0,
+
+                    None => cvar.wait(guard_state).unwrap_or_else(|e| e.into_inner()),
+                };
+            }
+        });
+
+        guard
+    }
+
+    /// Starts the budget for the current poll, with the runtime budget if
+    /// given.
+    pub fn arm(&self, max_runtime: Option<Duration>, fallback_payloads: FallbackPayloads) {
+        let (ref lock, ref cvar) = *self.state;
+        let mut guard_state = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        guard_state.deadline = max_runtime.map(|max_runtime| Instant::now() + max_runtime);
+        guard_state.fallback_payloads = Some(fallback_payloads);
+        cvar.notify_one();
+    }
+
+    /// Writes the payload and stops the budget of the current poll, an agent
+    /// exceeding the memory budget afterwards exits without another payload.
+    pub fn emit(&self, payload: &str) -> Result<()> {
+        let (ref lock, ref cvar) = *self.state;
+        let mut guard_state = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        guard_state.deadline = None;
+        guard_state.fallback_payloads = None;
+        cvar.notify_one();
+        write_payload(self.output.as_ref(), payload)
+    }
+}
+
+/// Renders the records into a payload that fits within the payload budget.
+/// When truncation is needed, trailing records are dropped and the marker
+/// record is appended instead. Returns whether truncation happened.
+pub fn fit_payload<F>(
+    budget: &BudgetConfig,
+    records: &[StatusStorageInfo],
+    marker: &StatusStorageInfo,
+    render: F) -> Result<(String, bool)>
+    where F: Fn(&[StatusStorageInfo]) -> Result<String> {
+
+    let full = render(records)?;
+
+    let max_payload_bytes = match budget.max_payload_bytes {
+        Some(max_payload_bytes) if full.len() > max_payload_bytes => max_payload_bytes,
+        _ => return Ok((full, false)),
+    };
+
+    let render_kept = |kept: usize| {
+        let mut truncated = records[..kept].to_vec();
+        truncated.push(marker.clone());
+        render(&truncated)
+    };
+
+    // payload size grows with the number of records kept, so binary search for
+    // the largest prefix that still fits together with the marker
+    let (mut lo, mut hi) = (0, records.len());
+
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+
+        if render_kept(mid)?.len() <= max_payload_bytes {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+
+    Ok((render_kept(lo)?, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use {FAILED, PASSED};
+
+    fn records(count: usize) -> Vec<StatusStorageInfo> {
+        (0..count).map(|i| StatusStorageInfo::new(format!("host{}", i), PASSED, None, None, None)).collect()
+    }
+
+    fn marker() -> StatusStorageInfo {
+        StatusStorageInfo::new("p.*".to_owned(), FAILED, None, None, Some("Payload truncated".to_owned()))
+    }
+
+    fn render(records: &[StatusStorageInfo]) -> Result<String> {
+        serde_json::to_string(records).chain_err(|| "Unable to render records")
+    }
+
+    fn fit(max_payload_bytes: Option<usize>, records: &[StatusStorageInfo]) -> (String, bool) {
+        let budget = BudgetConfig { max_payload_bytes, ..Default::default() };
+        fit_payload(&budget, records, &marker(), render).unwrap()
+    }
+
+    fn with_marker(records: &[StatusStorageInfo]) -> Vec<StatusStorageInfo> {
+        let mut records = records.to_vec();
+        records.push(marker());
+        records
+    }
+
+    #[test]
+    fn everything_fits() {
+        let records = records(5);
+        let full = render(&records).unwrap();
+
+        assert_eq!(fit(None, &records), (full.clone(), false));
+        assert_eq!(fit(Some(full.len()), &records), (full, false));
+    }
+
+    #[test]
+    fn nothing_fits() {
+        let records = records(5);
+        assert_eq!(fit(Some(1), &records), (render(&[marker()]).unwrap(), true));
+    }
+
+    #[test]
+    fn one_more_record_overflows() {
+        let records = records(10);
+        let three = render(&with_marker(&records[..3])).unwrap();
+
+        assert_eq!(fit(Some(three.len()), &records), (three.clone(), true));
+        assert_eq!(fit(Some(three.len() - 1), &records), (render(&with_marker(&records[..2])).unwrap(), true));
+    }
+}
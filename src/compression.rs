@@ -0,0 +1,52 @@
+use errors::*;
+use flate2::Compression as Level;
+use flate2::write::GzEncoder;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Compression of output files and HTTP request bodies.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Compression> {
+        match s {
+            "gzip" => Ok(Compression::Gzip),
+            _ => bail!("Unknown compression {}, expected gzip", s),
+        }
+    }
+}
+
+impl Compression {
+    /// Value of the `Content-Encoding` header of compressed bodies.
+    pub fn content_encoding(&self) -> &'static str {
+        match *self {
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match *self {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Level::default());
+
+                encoder.write_all(data)
+                    .and_then(|_| encoder.finish())
+                    .chain_err(|| "Unable to gzip payload")
+            },
+        }
+    }
+}
+
+/// Body of a request compressed as configured, along with its encoding.
+pub fn encode_body(compression: Option<Compression>, body: &str) -> Result<(Vec<u8>, Option<&'static str>)> {
+    match compression {
+        Some(compression) => Ok((compression.compress(body.as_bytes())?, Some(compression.content_encoding()))),
+        None => Ok((body.as_bytes().to_vec(), None)),
+    }
+}
@@ -0,0 +1,238 @@
+use ack;
+use common::StatusStorageInfo;
+use errors::*;
+use logging;
+use output;
+use serde_json::{self, Value};
+use sinks::syslog::local_hostname;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+use ureq;
+use {ALERT, FAILED};
+
+fn default_url() -> String {
+    "http://127.0.0.1:8500".to_owned()
+}
+
+fn default_service_name() -> String {
+    "lsf-agent".to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+/// Registration of the API server as a Consul service, with the health of the
+/// cluster as its TTL check.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsulConfig {
+    /// Consul agent to register with
+    #[serde(default = "default_url")]
+    pub url: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+
+    /// File containing the ACL token, so it does not need to live in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<String>,
+
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+
+    /// Defaults to the service name followed by the local hostname
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_id: Option<String>,
+
+    /// Address of the service, the address of the Consul node when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    #[serde(default)]
+    pub meta: BTreeMap<String, String>,
+
+    /// Time without a poll after which the check turns critical, three poll
+    /// intervals when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+
+    /// Time the check has to be critical for before Consul deregisters the
+    /// service, never when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deregister_after_secs: Option<u64>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Aggregated health of the cluster: critical if any host not acknowledged
+/// is FAILED, warning if any is in ALERT, and passing otherwise.
+pub fn check_status(records: &[StatusStorageInfo]) -> (&'static str, String) {
+    let failing: Vec<&StatusStorageInfo> = records.iter().filter(|record| !ack::is_ok(record)).collect();
+
+    let status = if failing.iter().any(|record| record.status == FAILED) {
+        "critical"
+    } else if failing.iter().any(|record| record.status == ALERT) {
+        "warning"
+    } else {
+        "passing"
+    };
+
+    let summary = output::summarize(records);
+
+    let mut check_output = format!("{} hosts: {} passed, {} alert, {} failed, {} downtime",
+        summary.total, summary.passed, summary.alert, summary.failed, summary.downtime);
+
+    if !failing.is_empty() {
+        let names: Vec<&str> = failing.iter().map(|record| record.name.as_str()).collect();
+        check_output.push_str(&format!("\nFailing: {}", names.join(", ")));
+    }
+
+    (status, check_output)
+}
+
+struct Registration {
+    config: ConsulConfig,
+    token: Option<String>,
+    service_id: String,
+    port: Option<u16>,
+    ttl_secs: u64,
+    registered: bool,
+}
+
+impl Registration {
+    fn check_id(&self) -> String {
+        format!("{}:health", self.service_id)
+    }
+
+    fn put(&self, path: &str, body: &Value) -> Result<()> {
+        let url = format!("{}{}", self.config.url.trim_end_matches('/'), path);
+
+        let mut request = ureq::put(&url)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .set("Content-Type", "application/json");
+
+        if let Some(ref token) = self.token {
+            request = request.set("X-Consul-Token", token);
+        }
+
+        let body = serde_json::to_string(body)
+            .chain_err(|| "Unable to serialize Consul request into string!")?;
+
+        request.send_string(&body)
+            .map(|_| ())
+            .map_err(|e| format!("Unable to PUT {}: {}", url, e).into())
+    }
+
+    fn register(&self) -> Result<()> {
+        let mut check = json!({
+            "CheckID": self.check_id(),
+            "Name": "LSF cluster health",
+            "TTL": format!("{}s", self.ttl_secs),
+        });
+
+        if let Some(secs) = self.config.deregister_after_secs {
+            check["DeregisterCriticalServiceAfter"] = json!(format!("{}s", secs));
+        }
+
+        let mut service = json!({
+            "ID": self.service_id,
+            "Name": self.config.service_name,
+            "Tags": self.config.tags,
+            "Meta": self.config.meta,
+            "Check": check,
+        });
+
+        if let Some(ref address) = self.config.address {
+            service["Address"] = json!(address);
+        }
+
+        if let Some(port) = self.port {
+            service["Port"] = json!(port);
+        }
+
+        self.put("/v1/agent/service/register", &service)
+    }
+
+    /// Registers the service unless done already, and reports the status of
+    /// the check.
+    fn report(&mut self, status: &str, check_output: &str) -> Result<()> {
+        if !self.registered {
+            self.register()?;
+            self.registered = true;
+            debug!(service_id = self.service_id.as_str(), "Registered Consul service");
+        }
+
+        let path = format!("/v1/agent/check/update/{}", self.check_id());
+        let res = self.put(&path, &json!({ "Status": status, "Output": check_output }));
+
+        // Consul may have lost the service, e.g. when its agent was reset
+        if res.is_err() {
+            self.registered = false;
+        }
+
+        res
+    }
+}
+
+/// Reports the health of every poll to Consul on a thread of its own, so that
+/// an unreachable Consul agent does not stall polling.
+pub struct Consul {
+    sender: Sender<(&'static str, String)>,
+}
+
+impl Consul {
+    /// Starts registering the service listening on the port, with the TTL of
+    /// the config or else the given one.
+    pub fn spawn(config: &ConsulConfig, port: Option<u16>, default_ttl_secs: u64) -> Result<Consul> {
+        let token = match (&config.token, &config.token_file) {
+            (Some(token), _) => Some(token.clone()),
+
+            (None, Some(token_file)) => {
+                let mut token = String::new();
+
+                File::open(token_file)
+                    .and_then(|mut f| f.read_to_string(&mut token))
+                    .chain_err(|| format!("Unable to read Consul token file at {}", token_file))?;
+
+                Some(token.trim().to_owned())
+            },
+
+            (None, None) => None,
+        };
+
+        let mut registration = Registration {
+            config: config.clone(),
+            token,
+            service_id: config.service_id.clone()
+                .unwrap_or_else(|| format!("{}-{}", config.service_name, local_hostname())),
+            port,
+            ttl_secs: config.ttl_secs.unwrap_or(default_ttl_secs),
+            registered: false,
+        };
+
+        let (sender, receiver) = mpsc::channel::<(&'static str, String)>();
+
+        thread::spawn(move || {
+            for (status, check_output) in receiver {
+                if let Err(e) = registration.report(status, &check_output) {
+                    warn!(error = %logging::chain(&e), "Unable to report health to Consul");
+                }
+            }
+        });
+
+        Ok(Consul { sender })
+    }
+
+    pub fn update(&self, records: &[StatusStorageInfo]) {
+        let _ = self.sender.send(check_status(records));
+    }
+}
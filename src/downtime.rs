@@ -0,0 +1,309 @@
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDateTime, Timelike};
+use common::StatusStorageInfo;
+use errors::*;
+use glob;
+use {DOWNTIME, PASSED};
+
+/// Scheduled downtime of the hosts matching any of `hosts`, either between
+/// `start` and `end` (RFC 3339), or for `durationMins` from every time the
+/// 5-field `cron` expression matches in local time.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DowntimeConfig {
+    pub hosts: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_mins: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+enum Schedule {
+    Between(DateTime<FixedOffset>, DateTime<FixedOffset>),
+    Cron(Cron, u32),
+}
+
+/// Downtime with its schedule parsed.
+pub struct Downtime {
+    hosts: Vec<String>,
+    schedule: Schedule,
+    comment: Option<String>,
+}
+
+/// Allowed values of each cron field, from minute to day of week.
+struct Cron {
+    fields: [Vec<bool>; 5],
+
+    /// Both day of month and day of week are restricted, in which case cron
+    /// matches days matching either
+    either_day: bool,
+}
+
+// day of week 7 is Sunday as well
+const CRON_RANGES: [(u32, u32); 5] = [(0, 59), (0, 23), (1, 31), (1, 12), (0, 7)];
+
+const DAY_OF_MONTH: usize = 2;
+const DAY_OF_WEEK: usize = 4;
+
+fn parse_cron_field(field: &str, (min, max): (u32, u32)) -> Result<Vec<bool>> {
+    let mut allowed = vec![false; max as usize + 1];
+
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(i) => (&part[..i], part[i + 1..].parse::<u32>()
+                .chain_err(|| format!("Invalid step in cron field {}", field))?),
+            None => (part, 1),
+        };
+
+        let (lo, hi) = if range == "*" {
+            (min, max)
+        } else {
+            let mut bounds = range.splitn(2, '-').map(|bound| bound.parse::<u32>());
+
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(lo)), None) => (lo, lo),
+                (Some(Ok(lo)), Some(Ok(hi))) => (lo, hi),
+                _ => bail!("Invalid cron field {}", field),
+            }
+        };
+
+        if lo < min || hi > max || lo > hi || step == 0 {
+            bail!("Cron field {} is out of range {}-{}", field, min, max);
+        }
+
+        for value in (lo..hi + 1).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+
+    Ok(allowed)
+}
+
+impl Cron {
+    fn parse(expr: &str) -> Result<Cron> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+
+        if fields.len() != 5 {
+            bail!("Cron expression {} must have 5 fields", expr);
+        }
+
+        let mut day_of_week = parse_cron_field(fields[DAY_OF_WEEK], CRON_RANGES[DAY_OF_WEEK])?;
+
+        if day_of_week[7] {
+            day_of_week[0] = true;
+        }
+
+        Ok(Cron {
+            fields: [
+                parse_cron_field(fields[0], CRON_RANGES[0])?,
+                parse_cron_field(fields[1], CRON_RANGES[1])?,
+                parse_cron_field(fields[DAY_OF_MONTH], CRON_RANGES[DAY_OF_MONTH])?,
+                parse_cron_field(fields[3], CRON_RANGES[3])?,
+                day_of_week,
+            ],
+
+            // as in Vixie cron, `*/2` does not restrict the days either
+            either_day: !fields[DAY_OF_MONTH].starts_with('*') && !fields[DAY_OF_WEEK].starts_with('*'),
+        })
+    }
+
+    fn day_matches(&self, t: &NaiveDateTime) -> bool {
+        let day_of_month = self.fields[DAY_OF_MONTH][t.day() as usize];
+        let day_of_week = self.fields[DAY_OF_WEEK][t.weekday().num_days_from_sunday() as usize];
+
+        if self.either_day {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+
+    /// Latest minute at or before `now` and not before `earliest` that the
+    /// expression matches, skipping months, days and hours that cannot match.
+    fn latest_match(&self, now: &NaiveDateTime, earliest: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut t = now.with_second(0)?.with_nanosecond(0)?;
+
+        while t >= *earliest {
+            t = if !self.fields[3][t.month() as usize] {
+                t.with_day(1)?.with_hour(0)?.with_minute(0)? - Duration::minutes(1)
+            } else if !self.day_matches(&t) {
+                t.with_hour(0)?.with_minute(0)? - Duration::minutes(1)
+            } else if !self.fields[1][t.hour() as usize] {
+                t.with_minute(0)? - Duration::minutes(1)
+            } else if !self.fields[0][t.minute() as usize] {
+                t - Duration::minutes(1)
+            } else {
+                return Some(t);
+            };
+        }
+
+        None
+    }
+}
+
+fn parse_time(s: &str) -> Result<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(s)
+        .chain_err(|| format!("Invalid downtime timestamp {}, expected RFC 3339", s))
+}
+
+impl Downtime {
+    pub fn parse(config: &DowntimeConfig) -> Result<Downtime> {
+        let schedule = match (config.start.as_ref(), config.end.as_ref(), config.cron.as_ref(), config.duration_mins) {
+            (Some(start), Some(end), None, None) => Schedule::Between(parse_time(start)?, parse_time(end)?),
+            (None, None, Some(cron), Some(duration_mins)) => Schedule::Cron(Cron::parse(cron)?, duration_mins),
+            _ => bail!("Downtime must have either start and end, or cron and durationMins"),
+        };
+
+        Ok(Downtime {
+            hosts: config.hosts.clone(),
+            schedule,
+            comment: config.comment.clone(),
+        })
+    }
+
+    fn is_active(&self, now: &DateTime<Local>) -> bool {
+        match self.schedule {
+            Schedule::Between(ref start, ref end) => *start <= *now && *now < *end,
+
+            // the window started at most durationMins ago, in local time
+            Schedule::Cron(_, 0) => false,
+
+            Schedule::Cron(ref cron, duration_mins) => {
+                let now = now.naive_local();
+                let earliest = now - Duration::minutes(duration_mins as i64 - 1);
+
+                cron.latest_match(&now, &earliest).is_some()
+            },
+        }
+    }
+}
+
+/// Reports failing hosts in an active downtime with the DOWNTIME status.
+pub fn apply(records: &mut [StatusStorageInfo], downtimes: &[Downtime], now: &DateTime<Local>) {
+    let active: Vec<&Downtime> = downtimes.iter().filter(|downtime| downtime.is_active(now)).collect();
+
+    if active.is_empty() {
+        return;
+    }
+
+    for record in records.iter_mut().filter(|record| record.status != PASSED) {
+        let downtime = active.iter()
+            .find(|downtime| downtime.hosts.iter().any(|pattern| glob::matches(pattern, &record.name)));
+
+        if let Some(downtime) = downtime {
+            let remark = match downtime.comment {
+                Some(ref comment) => format!("In scheduled downtime: {}", comment),
+                None => "In scheduled downtime".to_owned(),
+            };
+
+            record.status = DOWNTIME;
+
+            record.remarks = Some(match record.remarks {
+                Some(ref remarks) => format!("{} ({})", remarks, remark),
+                None => remark,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn matches(expr: &str, t: &NaiveDateTime) -> bool {
+        Cron::parse(expr).unwrap().latest_match(t, t).is_some()
+    }
+
+    #[test]
+    fn parses_lists_ranges_and_steps() {
+        let cron = Cron::parse("*/15 1-3,22 * * *").unwrap();
+
+        let minutes: Vec<_> = (0..60).filter(|&m| cron.fields[0][m]).collect();
+        let hours: Vec<_> = (0..24).filter(|&h| cron.fields[1][h]).collect();
+
+        assert_eq!(minutes, vec![0, 15, 30, 45]);
+        assert_eq!(hours, vec![1, 2, 3, 22]);
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expr in &["0 2 * *", "0 2 * * * *", "60 * * * *", "* 24 * * *", "* * 0 * *", "* * * 13 *",
+            "* * * * 8", "*/0 * * * *", "5-1 * * * *", "a * * * *", "1- * * * *"] {
+
+            assert!(Cron::parse(expr).is_err(), "{} should be rejected", expr);
+        }
+    }
+
+    #[test]
+    fn day_of_week_seven_is_sunday() {
+        // 2024-09-01 is a Sunday
+        assert!(matches("0 2 * * 7", &at(2024, 9, 1, 2, 0)));
+        assert!(matches("0 2 * * 0", &at(2024, 9, 1, 2, 0)));
+        assert!(matches("0 2 * * 5-7", &at(2024, 9, 1, 2, 0)));
+        assert!(!matches("0 2 * * 7", &at(2024, 9, 2, 2, 0)));
+    }
+
+    #[test]
+    fn restricted_days_match_either_field() {
+        let expr = "0 2 1 * 0";
+
+        assert!(matches(expr, &at(2024, 9, 1, 2, 0)));
+        assert!(matches(expr, &at(2024, 9, 8, 2, 0)));
+        assert!(matches(expr, &at(2024, 10, 1, 2, 0)));
+        assert!(!matches(expr, &at(2024, 10, 2, 2, 0)));
+    }
+
+    #[test]
+    fn unrestricted_day_field_requires_the_other() {
+        assert!(!matches("0 2 * * 0", &at(2024, 10, 1, 2, 0)));
+        assert!(!matches("0 2 1 * *", &at(2024, 9, 8, 2, 0)));
+        assert!(!matches("0 2 */2 * 0", &at(2024, 9, 8, 2, 0)));
+        assert!(matches("0 2 */2 * 0", &at(2024, 9, 15, 2, 0)));
+    }
+
+    #[test]
+    fn latest_match_skips_back_across_months() {
+        let cron = Cron::parse("30 2 1 * *").unwrap();
+        let now = at(2024, 10, 3, 10, 0);
+
+        assert_eq!(cron.latest_match(&now, &at(2024, 8, 1, 0, 0)), Some(at(2024, 10, 1, 2, 30)));
+        assert_eq!(cron.latest_match(&now, &at(2024, 10, 1, 2, 31)), None);
+        assert_eq!(Cron::parse("0 0 29 2 *").unwrap().latest_match(&now, &at(2020, 1, 1, 0, 0)), Some(at(2024, 2, 29, 0, 0)));
+    }
+
+    #[test]
+    fn cron_downtime_is_active_for_its_duration() {
+        let config = DowntimeConfig {
+            hosts: vec!["*".to_owned()],
+            start: None,
+            end: None,
+            cron: Some("0 2 * * 6".to_owned()),
+            duration_mins: Some(120),
+            comment: None,
+        };
+
+        let downtime = Downtime::parse(&config).unwrap();
+        let local = |t: NaiveDateTime| Local.from_local_datetime(&t).unwrap();
+
+        // 2024-06-15 is a Saturday
+        assert!(!downtime.is_active(&local(at(2024, 6, 15, 1, 59))));
+        assert!(downtime.is_active(&local(at(2024, 6, 15, 2, 0))));
+        assert!(downtime.is_active(&local(at(2024, 6, 15, 3, 59))));
+        assert!(!downtime.is_active(&local(at(2024, 6, 15, 4, 0))));
+    }
+}
@@ -0,0 +1,538 @@
+use encoding;
+use errors::*;
+use ring::{aead, agreement, hkdf, hmac};
+use ring::rand::{SecureRandom, SystemRandom};
+
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const RECIPIENT_PREFIX: &str = "age1";
+
+// plaintext size of every chunk of the payload but the last
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Recipients the payloads are encrypted to, for transports that are not
+/// trusted.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionConfig {
+    /// X25519 public keys as printed by `age-keygen`, i.e. `age1...`
+    pub recipients: Vec<String>,
+}
+
+/// Encrypts payloads into the age v1 format, so that they are decrypted with
+/// the `age` CLI or any of its libraries.
+#[derive(Clone, Debug)]
+pub struct Encryptor {
+    recipients: Vec<[u8; 32]>,
+}
+
+fn bech32_polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum = 1u32;
+
+    for &value in values {
+        let top = checksum >> 25;
+        checksum = (checksum & 0x1ffffff) << 5 ^ value as u32;
+
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+
+    checksum
+}
+
+/// Decodes the Bech32 `age1...` encoding of an X25519 public key.
+fn decode_recipient(recipient: &str) -> Result<[u8; 32]> {
+    let invalid = || Error::from(format!("Invalid age recipient {}", recipient));
+
+    if recipient.to_lowercase() != recipient && recipient.to_uppercase() != recipient {
+        return Err(invalid());
+    }
+
+    let recipient_lower = recipient.to_lowercase();
+    let data = recipient_lower.strip_prefix(RECIPIENT_PREFIX).ok_or_else(invalid)?;
+
+    let values = data.bytes()
+        .map(|c| BECH32_CHARSET.iter().position(|&x| x == c).map(|value| value as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+
+    // the human-readable part takes part in the checksum
+    let hrp = RECIPIENT_PREFIX.trim_end_matches('1').as_bytes();
+
+    let checked: Vec<u8> = hrp.iter().map(|c| c >> 5)
+        .chain(Some(0))
+        .chain(hrp.iter().map(|c| c & 0x1f))
+        .chain(values.iter().cloned())
+        .collect();
+
+    if values.len() < 6 || bech32_polymod(&checked) != 1 {
+        return Err(invalid());
+    }
+
+    let mut key = Vec::with_capacity(32);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for &value in &values[..values.len() - 6] {
+        acc = (acc << 5 | value as u32) & 0xfff;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            key.push((acc >> bits) as u8);
+        }
+    }
+
+    if bits >= 5 || acc & ((1 << bits) - 1) != 0 || key.len() != 32 {
+        return Err(invalid());
+    }
+
+    let mut public_key = [0u8; 32];
+    public_key.copy_from_slice(&key);
+    Ok(public_key)
+}
+
+fn hkdf_sha256(salt: &[u8], ikm: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+
+    hkdf::Salt::new(hkdf::HKDF_SHA256, salt)
+        .extract(ikm)
+        .expand(&[info], hkdf::HKDF_SHA256)
+        .and_then(|okm| okm.fill(&mut key))
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+    key
+}
+
+fn seal(key: &[u8; 32], nonce: [u8; 12], plaintext: &[u8]) -> Vec<u8> {
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::CHACHA20_POLY1305, key)
+        .expect("32 bytes is a valid ChaCha20-Poly1305 key length"));
+
+    let mut sealed = plaintext.to_vec();
+
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut sealed)
+        .expect("chunks are within the ChaCha20-Poly1305 length limit");
+
+    sealed
+}
+
+fn base64_unpadded(input: &[u8]) -> String {
+    encoding::base64(input).trim_end_matches('=').to_owned()
+}
+
+impl Encryptor {
+    pub fn from_config(config: &EncryptionConfig) -> Result<Encryptor> {
+        if config.recipients.is_empty() {
+            bail!("At least one recipient has to be given for encryption");
+        }
+
+        let recipients = config.recipients.iter()
+            .map(|recipient| decode_recipient(recipient))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Encryptor { recipients })
+    }
+
+    /// Encrypts the payload to every recipient.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let rng = SystemRandom::new();
+
+        let mut file_key = [0u8; 16];
+        rng.fill(&mut file_key).map_err(|_| "Unable to generate encryption file key")?;
+
+        let mut header = "age-encryption.org/v1\n".to_owned();
+
+        for recipient in &self.recipients {
+            let ephemeral = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng)
+                .map_err(|_| "Unable to generate ephemeral X25519 key")?;
+
+            let share = ephemeral.compute_public_key()
+                .map_err(|_| "Unable to compute ephemeral X25519 public key")?;
+
+            let mut salt = share.as_ref().to_vec();
+            salt.extend_from_slice(recipient);
+
+            let wrap_key = agreement::agree_ephemeral(
+                ephemeral,
+                &agreement::UnparsedPublicKey::new(&agreement::X25519, recipient),
+                |shared| hkdf_sha256(&salt, shared, b"age-encryption.org/v1/X25519"))
+                .map_err(|_| "Unable to agree on X25519 key with recipient")?;
+
+            let wrapped_file_key = seal(&wrap_key, [0u8; 12], &file_key);
+
+            header.push_str(&format!("-> X25519 {}\n{}\n",
+                base64_unpadded(share.as_ref()), base64_unpadded(&wrapped_file_key)));
+        }
+
+        header.push_str("---");
+
+        let mac_key = hmac::Key::new(hmac::HMAC_SHA256, &hkdf_sha256(&[], &file_key, b"header"));
+        let mac = hmac::sign(&mac_key, header.as_bytes());
+        header.push_str(&format!(" {}\n", base64_unpadded(mac.as_ref())));
+
+        let mut nonce = [0u8; 16];
+        rng.fill(&mut nonce).map_err(|_| "Unable to generate encryption nonce")?;
+
+        let payload_key = hkdf_sha256(&nonce, &file_key, b"payload");
+
+        let mut encrypted = header.into_bytes();
+        encrypted.extend_from_slice(&nonce);
+
+        // an empty payload still takes a single, empty last chunk
+        let chunks: Vec<&[u8]> = if plaintext.is_empty() { vec![&[]] } else { plaintext.chunks(CHUNK_SIZE).collect() };
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut chunk_nonce = [0u8; 12];
+            chunk_nonce[3..11].copy_from_slice(&(i as u64).to_be_bytes());
+
+            if i + 1 == chunks.len() {
+                chunk_nonce[11] = 1;
+            }
+
+            encrypted.extend(seal(&payload_key, chunk_nonce, chunk));
+        }
+
+        Ok(encrypted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// X25519 of RFC 7748 over 51-bit limbs, as ring only computes it for
+    /// ephemeral keys, which rules out decrypting to a fixed identity.
+    mod x25519 {
+        type Fe = [u64; 5];
+
+        const MASK: u64 = (1 << 51) - 1;
+
+        fn carry(mut h: [u128; 5]) -> Fe {
+            for _ in 0..2 {
+                for i in 0..4 {
+                    h[i + 1] += h[i] >> 51;
+                    h[i] &= MASK as u128;
+                }
+
+                h[0] += 19 * (h[4] >> 51);
+                h[4] &= MASK as u128;
+            }
+
+            [h[0] as u64, h[1] as u64, h[2] as u64, h[3] as u64, h[4] as u64]
+        }
+
+        fn add(a: &Fe, b: &Fe) -> Fe {
+            carry([0, 1, 2, 3, 4].map(|i| (a[i] + b[i]) as u128))
+        }
+
+        fn sub(a: &Fe, b: &Fe) -> Fe {
+            // adds 2p first so that no limb goes negative
+            let two_p = [0xfffffffffffda, 0xffffffffffffe, 0xffffffffffffe, 0xffffffffffffe, 0xffffffffffffe];
+            carry([0, 1, 2, 3, 4].map(|i| (a[i] + two_p[i] - b[i]) as u128))
+        }
+
+        fn mul(a: &Fe, b: &Fe) -> Fe {
+            let m = |i: usize, j: usize| a[i] as u128 * b[j] as u128;
+
+            carry([
+                m(0, 0) + 19 * (m(1, 4) + m(2, 3) + m(3, 2) + m(4, 1)),
+                m(0, 1) + m(1, 0) + 19 * (m(2, 4) + m(3, 3) + m(4, 2)),
+                m(0, 2) + m(1, 1) + m(2, 0) + 19 * (m(3, 4) + m(4, 3)),
+                m(0, 3) + m(1, 2) + m(2, 1) + m(3, 0) + 19 * m(4, 4),
+                m(0, 4) + m(1, 3) + m(2, 2) + m(3, 1) + m(4, 0),
+            ])
+        }
+
+        /// Raises to p - 2 = 2^255 - 21, the inverse.
+        fn invert(a: &Fe) -> Fe {
+            let mut result = [1, 0, 0, 0, 0];
+
+            for bit in (0..255).rev() {
+                result = mul(&result, &result);
+
+                if bit >= 5 || (0b01011 >> bit) & 1 == 1 {
+                    result = mul(&result, a);
+                }
+            }
+
+            result
+        }
+
+        fn from_bytes(b: &[u8; 32]) -> Fe {
+            let load = |i: usize| {
+                let mut word = [0u8; 8];
+                word.copy_from_slice(&b[i..i + 8]);
+                u64::from_le_bytes(word)
+            };
+
+            [load(0) & MASK, (load(6) >> 3) & MASK, (load(12) >> 6) & MASK, (load(19) >> 1) & MASK, (load(24) >> 12) & MASK]
+        }
+
+        fn to_bytes(h: &Fe) -> [u8; 32] {
+            let mut h = carry(h.map(|limb| limb as u128));
+
+            // subtracts p if the value is at least p
+            let mut q = (h[0] + 19) >> 51;
+
+            for limb in &h[1..] {
+                q = (limb + q) >> 51;
+            }
+
+            h[0] += 19 * q;
+
+            for i in 0..4 {
+                h[i + 1] += h[i] >> 51;
+                h[i] &= MASK;
+            }
+
+            h[4] &= MASK;
+
+            let mut out = [0u8; 32];
+            let (mut acc, mut bits, mut pos) = (0u128, 0, 0);
+
+            for limb in h.iter() {
+                acc |= (*limb as u128) << bits;
+                bits += 51;
+
+                while bits >= 8 && pos < 32 {
+                    out[pos] = acc as u8;
+                    acc >>= 8;
+                    bits -= 8;
+                    pos += 1;
+                }
+            }
+
+            out[pos..].iter_mut().for_each(|byte| {
+                *byte = acc as u8;
+                acc >>= 8;
+            });
+
+            out
+        }
+
+        pub fn scalar_mult(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+            let mut k = *scalar;
+            k[0] &= 248;
+            k[31] &= 127;
+            k[31] |= 64;
+
+            let x1 = from_bytes(u);
+            let (mut x2, mut z2, mut x3, mut z3) = ([1, 0, 0, 0, 0], [0; 5], x1, [1, 0, 0, 0, 0]);
+            let mut swap = 0;
+
+            for t in (0..255).rev() {
+                let k_t = (k[t / 8] >> (t % 8)) & 1;
+                swap ^= k_t;
+
+                if swap == 1 {
+                    ::std::mem::swap(&mut x2, &mut x3);
+                    ::std::mem::swap(&mut z2, &mut z3);
+                }
+
+                swap = k_t;
+
+                let a = add(&x2, &z2);
+                let aa = mul(&a, &a);
+                let b = sub(&x2, &z2);
+                let bb = mul(&b, &b);
+                let e = sub(&aa, &bb);
+                let c = add(&x3, &z3);
+                let d = sub(&x3, &z3);
+                let da = mul(&d, &a);
+                let cb = mul(&c, &b);
+
+                let da_plus_cb = add(&da, &cb);
+                let da_minus_cb = sub(&da, &cb);
+
+                x3 = mul(&da_plus_cb, &da_plus_cb);
+                z3 = mul(&x1, &mul(&da_minus_cb, &da_minus_cb));
+                x2 = mul(&aa, &bb);
+                z2 = mul(&e, &add(&aa, &mul(&[121665, 0, 0, 0, 0], &e)));
+            }
+
+            if swap == 1 {
+                ::std::mem::swap(&mut x2, &mut x3);
+                ::std::mem::swap(&mut z2, &mut z3);
+            }
+
+            to_bytes(&mul(&x2, &invert(&z2)))
+        }
+
+        pub fn public_key(scalar: &[u8; 32]) -> [u8; 32] {
+            let mut base_point = [0u8; 32];
+            base_point[0] = 9;
+            scalar_mult(scalar, &base_point)
+        }
+    }
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn key(s: &str) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&unhex(s));
+        key
+    }
+
+    fn unbase64(s: &str) -> Vec<u8> {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        let mut out = Vec::new();
+        let (mut acc, mut bits) = (0u32, 0);
+
+        for c in s.bytes() {
+            acc = acc << 6 | ALPHABET.iter().position(|&x| x == c).expect("invalid base64") as u32;
+            bits += 6;
+
+            if bits >= 8 {
+                bits -= 8;
+                out.push((acc >> bits) as u8);
+            }
+        }
+
+        out
+    }
+
+    fn open(key: &[u8; 32], nonce: [u8; 12], sealed: &[u8]) -> Option<Vec<u8>> {
+        let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::CHACHA20_POLY1305, key).unwrap());
+        let mut sealed = sealed.to_vec();
+
+        let plaintext = key.open_in_place(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut sealed).ok()?;
+        Some(plaintext.to_vec())
+    }
+
+    /// Decrypts an age v1 file to the X25519 identity as the spec describes,
+    /// or `None` if the file is not for the identity or was tampered with.
+    fn decrypt(identity: &[u8; 32], file: &[u8]) -> Option<Vec<u8>> {
+        let header_end = file.windows(4).position(|window| window == b"\n---")? + 4;
+        let mac_end = header_end + file[header_end..].iter().position(|&b| b == b'\n')?;
+
+        let header = ::std::str::from_utf8(&file[..header_end]).ok()?;
+        let mac = unbase64(::std::str::from_utf8(&file[header_end..mac_end]).ok()?.strip_prefix(' ')?);
+        let lines: Vec<&str> = header.lines().collect();
+
+        if lines[0] != "age-encryption.org/v1" {
+            return None;
+        }
+
+        let recipient = x25519::public_key(identity);
+
+        let file_key = lines.iter().enumerate()
+            .filter_map(|(i, line)| line.strip_prefix("-> X25519 ").map(|share| (unbase64(share), unbase64(lines[i + 1]))))
+            .find_map(|(share, wrapped_file_key)| {
+                let mut share_key = [0u8; 32];
+                share_key.copy_from_slice(&share);
+
+                let mut salt = share.clone();
+                salt.extend_from_slice(&recipient);
+
+                let shared = x25519::scalar_mult(identity, &share_key);
+                open(&hkdf_sha256(&salt, &shared, b"age-encryption.org/v1/X25519"), [0u8; 12], &wrapped_file_key)
+            })?;
+
+        let mac_key = hmac::Key::new(hmac::HMAC_SHA256, &hkdf_sha256(&[], &file_key, b"header"));
+        hmac::verify(&mac_key, header.as_bytes(), &mac).ok()?;
+
+        let payload = &file[mac_end + 1..];
+        let payload_key = hkdf_sha256(&payload[..16], &file_key, b"payload");
+        let chunks: Vec<&[u8]> = payload[16..].chunks(CHUNK_SIZE + 16).collect();
+        let mut plaintext = Vec::new();
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut chunk_nonce = [0u8; 12];
+            chunk_nonce[3..11].copy_from_slice(&(i as u64).to_be_bytes());
+            chunk_nonce[11] = (i + 1 == chunks.len()) as u8;
+
+            plaintext.extend(open(&payload_key, chunk_nonce, chunk)?);
+        }
+
+        Some(plaintext)
+    }
+
+    const IDENTITY: &str = "0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20";
+    const RECIPIENT: &str = "age1q73he0q5yzfu3d64msd3p6rvksnrwjk3d2598mgtmlqt9wrdr37q2vrn72";
+
+    #[test]
+    fn x25519_matches_rfc_7748() {
+        assert_eq!(
+            x25519::scalar_mult(
+                &key("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4"),
+                &key("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c")),
+            key("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552"));
+
+        let alice = key("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = key("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+
+        assert_eq!(x25519::public_key(&alice), key("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"));
+        assert_eq!(x25519::public_key(&bob), key("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"));
+        assert_eq!(x25519::scalar_mult(&alice, &x25519::public_key(&bob)),
+            key("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742"));
+    }
+
+    #[test]
+    fn decodes_recipient() {
+        assert_eq!(decode_recipient(RECIPIENT).unwrap(), x25519::public_key(&key(IDENTITY)));
+        assert_eq!(decode_recipient(&RECIPIENT.to_uppercase()).unwrap(), x25519::public_key(&key(IDENTITY)));
+        assert!(decode_recipient(&RECIPIENT.replace("q73", "q74")).is_err());
+        assert!(decode_recipient("age1").is_err());
+    }
+
+    /// File encrypted to `RECIPIENT` with fixed keys and nonce by a separate
+    /// implementation over OpenSSL (Python `cryptography`), following the
+    /// age v1 spec.
+    #[test]
+    fn decrypts_file_of_other_implementation() {
+        let mut file = b"age-encryption.org/v1\n-> X25519 VxR2nRFr92Q2rnS8eT0sMK0ZA8WaxSc4BcfiaYtBDDY\n/Q4jDJCqLxkDjaQUfyqnSF8GHz9HCRphC2dt2o3RkVw\n--- mU58tw1xIcGjz59Pk/WKJ9NurMxP6H6A+gmE/Ii3H/Q\n".to_vec();
+        file.extend(unhex("0f0e0d0c0b0a090807060504030201002f6895cf1746c842cf3db39d5b6d755126fc2ec012edba4baed1da4a726c27c7cbd0a67b9ee7c7f84fd060ed1333bd56ccb7"));
+
+        assert_eq!(decrypt(&key(IDENTITY), &file).unwrap(), b"[{\"name\":\"lsf.node1\",\"status\":0}]\n".to_vec());
+    }
+
+    #[test]
+    fn round_trips_payloads_of_any_size() {
+        let encryptor = Encryptor::from_config(&EncryptionConfig { recipients: vec![RECIPIENT.to_owned()] }).unwrap();
+
+        for &len in &[0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 2 * CHUNK_SIZE + 100] {
+            let plaintext: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let file = encryptor.encrypt(&plaintext).unwrap();
+
+            assert_eq!(decrypt(&key(IDENTITY), &file), Some(plaintext), "payload of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn every_recipient_decrypts() {
+        let other = key("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+
+        let encryptor = Encryptor {
+            recipients: vec![x25519::public_key(&other), decode_recipient(RECIPIENT).unwrap()],
+        };
+
+        let file = encryptor.encrypt(b"payload").unwrap();
+
+        assert_eq!(decrypt(&key(IDENTITY), &file), Some(b"payload".to_vec()));
+        assert_eq!(decrypt(&other, &file), Some(b"payload".to_vec()));
+        assert_eq!(decrypt(&key("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a"), &file), None);
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let encryptor = Encryptor::from_config(&EncryptionConfig { recipients: vec![RECIPIENT.to_owned()] }).unwrap();
+        let file = encryptor.encrypt(b"payload").unwrap();
+
+        // a stanza slipped into the header fails the header MAC
+        let header_end = file.windows(4).position(|window| window == b"\n---").unwrap() + 1;
+        let mut tampered_header = file[..header_end].to_vec();
+        tampered_header.extend_from_slice(b"-> grease\n\n");
+        tampered_header.extend_from_slice(&file[header_end..]);
+        assert_eq!(decrypt(&key(IDENTITY), &tampered_header), None);
+
+        let mut tampered_payload = file.clone();
+        *tampered_payload.last_mut().unwrap() ^= 1;
+        assert_eq!(decrypt(&key(IDENTITY), &tampered_payload), None);
+    }
+}
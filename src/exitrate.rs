@@ -0,0 +1,121 @@
+use common::StatusStorageInfo;
+use errors::*;
+use lsf::{self, Job, DONE_JOB, JOB_STAT_DONE, JOB_STAT_EXIT};
+use std::collections::{BTreeMap, BTreeSet};
+use Config;
+use {ALERT, FAILED, PASSED};
+
+fn default_min_jobs() -> usize {
+    10
+}
+
+/// Ratio of jobs finishing in EXIT rather than DONE above which a host gets
+/// raised to ALERT.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExitRateConfig {
+    pub max_exit_percent: f64,
+
+    /// Sliding window of finished jobs, since the previous poll if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_mins: Option<u64>,
+
+    /// Jobs a host has to finish within the window for its ratio to count
+    #[serde(default = "default_min_jobs")]
+    pub min_jobs: usize,
+}
+
+/// Finished jobs of a host within the window.
+#[derive(Default)]
+struct Tally {
+    done: usize,
+    exited: usize,
+}
+
+fn tally_by_host(jobs: &[Job], since: i64, now: i64) -> BTreeMap<&str, Tally> {
+    let mut tallies: BTreeMap<&str, Tally> = BTreeMap::new();
+
+    for job in jobs.iter().filter(|job| job.end_time > since && job.end_time <= now) {
+        // a job counts once per host, however many of its slots ran there
+        let hosts: BTreeSet<&str> = job.ex_hosts.iter().map(|host| host.as_str()).collect();
+
+        for host in hosts {
+            let tally = tallies.entry(host).or_default();
+
+            if job.status & JOB_STAT_EXIT != 0 {
+                tally.exited += 1;
+            } else if job.status & JOB_STAT_DONE != 0 {
+                tally.done += 1;
+            }
+        }
+    }
+
+    tallies
+}
+
+/// Raises the passing hosts whose jobs finished in EXIT above the configured
+/// ratio since `since` (or within the window) to ALERT. Adds a
+/// `{prefix}batch:jobs` record that is FAILED while mbatchd cannot be queried.
+pub fn apply(
+    records: &mut Vec<StatusStorageInfo>,
+    exit_rate: &ExitRateConfig,
+    config: &Config,
+    since: Option<i64>,
+    now: i64) -> Result<()> {
+
+    let since = match (exit_rate.window_mins, since) {
+        (Some(window_mins), _) => now - window_mins as i64 * 60,
+        (None, Some(since)) => since,
+
+        // nothing to compare against on the first poll
+        (None, None) => return Ok(()),
+    };
+
+    let jobs = match lsf::jobs(DONE_JOB) {
+        Ok(jobs) => jobs,
+
+        Err(Error(ErrorKind::QueryFailed(reason), _)) => {
+            records.push(StatusStorageInfo::new(
+                format!("{}batch:jobs", config.prefix),
+                FAILED,
+                None,
+                Some(config.critical_group_name.clone()),
+                Some(format!("Job query failed: {}", reason))));
+
+            return Ok(());
+        },
+
+        Err(e) => return Err(e),
+    };
+
+    let tallies: BTreeMap<String, Tally> = tally_by_host(&jobs, since, now).into_iter()
+        .map(|(host, tally)| (config.record_name(host), tally))
+        .collect();
+
+    for record in records.iter_mut() {
+        let tally = match tallies.get(&record.name) {
+            Some(tally) if tally.done + tally.exited >= exit_rate.min_jobs => tally,
+            _ => continue,
+        };
+
+        let finished = tally.done + tally.exited;
+        let exit_percent = tally.exited as f64 * 100.0 / finished as f64;
+
+        if exit_percent <= exit_rate.max_exit_percent {
+            continue;
+        }
+
+        if record.status == PASSED {
+            record.status = ALERT;
+        }
+
+        let reason = format!("{:.0}% of {} finished jobs exited, above {}%", exit_percent, finished, exit_rate.max_exit_percent);
+
+        record.remarks = Some(match record.remarks {
+            Some(ref remarks) => format!("{} ({})", remarks, reason),
+            None => reason,
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use common::StatusStorageInfo;
+use encoding;
+use errors::*;
+use logging;
+use serde_json;
+use sinks::http::BasicAuth;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+use tls;
+use ureq::{Agent, AgentBuilder};
+use FAILED;
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+/// Agents whose records `federate` pulls and merges into a single payload.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FederationConfig {
+    pub remotes: Vec<RemoteConfig>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Agent running `serve`, usually one per cluster or site.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteConfig {
+    pub name: String,
+
+    /// Base URL of the API server of the agent, e.g. `https://lsf-eu:8080`
+    pub url: String,
+
+    /// Prefix of the names of the records of the agent, `<name>.` when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+
+    /// Prefix the agent itself gives the names, removed before prefixing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strip_prefix: Option<String>,
+
+    /// Labels added to every record of the agent, overriding its own
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token: Option<String>,
+
+    /// File containing the bearer token, so it does not need to live in the
+    /// config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token_file: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basic_auth: Option<BasicAuth>,
+
+    /// CA bundle the certificate of the agent must chain to, the Mozilla
+    /// root certificates when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_path: Option<String>,
+}
+
+struct Remote {
+    config: RemoteConfig,
+    prefix: String,
+    agent: Agent,
+    authorization: Option<String>,
+}
+
+/// Records of a federated poll.
+pub struct FederatedPoll {
+    pub records: Vec<StatusStorageInfo>,
+
+    /// Remotes that could be polled, the others each having a FAILED record
+    /// instead
+    pub reached: usize,
+}
+
+/// Remotes of the federation, with their credentials resolved once.
+pub struct Federation {
+    remotes: Vec<Remote>,
+}
+
+impl Remote {
+    fn new(config: RemoteConfig, timeout: Duration) -> Result<Remote> {
+        let bearer_token = match (&config.bearer_token, &config.bearer_token_file) {
+            (Some(bearer_token), _) => Some(bearer_token.clone()),
+
+            (None, Some(bearer_token_file)) => {
+                let mut bearer_token = String::new();
+
+                File::open(bearer_token_file)
+                    .and_then(|mut f| f.read_to_string(&mut bearer_token))
+                    .chain_err(|| format!("Unable to read bearer token file at {}", bearer_token_file))?;
+
+                Some(bearer_token.trim().to_owned())
+            },
+
+            (None, None) => None,
+        };
+
+        let authorization = match (bearer_token, &config.basic_auth) {
+            (Some(bearer_token), _) => Some(format!("Bearer {}", bearer_token)),
+
+            (None, Some(basic_auth)) => {
+                let credentials = format!("{}:{}", basic_auth.username, basic_auth.password);
+                Some(format!("Basic {}", encoding::base64(credentials.as_bytes())))
+            },
+
+            (None, None) => None,
+        };
+
+        let agent = AgentBuilder::new()
+            .timeout(timeout)
+            .tls_config(tls::client_config(config.ca_path.as_deref())?)
+            .build();
+
+        let prefix = config.prefix.clone().unwrap_or_else(|| format!("{}.", config.name));
+        Ok(Remote { config, prefix, agent, authorization })
+    }
+
+    fn fetch(&self) -> Result<Vec<StatusStorageInfo>> {
+        let url = format!("{}/api/hosts", self.config.url.trim_end_matches('/'));
+        let mut request = self.agent.get(&url);
+
+        if let Some(ref authorization) = self.authorization {
+            request = request.set("Authorization", authorization);
+        }
+
+        let response = request.call()
+            .map_err(|e| Error::from(format!("Unable to GET {}: {}", url, e)))?
+            .into_string()
+            .chain_err(|| format!("Unable to read response of {}", url))?;
+
+        serde_json::from_str(&response)
+            .chain_err(|| format!("Unable to parse records of {}", url))
+    }
+
+    /// Records of the agent with their names prefixed and the labels added,
+    /// or a FAILED record for the agent if it could not be polled.
+    fn poll(&self, critical_group_name: &str) -> (Vec<StatusStorageInfo>, bool) {
+        let mut records = match self.fetch() {
+            Ok(records) => records,
+
+            Err(e) => {
+                warn!(remote = self.config.name.as_str(), error = %logging::chain(&e), "Unable to poll remote agent");
+
+                let record = StatusStorageInfo::new(
+                    format!("{}*", self.prefix),
+                    FAILED,
+                    None,
+                    Some(critical_group_name.to_owned()),
+                    Some(format!("Unable to poll remote agent {}: {}", self.config.name, e)));
+
+                return (vec![record], false);
+            },
+        };
+
+        for record in &mut records {
+            let name = match self.config.strip_prefix {
+                Some(ref strip_prefix) => record.name.strip_prefix(strip_prefix.as_str()).unwrap_or(&record.name),
+                None => &record.name,
+            };
+
+            record.name = format!("{}{}", self.prefix, name);
+
+            if !self.config.labels.is_empty() {
+                record.labels.get_or_insert_with(BTreeMap::new).extend(self.config.labels.clone());
+            }
+        }
+
+        (records, true)
+    }
+}
+
+impl Federation {
+    pub fn new(config: &FederationConfig) -> Result<Federation> {
+        if config.remotes.is_empty() {
+            bail!("Federation requires at least one remote");
+        }
+
+        let timeout = Duration::from_secs(config.timeout_secs);
+
+        let remotes = config.remotes.iter()
+            .map(|remote| Remote::new(remote.clone(), timeout)
+                .chain_err(|| format!("Invalid federation remote {}", remote.name)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Federation { remotes })
+    }
+
+    /// Polls every remote concurrently, merging their records in the order
+    /// of the remotes.
+    pub fn poll(&self, critical_group_name: &str) -> FederatedPoll {
+        let polls: Vec<(Vec<StatusStorageInfo>, bool)> = thread::scope(|scope| {
+            let handles: Vec<_> = self.remotes.iter()
+                .map(|remote| scope.spawn(move || remote.poll(critical_group_name)))
+                .collect();
+
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|_| (Vec::new(), false)))
+                .collect()
+        });
+
+        let reached = polls.iter().filter(|&&(_, reached)| reached).count();
+        let records = polls.into_iter().flat_map(|(records, _)| records).collect();
+
+        FederatedPoll { records, reached }
+    }
+}
@@ -0,0 +1,74 @@
+use common::{StatusStorageInfo, StorageInfo};
+use libc;
+use std::ffi::CString;
+use std::mem;
+use {ALERT, FAILED, PASSED};
+
+/// Mount point on the agent host whose usage gets checked.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesystemConfig {
+    pub path: String,
+
+    /// Record name without the prefix, the path when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_percent: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_percent: Option<f64>,
+}
+
+/// Used and total bytes of the filesystem mounted at the path.
+pub fn usage(path: &str) -> Option<StorageInfo> {
+    let c_path = CString::new(path).ok()?;
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    let block_size = stat.f_frsize as u64;
+    let total = stat.f_blocks as u64 * block_size;
+    let used = total - stat.f_bfree as u64 * block_size;
+
+    Some(StorageInfo::new(used, total))
+}
+
+fn used_percent(storage: &StorageInfo) -> f64 {
+    if storage.total == 0 { 0.0 } else { storage.used as f64 * 100.0 / storage.total as f64 }
+}
+
+/// Builds a record per filesystem with its usage, ALERT or FAILED once the
+/// used percentage reaches the configured level, and FAILED when it cannot
+/// be stat'ed.
+pub fn check(filesystems: &[FilesystemConfig], prefix: &str, critical_group_name: &str) -> Vec<StatusStorageInfo> {
+    filesystems.iter()
+        .map(|filesystem| {
+            let name = format!("{}{}", prefix, filesystem.name.as_ref().unwrap_or(&filesystem.path));
+
+            let (status, storage, remarks) = match usage(&filesystem.path) {
+                Some(storage) => {
+                    let percent = used_percent(&storage);
+                    let reached = |level: Option<f64>| level.is_some_and(|level| percent >= level);
+
+                    let status = if reached(filesystem.failed_percent) {
+                        FAILED
+                    } else if reached(filesystem.alert_percent) {
+                        ALERT
+                    } else {
+                        PASSED
+                    };
+
+                    (status, Some(storage), format!("{:.1}% of {} used", percent, filesystem.path))
+                },
+
+                None => (FAILED, None, format!("Unable to stat {}", filesystem.path)),
+            };
+
+            StatusStorageInfo::new(name, status, storage, Some(critical_group_name.to_owned()), Some(remarks))
+        })
+        .collect()
+}
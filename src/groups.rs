@@ -0,0 +1,54 @@
+use ack;
+use common::StatusStorageInfo;
+use glob;
+use {ALERT, FAILED, PASSED};
+
+/// Group of hosts matched by record name with `*` and `?` wildcards, rolled
+/// up into a record of its own.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupConfig {
+    pub name: String,
+    pub hosts: Vec<String>,
+}
+
+/// Builds the record of every group from the records of its members, PASSED
+/// if all members are OK, FAILED if none are and ALERT otherwise.
+pub fn rollup(
+    records: &[StatusStorageInfo],
+    groups: &[GroupConfig],
+    prefix: &str,
+    critical_group_name: &str) -> Vec<StatusStorageInfo> {
+
+    groups.iter()
+        .map(|group| {
+            let members: Vec<&StatusStorageInfo> = records.iter()
+                .filter(|record| group.hosts.iter().any(|pattern| glob::matches(pattern, &record.name)))
+                .collect();
+
+            let not_ok: Vec<&str> = members.iter()
+                .filter(|record| !ack::is_ok(record))
+                .map(|record| record.name.as_str())
+                .collect();
+
+            let (status, remarks) = if members.is_empty() {
+                (FAILED, "No hosts of the group were polled".to_owned())
+            } else if not_ok.is_empty() {
+                (PASSED, format!("All {} hosts OK", members.len()))
+            } else {
+                let status = if not_ok.len() == members.len() { FAILED } else { ALERT };
+                (status, format!("{} of {} hosts not OK: {}", not_ok.len(), members.len(), not_ok.join(", ")))
+            };
+
+            let mut record = StatusStorageInfo::new(
+                format!("{}{}", prefix, group.name),
+                status,
+                None,
+                Some(critical_group_name.to_owned()),
+                Some(remarks));
+
+            record.synthetic = true;
+            record
+        })
+        .collect()
+}
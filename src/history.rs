@@ -0,0 +1,257 @@
+use common::StatusStorageInfo;
+use errors::*;
+use output;
+use sinks::Sink;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[cfg(feature = "sqlite")]
+use chrono::{TimeZone, Utc, SecondsFormat};
+#[cfg(feature = "sqlite")]
+use rusqlite::{self, Connection};
+#[cfg(feature = "sqlite")]
+use serde_json;
+
+fn default_retention_days() -> u32 {
+    90
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryConfig {
+    /// SQLite database file, created if missing
+    pub path: String,
+
+    /// Entries older than this are pruned on every poll
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+}
+
+/// Status of a host in one past poll.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub timestamp: String,
+
+    #[serde(skip)]
+    pub unix_time: i64,
+
+    pub name: String,
+    pub status: i32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub critical_group_name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lim_status: Option<i32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remarks: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_indices: Option<BTreeMap<String, f32>>,
+}
+
+/// Columns of an export that are not load indices.
+pub const EXPORT_COLUMNS: &[&str] =
+    &["timestamp", "name", "status", "criticalGroupName", "limStatus", "remarks"];
+
+/// Renders the entries as CSV with the given columns, which are either one of
+/// `EXPORT_COLUMNS` or the name of a load index. Without columns, all of
+/// `EXPORT_COLUMNS` are exported followed by every recorded load index.
+pub fn to_csv(entries: &[HistoryEntry], columns: &[String]) -> String {
+    let columns: Vec<String> = if columns.is_empty() {
+        let load_indices: BTreeSet<&String> = entries.iter()
+            .filter_map(|entry| entry.load_indices.as_ref())
+            .flat_map(|load_indices| load_indices.keys())
+            .collect();
+
+        EXPORT_COLUMNS.iter()
+            .map(|column| column.to_string())
+            .chain(load_indices.into_iter().cloned())
+            .collect()
+    } else {
+        columns.to_vec()
+    };
+
+    let cell = |entry: &HistoryEntry, column: &str| match column {
+        "timestamp" => entry.timestamp.clone(),
+        "name" => entry.name.clone(),
+        "status" => entry.status.to_string(),
+        "criticalGroupName" => entry.critical_group_name.clone().unwrap_or_default(),
+        "limStatus" => entry.lim_status.map(|lim_status| lim_status.to_string()).unwrap_or_default(),
+        "remarks" => entry.remarks.clone().unwrap_or_default(),
+        index => entry.load_indices.as_ref()
+            .and_then(|load_indices| load_indices.get(index))
+            .map(|value| value.to_string())
+            .unwrap_or_default(),
+    };
+
+    let mut lines = vec![columns.iter().map(|c| output::csv_field(c)).collect::<Vec<_>>().join(",")];
+
+    for entry in entries {
+        lines.push(columns.iter()
+            .map(|column| output::csv_field(&cell(entry, column)))
+            .collect::<Vec<_>>()
+            .join(","));
+    }
+
+    lines.join("\n")
+}
+
+/// Embedded store of the per-host status of every poll.
+#[cfg(feature = "sqlite")]
+pub struct HistoryStore {
+    config: HistoryConfig,
+    conn: Connection,
+}
+
+#[cfg(feature = "sqlite")]
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS host_status (
+        ts INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        status INTEGER NOT NULL,
+        lim_status INTEGER,
+        remarks TEXT,
+        load_indices TEXT,
+        critical_group_name TEXT
+    );
+
+    CREATE INDEX IF NOT EXISTS host_status_name_ts ON host_status (name, ts);
+    CREATE INDEX IF NOT EXISTS host_status_ts ON host_status (ts);";
+
+#[cfg(feature = "sqlite")]
+impl HistoryStore {
+    pub fn open(config: HistoryConfig) -> Result<HistoryStore> {
+        let conn = Connection::open(&config.path)
+            .chain_err(|| format!("Unable to open history database at {}", config.path))?;
+
+        conn.execute_batch(SCHEMA)
+            .chain_err(|| format!("Unable to create history tables in {}", config.path))?;
+
+        migrate(&conn)
+            .chain_err(|| format!("Unable to migrate history tables in {}", config.path))?;
+
+        Ok(HistoryStore { config, conn })
+    }
+
+    fn record(&mut self, records: &[StatusStorageInfo], now: i64) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO host_status (ts, name, status, lim_status, remarks, load_indices, critical_group_name)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+
+            for record in records {
+                let load_indices = record.load_indices.as_ref()
+                    .and_then(|load_indices| serde_json::to_string(load_indices).ok());
+
+                insert.execute(rusqlite::params![
+                    now, record.name, record.status, record.lim_status, record.remarks, load_indices,
+                    record.critical_group_name])?;
+            }
+        }
+
+        let retention_secs = self.config.retention_days as i64 * 24 * 60 * 60;
+        tx.execute("DELETE FROM host_status WHERE ts < ?1", [now - retention_secs])?;
+
+        tx.commit()
+    }
+
+    /// Entries of the host since the given Unix time, oldest first.
+    pub fn query(&self, name: &str, since: i64) -> Result<Vec<HistoryEntry>> {
+        self.select("WHERE name = ?1 AND ts >= ?2 ORDER BY ts", rusqlite::params![name, since])
+            .chain_err(|| format!("Unable to query history of host {}", name))
+    }
+
+    /// Entries of all hosts between the given Unix times, by host and oldest
+    /// first.
+    pub fn query_all(&self, since: i64, until: i64) -> Result<Vec<HistoryEntry>> {
+        self.select("WHERE ts >= ?1 AND ts <= ?2 ORDER BY name, ts", rusqlite::params![since, until])
+            .chain_err(|| "Unable to query history of hosts")
+    }
+
+    fn select(&self, filter: &str, params: &[&dyn rusqlite::ToSql]) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let mut select = self.conn.prepare(&format!(
+            "SELECT ts, name, status, lim_status, remarks, load_indices, critical_group_name FROM host_status {}",
+            filter))?;
+
+        let rows = select.query_map(params, |row| {
+            let ts: i64 = row.get(0)?;
+            let load_indices: Option<String> = row.get(5)?;
+
+            Ok(HistoryEntry {
+                timestamp: Utc.timestamp_opt(ts, 0).single()
+                    .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+                    .unwrap_or_default(),
+                unix_time: ts,
+                name: row.get(1)?,
+                status: row.get(2)?,
+                critical_group_name: row.get(6)?,
+                lim_status: row.get(3)?,
+                remarks: row.get(4)?,
+                load_indices: load_indices.and_then(|load_indices| serde_json::from_str(&load_indices).ok()),
+            })
+        })?;
+
+        rows.collect()
+    }
+}
+
+/// Adds the columns introduced after the table was first created.
+#[cfg(feature = "sqlite")]
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let columns = conn.prepare("SELECT name FROM pragma_table_info('host_status')")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if !columns.iter().any(|column| column == "critical_group_name") {
+        conn.execute_batch("ALTER TABLE host_status ADD COLUMN critical_group_name TEXT")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+impl Sink for HistoryStore {
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let now = Utc::now().timestamp();
+
+        self.record(records, now)
+            .chain_err(|| format!("Unable to record poll into history database at {}", self.config.path))
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub struct HistoryStore;
+
+#[cfg(not(feature = "sqlite"))]
+impl HistoryStore {
+    pub fn open(_: HistoryConfig) -> Result<HistoryStore> {
+        bail!("History store requires lsf_agent to be built with the sqlite feature")
+    }
+
+    pub fn query(&self, _: &str, _: i64) -> Result<Vec<HistoryEntry>> {
+        unreachable!("History store cannot be opened without the sqlite feature")
+    }
+
+    pub fn query_all(&self, _: i64, _: i64) -> Result<Vec<HistoryEntry>> {
+        unreachable!("History store cannot be opened without the sqlite feature")
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+impl Sink for HistoryStore {
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    fn send(&mut self, _: &[StatusStorageInfo]) -> Result<()> {
+        unreachable!("History store cannot be opened without the sqlite feature")
+    }
+}
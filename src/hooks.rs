@@ -0,0 +1,156 @@
+use common::StatusStorageInfo;
+use errors::*;
+use glob;
+use logging;
+use output;
+use serde_json;
+use sinks::exec;
+use state::State;
+use std::collections::HashMap;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use ureq;
+use {ALERT, DOWNTIME, FAILED, PASSED};
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+/// Command or webhook receiving the record of every host whose status
+/// changed as given.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HookConfig {
+    /// Statuses the host changed from, e.g. `PASSED`, any when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Vec<String>>,
+
+    /// Statuses the host changed to, any when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Vec<String>>,
+
+    /// Only fires for records whose name matches any of the glob patterns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosts: Option<Vec<String>>,
+
+    /// Program and its arguments, run without a shell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+
+    /// URL the record gets POSTed to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Hook with its statuses resolved.
+#[derive(Clone, Debug)]
+pub struct Hook {
+    config: HookConfig,
+    from: Option<Vec<i32>>,
+    to: Option<Vec<i32>>,
+}
+
+fn to_status(name: &str) -> Result<i32> {
+    [PASSED, ALERT, FAILED, DOWNTIME].iter()
+        .cloned()
+        .find(|&status| output::to_check_status_str(status).eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown status {}, expected PASSED, ALERT, FAILED or DOWNTIME", name).into())
+}
+
+fn to_statuses(names: &Option<Vec<String>>) -> Result<Option<Vec<i32>>> {
+    names.as_ref()
+        .map(|names| names.iter().map(|name| to_status(name)).collect())
+        .transpose()
+}
+
+impl Hook {
+    pub fn from_config(config: &HookConfig) -> Result<Hook> {
+        if config.command.is_none() && config.url.is_none() {
+            bail!("Hook requires a command or url");
+        }
+
+        Ok(Hook {
+            config: config.clone(),
+            from: to_statuses(&config.from)?,
+            to: to_statuses(&config.to)?,
+        })
+    }
+
+    fn matches(&self, from: i32, record: &StatusStorageInfo) -> bool {
+        self.from.as_ref().is_none_or(|statuses| statuses.contains(&from))
+            && self.to.as_ref().is_none_or(|statuses| statuses.contains(&record.status))
+            && self.config.hosts.as_ref()
+                .is_none_or(|hosts| hosts.iter().any(|pattern| glob::matches(pattern, &record.name)))
+    }
+
+    fn run(&self, from: i32, record: &StatusStorageInfo) -> Result<()> {
+        let body = serde_json::to_string(record)
+            .chain_err(|| "Unable to serialize status storage record into string!")?;
+
+        let from = output::to_check_status_str(from);
+        let to = output::to_check_status_str(record.status);
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+
+        if let Some(ref command) = self.config.command {
+            let env = [
+                ("LSF_AGENT_HOST", record.name.clone()),
+                ("LSF_AGENT_FROM", from.to_owned()),
+                ("LSF_AGENT_TO", to.to_owned()),
+            ];
+
+            exec::run(command, &env, format!("{}\n", body).into_bytes(), timeout)?;
+        }
+
+        if let Some(ref url) = self.config.url {
+            let mut request = ureq::post(url)
+                .timeout(timeout)
+                .set("Content-Type", "application/json")
+                .set("X-Lsf-Agent-From", from)
+                .set("X-Lsf-Agent-To", to);
+
+            for (name, value) in &self.config.headers {
+                request = request.set(name, value);
+            }
+
+            request.send_string(&body)
+                .map_err(|e| Error::from(format!("Unable to POST hook to {}: {}", url, e)))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the matching hooks of every host whose status differs from the
+/// previous poll, each on its own thread so that polling goes on meanwhile.
+/// Hosts that were not seen before have no status to change from.
+pub fn fire(hooks: &[Hook], previous: &State, records: &[StatusStorageInfo]) -> Vec<JoinHandle<()>> {
+    let mut handles = Vec::new();
+
+    for record in records {
+        let from = match previous.hosts.get(&record.name) {
+            Some(host_state) if host_state.status != record.status => host_state.status,
+            _ => continue,
+        };
+
+        for hook in hooks.iter().filter(|hook| hook.matches(from, record)) {
+            let hook = hook.clone();
+            let record = record.clone();
+
+            debug!(name = record.name.as_str(), from, to = record.status, "Firing hook");
+
+            handles.push(thread::spawn(move || {
+                if let Err(e) = hook.run(from, &record) {
+                    error!(name = record.name.as_str(), error = %logging::chain(&e), "Hook failed");
+                }
+            }));
+        }
+    }
+
+    handles
+}
@@ -0,0 +1,153 @@
+use errors::*;
+use serde_json::{self, Value};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const INCLUDE_KEY: &str = "include";
+
+/// Merges the overlay into the base, merging objects key by key and
+/// replacing every other value, including arrays, by the one of the overlay.
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (&mut Value::Object(ref mut base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    },
+                }
+            }
+        },
+
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Reads the config file, merged over the files it includes in order, with
+/// relative paths resolved against the directory of the including file.
+pub fn load(path: &Path, including: &mut Vec<PathBuf>) -> Result<Value> {
+    let canonical = path.canonicalize()
+        .chain_err(|| format!("Unable to open config file at {}", path.display()))?;
+
+    if including.contains(&canonical) {
+        bail!("Config file at {} includes itself, directly or through other files", path.display());
+    }
+
+    let mut config_content = String::new();
+
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut config_content))
+        .chain_err(|| format!("Unable to read config file at {}", path.display()))?;
+
+    let mut value: Value = serde_json::from_str(&config_content)
+        .chain_err(|| format!("Unable to parse config file at {}", path.display()))?;
+
+    let includes = match value.as_object_mut().and_then(|object| object.remove(INCLUDE_KEY)) {
+        None => vec![],
+        Some(Value::String(include)) => vec![include],
+
+        Some(Value::Array(includes)) => includes.into_iter()
+            .map(|include| match include {
+                Value::String(include) => Ok(include),
+                _ => Err(Error::from(format!("Includes of config file at {} must be paths", path.display()))),
+            })
+            .collect::<Result<Vec<_>>>()?,
+
+        Some(_) => bail!("Include of config file at {} must be a path or a list of paths", path.display()),
+    };
+
+    if includes.is_empty() {
+        return Ok(value);
+    }
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut merged = Value::Object(Default::default());
+
+    including.push(canonical);
+
+    for include in includes {
+        merge(&mut merged, load(&dir.join(&include), including)?);
+    }
+
+    including.pop();
+
+    merge(&mut merged, value);
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+
+    /// Writes the files into a fresh directory of the test.
+    fn dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("lsf_agent-include-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        for &(path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            File::create(&path).unwrap().write_all(content.as_bytes()).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn merges_objects_and_replaces_arrays() {
+        let mut base = json!({ "a": { "x": 1, "y": [1, 2] }, "b": [1], "c": 1 });
+        merge(&mut base, json!({ "a": { "y": [3], "z": 2 }, "b": { "k": 1 }, "d": null }));
+
+        assert_eq!(base, json!({ "a": { "x": 1, "y": [3], "z": 2 }, "b": { "k": 1 }, "c": 1, "d": null }));
+    }
+
+    #[test]
+    fn including_file_takes_precedence() {
+        let dir = dir("precedence", &[
+            ("main.json", r#"{ "include": ["first.json", "second.json"], "prefix": "main." }"#),
+            ("first.json", r#"{ "prefix": "first.", "interval": 10, "hosts": ["a", "b"] }"#),
+            ("second.json", r#"{ "interval": 20, "hosts": ["c"] }"#),
+        ]);
+
+        let value = load(&dir.join("main.json"), &mut vec![]).unwrap();
+        assert_eq!(value, json!({ "prefix": "main.", "interval": 20, "hosts": ["c"] }));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_paths_against_the_including_file() {
+        let dir = dir("relative", &[
+            ("main.json", r#"{ "include": "conf/base.json" }"#),
+            ("conf/base.json", r#"{ "include": "common/sinks.json", "prefix": "base." }"#),
+            ("conf/common/sinks.json", r#"{ "sinks": [] }"#),
+        ]);
+
+        let value = load(&dir.join("main.json"), &mut vec![]).unwrap();
+        assert_eq!(value, json!({ "prefix": "base.", "sinks": [] }));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let dir = dir("cycle", &[
+            ("self.json", r#"{ "include": "self.json" }"#),
+            ("a.json", r#"{ "include": "b.json" }"#),
+            ("b.json", r#"{ "include": "a.json" }"#),
+        ]);
+
+        for file in &["self.json", "a.json"] {
+            let e = load(&dir.join(file), &mut vec![]).unwrap_err();
+            assert!(e.to_string().contains("includes itself"), "{}", e);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
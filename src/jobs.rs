@@ -0,0 +1,68 @@
+use lsf::{Job, JOB_STAT_PEND, JOB_STAT_PSUSP, JOB_STAT_RUN, JOB_STAT_SSUSP, JOB_STAT_USUSP};
+use std::collections::BTreeMap;
+
+/// Unfinished jobs of a user and the slots they take or ask for.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct JobSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    pub running: usize,
+    pub pending: usize,
+    pub suspended: usize,
+    pub running_slots: i64,
+    pub pending_slots: i64,
+    pub suspended_slots: i64,
+}
+
+impl JobSummary {
+    fn add(&mut self, job: &Job) {
+        let slots = job.slots as i64;
+
+        if job.status & JOB_STAT_RUN != 0 {
+            self.running += 1;
+            self.running_slots += slots;
+        } else if job.status & (JOB_STAT_PEND | JOB_STAT_PSUSP) != 0 {
+            self.pending += 1;
+            self.pending_slots += slots;
+        } else if job.status & (JOB_STAT_SSUSP | JOB_STAT_USUSP) != 0 {
+            self.suspended += 1;
+            self.suspended_slots += slots;
+        }
+    }
+}
+
+/// Totals of all jobs.
+pub fn total(jobs: &[Job]) -> JobSummary {
+    let mut summary = JobSummary::default();
+
+    for job in jobs {
+        summary.add(job);
+    }
+
+    summary
+}
+
+/// Totals per user, the users taking or asking for the most slots first, and
+/// only the first `top` of them if given.
+pub fn by_user(jobs: &[Job], top: Option<usize>) -> Vec<JobSummary> {
+    let mut by_user: BTreeMap<&str, JobSummary> = BTreeMap::new();
+
+    for job in jobs {
+        by_user.entry(job.user.as_str())
+            .or_insert_with(|| JobSummary { user: Some(job.user.clone()), ..JobSummary::default() })
+            .add(job);
+    }
+
+    let mut summaries: Vec<JobSummary> = by_user.into_values().collect();
+
+    summaries.sort_by(|a, b| (b.running_slots + b.pending_slots).cmp(&(a.running_slots + a.pending_slots))
+        .then_with(|| a.user.cmp(&b.user)));
+
+    if let Some(top) = top {
+        summaries.truncate(top);
+    }
+
+    summaries
+}
@@ -0,0 +1,50 @@
+use common::StatusStorageInfo;
+use glob;
+use std::collections::BTreeMap;
+
+/// Labels attached to the hosts matched by record name with `*` and `?`
+/// wildcards.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelConfig {
+    pub hosts: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Attaches the labels of every matching entry to the records, where later
+/// entries override the values of earlier ones.
+pub fn apply(records: &mut [StatusStorageInfo], label_configs: &[LabelConfig]) {
+    for record in records.iter_mut() {
+        let mut labels = record.labels.take().unwrap_or_default();
+
+        for label_config in label_configs.iter()
+            .filter(|label_config| label_config.hosts.iter().any(|pattern| glob::matches(pattern, &record.name))) {
+
+            labels.extend(label_config.labels.iter().map(|(key, value)| (key.clone(), value.clone())));
+        }
+
+        if !labels.is_empty() {
+            record.labels = Some(labels);
+        }
+    }
+}
+
+/// Labels every record with the cluster and LSF version, where known. Records
+/// already labelled with their own cluster keep it.
+pub fn apply_cluster(records: &mut [StatusStorageInfo], cluster_name: Option<&str>, lsf_version: Option<&str>) {
+    for record in records.iter_mut() {
+        let mut labels = record.labels.take().unwrap_or_default();
+
+        if let Some(cluster_name) = cluster_name {
+            labels.entry("cluster".to_owned()).or_insert_with(|| cluster_name.to_owned());
+        }
+
+        if let Some(lsf_version) = lsf_version {
+            labels.insert("lsf_version".to_owned(), lsf_version.to_owned());
+        }
+
+        if !labels.is_empty() {
+            record.labels = Some(labels);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use common::StatusStorageInfo;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use {ALERT, FAILED, PASSED};
+
+/// Levels of the time taken by the LIM query of a cluster, slow responses
+/// being an early sign of an overloaded master LIM.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_ms: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_ms: Option<u64>,
+}
+
+/// Builds the record of the query of a cluster, or of all clusters at once,
+/// with the time taken in `latencyMs`.
+pub fn to_record(
+    config: &LatencyConfig,
+    cluster: Option<&str>,
+    duration: Duration,
+    prefix: &str,
+    critical_group_name: &str) -> StatusStorageInfo {
+
+    let ms = duration.as_millis() as u64;
+    let reached = |level: Option<u64>| level.is_some_and(|level| ms >= level);
+
+    let (status, level) = if reached(config.failed_ms) {
+        (FAILED, config.failed_ms)
+    } else if reached(config.alert_ms) {
+        (ALERT, config.alert_ms)
+    } else {
+        (PASSED, None)
+    };
+
+    let (name, queried) = match cluster {
+        Some(cluster) => (format!("{}{}:lim", prefix, cluster), format!("LIM query of cluster {}", cluster)),
+        None => (format!("{}lim", prefix), "LIM query".to_owned()),
+    };
+
+    let remarks = match level {
+        Some(level) => format!("{} took {}ms (at least {}ms)", queried, ms, level),
+        None => format!("{} took {}ms", queried, ms),
+    };
+
+    let mut record = StatusStorageInfo::new(name, status, None, Some(critical_group_name.to_owned()), Some(remarks));
+
+    let mut load_indices = BTreeMap::new();
+    load_indices.insert("latencyMs".to_owned(), ms as f32);
+    record.load_indices = Some(load_indices);
+
+    record
+}
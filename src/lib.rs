@@ -0,0 +1,831 @@
+//! Polls the status of LSF hosts from LIM and converts it into status
+//! records, as printed by the `lsf_agent` binary.
+//!
+//! ```ignore
+//! let config = lsf_agent::Config::from_file("config.json")?;
+//! let mut records = lsf_agent::poll_hosts(&config)?;
+//!
+//! // statuses as reported by the binary, e.g. with thresholds and downtimes
+//! lsf_agent::evaluate(&config, &mut records, &lsf_agent::state::State::default(), &config.downtimes()?)?;
+//! ```
+//!
+//! With `isolateQuery` or `clusters`, queries run in a worker process that
+//! re-executes the current binary, which then has to call
+//! `worker::run_worker` first thing when `worker::WORKER_ENV` is set.
+
+// serde_derive 1.0.11 wraps the derived impls in an anonymous const
+#![allow(non_local_definitions)]
+
+extern crate chrono;
+
+#[macro_use]
+extern crate derive_new;
+
+#[macro_use]
+extern crate error_chain;
+extern crate flate2;
+
+#[cfg(feature = "kafka")]
+extern crate kafka;
+extern crate libc;
+extern crate libresolv_sys;
+extern crate ring;
+
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+extern crate rustls;
+extern crate serde;
+
+#[macro_use]
+extern crate serde_derive;
+
+#[macro_use]
+extern crate serde_json;
+
+#[macro_use]
+extern crate tracing;
+extern crate ureq;
+extern crate webpki_roots;
+
+use chrono::{Local, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::Duration;
+
+pub mod common {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[derive(Serialize, Deserialize, Clone, Debug, new)]
+    #[serde(rename_all = "camelCase")]
+    pub struct StorageInfo {
+        pub used: u64,
+        pub total: u64,
+    }
+
+    /// Batch status and job slots of a host, as seen by mbatchd.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(rename_all = "camelCase")]
+    pub struct BatchInfo {
+        /// Status as shown by `bhosts`, e.g. `ok` or `closed_Adm`
+        pub status: String,
+
+        pub max_jobs: i32,
+        pub num_jobs: i32,
+        pub num_run: i32,
+        pub num_ssusp: i32,
+        pub num_ususp: i32,
+        pub closed: bool,
+        pub full: bool,
+
+        /// Comment of the administrator who last opened or closed the host
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub control_message: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, new)]
+    #[serde(rename_all = "camelCase")]
+    pub struct StatusStorageInfo {
+        pub name: String,
+        pub status: i32,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub storage: Option<StorageInfo>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]        
+        pub critical_group_name: Option<String>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]        
+        pub remarks: Option<String>,
+
+        #[new(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub lim_status: Option<i32>,
+
+        #[new(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub load_indices: Option<BTreeMap<String, f32>>,
+
+        /// Load indices currently above their alert threshold, and storage
+        /// as `storage:<what>`, carried over to the next poll through the state
+        #[new(default)]
+        #[serde(skip)]
+        pub load_alerts: BTreeSet<String>,
+
+        /// Failure is a known issue, left out of the exit code
+        #[new(default)]
+        #[serde(default, skip_serializing_if = "is_false")]
+        pub acknowledged: bool,
+
+        #[new(default)]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub labels: Option<BTreeMap<String, String>>,
+
+        #[new(default)]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub batch: Option<BatchInfo>,
+
+        /// Record of a group, quorum or the agent itself rather than of a
+        /// host, left out of the share of `--max-failed-percent`
+        #[new(default)]
+        #[serde(skip)]
+        pub synthetic: bool,
+    }
+
+    fn is_false(value: &bool) -> bool {
+        !*value
+    }
+}
+
+pub use common::StatusStorageInfo;
+
+pub mod lsf;
+
+#[cfg(feature = "bindgen")]
+mod lsf_sys;
+use lsf::{HostLoad, LIM_OK, ALL_CLUSTERS};
+
+mod encoding;
+
+mod include;
+
+pub mod budget;
+use budget::BudgetConfig;
+
+pub mod compression;
+
+pub mod encryption;
+use encryption::{EncryptionConfig, Encryptor};
+
+pub mod output;
+use output::OutputFormat;
+
+pub mod outfile;
+
+pub mod logging;
+
+pub mod pidfile;
+
+pub mod retry;
+use retry::RetryConfig;
+
+pub mod poll;
+use poll::{ClusterQuery, QuerySettings, RecordedQuery};
+
+pub mod latency;
+use latency::LatencyConfig;
+
+pub mod history;
+use history::{HistoryConfig, HistoryStore};
+
+pub mod hooks;
+use hooks::{Hook, HookConfig};
+
+pub mod notify;
+use notify::{NotificationsConfig, Notifier};
+
+pub mod report;
+
+pub mod schema;
+
+pub mod signing;
+use signing::{Signer, SigningConfig};
+
+pub mod telemetry;
+use telemetry::TelemetryConfig;
+
+pub mod state;
+
+pub mod template;
+
+pub mod top;
+
+pub mod watch;
+use state::{FlapConfig, State};
+
+mod glob;
+
+pub mod ack;
+use ack::AckConfig;
+
+pub mod labels;
+use labels::LabelConfig;
+
+pub mod groups;
+use groups::GroupConfig;
+
+pub mod quorum;
+use quorum::QuorumConfig;
+
+pub mod filesystems;
+use filesystems::FilesystemConfig;
+
+pub mod shared;
+use shared::SharedResourceConfig;
+
+pub mod batch;
+use batch::BatchConfig;
+
+pub mod exitrate;
+use exitrate::ExitRateConfig;
+
+pub mod jobs;
+
+pub mod queues;
+use queues::QueueThreshold;
+
+pub mod locks;
+use locks::LockConfig;
+
+pub mod normalize;
+
+pub mod downtime;
+use downtime::{Downtime, DowntimeConfig};
+
+pub mod thresholds;
+use thresholds::{LoadThreshold, StorageThreshold};
+
+pub mod server;
+use server::ServerConfig;
+
+pub mod consul;
+
+pub mod nrpe;
+
+pub mod federation;
+use federation::FederationConfig;
+
+mod tls;
+
+pub mod systemd;
+
+pub mod worker;
+
+pub mod sinks;
+use sinks::Sink;
+use sinks::batching::{BatchingConfig, BatchingSink};
+use sinks::config::SinkConfig;
+use sinks::cloudwatch::{CloudWatchConfig, CloudWatchSink};
+use sinks::datadog::{DatadogConfig, DatadogSink};
+use sinks::dedup::{DedupConfig, DedupSink};
+use sinks::etcd::{EtcdConfig, EtcdSink};
+use sinks::exec::{ExecConfig, ExecSink};
+use sinks::graphite::{GraphiteConfig, GraphiteSink};
+use sinks::http::{HttpConfig, HttpSink};
+use sinks::icinga::{IcingaConfig, IcingaSink};
+use sinks::kafka::{KafkaConfig, KafkaSink};
+use sinks::nsca::{NscaConfig, NscaSink};
+use sinks::otlp::{OtlpConfig, OtlpSink};
+use sinks::pushgateway::{PushgatewayConfig, PushgatewaySink};
+use sinks::redis::{RedisConfig, RedisSink};
+use sinks::sensu::{SensuConfig, SensuSink};
+use sinks::spool::{SpoolConfig, SpoolSink};
+use sinks::statsd::{StatsdConfig, StatsdSink};
+use sinks::syslog::{SyslogConfig, SyslogSink};
+use sinks::zabbix::{ZabbixConfig, ZabbixSink};
+
+// status values
+pub const PASSED: i32 = 0;
+pub const ALERT: i32 = 1;
+pub const FAILED: i32 = 2;
+pub const DOWNTIME: i32 = 3;
+
+pub mod errors {
+    error_chain! {
+        errors {
+            QueryFailed(reason: String) {
+                description("LSF query failed")
+                display("LSF query failed: {}", reason)
+            }
+        }
+    }
+
+    impl Error {
+        /// Stable identifier of the kind of error, for machine-readable output.
+        pub fn code(&self) -> &'static str {
+            match *self.kind() {
+                ErrorKind::QueryFailed(_) => "QUERY_FAILED",
+                _ => "ERROR",
+            }
+        }
+    }
+}
+
+use errors::*;
+
+/// Agent configuration, read from the JSON config file.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    pub prefix: String,
+    pub name_mapping: HashMap<String, String>,
+    pub critical_group_name: String,
+    pub from_host: Option<String>,
+    pub load_options: Option<Vec<String>>,
+    pub external_indices: Option<Vec<String>>,
+    pub load_views: Option<Vec<String>>,
+    pub query_timeout_secs: Option<u64>,
+    pub isolate_query: Option<bool>,
+    pub retry: Option<RetryConfig>,
+    pub query_latency: Option<LatencyConfig>,
+    pub clusters: Option<Vec<String>>,
+    pub remarks_template: Option<String>,
+    pub state_file: Option<String>,
+    pub flap: Option<FlapConfig>,
+    pub normalize_load: Option<bool>,
+    pub load_thresholds: Option<BTreeMap<String, LoadThreshold>>,
+    pub storage_thresholds: Option<Vec<StorageThreshold>>,
+    pub downtimes: Option<Vec<DowntimeConfig>>,
+    pub acknowledged: Option<Vec<AckConfig>>,
+    pub labels: Option<Vec<LabelConfig>>,
+    pub cluster_labels: Option<bool>,
+    pub groups: Option<Vec<GroupConfig>>,
+    pub quorum: Option<QuorumConfig>,
+    pub filesystems: Option<Vec<FilesystemConfig>>,
+    pub shared_resources: Option<Vec<SharedResourceConfig>>,
+    pub batch: Option<BatchConfig>,
+    pub exit_rate: Option<ExitRateConfig>,
+    pub queues: Option<Vec<QueueThreshold>>,
+    pub locks: Option<LockConfig>,
+    pub telemetry: Option<TelemetryConfig>,
+    pub hooks: Option<Vec<HookConfig>>,
+    pub notifications: Option<NotificationsConfig>,
+    pub signing: Option<SigningConfig>,
+    pub encryption: Option<EncryptionConfig>,
+    pub sinks: Option<Vec<SinkConfig>>,
+    pub sink_queue_size: Option<usize>,
+    pub sink_dedup: Option<BTreeMap<String, DedupConfig>>,
+    pub sink_spool: Option<BTreeMap<String, SpoolConfig>>,
+    pub sink_batching: Option<BTreeMap<String, BatchingConfig>>,
+    pub server: Option<ServerConfig>,
+    pub federation: Option<FederationConfig>,
+    pub budget: Option<BudgetConfig>,
+    pub graphite: Option<GraphiteConfig>,
+    pub statsd: Option<StatsdConfig>,
+    pub zabbix: Option<ZabbixConfig>,
+    pub nsca: Option<NscaConfig>,
+    pub sensu: Option<SensuConfig>,
+    pub icinga: Option<IcingaConfig>,
+    pub datadog: Option<DatadogConfig>,
+    pub cloudwatch: Option<CloudWatchConfig>,
+    pub otlp: Option<OtlpConfig>,
+    pub pushgateway: Option<PushgatewayConfig>,
+    pub http: Option<HttpConfig>,
+    pub kafka: Option<KafkaConfig>,
+    pub redis: Option<RedisConfig>,
+    pub etcd: Option<EtcdConfig>,
+    pub syslog: Option<SyslogConfig>,
+    pub exec: Option<ExecConfig>,
+    pub history: Option<HistoryConfig>,
+}
+
+impl Config {
+    pub fn from_file(path: &str) -> Result<Config> {
+        Config::from_files(&[path])
+    }
+
+    /// Reads the config files along with their includes, where later files
+    /// override earlier ones.
+    pub fn from_files(paths: &[&str]) -> Result<Config> {
+        let mut config_content = serde_json::Value::Object(Default::default());
+
+        for path in paths {
+            include::merge(&mut config_content, include::load(Path::new(path), &mut Vec::new())?);
+        }
+
+        serde_json::from_value(config_content)
+            .chain_err(|| "Unable to parse config content into structure!")
+    }
+
+    /// Record name of a host, mapped and prefixed as configured.
+    pub fn record_name(&self, host_name: &str) -> String {
+        let mapped_host_name = self.name_mapping.get(host_name).map(|name| name.as_str()).unwrap_or(host_name);
+        format!("{}{}", self.prefix, mapped_host_name)
+    }
+
+    /// Signer of the payloads, if signing is configured.
+    pub fn signer(&self) -> Result<Option<Signer>> {
+        self.signing.as_ref().map(Signer::from_config).transpose()
+    }
+
+    /// Resolves the hooks on status changes.
+    pub fn hooks(&self) -> Result<Vec<Hook>> {
+        self.hooks.iter().flatten().map(Hook::from_config).collect()
+    }
+
+    /// Creates every configured notifier.
+    pub fn notifiers(&self) -> Result<Vec<Box<dyn Notifier>>> {
+        match self.notifications {
+            Some(ref notifications) => notify::notifiers(notifications),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Parses the scheduled downtimes.
+    pub fn downtimes(&self) -> Result<Vec<Downtime>> {
+        self.downtimes.iter().flatten().map(Downtime::parse).collect()
+    }
+
+    /// Encryptor of the payloads, if encryption is configured.
+    pub fn encryptor(&self) -> Result<Option<Encryptor>> {
+        self.encryption.as_ref().map(Encryptor::from_config).transpose()
+    }
+
+    /// Resolves how LIM gets queried.
+    pub fn query_settings(&self) -> Result<QuerySettings> {
+        let mut options = ALL_CLUSTERS;
+
+        for name in self.load_options.iter().flatten() {
+            match lsf::to_load_option(name) {
+                Some(option) => options |= option,
+                None => bail!("Unknown ls_load option flag {}", name),
+            }
+        }
+
+        let views = self.load_views.iter().flatten()
+            .map(|name| match lsf::to_load_option(name) {
+                Some(option @ lsf::EFFECTIVE) | Some(option @ lsf::NORMALIZE) => Ok((name.to_lowercase(), option)),
+                _ => bail!("Load view {} is neither EFFECTIVE nor NORMALIZE", name),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(QuerySettings {
+            options,
+            from_host: self.from_host.clone(),
+            indices: self.external_indices.clone().unwrap_or_default(),
+            views,
+            timeout: self.query_timeout_secs.map(Duration::from_secs),
+            isolate: self.isolate_query.unwrap_or(false),
+            retry: self.retry.clone().unwrap_or_default(),
+            record: None,
+            replay: None,
+        })
+    }
+
+    /// Creates every configured sink.
+    pub fn sinks(&self) -> Result<Vec<Box<dyn Sink>>> {
+        let config = self;
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+
+        if let Some(ref graphite) = config.graphite {
+            sinks.push(Box::new(GraphiteSink::new(graphite.clone())));
+        }
+
+        if let Some(ref statsd) = config.statsd {
+            sinks.push(Box::new(StatsdSink::new(statsd.clone())));
+        }
+
+        if let Some(ref zabbix) = config.zabbix {
+            sinks.push(Box::new(ZabbixSink::new(zabbix.clone())));
+        }
+
+        if let Some(ref nsca) = config.nsca {
+            sinks.push(Box::new(NscaSink::new(nsca.clone())?));
+        }
+
+        if let Some(ref sensu) = config.sensu {
+            sinks.push(Box::new(SensuSink::new(sensu.clone())));
+        }
+
+        if let Some(ref icinga) = config.icinga {
+            sinks.push(Box::new(IcingaSink::new(icinga.clone())?));
+        }
+
+        if let Some(ref datadog) = config.datadog {
+            sinks.push(Box::new(DatadogSink::new(datadog.clone())?));
+        }
+
+        if let Some(ref cloudwatch) = config.cloudwatch {
+            sinks.push(Box::new(CloudWatchSink::new(cloudwatch.clone())?));
+        }
+
+        if let Some(ref otlp) = config.otlp {
+            sinks.push(Box::new(OtlpSink::new(otlp.clone())));
+        }
+
+        if let Some(ref pushgateway) = config.pushgateway {
+            sinks.push(Box::new(PushgatewaySink::new(pushgateway.clone())));
+        }
+
+        if let Some(ref http) = config.http {
+            sinks.push(Box::new(HttpSink::new(http.clone(), self.signer()?, self.encryptor()?)));
+        }
+
+        if let Some(ref kafka) = config.kafka {
+            sinks.push(Box::new(KafkaSink::new(kafka.clone())?));
+        }
+
+        if let Some(ref redis) = config.redis {
+            sinks.push(Box::new(RedisSink::new(redis.clone())));
+        }
+
+        if let Some(ref etcd) = config.etcd {
+            sinks.push(Box::new(EtcdSink::new(etcd.clone())));
+        }
+
+        if let Some(ref syslog) = config.syslog {
+            sinks.push(Box::new(SyslogSink::new(syslog.clone())?));
+        }
+
+        if let Some(ref exec) = config.exec {
+            sinks.push(Box::new(ExecSink::new(exec.clone(), OutputFormat::Json)?));
+        }
+
+        if let Some(ref history) = config.history {
+            sinks.push(Box::new(HistoryStore::open(history.clone())?));
+        }
+
+        for sink in config.sinks.iter().flatten() {
+            sinks.push(sink.to_sink(self.signer()?, self.encryptor()?)?);
+        }
+
+        for (i, sink) in sinks.iter().enumerate() {
+            if sinks[..i].iter().any(|other| other.name() == sink.name()) {
+                bail!("More than one sink is named {}, set distinct names in sinks", sink.name());
+            }
+        }
+
+        let sink_dedup = config.sink_dedup.clone().unwrap_or_default();
+        let sink_spool = config.sink_spool.clone().unwrap_or_default();
+        let sink_batching = config.sink_batching.clone().unwrap_or_default();
+
+        let wrapped = sink_dedup.keys().map(|name| ("sinkDedup", name))
+            .chain(sink_spool.keys().map(|name| ("sinkSpool", name)))
+            .chain(sink_batching.keys().map(|name| ("sinkBatching", name)));
+
+        for (option, name) in wrapped {
+            if !sinks.iter().any(|sink| sink.name() == name) {
+                bail!("{} names {}, which is not a configured sink", option, name);
+            }
+        }
+
+        // dedup goes outside the batching and the spool, so that the polls it
+        // skips are never batched nor spooled, and batches get spooled whole
+        let sinks = sinks.into_iter()
+            .map(|sink| match sink_spool.get(sink.name()) {
+                Some(spool) => SpoolSink::open(sink, spool.clone()).map(|sink| Box::new(sink) as Box<dyn Sink>),
+                None => Ok(sink),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(sinks.into_iter()
+            .map(|sink| match sink_batching.get(sink.name()) {
+                Some(batching) => Box::new(BatchingSink::new(sink, batching.clone())) as Box<dyn Sink>,
+                None => sink,
+            })
+            .map(|sink| match sink_dedup.get(sink.name()) {
+                Some(dedup) => Box::new(DedupSink::new(sink, dedup.clone())) as Box<dyn Sink>,
+                None => sink,
+            })
+            .collect())
+    }
+}
+
+/// Adds the local filesystem, shared resource, batch, lock, exit rate and
+/// queue checks to the polled records, normalizes the load, applies the
+/// thresholds, downtimes, acknowledgements and labels, and adds the group and
+/// quorum records, as the `lsf_agent` binary does after every poll. The state
+/// holds the previous poll, for the exit rate and threshold hysteresis.
+pub fn evaluate(config: &Config, records: &mut Vec<StatusStorageInfo>, state: &State, downtimes: &[Downtime]) -> Result<()> {
+    if let Some(ref filesystems) = config.filesystems {
+        records.extend(filesystems::check(filesystems, &config.prefix, &config.critical_group_name));
+    }
+
+    if let Some(ref shared_resources) = config.shared_resources {
+        records.extend(shared::check(shared_resources, &config.prefix, &config.critical_group_name));
+    }
+
+    if let Some(ref batch) = config.batch {
+        batch::apply(records, batch, config)?;
+    }
+
+    if let Some(ref locks) = config.locks {
+        locks::apply(records, locks);
+    }
+
+    if let Some(ref exit_rate) = config.exit_rate {
+        exitrate::apply(records, exit_rate, config, state.polled_at, Utc::now().timestamp())?;
+    }
+
+    if let Some(ref queues) = config.queues {
+        records.extend(queues::check(queues, &config.prefix, &config.critical_group_name));
+    }
+
+    if config.normalize_load.unwrap_or(false) {
+        normalize::apply(records, config);
+    }
+
+    if let Some(ref load_thresholds) = config.load_thresholds {
+        thresholds::apply(records, load_thresholds, state);
+    }
+
+    if let Some(ref storage_thresholds) = config.storage_thresholds {
+        thresholds::apply_storage(records, storage_thresholds, state);
+    }
+
+    downtime::apply(records, downtimes, &Local::now());
+
+    if let Some(ref acknowledged) = config.acknowledged {
+        ack::apply(records, acknowledged);
+    }
+
+    if let Some(ref label_configs) = config.labels {
+        labels::apply(records, label_configs);
+    }
+
+    if let Some(ref groups) = config.groups {
+        let group_records = groups::rollup(records, groups, &config.prefix, &config.critical_group_name);
+        records.extend(group_records);
+    }
+
+    if let Some(ref quorum) = config.quorum {
+        let quorum_record = quorum::check(records, quorum, &config.prefix, &config.critical_group_name)?;
+        records.push(quorum_record);
+    }
+
+    for record in records.iter() {
+        debug!(record = %record.name, status = output::to_check_status_str(record.status),
+            acknowledged = record.acknowledged, remarks = record.remarks.as_deref().unwrap_or(""), "Evaluated record");
+    }
+
+    Ok(())
+}
+
+/// Queries LIM once and converts the results into status records.
+pub fn poll_hosts(config: &Config) -> Result<Vec<StatusStorageInfo>> {
+    poll_hosts_with(config, &config.query_settings()?)
+}
+
+/// Queries LIM once with the given settings, which can be resolved once
+/// upfront when polling repeatedly.
+pub fn poll_hosts_with(config: &Config, settings: &QuerySettings) -> Result<Vec<StatusStorageInfo>> {
+    let clusters = config.clusters.clone().unwrap_or_default();
+    let mut status_storage_infos = Vec::new();
+    let mut recorded_queries = Vec::new();
+
+    for cluster_query in poll::query_all(settings, &clusters) {
+        if settings.record.is_some() {
+            recorded_queries.push(RecordedQuery::new(settings.options, &cluster_query));
+        }
+
+        if let Some(ref query_latency) = config.query_latency {
+            status_storage_infos.push(latency::to_record(
+                query_latency,
+                cluster_query.cluster.as_deref(),
+                cluster_query.duration,
+                &config.prefix,
+                &config.critical_group_name));
+        }
+
+        status_storage_infos.extend(to_cluster_status_storage_infos(config, cluster_query)?);
+    }
+
+    // each view takes another query, with the option flag of the view added
+    for &(ref suffix, option) in &settings.views {
+        let view_settings = QuerySettings { options: settings.options | option, ..settings.clone() };
+        let mut view_indices: HashMap<String, BTreeMap<String, f32>> = HashMap::new();
+
+        for cluster_query in poll::query_all(&view_settings, &clusters) {
+            if settings.record.is_some() {
+                recorded_queries.push(RecordedQuery::new(view_settings.options, &cluster_query));
+            }
+
+            for record in to_cluster_status_storage_infos(config, cluster_query)? {
+                if let Some(load_indices) = record.load_indices {
+                    view_indices.insert(record.name, load_indices);
+                }
+            }
+        }
+
+        for status_storage_info in &mut status_storage_infos {
+            let view_load_indices = match view_indices.get(&status_storage_info.name) {
+                Some(view_load_indices) => view_load_indices,
+                None => continue,
+            };
+
+            let load_indices = match status_storage_info.load_indices {
+                Some(ref mut load_indices) => load_indices,
+                None => continue,
+            };
+
+            for index in lsf::RUN_QUEUE_INDEX_NAMES.iter() {
+                if let Some(&value) = view_load_indices.get(*index) {
+                    load_indices.insert(format!("{}_{}", index, suffix), value);
+                }
+            }
+        }
+    }
+
+    if let Some(ref record) = settings.record {
+        poll::save_recording(record, &recorded_queries)?;
+    }
+
+    Ok(status_storage_infos)
+}
+
+fn to_cluster_status_storage_infos(config: &Config, cluster_query: ClusterQuery) -> Result<Vec<StatusStorageInfo>> {
+    // stands in for all hosts of the cluster when none could be queried
+    let cluster_failed = |remarks: String| {
+        let name = match cluster_query.cluster {
+            Some(ref cluster) => format!("{}{}:*", config.prefix, cluster),
+            None => format!("{}*", config.prefix),
+        };
+
+        vec![StatusStorageInfo::new(
+            name,
+            FAILED,
+            None,
+            Some(config.critical_group_name.clone()),
+            Some(remarks))]
+    };
+
+    match cluster_query.host_loads {
+        Ok(ref host_loads) if host_loads.is_empty() =>
+            Ok(cluster_failed("Unable to connect any of the LSF nodes".to_owned())),
+
+        Ok(ref host_loads) => Ok(to_status_storage_infos(config, host_loads)),
+
+        Err(Error(ErrorKind::QueryFailed(ref reason), _)) =>
+            Ok(cluster_failed(format!("LSF query failed: {}", reason))),
+
+        Err(e) => Err(e),
+    }
+}
+
+/// Converts the load information of hosts into status records, with names
+/// mapped and prefixed as configured.
+pub fn to_status_storage_infos(config: &Config, host_loads: &[HostLoad]) -> Vec<StatusStorageInfo> {
+    host_loads.iter()
+        .map(|host_load| {
+            let status = host_load.status;
+            let status_str = lsf::to_status_str(status);
+
+            let conv_status = if status == LIM_OK { PASSED } else { FAILED };
+            let critical_group_name = config.critical_group_name.clone();
+
+            let mapped_host_name = match config.name_mapping.get(&host_load.host_name) {
+                Some(mapped_host_name) if !host_load.invalid_host_name => mapped_host_name,
+                _ => &host_load.host_name,
+            };
+
+            let name = format!("{}{}", config.prefix, mapped_host_name);
+
+            debug!(host = %host_load.host_name, record = %name, lim_status = status_str,
+                mapped = config.name_mapping.contains_key(&host_load.host_name) && !host_load.invalid_host_name,
+                passed = conv_status == PASSED, "Converted host");
+
+            let remarks = match config.remarks_template {
+                Some(ref remarks_template) => template::render(remarks_template, |placeholder| match placeholder {
+                    "statusName" => Some(status_str.to_owned()),
+                    "statusCode" => Some(status.to_string()),
+                    "status" => Some(output::to_check_status_str(conv_status).to_owned()),
+                    "host" => Some(name.clone()),
+                    "rawHost" => Some(host_load.host_name.clone()),
+                    "cluster" => Some(host_load.cluster.clone().unwrap_or_default()),
+
+                    // load indices by name, dashes where LIM has no value
+                    index => match lsf::BUILTIN_INDEX_NAMES.iter().position(|&name| name == index) {
+                        Some(i) => Some(match host_load.li.get(i) {
+                            Some(&Some(value)) => value.to_string(),
+                            _ => "-".to_owned(),
+                        }),
+
+                        None if config.external_indices.iter().flatten().any(|name| name == index) =>
+                            Some(host_load.external_indices.get(index)
+                                .map(|value| value.to_string())
+                                .unwrap_or_else(|| "-".to_owned())),
+
+                        None => None,
+                    },
+                }),
+
+                None => format!("Status code: {} ({})", status, status_str),
+            };
+
+            let mut status_storage_info = StatusStorageInfo::new(
+                name,
+                conv_status,
+                None,
+                Some(critical_group_name),
+                Some(remarks));
+
+            status_storage_info.lim_status = Some(status);
+
+            // hosts of the local cluster get theirs after the poll
+            if let (Some(true), Some(cluster)) = (config.cluster_labels, host_load.cluster.as_ref()) {
+                let mut labels = BTreeMap::new();
+                labels.insert("cluster".to_owned(), cluster.clone());
+                status_storage_info.labels = Some(labels);
+            }
+
+            if !host_load.li.is_empty() {
+                status_storage_info.load_indices = Some(lsf::BUILTIN_INDEX_NAMES.iter()
+                    .zip(&host_load.li)
+                    .filter_map(|(name, value)| value.map(|value| (name.to_string(), value)))
+                    .chain(host_load.external_indices.iter().map(|(name, &value)| (name.clone(), value)))
+                    .collect());
+            }
+
+            status_storage_info
+        })
+        .collect()
+}
@@ -0,0 +1,66 @@
+use common::StatusStorageInfo;
+use lsf::{LIM_LOCKEDM, LIM_LOCKEDU, LIM_LOCKEDU_RMS, LIM_LOCKEDW};
+use {FAILED, PASSED};
+
+/// Handling of hosts locked on purpose.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LockConfig {
+    /// Report hosts locked by an administrator, and nothing else, as PASSED
+    #[serde(default)]
+    pub admin_locked_passed: bool,
+}
+
+/// Reasons of the lock bits set in the LIM status.
+fn lock_reasons(lim_status: i32) -> Vec<&'static str> {
+    [
+        (LIM_LOCKEDU, "locked by admin"),
+        (LIM_LOCKEDW, "locked by run window"),
+        (LIM_LOCKEDM, "locked by master LIM"),
+        (LIM_LOCKEDU_RMS, "locked by RMS"),
+    ].iter()
+        .filter(|&&(flag, _)| lim_status & flag != 0)
+        .map(|&(_, reason)| reason)
+        .collect()
+}
+
+/// Attributes the locks of hosts in their remarks, together with the comment
+/// of the administrator when the batch view is available, and reports hosts
+/// only locked by an administrator as PASSED if configured.
+pub fn apply(records: &mut [StatusStorageInfo], locks: &LockConfig) {
+    for record in records.iter_mut() {
+        let lim_status = match record.lim_status {
+            Some(lim_status) => lim_status,
+            None => continue,
+        };
+
+        let mut reasons: Vec<String> = lock_reasons(lim_status).iter().map(|reason| reason.to_string()).collect();
+
+        let batch_closed_by_admin = record.batch.as_ref().is_some_and(|batch| batch.status == "closed_Adm");
+
+        if batch_closed_by_admin {
+            reasons.push("closed by admin".to_owned());
+        }
+
+        if reasons.is_empty() {
+            continue;
+        }
+
+        let control_message = record.batch.as_ref().and_then(|batch| batch.control_message.as_ref());
+
+        let mut reason = reasons.join(", ");
+
+        if let Some(control_message) = control_message {
+            reason = format!("{}: {}", reason, control_message);
+        }
+
+        if locks.admin_locked_passed && lim_status == LIM_LOCKEDU && record.status == FAILED {
+            record.status = PASSED;
+        }
+
+        record.remarks = Some(match record.remarks {
+            Some(ref remarks) => format!("{} ({})", remarks, reason),
+            None => reason,
+        });
+    }
+}
@@ -0,0 +1,345 @@
+use chrono::{SecondsFormat, Utc};
+use errors::*;
+use serde_json::{Map, Value};
+use libc;
+use std::fmt::{self, Write as FmtWrite};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+use tracing::{self, Event, Level, Metadata, Subscriber};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<LogFormat> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => bail!("Unknown log format {}, expected text or json", s),
+        }
+    }
+}
+
+/// When the log file gets rotated, and how many rotated files are kept.
+#[derive(Clone, Debug)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+    pub keep: usize,
+}
+
+impl Default for Rotation {
+    fn default() -> Rotation {
+        Rotation { max_bytes: None, max_age: None, keep: 5 }
+    }
+}
+
+/// Log file of `init`, shared with the logger so that `rotate_if_due` can
+/// also rotate it between events.
+static LOG_FILE: OnceLock<Arc<Mutex<RotatingFile>>> = OnceLock::new();
+
+/// Log file that is renamed to `<path>.1`, shifting the older ones up to
+/// `<path>.<keep>`, once it reaches the size or age of the rotation. The
+/// file also replaces stderr if `redirect_stderr` is set, so that output not
+/// going through the logger ends up in it too.
+struct RotatingFile {
+    path: String,
+    rotation: Rotation,
+    redirect_stderr: bool,
+    file: File,
+    opened_at: SystemTime,
+}
+
+impl RotatingFile {
+    fn open(path: &str, rotation: Rotation, redirect_stderr: bool) -> Result<RotatingFile> {
+        let file = RotatingFile::open_file(path, redirect_stderr)?;
+
+        // the age of a file from a previous run counts from its creation
+        let opened_at = file.metadata().and_then(|metadata| metadata.created()).unwrap_or_else(|_| SystemTime::now());
+
+        Ok(RotatingFile { path: path.to_owned(), rotation, redirect_stderr, file, opened_at })
+    }
+
+    fn open_file(path: &str, redirect_stderr: bool) -> Result<File> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .chain_err(|| format!("Unable to open log file at {}", path))?;
+
+        if redirect_stderr && unsafe { libc::dup2(file.as_raw_fd(), libc::STDERR_FILENO) } < 0 {
+            bail!("Unable to redirect stderr into log file at {}", path);
+        }
+
+        Ok(file)
+    }
+
+    fn due(&self) -> bool {
+        // stderr writes the file too, so only its size is reliable
+        let size_due = self.rotation.max_bytes
+            .is_some_and(|max_bytes| self.file.metadata().map(|metadata| metadata.len() >= max_bytes).unwrap_or(false));
+
+        let age_due = self.rotation.max_age
+            .is_some_and(|max_age| self.opened_at.elapsed().map(|age| age >= max_age).unwrap_or(false));
+
+        size_due || age_due
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        let rotated = |n: usize| format!("{}.{}", self.path, n);
+
+        if self.rotation.keep == 0 {
+            let _ = fs::remove_file(&self.path);
+        } else {
+            let _ = fs::remove_file(rotated(self.rotation.keep));
+
+            for n in (1..self.rotation.keep).rev() {
+                let _ = fs::rename(rotated(n), rotated(n + 1));
+            }
+
+            fs::rename(&self.path, rotated(1))
+                .chain_err(|| format!("Unable to rotate log file at {}", self.path))?;
+        }
+
+        self.file = RotatingFile::open_file(&self.path, self.redirect_stderr)?;
+        self.opened_at = SystemTime::now();
+        Ok(())
+    }
+
+    fn rotate_if_due(&mut self) {
+        if self.due() {
+            // keeps writing into the current file rather than losing lines
+            if let Err(e) = self.rotate() {
+                let _ = writeln!(self.file, "Unable to rotate log file: {}", e);
+            }
+        }
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.rotate_if_due();
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Writes into the log file shared with `LOG_FILE`.
+struct SharedFile(Arc<Mutex<RotatingFile>>);
+
+impl Write for SharedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+/// Fields of an event, in the order they were given.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    values: Vec<(&'static str, Value)>,
+}
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.values.push((field.name(), Value::from(value)));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.values.push((field.name(), Value::from(value)));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.values.push((field.name(), Value::from(value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.values.push((field.name(), Value::from(value)));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.values.push((field.name(), Value::from(format!("{:?}", value))));
+        }
+    }
+}
+
+/// Writes the events up to the level as a line each, without keeping track of
+/// spans.
+struct Logger {
+    level: Level,
+    format: LogFormat,
+    writer: Mutex<Box<dyn Write + Send>>,
+    next_span_id: AtomicUsize,
+}
+
+impl Logger {
+    fn render(&self, metadata: &Metadata, fields: Fields) -> String {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+
+        match self.format {
+            LogFormat::Text => {
+                let mut line = format!("{} {:>5} {}: {}", timestamp, metadata.level(), metadata.target(), fields.message);
+
+                for (name, value) in fields.values {
+                    let _ = match value {
+                        Value::String(value) => write!(line, " {}={}", name, value),
+                        value => write!(line, " {}={}", name, value),
+                    };
+                }
+
+                line
+            },
+
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert("timestamp".to_owned(), Value::from(timestamp));
+                object.insert("level".to_owned(), Value::from(metadata.level().to_string()));
+                object.insert("target".to_owned(), Value::from(metadata.target()));
+                object.insert("message".to_owned(), Value::from(fields.message));
+
+                for (name, value) in fields.values {
+                    object.insert(name.to_owned(), value);
+                }
+
+                Value::Object(object).to_string()
+            },
+        }
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        *metadata.level() <= self.level
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(LevelFilter::from_level(self.level))
+    }
+
+    fn new_span(&self, _: &Attributes) -> Id {
+        Id::from_u64(self.next_span_id.fetch_add(1, Ordering::Relaxed) as u64 + 1)
+    }
+
+    fn record(&self, _: &Id, _: &Record) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let line = self.render(event.metadata(), fields);
+
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writer.write_all(format!("{}\n", line).as_bytes());
+        }
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+/// The error followed by its causes, separated by `: `, to log as a single
+/// field.
+pub fn chain(e: &Error) -> String {
+    e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ")
+}
+
+/// Level of the events logged with `-v` given `verbosity` times.
+pub fn to_level(verbosity: u64) -> Level {
+    match verbosity {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+/// Logs the events of the whole process up to the level into the file,
+/// appended to and rotated as given, or to stderr.
+pub fn init(level: Level, format: LogFormat, log_file: Option<&str>, rotation: Rotation) -> Result<()> {
+    let writer: Box<dyn Write + Send> = match log_file {
+        Some(log_file) => {
+            let file = Arc::new(Mutex::new(RotatingFile::open(log_file, rotation, true)?));
+            let _ = LOG_FILE.set(file.clone());
+            Box::new(SharedFile(file))
+        },
+
+        None => Box::new(io::stderr()),
+    };
+
+    let logger = Logger {
+        level,
+        format,
+        writer: Mutex::new(writer),
+        next_span_id: AtomicUsize::new(0),
+    };
+
+    tracing::subscriber::set_global_default(logger)
+        .chain_err(|| "Unable to set up logging")
+}
+
+/// Rotates the log file of `init` if it is due, which is otherwise only
+/// checked when the logger writes into it, so that output written to stderr
+/// directly, e.g. by liblsf, is rotated too.
+pub fn rotate_if_due() {
+    if let Some(file) = LOG_FILE.get() {
+        file.lock().unwrap_or_else(|e| e.into_inner()).rotate_if_due();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn rotates_outside_of_writes() {
+        let path = env::temp_dir().join(format!("lsf_agent-logging-{}.log", process::id()));
+        let path = path.to_str().unwrap();
+        let rotated = format!("{}.1", path);
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(&rotated);
+
+        let rotation = Rotation { max_bytes: Some(16), max_age: None, keep: 1 };
+        let mut file = RotatingFile::open(path, rotation, false).unwrap();
+
+        // written directly, as stderr would
+        file.file.write_all(b"more than sixteen bytes\n").unwrap();
+        assert!(fs::metadata(&rotated).is_err());
+
+        file.rotate_if_due();
+
+        assert_eq!(fs::read_to_string(&rotated).unwrap(), "more than sixteen bytes\n");
+        assert_eq!(fs::metadata(path).unwrap().len(), 0);
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(&rotated);
+    }
+}
@@ -0,0 +1,940 @@
+use errors::*;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::process::{Command, Stdio};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Once};
+use std::thread;
+use std::time::Duration;
+
+// set while a query runs, liblsf must not be entered concurrently
+static QUERY_IN_FLIGHT: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "bindgen")]
+use lsf_sys::{ls_getclustername, ls_load, ls_loadinfo, ls_info, ls_sharedresourceinfo, lsInfo, lsSharedResourceInfo,
+    ls_gethostinfo, hostInfo, lsb_closejobinfo, lsb_hostinfo, lsb_init, lsb_openjobinfo, lsb_readjobinfo, lsb_sysmsg, hostInfoEnt, jobInfoEnt};
+
+#[cfg(not(feature = "bindgen"))]
+use self::fallback::{ls_getclustername, ls_load, ls_loadinfo, ls_info, ls_sharedresourceinfo, lsInfo, lsSharedResourceInfo,
+    ls_gethostinfo, hostInfo, lsb_closejobinfo, lsb_hostinfo, lsb_init, lsb_openjobinfo, lsb_readjobinfo, lsb_sysmsg, hostInfoEnt, jobInfoEnt};
+
+/// Hand-written bindings, for building without the LSF headers at hand.
+#[cfg(not(feature = "bindgen"))]
+mod fallback {
+    use libresolv_sys::MAXHOSTNAMELEN;
+    use libc::time_t;
+    use std::os::raw::{c_char, c_float, c_int, c_longlong};
+
+    #[link(name = "lsf")]
+    extern "C" {
+        pub fn ls_load(resreq: *mut c_char, numhosts: *mut c_int, options: c_int, fromhost: *mut c_char) -> *mut hostLoad;
+
+        pub fn ls_loadinfo(resreq: *mut c_char, numhosts: *mut c_int, options: c_int, fromhost: *mut c_char,
+            hostlist: *mut *mut c_char, listsize: c_int, indxnamelist: *mut *mut *mut c_char) -> *mut hostLoad;
+
+        pub fn ls_getclustername() -> *mut c_char;
+
+        pub fn ls_sharedresourceinfo(resources: *mut *mut c_char, numResources: *mut c_int, hostName: *mut c_char,
+            options: c_int) -> *mut lsSharedResourceInfo;
+
+        pub fn ls_info() -> *mut lsInfo;
+
+        pub fn ls_gethostinfo(resReq: *mut c_char, numhosts: *mut c_int, hostlist: *mut *mut c_char, listsize: c_int,
+            options: c_int) -> *mut hostInfo;
+    }
+
+    #[link(name = "bat")]
+    extern "C" {
+        pub fn lsb_init(appName: *mut c_char) -> c_int;
+
+        pub fn lsb_hostinfo(hosts: *mut *mut c_char, numHosts: *mut c_int) -> *mut hostInfoEnt;
+
+        pub fn lsb_sysmsg() -> *mut c_char;
+
+        pub fn lsb_openjobinfo(jobId: c_longlong, jobName: *mut c_char, userName: *mut c_char, queueName: *mut c_char,
+            hostName: *mut c_char, options: c_int) -> c_int;
+
+        pub fn lsb_readjobinfo(more: *mut c_int) -> *mut jobInfoEnt;
+
+        pub fn lsb_closejobinfo();
+    }
+
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub struct hostLoad {
+        pub hostName: [c_char; MAXHOSTNAMELEN as usize],
+        pub status: *mut c_int,
+        pub li: *mut c_float,
+    }
+
+    /// Fields of `hostInfo` as of LSF 10.1, other versions need the generated
+    /// bindings.
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub struct hostInfo {
+        pub hostName: [c_char; MAXHOSTNAMELEN as usize],
+        pub hostType: *mut c_char,
+        pub hostModel: *mut c_char,
+        pub cpuFactor: c_float,
+        pub maxCpus: c_int,
+        pub maxMem: c_int,
+        pub maxSwap: c_int,
+        pub maxTmp: c_int,
+        pub nDisks: c_int,
+        pub nRes: c_int,
+        pub resources: *mut *mut c_char,
+        pub nDRes: c_int,
+        pub DResources: *mut *mut c_char,
+        pub windows: *mut c_char,
+        pub numIndx: c_int,
+        pub busyThreshold: *mut c_float,
+        pub isServer: c_char,
+        pub licensed: c_char,
+        pub rexPriority: c_int,
+        pub licFeaturesNeeded: c_int,
+        pub licClass: c_longlong,
+        pub cores: c_int,
+        pub pprocs: c_int,
+        pub cores_per_proc: c_int,
+        pub threads_per_core: c_int,
+    }
+
+    /// Fields of `hostInfoEnt` as of LSF 10.1, other versions need the
+    /// generated bindings.
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub struct hostInfoEnt {
+        pub host: *mut c_char,
+        pub hStatus: c_int,
+        pub busySched: *mut c_int,
+        pub busyStop: *mut c_int,
+        pub load: *mut c_float,
+        pub loadSched: *mut c_float,
+        pub loadStop: *mut c_float,
+        pub windows: *mut c_char,
+        pub userJobLimit: c_int,
+        pub maxJobs: c_int,
+        pub numJobs: c_int,
+        pub numRUN: c_int,
+        pub numSSUSP: c_int,
+        pub numUSUSP: c_int,
+        pub mig: c_int,
+        pub attr: c_int,
+        pub realLoad: *mut c_float,
+        pub numRESERVE: c_int,
+        pub chkSig: c_int,
+        pub cnsmrUsage: c_float,
+        pub prvdrUsage: c_float,
+        pub cnsmrAvail: c_float,
+        pub prvdrAvail: c_float,
+        pub maxAvail: c_float,
+        pub maxExitRate: c_float,
+        pub numExitRate: c_float,
+        pub hCtrlMsg: *mut c_char,
+    }
+
+    pub const LSF_RLIM_NLIMITS: usize = 12;
+
+    /// Leading fields of `submit` only, as part of the leading `jobInfoEnt`.
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub struct submit {
+        pub options: c_int,
+        pub options2: c_int,
+        pub jobName: *mut c_char,
+        pub queue: *mut c_char,
+        pub numAskedHosts: c_int,
+        pub askedHosts: *mut *mut c_char,
+        pub resReq: *mut c_char,
+        pub rLimits: [c_int; LSF_RLIM_NLIMITS],
+        pub hostSpec: *mut c_char,
+        pub numProcessors: c_int,
+    }
+
+    /// Leading fields of `jobInfoEnt` only, which is never allocated on this
+    /// side nor read as an array.
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub struct jobInfoEnt {
+        pub jobId: c_longlong,
+        pub user: *mut c_char,
+        pub status: c_int,
+        pub reasonTb: *mut c_int,
+        pub numReasons: c_int,
+        pub reasons: c_int,
+        pub subreasons: c_int,
+        pub jobPid: c_int,
+        pub submitTime: time_t,
+        pub reserveTime: time_t,
+        pub startTime: time_t,
+        pub predictedStartTime: time_t,
+        pub endTime: time_t,
+        pub forwardTime: time_t,
+        pub cpuTime: c_float,
+        pub umask: c_int,
+        pub cwd: *mut c_char,
+        pub subHomeDir: *mut c_char,
+        pub fromHost: *mut c_char,
+        pub exHosts: *mut *mut c_char,
+        pub numExHosts: c_int,
+        pub cpuFactor: c_float,
+        pub nIdx: c_int,
+        pub loadSched: *mut c_float,
+        pub loadStop: *mut c_float,
+        pub submit: submit,
+    }
+
+    pub const MAXLSFNAMELEN: usize = 40;
+    pub const MAXRESDESLEN: usize = 256;
+
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub struct resItem {
+        pub name: [c_char; MAXLSFNAMELEN],
+        pub des: [c_char; MAXRESDESLEN],
+        pub valueType: c_int,
+        pub orderType: c_int,
+        pub flags: c_int,
+        pub interval: c_int,
+    }
+
+    /// Leading fields of `lsInfo` only, which is never allocated on this side.
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub struct lsInfo {
+        pub nRes: c_int,
+        pub resTable: *mut resItem,
+    }
+
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub struct lsSharedResourceInstance {
+        pub value: *mut c_char,
+        pub nHosts: c_int,
+        pub hostList: *mut *mut c_char,
+    }
+
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub struct lsSharedResourceInfo {
+        pub resourceName: *mut c_char,
+        pub nInstances: c_int,
+        pub instances: *mut lsSharedResourceInstance,
+    }
+}
+
+// LSF status flags
+pub const LIM_OK: i32 = 0x00000000;
+pub const LIM_UNAVAIL: i32 = 0x00010000;
+pub const LIM_LOCKEDU: i32 = 0x00020000;
+pub const LIM_LOCKEDW: i32 = 0x00040000;
+pub const LIM_BUSY: i32 = 0x00080000;
+pub const LIM_RESDOWN: i32 = 0x00100000;
+pub const LIM_UNLICENSED: i32 = 0x00200000;
+pub const LIM_SBDDOWN: i32 = 0x00400000;
+pub const LIM_LOCKEDM: i32 = 0x00800000;
+pub const LIM_PEMDOWN: i32 = 0x01000000;
+pub const LIM_EXPIRED: i32 = 0x02000000;
+pub const LIM_RLAUP: i32 = 0x04000000;
+
+#[allow(overflowing_literals)]
+pub const LIM_LOCKEDU_RMS: i32 = 0x80000000;
+// pub const LIM_OK_MASK: i32 = 0x02bf0000;
+
+// the status flags above must agree with the headers of the installed LSF
+#[cfg(feature = "bindgen")]
+const _: [(); 0] = [(); (
+    (LIM_UNAVAIL != ::lsf_sys::LIM_UNAVAIL as i32) as usize +
+    (LIM_LOCKEDU != ::lsf_sys::LIM_LOCKEDU as i32) as usize +
+    (LIM_LOCKEDW != ::lsf_sys::LIM_LOCKEDW as i32) as usize +
+    (LIM_BUSY != ::lsf_sys::LIM_BUSY as i32) as usize +
+    (LIM_RESDOWN != ::lsf_sys::LIM_RESDOWN as i32) as usize +
+    (LIM_SBDDOWN != ::lsf_sys::LIM_SBDDOWN as i32) as usize +
+    (LIM_LOCKEDM != ::lsf_sys::LIM_LOCKEDM as i32) as usize)];
+
+// resItem flags
+pub const RESF_BUILTIN: i32 = 0x01;
+pub const RESF_DYNAMIC: i32 = 0x02;
+pub const RESF_GLOBAL: i32 = 0x04;
+pub const RESF_SHARED: i32 = 0x08;
+pub const RESF_EXTERNAL: i32 = 0x20;
+
+// lsb_hostinfo host status flags
+pub const HOST_STAT_OK: i32 = 0x0;
+pub const HOST_STAT_BUSY: i32 = 0x01;
+pub const HOST_STAT_WIND: i32 = 0x02;
+pub const HOST_STAT_DISABLED: i32 = 0x04;
+pub const HOST_STAT_LOCKED: i32 = 0x08;
+pub const HOST_STAT_FULL: i32 = 0x10;
+pub const HOST_STAT_UNREACH: i32 = 0x20;
+pub const HOST_STAT_UNAVAIL: i32 = 0x40;
+pub const HOST_STAT_UNLICENSED: i32 = 0x80;
+pub const HOST_STAT_NO_LIM: i32 = 0x100;
+pub const HOST_STAT_EXCLUSIVE: i32 = 0x200;
+pub const HOST_STAT_LOCKED_MASTER: i32 = 0x400;
+
+// lsb_openjobinfo options
+pub const CUR_JOB: i32 = 0x0000;
+pub const ALL_JOB: i32 = 0x0001;
+pub const DONE_JOB: i32 = 0x0002;
+pub const PEND_JOB: i32 = 0x0004;
+pub const RUN_JOB: i32 = 0x0020;
+
+// job status flags
+pub const JOB_STAT_PEND: i32 = 0x01;
+pub const JOB_STAT_PSUSP: i32 = 0x02;
+pub const JOB_STAT_RUN: i32 = 0x04;
+pub const JOB_STAT_SSUSP: i32 = 0x08;
+pub const JOB_STAT_USUSP: i32 = 0x10;
+pub const JOB_STAT_EXIT: i32 = 0x20;
+pub const JOB_STAT_DONE: i32 = 0x40;
+
+// ls_load option flags
+pub const EXACT: i32 = 0x01;
+pub const OK_ONLY: i32 = 0x02;
+pub const NORMALIZE: i32 = 0x04;
+pub const LOCALITY: i32 = 0x08;
+pub const ALL_CLUSTERS: i32 = 0x80;
+pub const EFFECTIVE: i32 = 0x100;
+
+// built-in load indices, in the order LIM reports them
+pub const BUILTIN_INDEX_NAMES: [&str; 11] =
+    ["r15s", "r1m", "r15m", "ut", "pg", "io", "ls", "it", "tmp", "swp", "mem"];
+
+// load indices that differ between the EFFECTIVE, NORMALIZE and raw views
+pub const RUN_QUEUE_INDEX_NAMES: [&str; 3] = ["r15s", "r1m", "r15m"];
+
+// LIM reports unavailable indices with this value
+const INFINIT_LOAD: f32 = 0x7fffffff as f32;
+
+pub fn to_status_str(status: i32) -> &'static str {
+    match status {
+        LIM_OK => "LIM_OK",
+        LIM_UNAVAIL => "LIM_UNAVAIL",
+        LIM_LOCKEDU => "LIM_LOCKEDU",
+        LIM_LOCKEDW => "LIM_LOCKEDW",
+        LIM_BUSY => "LIM_BUSY",
+        LIM_RESDOWN => "LIM_RESDOWN",
+        LIM_UNLICENSED => "LIM_UNLICENSED",
+        LIM_SBDDOWN => "LIM_SBDDOWN",
+        LIM_LOCKEDM => "LIM_LOCKEDM",
+        LIM_PEMDOWN => "LIM_PEMDOWN",
+        LIM_EXPIRED => "LIM_EXPIRED",
+        LIM_RLAUP => "LIM_RLAUP",
+        LIM_LOCKEDU_RMS => "LIM_LOCKEDU_RMS",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Batch status of a host as shown by `bhosts`.
+pub fn to_batch_status_str(status: i32) -> &'static str {
+    if status == HOST_STAT_OK {
+        "ok"
+    } else if status & HOST_STAT_UNAVAIL != 0 {
+        "unavail"
+    } else if status & (HOST_STAT_UNREACH | HOST_STAT_NO_LIM) != 0 {
+        "unreach"
+    } else if status & HOST_STAT_UNLICENSED != 0 {
+        "unlicensed"
+    } else if status & HOST_STAT_DISABLED != 0 {
+        "closed_Adm"
+    } else if status & (HOST_STAT_LOCKED | HOST_STAT_LOCKED_MASTER) != 0 {
+        "closed_Lock"
+    } else if status & HOST_STAT_WIND != 0 {
+        "closed_Wind"
+    } else if status & HOST_STAT_FULL != 0 {
+        "closed_Full"
+    } else if status & HOST_STAT_EXCLUSIVE != 0 {
+        "closed_Excl"
+    } else if status & HOST_STAT_BUSY != 0 {
+        "closed_Busy"
+    } else {
+        "closed"
+    }
+}
+
+/// Status flag of the given name, the inverse of `to_status_str`.
+pub fn to_status_flag(name: &str) -> Option<i32> {
+    [LIM_OK, LIM_UNAVAIL, LIM_LOCKEDU, LIM_LOCKEDW, LIM_BUSY, LIM_RESDOWN, LIM_UNLICENSED,
+        LIM_SBDDOWN, LIM_LOCKEDM, LIM_PEMDOWN, LIM_EXPIRED, LIM_RLAUP, LIM_LOCKEDU_RMS].iter()
+        .find(|&&flag| to_status_str(flag) == name)
+        .cloned()
+}
+
+pub fn to_load_option(name: &str) -> Option<i32> {
+    match name {
+        "EXACT" => Some(EXACT),
+        "OK_ONLY" => Some(OK_ONLY),
+        "NORMALIZE" => Some(NORMALIZE),
+        "LOCALITY" => Some(LOCALITY),
+        "ALL_CLUSTERS" => Some(ALL_CLUSTERS),
+        "EFFECTIVE" => Some(EFFECTIVE),
+        _ => None,
+    }
+}
+
+/// Decoded `hostLoad` entry, detached from the memory owned by liblsf.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HostLoad {
+    /// Host name, or its escaped form if LIM returned a name that is not UTF-8
+    pub host_name: String,
+
+    #[serde(default)]
+    pub invalid_host_name: bool,
+
+    pub status: i32,
+
+    /// Cluster the host was queried from, when clusters are polled separately
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+
+    /// Built-in load indices, `None` where LIM has no value for the index
+    #[serde(default)]
+    pub li: Vec<Option<f32>>,
+
+    /// Requested external indices that LIM has a value for, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub external_indices: BTreeMap<String, f32>,
+}
+
+/// Name of the local cluster, or `None` if LIM cannot be reached.
+pub fn cluster_name() -> Option<String> {
+    let cluster_name = unsafe { ls_getclustername() };
+
+    if cluster_name.is_null() {
+        return None;
+    }
+
+    Some(unsafe { CStr::from_ptr(cluster_name) }.to_string_lossy().into_owned())
+}
+
+/// Resource defined in the LIM resource table.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    pub name: String,
+    pub description: String,
+
+    /// One of Boolean, Numeric, String or External
+    pub value_type: String,
+
+    /// One of Increasing, Decreasing or NA
+    pub order: String,
+
+    pub built_in: bool,
+    pub dynamic: bool,
+    pub shared: bool,
+    pub external: bool,
+
+    /// Update interval of dynamic resources in seconds
+    pub interval: i32,
+}
+
+/// Queries LIM for its resource table.
+// enums are unsigned in the generated bindings
+#[allow(clippy::unnecessary_cast)]
+pub fn resources() -> Result<Vec<Resource>> {
+    if QUERY_IN_FLIGHT.load(Ordering::SeqCst) {
+        bail!(ErrorKind::QueryFailed("previous ls_load call has not returned yet".to_owned()));
+    }
+
+    debug!("Calling ls_info");
+    let info = unsafe { ls_info() };
+
+    if info.is_null() {
+        bail!(ErrorKind::QueryFailed("ls_info returned no resource table".to_owned()));
+    }
+
+    let info: &lsInfo = unsafe { &*info };
+
+    if info.resTable.is_null() || info.nRes <= 0 {
+        return Ok(vec![]);
+    }
+
+    let res_table = unsafe { slice::from_raw_parts(info.resTable, info.nRes as usize) };
+
+    let resources = res_table.iter()
+        .map(|res_item| Resource {
+            name: to_string_lossy(res_item.name.as_ptr()),
+            description: to_string_lossy(res_item.des.as_ptr()),
+
+            value_type: match res_item.valueType as i32 {
+                0 => "Boolean",
+                1 => "Numeric",
+                2 => "String",
+                _ => "External",
+            }.to_owned(),
+
+            order: match res_item.orderType as i32 {
+                0 => "Increasing",
+                1 => "Decreasing",
+                _ => "NA",
+            }.to_owned(),
+
+            built_in: res_item.flags & RESF_BUILTIN != 0,
+            dynamic: res_item.flags & RESF_DYNAMIC != 0,
+            shared: res_item.flags & (RESF_SHARED | RESF_GLOBAL) != 0,
+            external: res_item.flags & RESF_EXTERNAL != 0,
+            interval: res_item.interval,
+        })
+        .collect();
+
+    Ok(resources)
+}
+
+/// Static configuration of a host, decoded from `hostInfo`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HostInfo {
+    pub host_name: String,
+    pub host_model: String,
+    pub cpu_factor: f32,
+    pub max_cpus: i32,
+}
+
+/// Queries LIM for the static configuration of all hosts.
+pub fn host_infos() -> Result<Vec<HostInfo>> {
+    if QUERY_IN_FLIGHT.load(Ordering::SeqCst) {
+        bail!(ErrorKind::QueryFailed("previous ls_load call has not returned yet".to_owned()));
+    }
+
+    let mut numhosts: c_int = 0;
+    debug!("Calling ls_gethostinfo");
+    let infos = unsafe { ls_gethostinfo(ptr::null_mut(), &mut numhosts, ptr::null_mut(), 0, 0) };
+
+    if infos.is_null() {
+        bail!(ErrorKind::QueryFailed("ls_gethostinfo returned no hosts".to_owned()));
+    }
+
+    let infos: &[hostInfo] = unsafe { slice::from_raw_parts(infos, numhosts.max(0) as usize) };
+
+    Ok(infos.iter()
+        .map(|info| HostInfo {
+            host_name: to_string_lossy(info.hostName.as_ptr()),
+            host_model: to_string_lossy(info.hostModel),
+            cpu_factor: info.cpuFactor,
+            max_cpus: info.maxCpus,
+        })
+        .collect())
+}
+
+/// Batch view of a host by mbatchd, decoded from `hostInfoEnt`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchHost {
+    pub host_name: String,
+    pub status: i32,
+    pub max_jobs: i32,
+    pub num_jobs: i32,
+    pub num_run: i32,
+    pub num_ssusp: i32,
+    pub num_ususp: i32,
+
+    /// Comment of the administrator who last opened or closed the host
+    pub control_message: Option<String>,
+}
+
+static LSB_INIT: Once = Once::new();
+static LSB_INIT_OK: AtomicBool = AtomicBool::new(false);
+
+fn lsb_error(call: &str) -> Error {
+    let msg = unsafe { lsb_sysmsg() };
+    let msg = if msg.is_null() { "unknown error".to_owned() } else { to_string_lossy(msg) };
+    ErrorKind::QueryFailed(format!("{} failed: {}", call, msg)).into()
+}
+
+/// Initializes LSBLIB once per process, required before any `lsb_` call.
+fn init_batch() -> Result<()> {
+    LSB_INIT.call_once(|| {
+        let app_name = CString::new("lsf_agent").expect("static app name is a valid C string");
+        let rc = unsafe { lsb_init(app_name.as_ptr() as *mut c_char) };
+        LSB_INIT_OK.store(rc >= 0, Ordering::SeqCst);
+    });
+
+    if LSB_INIT_OK.load(Ordering::SeqCst) {
+        Ok(())
+    } else {
+        Err(lsb_error("lsb_init"))
+    }
+}
+
+/// Queries mbatchd for the batch status and job counts of all hosts.
+pub fn batch_hosts() -> Result<Vec<BatchHost>> {
+    if QUERY_IN_FLIGHT.load(Ordering::SeqCst) {
+        bail!(ErrorKind::QueryFailed("previous ls_load call has not returned yet".to_owned()));
+    }
+
+    init_batch()?;
+
+    let mut num_hosts: c_int = 0;
+    debug!("Calling lsb_hostinfo");
+    let infos = unsafe { lsb_hostinfo(ptr::null_mut(), &mut num_hosts) };
+
+    if infos.is_null() {
+        return Err(lsb_error("lsb_hostinfo"));
+    }
+
+    let infos: &[hostInfoEnt] = unsafe { slice::from_raw_parts(infos, num_hosts.max(0) as usize) };
+
+    Ok(infos.iter()
+        .map(|info| BatchHost {
+            host_name: to_string_lossy(info.host),
+            status: info.hStatus,
+            max_jobs: info.maxJobs,
+            num_jobs: info.numJobs,
+            num_run: info.numRUN,
+            num_ssusp: info.numSSUSP,
+            num_ususp: info.numUSUSP,
+            control_message: Some(to_string_lossy(info.hCtrlMsg)).filter(|msg| !msg.is_empty()),
+        })
+        .collect())
+}
+
+/// Job known to mbatchd, decoded from `jobInfoEnt`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub job_id: i64,
+    pub user: String,
+    pub queue: String,
+    pub status: i32,
+
+    /// Slots used once started, or asked for while pending
+    pub slots: i32,
+
+    /// Unix time the job finished at, 0 while it has not
+    pub end_time: i64,
+
+    /// Execution host of every slot of the job
+    pub ex_hosts: Vec<String>,
+}
+
+/// Queries mbatchd for the jobs of all users selected by the
+/// `lsb_openjobinfo` options, e.g. `DONE_JOB` for the finished ones.
+pub fn jobs(options: i32) -> Result<Vec<Job>> {
+    if QUERY_IN_FLIGHT.load(Ordering::SeqCst) {
+        bail!(ErrorKind::QueryFailed("previous ls_load call has not returned yet".to_owned()));
+    }
+
+    init_batch()?;
+
+    let all_users = CString::new("all").expect("static user name is a valid C string");
+
+    debug!(options, user = "all", "Calling lsb_openjobinfo");
+
+    let num_jobs = unsafe {
+        lsb_openjobinfo(0, ptr::null_mut(), all_users.as_ptr() as *mut c_char, ptr::null_mut(), ptr::null_mut(), options)
+    };
+
+    // no matching jobs is reported as an error too
+    if num_jobs <= 0 {
+        unsafe { lsb_closejobinfo() };
+        return Ok(vec![]);
+    }
+
+    let mut jobs = Vec::with_capacity(num_jobs as usize);
+    let mut more: c_int = 1;
+
+    while more > 0 {
+        let info = unsafe { lsb_readjobinfo(&mut more) };
+
+        if info.is_null() {
+            break;
+        }
+
+        let info: &jobInfoEnt = unsafe { &*info };
+
+        let ex_hosts: Vec<String> = if info.exHosts.is_null() || info.numExHosts <= 0 {
+            vec![]
+        } else {
+            unsafe { slice::from_raw_parts(info.exHosts, info.numExHosts as usize) }.iter()
+                .map(|&host| to_string_lossy(host))
+                .collect()
+        };
+
+        jobs.push(Job {
+            job_id: info.jobId as i64,
+            user: to_string_lossy(info.user),
+            queue: to_string_lossy(info.submit.queue),
+            status: info.status,
+            slots: if ex_hosts.is_empty() { info.submit.numProcessors.max(1) } else { ex_hosts.len() as i32 },
+            end_time: info.endTime as i64,
+            ex_hosts,
+        });
+    }
+
+    unsafe { lsb_closejobinfo() };
+    Ok(jobs)
+}
+
+/// Value of a shared resource on the hosts of one of its instances.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedResourceInstance {
+    pub value: String,
+    pub hosts: Vec<String>,
+}
+
+/// Cluster-wide resource shared by hosts, such as floating licenses.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedResource {
+    pub name: String,
+    pub instances: Vec<SharedResourceInstance>,
+}
+
+fn to_string_lossy(s: *const c_char) -> String {
+    if s.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+    }
+}
+
+/// Queries LIM for the given shared resources, or all of them if none are
+/// given.
+pub fn shared_resources(names: &[String]) -> Result<Vec<SharedResource>> {
+    if QUERY_IN_FLIGHT.load(Ordering::SeqCst) {
+        bail!(ErrorKind::QueryFailed("previous ls_load call has not returned yet".to_owned()));
+    }
+
+    let names = names.iter()
+        .map(|name| CString::new(name.clone())
+            .chain_err(|| format!("Unable to convert shared resource {} into C string", name)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut name_ptrs: Vec<*mut c_char> = names.iter()
+        .map(|name| name.as_ptr() as *mut c_char)
+        .chain(Some(ptr::null_mut()))
+        .collect();
+
+    let resources_ptr = if names.is_empty() { ptr::null_mut() } else { name_ptrs.as_mut_ptr() };
+    let mut num_resources = names.len() as c_int;
+
+    debug!(names = ?names, "Calling ls_sharedresourceinfo");
+    let infos = unsafe { ls_sharedresourceinfo(resources_ptr, &mut num_resources, ptr::null_mut(), 0) };
+
+    if infos.is_null() {
+        bail!(ErrorKind::QueryFailed("ls_sharedresourceinfo returned no resources".to_owned()));
+    }
+
+    let infos: &[lsSharedResourceInfo] = unsafe { slice::from_raw_parts(infos, num_resources.max(0) as usize) };
+
+    let resources = infos.iter()
+        .map(|info| {
+            let instances = if info.instances.is_null() || info.nInstances <= 0 {
+                &[][..]
+            } else {
+                unsafe { slice::from_raw_parts(info.instances, info.nInstances as usize) }
+            };
+
+            SharedResource {
+                name: to_string_lossy(info.resourceName),
+                instances: instances.iter()
+                    .map(|instance| SharedResourceInstance {
+                        value: to_string_lossy(instance.value),
+                        hosts: if instance.hostList.is_null() || instance.nHosts <= 0 {
+                            vec![]
+                        } else {
+                            unsafe { slice::from_raw_parts(instance.hostList, instance.nHosts as usize) }.iter()
+                                .map(|&host| to_string_lossy(host))
+                                .collect()
+                        },
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(resources)
+}
+
+/// Version banner of the installed LSF, e.g. "IBM Spectrum LSF Standard
+/// 10.1.0.13, Jun 07 2022", from the first line printed by `lsid`, since
+/// liblsf has no call returning it. `None` if `lsid` cannot be run.
+pub fn version() -> Option<String> {
+    let output = Command::new("lsid").stdin(Stdio::null()).stderr(Stdio::null()).output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout).lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_owned())
+}
+
+/// Queries LIM for the load information of all hosts, or only of the hosts
+/// in the given cluster, together with the given external (ELIM) indices.
+pub fn load(options: i32, from_host: Option<&str>, cluster: Option<&str>, indices: &[String]) -> Result<Vec<HostLoad>> {
+    let from_host = match from_host {
+        Some(from_host) => Some(CString::new(from_host)
+            .chain_err(|| format!("Unable to convert from host {} into C string", from_host))?),
+        None => None,
+    };
+
+    let from_host_ptr = match from_host {
+        Some(ref from_host) => from_host.as_ptr() as *mut c_char,
+        None => ptr::null_mut(),
+    };
+
+    let cluster = match cluster {
+        Some(cluster) => Some(CString::new(cluster)
+            .chain_err(|| format!("Unable to convert cluster {} into C string", cluster))?),
+        None => None,
+    };
+
+    // external indices are only returned when asked for by name, along with
+    // the built-in ones
+    let index_names = if indices.is_empty() {
+        vec![]
+    } else {
+        BUILTIN_INDEX_NAMES.iter()
+            .map(|name| name.to_string())
+            .chain(indices.iter().cloned())
+            .map(|name| CString::new(name.clone())
+                .chain_err(|| format!("Unable to convert load index {} into C string", name)))
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let mut index_name_ptrs: Vec<*mut c_char> = index_names.iter()
+        .map(|name| name.as_ptr() as *mut c_char)
+        .chain(Some(ptr::null_mut()))
+        .collect();
+
+    let mut numhosts: c_int = 0;
+
+    let mut indxnamelist: *mut *mut c_char = if index_names.is_empty() {
+        ptr::null_mut()
+    } else {
+        index_name_ptrs.as_mut_ptr()
+    };
+
+    debug!(options, from_host = ?from_host, cluster = ?cluster, indices = ?indices,
+        call = if cluster.is_some() || !index_names.is_empty() { "ls_loadinfo" } else { "ls_load" }, "Querying LIM");
+
+    let host_load_vals = match cluster {
+        Some(ref cluster) => {
+            let mut hostlist = [cluster.as_ptr() as *mut c_char];
+
+            unsafe {
+                ls_loadinfo(ptr::null_mut(), &mut numhosts, options, from_host_ptr,
+                    hostlist.as_mut_ptr(), 1, &mut indxnamelist)
+            }
+        },
+
+        None if !index_names.is_empty() => unsafe {
+            ls_loadinfo(ptr::null_mut(), &mut numhosts, options, from_host_ptr,
+                ptr::null_mut(), 0, &mut indxnamelist)
+        },
+
+        None => unsafe { ls_load(ptr::null_mut(), &mut numhosts, options, from_host_ptr) },
+    };
+
+    if host_load_vals.is_null() || numhosts <= 0 {
+        debug!(numhosts, "LIM returned no hosts");
+        return Ok(vec![]);
+    }
+
+    debug!(numhosts, "LIM returned hosts");
+
+    // names of the indices in the order of the values, as returned by LIM
+    let returned_names: Vec<String> = if index_names.is_empty() || indxnamelist.is_null() {
+        BUILTIN_INDEX_NAMES.iter().map(|name| name.to_string()).collect()
+    } else {
+        (0..)
+            .map(|i| unsafe { *indxnamelist.offset(i) })
+            .take_while(|name| !name.is_null())
+            .map(|name| unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned())
+            .collect()
+    };
+
+    let host_load_vals = unsafe { slice::from_raw_parts(host_load_vals, numhosts as usize) };
+
+    let host_loads = host_load_vals.iter()
+        .map(|host_load| {
+            let host_name_raw = unsafe { CStr::from_ptr(host_load.hostName.as_ptr()) };
+
+            // very unlikely to be unable to interpret cstr as str here
+            let (host_name, invalid_host_name) = match host_name_raw.to_str() {
+                Ok(host_name) => (host_name.to_owned(), false),
+                Err(_) => (format!("{:?}", host_name_raw), true),
+            };
+
+            let values: Vec<Option<f32>> = if host_load.li.is_null() {
+                vec![]
+            } else {
+                unsafe { slice::from_raw_parts(host_load.li, returned_names.len()) }.iter()
+                    .map(|&value| if value >= INFINIT_LOAD { None } else { Some(value) })
+                    .collect()
+            };
+
+            let value_of = |name: &str| returned_names.iter()
+                .position(|returned_name| returned_name == name)
+                .and_then(|i| values.get(i).cloned())
+                .and_then(|value| value);
+
+            let li = if values.is_empty() {
+                vec![]
+            } else {
+                BUILTIN_INDEX_NAMES.iter().map(|name| value_of(name)).collect()
+            };
+
+            let external_indices = indices.iter()
+                .filter_map(|name| value_of(name).map(|value| (name.clone(), value)))
+                .collect();
+
+            HostLoad {
+                host_name,
+                invalid_host_name,
+                status: unsafe { *host_load.status },
+                cluster: None,
+                li,
+                external_indices,
+            }
+        })
+        .collect();
+
+    Ok(host_loads)
+}
+
+/// Queries LIM like `load`, but abandons the query if it does not return within
+/// the timeout, in which case no hosts are returned. An abandoned query keeps
+/// its thread, and later queries return no hosts until it completes.
+pub fn load_with_timeout(
+    options: i32,
+    from_host: Option<String>,
+    indices: Vec<String>,
+    timeout: Option<Duration>) -> Result<Vec<HostLoad>> {
+
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return load(options, from_host.as_deref(), None, &indices),
+    };
+
+    if QUERY_IN_FLIGHT.swap(true, Ordering::SeqCst) {
+        warn!("Previous ls_load call has not returned yet, skipping query");
+        return Ok(vec![]);
+    }
+
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let res = load(options, from_host.as_deref(), None, &indices);
+        QUERY_IN_FLIGHT.store(false, Ordering::SeqCst);
+        let _ = tx.send(res);
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(res) => res,
+        Err(_) => {
+            warn!(timeout_secs = timeout.as_secs(), "ls_load call did not return in time, abandoning it");
+            Ok(vec![])
+        },
+    }
+}
@@ -0,0 +1,5 @@
+//! Bindings generated from `lsf.h` by the build script.
+
+#![allow(non_camel_case_types, non_snake_case, non_upper_case_globals, dead_code)]
+
+include!(concat!(env!("OUT_DIR"), "/lsf_sys.rs"));
@@ -1,228 +1,801 @@
-#[macro_use]
-extern crate derive_new;
+// serde_derive 1.0.11 wraps the derived impls in an anonymous const
+#![allow(non_local_definitions)]
 
+extern crate chrono;
 #[macro_use]
 extern crate error_chain;
-extern crate libresolv_sys;
+extern crate lsf_agent;
 
 #[macro_use]
-extern crate serde_derive;
 extern crate serde_json;
 extern crate structopt;
 
 #[macro_use]
 extern crate structopt_derive;
 
-use libresolv_sys::MAXHOSTNAMELEN;
-use std::collections::HashMap;
-use std::ffi::CStr;
-use std::fs::File;
-use std::io::{self, Read, Write};
-use std::os::raw::{c_char, c_float, c_int};
+#[macro_use]
+extern crate tracing;
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
 use std::process;
-use std::ptr;
-use std::slice;
+use std::str::FromStr;
+use chrono::{SecondsFormat, Utc};
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
 
-extern {
-    #[link(name="lsf")]
-    fn ls_load(resreq: *mut c_char, numhosts: *mut c_int, options: c_int, fromhost: *mut c_char) -> *mut hostLoad; 
+use lsf_agent::{ack, budget, hooks, jobs, labels, logging, lsf, notify, nrpe, output, poll, report, schema, server, sinks, systemd, telemetry, top, watch, worker};
+use lsf_agent::{Config, StatusStorageInfo, FAILED, PASSED};
+use lsf_agent::history::{self, HistoryStore};
+use lsf_agent::errors::*;
+use lsf_agent::compression::Compression;
+use lsf_agent::logging::LogFormat;
+use lsf_agent::outfile::OutputFile;
+use lsf_agent::output::{OutputFormat, SchemaVersion, SortKey};
+use lsf_agent::pidfile::PidFile;
+use lsf_agent::consul::Consul;
+use lsf_agent::federation::Federation;
+use lsf_agent::server::ServerState;
+use lsf_agent::sinks::syslog::local_hostname;
+use lsf_agent::state::State;
+
+// exit code
+const NORMAL: i32 = 0;
+// const INVALID_ARGS: i32 = 1;
+const ALREADY_RUNNING: i32 = 123;
+const BUDGET_EXCEEDED: i32 = 125;
+const ERROR: i32 = 127;
+
+// polls buffered per sink before records get dropped
+const DEFAULT_SINK_QUEUE_SIZE: usize = 4;
+
+const DEFAULT_SERVE_INTERVAL_SECS: u64 = 60;
+const DEFAULT_TOP_INTERVAL_SECS: u64 = 5;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "LSF Agent", about = "Simple LSF program to poll for LSF host status.")]
+struct MainArgMap {
+    #[structopt(short = "c", long = "config", help = "Configuration file path, may be repeated to override earlier files", required = true, number_of_values = 1)]
+    config_paths: Vec<String>,
+
+    #[structopt(long = "from-host", help = "Evaluate the query as if originating from this host, overrides config")]
+    from_host: Option<String>,
+
+    #[structopt(long = "load-option", help = "ls_load option flag (EXACT, OK_ONLY, NORMALIZE, LOCALITY), may be repeated, overrides config")]
+    load_options: Vec<String>,
+
+    #[structopt(long = "query-timeout", help = "Abandon the LSF query after the given number of seconds, overrides config")]
+    query_timeout_secs: Option<u64>,
+
+    #[structopt(long = "isolate-query", help = "Run the LSF query in a separate worker process")]
+    isolate_query: bool,
+
+    #[structopt(short = "f", long = "format", help = "Output format (json, ndjson, yaml, csv, table, nagios, influx, sensu, collectd)", default_value = "json")]
+    format: OutputFormat,
+
+    #[structopt(short = "i", long = "interval", help = "Poll continuously every given number of seconds")]
+    interval: Option<u64>,
+
+    #[structopt(long = "pid-file", help = "PID file to lock so that only a single instance runs at a time")]
+    pid_file: Option<String>,
+
+    #[structopt(long = "wait-lock", help = "Wait for the running instance to exit instead of exiting with code 123")]
+    wait_lock: bool,
+
+    #[structopt(long = "state-file", help = "File to persist the statuses of the previous poll in, overrides config")]
+    state_file: Option<String>,
+
+    #[structopt(long = "output", help = "File to atomically replace with the payload of every poll instead of printing it")]
+    output: Option<String>,
+
+    #[structopt(long = "output-keep", help = "Number of timestamped copies of previous payloads to keep next to the output file", default_value = "0")]
+    output_keep: usize,
+
+    #[structopt(long = "output-compression", help = "Compress the output file (gzip)")]
+    output_compression: Option<Compression>,
+
+    #[structopt(long = "record", help = "File to write the decoded results of the LIM queries of every poll into")]
+    record: Option<String>,
+
+    #[structopt(long = "replay", help = "File recorded with --record to use instead of querying LIM")]
+    replay: Option<String>,
+
+    #[structopt(long = "changes-only", help = "Only output records whose status changed since the previous poll")]
+    changes_only: bool,
+
+    #[structopt(long = "schema-version", help = "Shape of the printed records, 1 for the original shape without the fields added since", default_value = "2")]
+    schema_version: SchemaVersion,
+
+    #[structopt(long = "max-failed-percent", help = "Only exit with an error when more than this percentage of hosts is not OK, not counting the group, quorum and telemetry records")]
+    max_failed_percent: Option<f64>,
+
+    #[structopt(long = "only-failed", help = "Only output records that did not pass")]
+    only_failed: bool,
+
+    #[structopt(long = "only-status", help = "Only output hosts with the given LIM status flag (e.g. LIM_UNAVAIL), may be repeated")]
+    only_status: Vec<String>,
+
+    #[structopt(long = "sort-by", help = "Sort the records by name, status or a load index such as r1m, optionally followed by :asc or :desc")]
+    sort_by: Option<SortKey>,
+
+    #[structopt(long = "watch", help = "Poll every -i seconds (default 5) and only print the hosts that appeared, disappeared or changed status")]
+    watch: bool,
+
+    #[structopt(long = "color", help = "Color the statuses of the table format, unless NO_COLOR is set or stdout is not a terminal")]
+    color: bool,
+
+    #[structopt(long = "summary", help = "Print the counts and names of failing hosts instead of the records, or alongside them with --envelope")]
+    summary: bool,
+
+    #[structopt(long = "envelope", help = "Wrap the records in an object with the time, agent and poll details (json, ndjson, yaml)")]
+    envelope: bool,
+
+    #[structopt(short = "v", long = "verbose", help = "Log what the agent does on stderr, more with -vv and -vvv")]
+    verbose: u64,
+
+    #[structopt(long = "log-format", help = "Format of the log lines (text, json)", default_value = "text")]
+    log_format: LogFormat,
+
+    #[structopt(long = "log-file", help = "File to append the log lines and everything else written to stderr to")]
+    log_file: Option<String>,
+
+    #[structopt(long = "log-max-bytes", help = "Rotate the log file once it reaches the given size")]
+    log_max_bytes: Option<u64>,
+
+    #[structopt(long = "log-max-age", help = "Rotate the log file once it is older than the given hours")]
+    log_max_age_hours: Option<u64>,
+
+    #[structopt(long = "log-keep", help = "Number of rotated log files to keep", default_value = "5")]
+    log_keep: usize,
+
+    #[structopt(long = "error-format", help = "Format of errors on stderr (text, json)", default_value = "text")]
+    error_format: ErrorFormat,
+
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
 }
 
-#[repr(C)]
-pub struct hostLoad {
-    host_name: [c_char; MAXHOSTNAMELEN as usize],
-    status: *mut c_int,
-    li: *mut c_float,
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ErrorFormat {
+    Text,
+    Json,
 }
 
-mod common {
-    #[derive(Serialize, Deserialize, Clone, Debug)]
-    #[serde(rename_all = "camelCase")]
-    pub struct StorageInfo {
-        used: u64,
-        total: u64,
+impl FromStr for ErrorFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ErrorFormat> {
+        match s {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => bail!("Unknown error format {}, expected text or json", s),
+        }
     }
+}
 
-    #[derive(Serialize, Deserialize, Clone, Debug, new)]
-    #[serde(rename_all = "camelCase")]
-    pub struct StatusStorageInfo {
-        pub name: String,
-        pub status: i32,
+#[derive(StructOpt, Debug)]
+enum HistoryCommand {
+    #[structopt(name = "export", about = "Export the recorded statuses of all hosts")]
+    Export {
+        #[structopt(short = "f", long = "format", help = "Export format (csv)", default_value = "csv")]
+        format: String,
 
-        #[serde(skip_serializing_if = "Option::is_none")]
-        pub storage: Option<StorageInfo>,
+        #[structopt(long = "columns", help = "Comma separated columns to export, defaults to all")]
+        columns: Option<String>,
 
-        #[serde(skip_serializing_if = "Option::is_none")]        
-        pub critical_group_name: Option<String>,
+        #[structopt(long = "since", help = "Start of the range, as a RFC 3339 time or a duration before now", default_value = "30d")]
+        since: String,
 
-        #[serde(skip_serializing_if = "Option::is_none")]        
-        pub remarks: Option<String>,
-    }
+        #[structopt(long = "until", help = "End of the range, as a RFC 3339 time or a duration before now")]
+        until: Option<String>,
+
+        #[structopt(short = "o", long = "output", help = "File to write the export into instead of stdout")]
+        output: Option<String>,
+    },
 }
 
-use common::StatusStorageInfo;
+#[derive(StructOpt, Debug)]
+enum Command {
+    #[structopt(name = "serve", about = "Serve the latest poll results over a REST API")]
+    Serve {
+        #[structopt(long = "bind", help = "Address to listen on", default_value = "0.0.0.0:8080")]
+        bind: String,
+    },
+
+    #[structopt(name = "federate", about = "Merge the records of the remote agents of the federation config instead of polling LSF")]
+    Federate {
+        #[structopt(long = "bind", help = "Serve the merged records over the REST API on the given address instead of printing them")]
+        bind: Option<String>,
+    },
+
+    #[structopt(name = "history", about = "Print the recorded statuses of a host from the history store")]
+    History {
+        #[structopt(help = "Record name of the host")]
+        host: Option<String>,
+
+        #[structopt(long = "hours", help = "Number of hours to look back", default_value = "24")]
+        hours: u64,
+
+        #[structopt(subcommand)]
+        cmd: Option<HistoryCommand>,
+    },
+
+    #[structopt(name = "top", about = "Show an interactive dashboard of the hosts, refreshed every -i seconds (default 5)")]
+    Top,
+
+    #[structopt(name = "resources", about = "Print the LIM resource table as JSON")]
+    Resources,
+
+    #[structopt(name = "jobs", about = "Print the running, pending and suspended jobs and their slots as JSON")]
+    Jobs {
+        #[structopt(long = "by-user", help = "Summarize per user, the users with the most slots first")]
+        by_user: bool,
+
+        #[structopt(long = "top", help = "Only print the given number of users")]
+        top: Option<usize>,
+    },
+
+    #[structopt(name = "shared-resources", about = "Print the shared resources defined in the cluster with their values as JSON")]
+    SharedResources,
+
+    #[structopt(name = "schema", about = "Print the JSON Schema of the output of the given format, schema version and envelope")]
+    Schema,
+
+    #[structopt(name = "report", about = "Report the availability of hosts and groups from the history store")]
+    Report {
+        #[structopt(long = "since", help = "Period to report on, e.g. 30d, 12h", default_value = "30d")]
+        since: String,
+
+        #[structopt(short = "f", long = "format", help = "Report format (json, table, html)", default_value = "json")]
+        format: String,
+
+        #[structopt(short = "o", long = "output", help = "File to write the report into instead of stdout")]
+        output: Option<String>,
+    },
+}
 
-// LSF status flags
-const LIM_OK: i32 = 0x00000000;
-const LIM_UNAVAIL: i32 = 0x00010000;
-const LIM_LOCKEDU: i32 = 0x00020000;
-const LIM_LOCKEDW: i32 = 0x00040000;
-const LIM_BUSY: i32 = 0x00080000;
-const LIM_RESDOWN: i32 = 0x00100000;
-const LIM_UNLICENSED: i32 = 0x00200000;
-const LIM_SBDDOWN: i32 = 0x00400000;
-const LIM_LOCKEDM: i32 = 0x00800000;
-const LIM_PEMDOWN: i32 = 0x01000000;
-const LIM_EXPIRED: i32 = 0x02000000;
-const LIM_RLAUP: i32 = 0x04000000;
+fn run(main_arg_map: &MainArgMap) -> Result<i32> {
+    let rotation = logging::Rotation {
+        max_bytes: main_arg_map.log_max_bytes,
+        max_age: main_arg_map.log_max_age_hours.map(|hours| Duration::from_secs(hours * 60 * 60)),
+        keep: main_arg_map.log_keep,
+    };
 
-#[allow(overflowing_literals)]
-const LIM_LOCKEDU_RMS: i32 = 0x80000000;
-// const LIM_OK_MASK: i32 = 0x02bf0000;
-const ALL_CLUSTERS: i32 = 0x80;
+    logging::init(logging::to_level(main_arg_map.verbose), main_arg_map.log_format, main_arg_map.log_file.as_deref(), rotation)?;
 
-// status values
-const PASSED: i32 = 0;
-// const ALERT: i32 = 1;
-const FAILED: i32 = 2;
+    // depend on the arguments and LIM only
+    match main_arg_map.cmd {
+        Some(Command::Schema) => return print_schema(main_arg_map),
+        Some(Command::Resources) => return print_resources(),
+        Some(Command::SharedResources) => return print_shared_resources(),
+        Some(Command::Jobs { by_user, top }) => return print_jobs(by_user, top),
+        _ => (),
+    }
 
-// exit code
-const NORMAL: i32 = 0;
-// const INVALID_ARGS: i32 = 1;
-const ERROR: i32 = 127;
+    let _pid_file = match main_arg_map.pid_file {
+        Some(ref pid_file) => match PidFile::acquire(pid_file, main_arg_map.wait_lock)? {
+            Some(pid_file) => Some(pid_file),
+            None => {
+                error!(pid_file = pid_file.as_str(), "Another instance holds the lock on the PID file");
+                return Ok(ALREADY_RUNNING);
+            },
+        },
+
+        None => None,
+    };
+
+    let config_paths: Vec<&str> = main_arg_map.config_paths.iter().map(String::as_str).collect();
+    let mut config = Config::from_files(&config_paths)?;
+    info!(paths = ?config_paths, "Loaded config");
+
+    if main_arg_map.from_host.is_some() {
+        config.from_host = main_arg_map.from_host.clone();
+    }
+
+    if !main_arg_map.load_options.is_empty() {
+        config.load_options = Some(main_arg_map.load_options.clone());
+    }
+
+    if main_arg_map.query_timeout_secs.is_some() {
+        config.query_timeout_secs = main_arg_map.query_timeout_secs;
+    }
+
+    if main_arg_map.isolate_query {
+        config.isolate_query = Some(true);
+    }
+
+    match main_arg_map.cmd {
+        Some(Command::History { cmd: Some(HistoryCommand::Export { ref format, ref columns, ref since, ref until, ref output }), .. }) =>
+            return export_history(&config, format, columns.as_deref(), since, until.as_deref(), output.as_deref()),
+
+        Some(Command::History { host: Some(ref host), hours, .. }) => return print_history(&config, host, hours),
+        Some(Command::History { host: None, .. }) => bail!("Either a host or the export subcommand is required"),
+
+        Some(Command::Report { ref since, ref format, ref output }) =>
+            return print_report(&config, since, format, output.as_deref()),
+        _ => (),
+    }
+
+    if main_arg_map.envelope && !main_arg_map.format.supports_envelope() {
+        bail!("--envelope is only supported with the json, ndjson and yaml formats");
+    }
+
+    if main_arg_map.summary && !main_arg_map.format.supports_envelope() {
+        bail!("--summary is only supported with the json, ndjson and yaml formats");
+    }
+
+    let budget = config.budget.clone().unwrap_or_default();
+
+    let mut settings = config.query_settings()?;
+    settings.record = main_arg_map.record.clone();
+
+    if let Some(ref replay) = main_arg_map.replay {
+        settings.replay = Some(poll::load_recording(replay)?);
+    }
+
+    debug!(options = settings.options, from_host = ?settings.from_host, indices = ?settings.indices,
+        timeout = ?settings.timeout, isolate = settings.isolate, replay = settings.replay.is_some(), "Resolved query settings");
+
+    let flap = config.flap.clone().unwrap_or_default();
+
+    let downtimes = config.downtimes()?;
+
+    // interactive modes only poll and show the hosts
+    if main_arg_map.watch || matches!(main_arg_map.cmd, Some(Command::Top)) {
+        let interval = Duration::from_secs(main_arg_map.interval.unwrap_or(DEFAULT_TOP_INTERVAL_SECS));
+        let mut state = State::default();
+
+        let poll = || {
+            let mut records = lsf_agent::poll_hosts_with(&config, &settings)?;
+            lsf_agent::evaluate(&config, &mut records, &state, &downtimes)?;
+            state.observe(&mut records, &flap, Utc::now().timestamp());
+            Ok(records)
+        };
+
+        match main_arg_map.cmd {
+            Some(Command::Top) => top::run(interval, poll)?,
+            _ => watch::run(interval, output::color_supported(), poll)?,
+        }
+
+        return Ok(NORMAL);
+    }
+
+    let encryptor = config.encryptor()?;
+    let output_file = main_arg_map.output.as_ref().map(|output| OutputFile::new(output, main_arg_map.output_keep, main_arg_map.output_compression, encryptor));
+    let guard = budget::RuntimeGuard::spawn(BUDGET_EXCEEDED, output_file, budget.max_memory_bytes);
+    let mut notifier = systemd::Notifier::from_env();
+
+    let sinks = config.sinks()?;
+
+    let federation = match main_arg_map.cmd {
+        Some(Command::Federate { .. }) => match config.federation {
+            Some(ref federation) => Some(Federation::new(federation)?),
+            None => bail!("federate requires a federation config"),
+        },
+
+        _ => None,
+    };
+
+    let bind = match main_arg_map.cmd {
+        Some(Command::Serve { ref bind }) | Some(Command::Federate { bind: Some(ref bind) }) => Some(bind),
+        _ => None,
+    };
+
+    let server_state = match bind {
+        Some(bind) => {
+            let server_config = config.server.clone().unwrap_or_default();
+            let interval = main_arg_map.interval.unwrap_or(DEFAULT_SERVE_INTERVAL_SECS);
+            let server_state = ServerState::new(&server_config, Duration::from_secs(interval));
+            server::spawn(bind, &server_config, server_state.clone())?;
+
+            if let Some(ref nrpe) = server_config.nrpe {
+                nrpe::spawn(nrpe, &config.prefix, server_state.clone())?;
+            }
+
+            Some(server_state)
+        },
+
+        _ => None,
+    };
+
+    // the server keeps polling, so that the results it serves stay fresh, as
+    // does the agent under the collectd exec plugin, at the interval of collectd
+    let interval = main_arg_map.interval
+        .or_else(|| server_state.as_ref().map(|_| DEFAULT_SERVE_INTERVAL_SECS))
+        .or_else(|| if main_arg_map.format == OutputFormat::Collectd { output::collectd_interval() } else { None });
+
+    let consul = match (bind, config.server.as_ref().and_then(|server| server.consul.as_ref())) {
+        (Some(bind), Some(consul)) => {
+            let port = bind.rsplit(':').next().and_then(|port| port.parse().ok());
+            Some(Consul::spawn(consul, port, 3 * interval.unwrap_or(DEFAULT_SERVE_INTERVAL_SECS))?)
+        },
+
+        _ => None,
+    };
+
+    let state_file = main_arg_map.state_file.as_ref().or(config.state_file.as_ref());
+
+    let mut state = match state_file {
+        Some(state_file) => State::load(state_file)?,
+        None => State::default(),
+    };
+
+    let dispatcher = sinks::Dispatcher::spawn(sinks, config.sink_queue_size.unwrap_or(DEFAULT_SINK_QUEUE_SIZE));
+
+    let color = main_arg_map.color && output::color_supported();
+    let signer = config.signer()?;
+    let hooks = config.hooks()?;
+    let notifications = notify::Notifications::spawn(config.notifiers()?);
+
+    let only_status = main_arg_map.only_status.iter()
+        .map(|name| lsf::to_status_flag(name).ok_or_else(|| Error::from(format!("Unknown LIM status flag {}", name))))
+        .collect::<Result<Vec<_>>>()?;
+
+    // the local cluster and LSF version do not change while running
+    let cluster_labels = config.cluster_labels.unwrap_or(false);
+
+    let (cluster_name, lsf_version) = if main_arg_map.envelope || cluster_labels {
+        (lsf::cluster_name(), lsf::version())
+    } else {
+        (None, None)
+    };
+
+    let envelope = |poll_duration: Duration, host_count: usize| if main_arg_map.envelope {
+        Some(output::Envelope {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            agent_host: local_hostname(),
+            agent_version: env!("CARGO_PKG_VERSION").to_owned(),
+            cluster_name: cluster_name.clone(),
+            lsf_version: lsf_version.clone(),
+            poll_duration_ms: poll_duration.as_millis() as u64,
+            host_count,
+        })
+    } else {
+        None
+    };
+
+    loop {
+        logging::rotate_if_due();
+
+        if server_state.is_none() && (budget.max_runtime_secs.is_some() || budget.max_memory_bytes.is_some()) {
+            let max_runtime = budget.max_runtime_secs.map(Duration::from_secs);
+
+            let render_fallback = |remarks: String| {
+                let fallback = vec![StatusStorageInfo::new(
+                    format!("{}*", config.prefix),
+                    FAILED,
+                    None,
+                    Some(config.critical_group_name.clone()),
+                    Some(remarks))];
+
+                let fallback_envelope = envelope(max_runtime.unwrap_or_default(), 0);
+                let fallback_summary = if main_arg_map.summary { Some(output::summarize(&fallback)) } else { None };
+
+                let fallback_options = output::RenderOptions {
+                    envelope: fallback_envelope.as_ref(),
+                    summary: fallback_summary.as_ref(),
+                    color,
+                    signer: signer.as_ref(),
+                    interval_secs: interval,
+                };
+
+                output::render_with(main_arg_map.format, &fallback, &fallback_options)
+            };
+
+            let fallback_payloads = budget::FallbackPayloads {
+                runtime: render_fallback(format!("Agent exceeded runtime budget of {}s", budget.max_runtime_secs.unwrap_or(0)))?,
+                memory: render_fallback(format!("Agent exceeded memory budget of {} bytes", budget.max_memory_bytes.unwrap_or(0)))?,
+            };
+
+            guard.arm(max_runtime, fallback_payloads);
+        }
+
+        let poll_started = Instant::now();
+
+        // the remote agents have evaluated their records already
+        let (mut status_storage_infos, reachable, poll_duration) = match federation {
+            Some(ref federation) => {
+                let poll = federation.poll(&config.critical_group_name);
+                (poll.records, poll.reached > 0, poll_started.elapsed())
+            },
+
+            None => {
+                let mut records = lsf_agent::poll_hosts_with(&config, &settings)?;
+                let poll_duration = poll_started.elapsed();
+
+                lsf_agent::evaluate(&config, &mut records, &state, &downtimes)?;
+
+                if cluster_labels {
+                    labels::apply_cluster(&mut records, cluster_name.as_deref(), lsf_version.as_deref());
+                }
+
+                let reachable = !telemetry::poll_failed(&records, &config.prefix);
+                (records, reachable, poll_duration)
+            },
+        };
+
+        if let Some(ref telemetry_config) = config.telemetry {
+            state.failed_polls = if telemetry::poll_failed(&status_storage_infos, &config.prefix) { state.failed_polls + 1 } else { 0 };
+
+            let stats = telemetry::AgentStats {
+                poll_duration,
+                failed_polls: state.failed_polls,
+                sink_errors: dispatcher.error_count(),
+                sink_drops: dispatcher.drop_count(),
+                memory_bytes: telemetry::resident_bytes(),
+            };
+
+            status_storage_infos.push(telemetry::to_record(telemetry_config, &stats, &config.prefix, &config.critical_group_name));
+        }
+
+        // without a state file, the first poll counts every host as changed
+        let previous_state = state.clone();
+        state.observe(&mut status_storage_infos, &flap, Utc::now().timestamp());
+
+        let hook_handles = hooks::fire(&hooks, &previous_state, &status_storage_infos);
+        if let Some(ref notifications_config) = config.notifications {
+            notifications.notify(notify::events(notifications_config, &previous_state, &mut state,
+                &status_storage_infos, Utc::now().timestamp()));
+        }
+
+        // the records of groups, quorum and the agent would skew the share of hosts
+        let host_records: Vec<&StatusStorageInfo> = status_storage_infos.iter()
+            .filter(|record| !record.synthetic)
+            .collect();
+
+        let healthy = match main_arg_map.max_failed_percent {
+            Some(max_failed_percent) if !host_records.is_empty() => {
+                let not_ok_count = host_records.iter().filter(|record| !ack::is_ok(record)).count();
+                not_ok_count as f64 * 100.0 / host_records.len() as f64 <= max_failed_percent
+            },
+
+            _ => status_storage_infos.iter().all(ack::is_ok),
+        };
+
+        let exit_code = match (main_arg_map.format, healthy) {
+            (OutputFormat::Nagios, _) => output::nagios_exit_code(&status_storage_infos),
+            (_, true) => NORMAL,
+            _ => ERROR,
+        };
+
+        let truncation_marker = StatusStorageInfo::new(
+            format!("{}*", config.prefix),
+            FAILED,
+            None,
+            Some(config.critical_group_name.clone()),
+            Some("Payload truncated to fit within payload budget".to_owned()));
+
+        let output_records = if main_arg_map.changes_only {
+            previous_state.changed(&status_storage_infos)
+        } else {
+            status_storage_infos.clone()
+        };
+
+        let output_records: Vec<StatusStorageInfo> = output_records.into_iter()
+            .filter(|record| !main_arg_map.only_failed || record.status != PASSED)
+            .filter(|record| only_status.is_empty() || only_status.iter().any(|&flag| match record.lim_status {
+                Some(lim_status) if flag == lsf::LIM_OK => lim_status == lsf::LIM_OK,
+                Some(lim_status) => lim_status & flag != 0,
+                None => false,
+            }))
+            .collect();
+
+        let output_records = output::arrange(main_arg_map.schema_version, main_arg_map.sort_by.as_ref(), output_records);
+
+        let poll_envelope = envelope(poll_duration, status_storage_infos.len());
 
-fn to_status_str(status: i32) -> &'static str {
-    match status {
-        LIM_OK => "LIM_OK",
-        LIM_UNAVAIL => "LIM_UNAVAIL",
-        LIM_LOCKEDU => "LIM_LOCKEDU",
-        LIM_LOCKEDW => "LIM_LOCKEDW",
-        LIM_BUSY => "LIM_BUSY",
-        LIM_RESDOWN => "LIM_RESDOWN",
-        LIM_UNLICENSED => "LIM_UNLICENSED",
-        LIM_SBDDOWN => "LIM_SBDDOWN",
-        LIM_LOCKEDM => "LIM_LOCKEDM",
-        LIM_PEMDOWN => "LIM_PEMDOWN",
-        LIM_EXPIRED => "LIM_EXPIRED",
-        LIM_RLAUP => "LIM_RLAUP",
-        LIM_LOCKEDU_RMS => "LIM_LOCKEDU_RMS",
-        _ => "UNKNOWN",
+        // covers all hosts, even with --changes-only
+        let summary = if main_arg_map.summary { Some(output::summarize(&status_storage_infos)) } else { None };
+
+        let options = output::RenderOptions {
+            envelope: poll_envelope.as_ref(),
+            summary: summary.as_ref(),
+            color,
+            signer: signer.as_ref(),
+            interval_secs: interval,
+        };
+
+        // status_storage_infos
+        let (status_storage_infos_str, truncated) =
+            budget::fit_payload(&budget, &output_records, &truncation_marker,
+                |records| output::render_with(main_arg_map.format, records, &options))?;
+
+        match server_state {
+            Some(ref server_state) => {
+                server_state.update(&status_storage_infos, reachable);
+
+                if let Some(ref consul) = consul {
+                    consul.update(&status_storage_infos);
+                }
+            },
+            None => guard.emit(&status_storage_infos_str)?,
+        }
+
+        // only persisted once emitted, so that changes are not lost on failure
+        if let Some(state_file) = state_file {
+            state.save(state_file)?;
+        }
+
+        dispatcher.dispatch(&status_storage_infos);
+
+        let failed_count = status_storage_infos.iter()
+            .filter(|status_storage_info| status_storage_info.status != PASSED)
+            .count();
+
+        notifier.poll_done(&format!("{} hosts, {} not passed", status_storage_infos.len(), failed_count));
+
+        match interval {
+            Some(interval) => notifier.sleep(Duration::from_secs(interval)),
+            None => {
+                for handle in hook_handles {
+                    let _ = handle.join();
+                }
+
+                dispatcher.finish();
+                notifications.finish();
+                return Ok(if truncated { BUDGET_EXCEEDED } else { exit_code });
+            },
+        }
     }
 }
 
-mod errors {
-    error_chain! {}
+fn print_schema(main_arg_map: &MainArgMap) -> Result<i32> {
+    let schema = schema::output_schema(
+        main_arg_map.format, main_arg_map.schema_version, main_arg_map.envelope, main_arg_map.summary)?;
+
+    let schema_str = serde_json::to_string_pretty(&schema)
+        .chain_err(|| "Unable to serialize schema into string!")?;
+
+    println!("{}", schema_str);
+    Ok(NORMAL)
 }
 
-use errors::*;
+fn print_resources() -> Result<i32> {
+    let resources = lsf::resources()?;
+
+    let resources_str = serde_json::to_string_pretty(&resources)
+        .chain_err(|| "Unable to serialize resources into string!")?;
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct Config {
-    prefix: String,
-    name_mapping: HashMap<String, String>,
-    critical_group_name: String,
+    println!("{}", resources_str);
+    Ok(NORMAL)
 }
 
+fn print_jobs(by_user: bool, top: Option<usize>) -> Result<i32> {
+    let all_jobs = lsf::jobs(lsf::CUR_JOB)?;
 
-#[derive(StructOpt, Debug)]
-#[structopt(name = "LSF Agent", about = "Simple LSF program to poll for LSF host status.")]
-struct MainArgMap {
-    #[structopt(short = "c", long = "config", help = "Configuration file path")]
-    config_path: String,
+    let jobs_str = if by_user {
+        serde_json::to_string_pretty(&jobs::by_user(&all_jobs, top))
+    } else {
+        serde_json::to_string_pretty(&jobs::total(&all_jobs))
+    }.chain_err(|| "Unable to serialize job summary into string!")?;
+
+    println!("{}", jobs_str);
+    Ok(NORMAL)
 }
 
-fn run() -> Result<i32> {
-    let main_arg_map = MainArgMap::from_args();
+fn print_shared_resources() -> Result<i32> {
+    let resources = lsf::shared_resources(&[])?;
 
-    let config_content = {
-        let mut config_file = File::open(&main_arg_map.config_path)
-            .chain_err(|| format!("Unable to open config file at {}", main_arg_map.config_path))?;
+    let resources_str = serde_json::to_string_pretty(&resources)
+        .chain_err(|| "Unable to serialize shared resources into string!")?;
 
-        let mut buf = String::new();
-        let _ = config_file.read_to_string(&mut buf)
-            .chain_err(|| "Unable to read config file into string")?;
+    println!("{}", resources_str);
+    Ok(NORMAL)
+}
 
-        buf
-    };
+fn open_history(config: &Config) -> Result<HistoryStore> {
+    match config.history {
+        Some(ref history) => HistoryStore::open(history.clone()),
+        None => bail!("No history store is configured"),
+    }
+}
 
-    let config: Config = serde_json::from_str(&config_content)
-        .chain_err(|| "Unable to parse config content into structure!")?;
-
-    let mut numhosts: c_int = 0;
-    let host_load_vals = unsafe { ls_load(ptr::null_mut(), &mut numhosts, ALL_CLUSTERS, ptr::null_mut()) };
-    let host_load_vals = unsafe { slice::from_raw_parts(host_load_vals, numhosts as usize) };
-
-    let numhosts = numhosts;
-
-    let status_storage_infos =
-        if numhosts > 0 {
-            host_load_vals.into_iter()
-                .map(|host_load| {
-                    let status = unsafe { *host_load.status };
-                    let status_str = to_status_str(status);
-
-                    let host_name_raw = unsafe { CStr::from_ptr(host_load.host_name.as_ptr()) };
-                    let host_name = host_name_raw.to_str();
-
-                    let conv_status = if status == LIM_OK { PASSED } else { FAILED };
-                    let critical_group_name = config.critical_group_name.clone();
-
-                    // very unlikely to be unable to interpret cstr as str here
-                    match host_name {
-                        Ok(host_name) => {
-                            let mapped_host_name = match config.name_mapping.get(host_name) {
-                                Some(mapped_host_name) => mapped_host_name,
-                                None => host_name,
-                            };
-
-                            StatusStorageInfo::new(
-                                format!("{}{}", config.prefix, mapped_host_name),
-                                conv_status,
-                                None,
-                                Some(critical_group_name),
-                                Some(format!("Status code: {} ({})", status, status_str)))
-                        },
-
-                        Err(_) => StatusStorageInfo::new(
-                            format!("{}{:?}", config.prefix, host_name_raw),
-                            conv_status,
-                            None,
-                            Some(critical_group_name),
-                            Some(format!("Status code: {} ({})", status, status_str))),
-                    }
-                })
-                .collect()
-        } else {
-            vec![StatusStorageInfo::new(
-                format!("{}*", config.prefix),
-                FAILED,
-                None,
-                Some(config.critical_group_name.clone()),
-                Some("Unable to connect any of the LSF nodes".to_owned()))]
-        };
+fn print_history(config: &Config, host: &str, hours: u64) -> Result<i32> {
+    let history = open_history(config)?;
 
-    let all_passed = status_storage_infos.iter()
-        .all(|status_storage_info| status_storage_info.status == PASSED);
+    let since = Utc::now().timestamp() - (hours * 60 * 60) as i64;
+    let entries = history.query(host, since)?;
 
-    let exit_code = match all_passed {
-        true => NORMAL,
-        _ => ERROR,
-    };
+    let entries_str = serde_json::to_string(&entries)
+        .chain_err(|| "Unable to serialize history entries into string!")?;
+
+    println!("{}", entries_str);
+    Ok(NORMAL)
+}
+
+fn print_report(config: &Config, since: &str, format: &str, output: Option<&str>) -> Result<i32> {
+    let history = open_history(config)?;
 
-    // status_storage_infos
-    let status_storage_infos_str = serde_json::to_string(&status_storage_infos)
-        .chain_err(|| "Unable to serialize list of status storage into string!")?;
+    let until = Utc::now().timestamp();
+    let since = until - report::parse_duration(since)?;
 
-    println!("{}", status_storage_infos_str);
+    let entries = history.query_all(since, until)?;
+    let report = report::build(&entries, since, until);
+    let report_str = report::render(&report, &entries, format)?;
 
-    Ok(exit_code)
+    write_output(output, &report_str)?;
+    Ok(NORMAL)
+}
+
+fn export_history(
+    config: &Config,
+    format: &str,
+    columns: Option<&str>,
+    since: &str,
+    until: Option<&str>,
+    output: Option<&str>) -> Result<i32> {
+
+    if format != "csv" {
+        bail!("Unknown export format {}, expected csv", format);
+    }
+
+    let history = open_history(config)?;
+
+    let now = Utc::now().timestamp();
+    let since = report::parse_time(since, now)?;
+    let until = until.map(|until| report::parse_time(until, now)).unwrap_or(Ok(now))?;
+
+    let columns: Vec<String> = columns
+        .map(|columns| columns.split(',').map(|column| column.trim().to_owned()).filter(|c| !c.is_empty()).collect())
+        .unwrap_or_default();
+
+    let csv = history::to_csv(&history.query_all(since, until)?, &columns);
+
+    write_output(output, &csv)?;
+    Ok(NORMAL)
+}
+
+/// Writes into the file if given, otherwise prints to stdout.
+fn write_output(output: Option<&str>, content: &str) -> Result<()> {
+    match output {
+        Some(output) => fs::write(output, content)
+            .chain_err(|| format!("Unable to write into {}", output)),
+
+        None => {
+            println!("{}", content);
+            Ok(())
+        },
+    }
+}
+
+/// Prints the error as a single line JSON object on stderr, for log pipelines.
+fn print_json_error(e: &Error, exit_code: i32) {
+    let error = json!({
+        "code": e.code(),
+        "message": e.to_string(),
+        "causes": e.iter().skip(1).map(|e| e.to_string()).collect::<Vec<_>>(),
+        "exitCode": exit_code,
+    });
+
+    writeln!(&mut io::stderr(), "{}", error)
+        .expect("Unable to write error into stderr!");
 }
 
 fn main() {
-    match run() {
+    if env::var_os(worker::WORKER_ENV).is_some() {
+        process::exit(worker::run_worker());
+    }
+
+    let main_arg_map = MainArgMap::from_args();
+
+    match run(&main_arg_map) {
         Ok(exit_code) => process::exit(exit_code),
+
+        Err(ref e) if main_arg_map.format == OutputFormat::Nagios => {
+            if main_arg_map.error_format == ErrorFormat::Json {
+                print_json_error(e, output::NAGIOS_UNKNOWN);
+            }
+
+            println!("LSF UNKNOWN - {}", e);
+            process::exit(output::NAGIOS_UNKNOWN);
+        },
+
+        Err(ref e) if main_arg_map.error_format == ErrorFormat::Json => {
+            print_json_error(e, 1);
+            process::exit(1);
+        },
+
         Err(ref e) => {
             let stderr = &mut io::stderr();
 
@@ -0,0 +1,44 @@
+use common::StatusStorageInfo;
+use logging;
+use lsf::{self, RUN_QUEUE_INDEX_NAMES};
+use std::collections::HashMap;
+use Config;
+
+/// Adds the run queue lengths per core (`r1m_per_core`) and normalized by
+/// CPU factor as well (`r1m_normalized`) to the load indices of the hosts, so
+/// that thresholds work across hosts of differing sizes.
+pub fn apply(records: &mut [StatusStorageInfo], config: &Config) {
+    let host_infos = match lsf::host_infos() {
+        Ok(host_infos) => host_infos,
+        Err(e) => {
+            warn!(error = %logging::chain(&e), "Unable to query host information, load is not normalized");
+            return;
+        },
+    };
+
+    let by_name: HashMap<String, &lsf::HostInfo> = host_infos.iter()
+        .map(|host_info| (config.record_name(&host_info.host_name), host_info))
+        .collect();
+
+    for record in records.iter_mut() {
+        let host_info = match by_name.get(&record.name) {
+            Some(host_info) if host_info.max_cpus > 0 => host_info,
+            _ => continue,
+        };
+
+        let load_indices = match record.load_indices {
+            Some(ref mut load_indices) => load_indices,
+            None => continue,
+        };
+
+        let cores = host_info.max_cpus as f32;
+        let cpu_factor = if host_info.cpu_factor > 0.0 { host_info.cpu_factor } else { 1.0 };
+
+        for index in RUN_QUEUE_INDEX_NAMES.iter() {
+            if let Some(&value) = load_indices.get(*index) {
+                load_indices.insert(format!("{}_per_core", index), value / cores);
+                load_indices.insert(format!("{}_normalized", index), value / (cores * cpu_factor));
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use chrono::Utc;
+use errors::*;
+use notify::{Event, EventKind, NotificationsConfig, Notifier};
+use notify::smtp::{self, Mail, Security, Server};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::Read;
+use std::time::{Duration, Instant};
+use template;
+
+fn default_port() -> u16 {
+    587
+}
+
+fn default_security() -> Security {
+    Security::Starttls
+}
+
+fn default_subject_template() -> String {
+    "[lsf_agent] {failed} failing, {cleared} recovered".to_owned()
+}
+
+fn default_body_template() -> String {
+    "{events}".to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+// window of `maxPerHour`
+const RATE_WINDOW: Duration = Duration::from_secs(3600);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailConfig {
+    pub host: String,
+
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    /// `starttls`, `tls` or `none`
+    #[serde(default = "default_security")]
+    pub security: Security,
+
+    /// CA bundle the server certificate must chain to, the Mozilla root
+    /// certificates when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_path: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    /// File containing the password, so it does not need to live in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<String>,
+
+    pub from: String,
+
+    /// Recipients of the events of groups without recipients of their own
+    #[serde(default)]
+    pub to: Vec<String>,
+
+    /// Recipients by critical group name
+    #[serde(default)]
+    pub group_to: BTreeMap<String, Vec<String>>,
+
+    /// With the `{failed}`, `{cleared}` and `{names}` placeholders
+    #[serde(default = "default_subject_template")]
+    pub subject_template: String,
+
+    /// With the `{events}` placeholder for the lines of the events, and the
+    /// placeholders of the subject
+    #[serde(default = "default_body_template")]
+    pub body_template: String,
+
+    /// Mails sent at most within any hour, suppressing the rest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_per_hour: Option<usize>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Mails the events of a poll, in a mail for every distinct list of
+/// recipients.
+pub struct EmailNotifier {
+    config: EmailConfig,
+    notifications: NotificationsConfig,
+    password: Option<String>,
+    sent: VecDeque<Instant>,
+    suppressed: usize,
+}
+
+impl EmailNotifier {
+    pub fn new(config: EmailConfig, notifications: NotificationsConfig) -> Result<EmailNotifier> {
+        let password = match (&config.password, &config.password_file) {
+            (Some(password), _) => Some(password.clone()),
+
+            (None, Some(password_file)) => {
+                let mut password = String::new();
+
+                File::open(password_file)
+                    .and_then(|mut f| f.read_to_string(&mut password))
+                    .chain_err(|| format!("Unable to read SMTP password file at {}", password_file))?;
+
+                Some(password.trim().to_owned())
+            },
+
+            (None, None) => None,
+        };
+
+        if config.username.is_some() != password.is_some() {
+            bail!("SMTP authentication requires both a username and a password");
+        }
+
+        Ok(EmailNotifier { config, notifications, password, sent: VecDeque::new(), suppressed: 0 })
+    }
+
+    fn server(&self) -> Server<'_> {
+        Server {
+            host: &self.config.host,
+            port: self.config.port,
+            security: self.config.security,
+            credentials: self.config.username.as_deref().zip(self.password.as_deref()),
+            ca_path: self.config.ca_path.as_deref(),
+            timeout: Duration::from_secs(self.config.timeout_secs),
+        }
+    }
+
+    fn recipients(&self, event: &Event) -> &[String] {
+        event.record.critical_group_name.as_ref()
+            .and_then(|group| self.config.group_to.get(group))
+            .unwrap_or(&self.config.to)
+    }
+
+    /// Whether another mail is allowed within the hour, counting it if so.
+    fn take_rate(&mut self) -> bool {
+        let max_per_hour = match self.config.max_per_hour {
+            Some(max_per_hour) => max_per_hour,
+            None => return true,
+        };
+
+        while self.sent.front().is_some_and(|sent| sent.elapsed() >= RATE_WINDOW) {
+            self.sent.pop_front();
+        }
+
+        if self.sent.len() >= max_per_hour {
+            return false;
+        }
+
+        self.sent.push_back(Instant::now());
+        true
+    }
+
+    fn render(&self, template: &str, events: &[&Event], lines: &str) -> String {
+        let count = |kind| events.iter().filter(|event| event.kind == kind).count();
+
+        template::render(template, |placeholder| match placeholder {
+            "failed" => Some(events.iter().filter(|event| event.kind.is_failing()).count().to_string()),
+            "cleared" => Some(count(EventKind::Cleared).to_string()),
+            "names" => Some(events.iter().map(|event| event.record.name.as_str()).collect::<Vec<_>>().join(", ")),
+            "events" => Some(lines.to_owned()),
+            _ => None,
+        })
+    }
+}
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn groups(&self) -> Option<&[String]> {
+        None
+    }
+
+    fn notify(&mut self, events: &[Event]) -> Result<()> {
+        let mut by_recipients: BTreeMap<&[String], Vec<&Event>> = BTreeMap::new();
+
+        for event in events {
+            let recipients = self.recipients(event);
+
+            if !recipients.is_empty() {
+                by_recipients.entry(recipients).or_default().push(event);
+            }
+        }
+
+        let mut mails = Vec::new();
+
+        for (recipients, events) in by_recipients {
+            let lines: Vec<String> = events.iter().map(|event| event.render(&self.notifications)).collect();
+
+            let subject = self.render(&self.config.subject_template, &events, "");
+            let mut body = self.render(&self.config.body_template, &events, &lines.join("\n"));
+
+            if self.suppressed > 0 {
+                body = format!("{}\n\n{} earlier mail(s) were suppressed by the rate limit.", body, self.suppressed);
+            }
+
+            mails.push((recipients.to_vec(), subject, body));
+        }
+
+        for (recipients, subject, body) in mails {
+            if !self.take_rate() {
+                self.suppressed += 1;
+                warn!(recipients = %recipients.join(", "), max_per_hour = self.config.max_per_hour.unwrap_or_default(),
+                    "Suppressing mail by the rate limit");
+                continue;
+            }
+
+            let mail = Mail { from: &self.config.from, to: &recipients, subject: &subject, body: &body };
+            smtp::send(&self.server(), &mail, &Utc::now().to_rfc2822())?;
+            self.suppressed = 0;
+        }
+
+        Ok(())
+    }
+}
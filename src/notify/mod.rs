@@ -0,0 +1,355 @@
+use ack;
+use common::StatusStorageInfo;
+use errors::*;
+use logging;
+use output;
+use state::{OpenAlert, State};
+use std::collections::btree_map::Entry;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use template;
+use {FAILED, PASSED};
+
+pub mod email;
+use self::email::{EmailConfig, EmailNotifier};
+
+mod smtp;
+
+pub mod snmp;
+use self::snmp::{SnmpConfig, SnmpNotifier};
+
+pub mod webhook;
+use self::webhook::{WebhookConfig, WebhookNotifier};
+
+fn default_failed_template() -> String {
+    "{name} is {status}: {remarks}".to_owned()
+}
+
+fn default_cleared_template() -> String {
+    "{name} has recovered: {remarks}".to_owned()
+}
+
+fn default_escalated_template() -> String {
+    "{name} is still {status}, escalating: {remarks}".to_owned()
+}
+
+/// Notifiers that additionally receive the events of hosts that have been
+/// FAILED for this long.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EscalationConfig {
+    pub after_secs: u64,
+
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+
+    #[serde(default)]
+    pub email: Vec<EmailConfig>,
+
+    #[serde(default)]
+    pub snmp: Vec<SnmpConfig>,
+}
+
+/// Messages sent when hosts start failing or recover.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationsConfig {
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+
+    #[serde(default)]
+    pub email: Vec<EmailConfig>,
+
+    #[serde(default)]
+    pub snmp: Vec<SnmpConfig>,
+
+    /// Line of the message for every host that started failing, with the
+    /// `{name}`, `{status}`, `{previousStatus}`, `{group}` and `{remarks}`
+    /// placeholders
+    #[serde(default = "default_failed_template")]
+    pub failed_template: String,
+
+    /// Line of the message for every host that recovered
+    #[serde(default = "default_cleared_template")]
+    pub cleared_template: String,
+
+    /// Line of the message for every host escalated to the notifiers of an
+    /// escalation
+    #[serde(default = "default_escalated_template")]
+    pub escalated_template: String,
+
+    /// Notifies hosts that are still failing again once this long has passed
+    /// since they were last notified, never when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renotify_interval_secs: Option<u64>,
+
+    /// In ascending order of `afterSecs`
+    #[serde(default)]
+    pub escalations: Vec<EscalationConfig>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    Failed,
+
+    /// Host is still failing since it was last notified
+    Reminder,
+
+    /// Host has been FAILED long enough for the next escalation
+    Escalated,
+
+    Cleared,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            EventKind::Failed => "failed",
+            EventKind::Reminder => "reminder",
+            EventKind::Escalated => "escalated",
+            EventKind::Cleared => "cleared",
+        }
+    }
+
+    pub fn is_failing(&self) -> bool {
+        *self != EventKind::Cleared
+    }
+}
+
+/// Change of a host between failing and not failing, or a reminder that it
+/// is still failing.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub kind: EventKind,
+
+    /// Status of the previous poll, none for hosts not seen before
+    pub previous_status: Option<i32>,
+
+    pub record: StatusStorageInfo,
+
+    /// Escalations reached by the alert of the host, or the one reached by
+    /// escalated events
+    pub escalation: usize,
+}
+
+impl Event {
+    /// Renders the line of the event from the template of its kind.
+    pub fn render(&self, config: &NotificationsConfig) -> String {
+        let template = match self.kind {
+            EventKind::Failed | EventKind::Reminder => &config.failed_template,
+            EventKind::Escalated => &config.escalated_template,
+            EventKind::Cleared => &config.cleared_template,
+        };
+
+        template::render(template, |placeholder| match placeholder {
+            "name" => Some(self.record.name.clone()),
+            "status" => Some(output::to_check_status_str(self.record.status).to_owned()),
+            "previousStatus" => Some(self.previous_status.map_or("UNKNOWN", output::to_check_status_str).to_owned()),
+            "group" => Some(self.record.critical_group_name.clone().unwrap_or_default()),
+            "remarks" => Some(self.record.remarks.clone().unwrap_or_default()),
+            _ => None,
+        })
+    }
+}
+
+/// Failing hosts without an open alert, opening one, failing hosts whose
+/// alert is due for a reminder or the next escalation, and passing hosts with
+/// an open alert, closing it. Acknowledged failures do not count as failing.
+/// Escalations count the time since the host became FAILED in the current
+/// state.
+pub fn events(config: &NotificationsConfig, previous: &State, state: &mut State,
+    records: &[StatusStorageInfo], now: i64) -> Vec<Event> {
+
+    let mut events = Vec::new();
+
+    for record in records {
+        let previous_status = previous.hosts.get(&record.name).map(|host_state| host_state.status);
+        let event = |kind, escalation| Event { kind, previous_status, record: record.clone(), escalation };
+
+        if ack::is_ok(record) {
+            if record.status == PASSED {
+                if let Some(alert) = state.alerts.remove(&record.name) {
+                    events.push(event(EventKind::Cleared, alert.escalation));
+                }
+            }
+
+            continue;
+        }
+
+        let failed_secs = state.hosts.get(&record.name)
+            .filter(|host_state| host_state.status == FAILED)
+            .map(|host_state| now - host_state.since);
+
+        let reached = config.escalations.iter()
+            .take_while(|escalation| failed_secs.is_some_and(|secs| secs >= escalation.after_secs as i64))
+            .count();
+
+        let alert = match state.alerts.entry(record.name.clone()) {
+            Entry::Vacant(entry) => {
+                events.push(event(EventKind::Failed, 0));
+                entry.insert(OpenAlert { opened_at: now, notified_at: now, escalation: 0 })
+            },
+
+            Entry::Occupied(entry) => {
+                let alert = entry.into_mut();
+
+                if config.renotify_interval_secs.is_some_and(|secs| now - alert.notified_at >= secs as i64) {
+                    alert.notified_at = now;
+                    events.push(event(EventKind::Reminder, alert.escalation));
+                }
+
+                alert
+            },
+        };
+
+        while alert.escalation < reached {
+            alert.escalation += 1;
+            events.push(event(EventKind::Escalated, alert.escalation));
+        }
+    }
+
+    events
+}
+
+/// Destination of the notifications.
+pub trait Notifier: Send {
+    fn name(&self) -> &str;
+
+    /// Critical groups whose events the notifier receives, all when none
+    fn groups(&self) -> Option<&[String]>;
+
+    /// Escalation the notifier belongs to, starting from 1, or 0 for the
+    /// notifiers notified right away
+    fn escalation(&self) -> usize {
+        0
+    }
+
+    fn notify(&mut self, events: &[Event]) -> Result<()>;
+}
+
+/// Whether the notifier receives the event. Escalated events only go to the
+/// notifiers of the escalation reached, and other events to the notifiers of
+/// every escalation reached by the alert.
+fn routes(notifier: &dyn Notifier, event: &Event) -> bool {
+    let escalation = match event.kind {
+        EventKind::Escalated => notifier.escalation() == event.escalation,
+        _ => notifier.escalation() <= event.escalation,
+    };
+
+    escalation && notifier.groups().is_none_or(|groups| event.record.critical_group_name.as_ref()
+        .is_some_and(|group| groups.contains(group)))
+}
+
+/// Puts the notifier of an escalation into it.
+struct EscalationNotifier {
+    inner: Box<dyn Notifier>,
+    escalation: usize,
+}
+
+impl Notifier for EscalationNotifier {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn groups(&self) -> Option<&[String]> {
+        self.inner.groups()
+    }
+
+    fn escalation(&self) -> usize {
+        self.escalation
+    }
+
+    fn notify(&mut self, events: &[Event]) -> Result<()> {
+        self.inner.notify(events)
+    }
+}
+
+/// Creates every configured notifier.
+pub fn notifiers(config: &NotificationsConfig) -> Result<Vec<Box<dyn Notifier>>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+
+    for webhook in &config.webhooks {
+        notifiers.push(Box::new(WebhookNotifier::new(webhook.clone(), config.clone())?));
+    }
+
+    for email in &config.email {
+        notifiers.push(Box::new(EmailNotifier::new(email.clone(), config.clone())?));
+    }
+
+    for snmp in &config.snmp {
+        notifiers.push(Box::new(SnmpNotifier::new(snmp.clone(), config.clone())?));
+    }
+
+    for (i, escalation) in config.escalations.iter().enumerate() {
+        if i > 0 && escalation.after_secs <= config.escalations[i - 1].after_secs {
+            bail!("Escalations must be in ascending order of afterSecs");
+        }
+
+        let mut inner: Vec<Box<dyn Notifier>> = Vec::new();
+
+        for webhook in &escalation.webhooks {
+            inner.push(Box::new(WebhookNotifier::new(webhook.clone(), config.clone())?));
+        }
+
+        for email in &escalation.email {
+            inner.push(Box::new(EmailNotifier::new(email.clone(), config.clone())?));
+        }
+
+        for snmp in &escalation.snmp {
+            inner.push(Box::new(SnmpNotifier::new(snmp.clone(), config.clone())?));
+        }
+
+        notifiers.extend(inner.into_iter()
+            .map(|inner| Box::new(EscalationNotifier { inner, escalation: i + 1 }) as Box<dyn Notifier>));
+    }
+
+    Ok(notifiers)
+}
+
+/// Sends the events of every poll to the notifiers routed to, on a thread of
+/// their own so that slow notifiers do not stall polling.
+pub struct Notifications {
+    sender: Sender<Vec<Event>>,
+    handle: JoinHandle<()>,
+}
+
+impl Notifications {
+    pub fn spawn(mut notifiers: Vec<Box<dyn Notifier>>) -> Notifications {
+        let (sender, receiver) = mpsc::channel::<Vec<Event>>();
+
+        let handle = thread::spawn(move || {
+            for events in receiver {
+                for notifier in notifiers.iter_mut() {
+                    let routed: Vec<Event> = events.iter()
+                        .filter(|event| routes(notifier.as_ref(), event))
+                        .cloned()
+                        .collect();
+
+                    if routed.is_empty() {
+                        continue;
+                    }
+
+                    match notifier.notify(&routed) {
+                        Ok(()) => debug!(notifier = notifier.name(), events = routed.len(), "Sent notification"),
+
+                        Err(e) => error!(notifier = notifier.name(), error = %logging::chain(&e), "Unable to send notification"),
+                    }
+                }
+            }
+        });
+
+        Notifications { sender, handle }
+    }
+
+    pub fn notify(&self, events: Vec<Event>) {
+        if !events.is_empty() {
+            let _ = self.sender.send(events);
+        }
+    }
+
+    /// Waits for the notifications of every poll to be sent.
+    pub fn finish(self) {
+        drop(self.sender);
+        let _ = self.handle.join();
+    }
+}
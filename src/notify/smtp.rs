@@ -0,0 +1,234 @@
+use encoding;
+use errors::*;
+use rustls::{ClientConnection, StreamOwned};
+use rustls::pki_types::ServerName;
+use sinks::syslog::local_hostname;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tls;
+
+// encoded-words are limited to 75 characters, which leaves 60 characters of
+// base64 for 45 bytes of text
+const ENCODED_WORD_BYTES: usize = 45;
+
+/// How the connection to the SMTP server is secured.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// Upgrades a plain connection with STARTTLS, usually on port 587
+    Starttls,
+
+    /// Connects with TLS right away, usually on port 465
+    Tls,
+
+    None,
+}
+
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// Message to send, with its headers already known.
+pub struct Mail<'a> {
+    pub from: &'a str,
+    pub to: &'a [String],
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+/// Server to send mails through, and the credentials to log in with.
+pub struct Server<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub security: Security,
+    pub credentials: Option<(&'a str, &'a str)>,
+    pub ca_path: Option<&'a str>,
+    pub timeout: Duration,
+}
+
+struct Client {
+    stream: Box<dyn Stream>,
+    buf: Vec<u8>,
+}
+
+impl Client {
+    /// Reads a reply, which spans lines as long as they continue with `-`
+    /// after the code, and fails unless its code is the expected one.
+    fn expect(&mut self, code: u16) -> Result<String> {
+        let mut reply = String::new();
+
+        loop {
+            let line = self.read_line()?;
+
+            if line.len() < 3 || !line.is_char_boundary(3) {
+                bail!("Malformed SMTP reply {:?}", line);
+            }
+
+            reply.push_str(&line);
+            reply.push('\n');
+
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return match line[..3].parse::<u16>() {
+                    Ok(actual) if actual == code => Ok(reply),
+                    _ => bail!("SMTP server replied {}", reply.trim_end()),
+                };
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        loop {
+            if let Some(end) = self.buf.windows(2).position(|window| window == b"\r\n") {
+                let line = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+                self.buf.drain(..end + 2);
+                return Ok(line);
+            }
+
+            let mut chunk = [0u8; 512];
+
+            let read = self.stream.read(&mut chunk)
+                .chain_err(|| "Unable to read from SMTP server")?;
+
+            if read == 0 {
+                bail!("SMTP server closed the connection");
+            }
+
+            self.buf.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    fn command(&mut self, command: &str, code: u16) -> Result<String> {
+        self.stream.write_all(format!("{}\r\n", command).as_bytes())
+            .and_then(|_| self.stream.flush())
+            .chain_err(|| "Unable to write to SMTP server")?;
+
+        self.expect(code)
+    }
+}
+
+fn connect_tls(tcp: TcpStream, server: &Server) -> Result<Box<dyn Stream>> {
+    let server_name = ServerName::try_from(server.host.to_owned())
+        .chain_err(|| format!("Invalid SMTP server name {}", server.host))?;
+
+    let connection = ClientConnection::new(tls::client_config(server.ca_path)?, server_name)
+        .chain_err(|| "Unable to set up TLS to SMTP server")?;
+
+    Ok(Box::new(StreamOwned::new(connection, tcp)))
+}
+
+/// Encodes the header value as RFC 2047 if it is not plain ASCII, with line
+/// breaks, e.g. from LSF remarks, replaced so they cannot start new headers.
+fn header_value(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+
+    if value.is_ascii() {
+        return value;
+    }
+
+    let mut words = Vec::new();
+    let mut start = 0;
+
+    for (i, c) in value.char_indices() {
+        if i + c.len_utf8() - start > ENCODED_WORD_BYTES {
+            words.push(&value[start..i]);
+            start = i;
+        }
+    }
+
+    words.push(&value[start..]);
+
+    words.iter()
+        .map(|word| format!("=?utf-8?b?{}?=", encoding::base64(word.as_bytes())))
+        .collect::<Vec<_>>()
+        .join("\r\n ")
+}
+
+/// Renders the message with CRLF line endings, and dots at the start of
+/// lines doubled so that they do not end the data early.
+fn message(mail: &Mail, date: &str) -> String {
+    let mut message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+        mail.from, mail.to.join(", "), header_value(mail.subject), date);
+
+    for line in mail.body.lines() {
+        if line.starts_with('.') {
+            message.push('.');
+        }
+
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+
+    message
+}
+
+/// Sends the mail in a session of its own.
+pub fn send(server: &Server, mail: &Mail, date: &str) -> Result<()> {
+    let address = (server.host, server.port).to_socket_addrs()
+        .chain_err(|| format!("Unable to resolve SMTP server {}", server.host))?
+        .next()
+        .ok_or_else(|| Error::from(format!("SMTP server {} has no address", server.host)))?;
+
+    let tcp = TcpStream::connect_timeout(&address, server.timeout)
+        .and_then(|tcp| tcp.set_read_timeout(Some(server.timeout)).map(|_| tcp))
+        .and_then(|tcp| tcp.set_write_timeout(Some(server.timeout)).map(|_| tcp))
+        .chain_err(|| format!("Unable to connect to SMTP server {}:{}", server.host, server.port))?;
+
+    let stream: Box<dyn Stream> = match server.security {
+        Security::Tls => connect_tls(tcp.try_clone().chain_err(|| "Unable to set up TLS to SMTP server")?, server)?,
+        _ => Box::new(tcp.try_clone().chain_err(|| "Unable to connect to SMTP server")?),
+    };
+
+    let mut client = Client { stream, buf: Vec::new() };
+    let ehlo = format!("EHLO {}", local_hostname());
+
+    client.expect(220)?;
+    client.command(&ehlo, 250)?;
+
+    if server.security == Security::Starttls {
+        client.command("STARTTLS", 220)?;
+        client = Client { stream: connect_tls(tcp, server)?, buf: Vec::new() };
+        client.command(&ehlo, 250)?;
+    }
+
+    if let Some((username, password)) = server.credentials {
+        let credentials = format!("\0{}\0{}", username, password);
+        client.command(&format!("AUTH PLAIN {}", encoding::base64(credentials.as_bytes())), 235)?;
+    }
+
+    client.command(&format!("MAIL FROM:<{}>", mail.from), 250)?;
+
+    for to in mail.to {
+        client.command(&format!("RCPT TO:<{}>", to), 250)?;
+    }
+
+    client.command("DATA", 354)?;
+    client.command(&format!("{}.", message(mail, date)), 250)?;
+
+    // the mail has been accepted, whatever happens to the goodbye
+    let _ = client.command("QUIT", 221);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_value_strips_line_breaks() {
+        assert_eq!(header_value("down\r\nBcc: x@example.com"), "down  Bcc: x@example.com");
+    }
+
+    #[test]
+    fn header_value_splits_encoded_words() {
+        let value = header_value(&"é".repeat(30));
+        let words: Vec<_> = value.split("\r\n ").collect();
+
+        assert_eq!(words.len(), 2);
+        assert!(words.iter().all(|word| word.len() <= 75));
+        assert_eq!(words[0], format!("=?utf-8?b?{}?=", encoding::base64("é".repeat(22).as_bytes())));
+        assert_eq!(words[1], format!("=?utf-8?b?{}?=", encoding::base64("é".repeat(8).as_bytes())));
+    }
+}
@@ -0,0 +1,561 @@
+use chrono::Utc;
+use errors::*;
+use notify::{Event, EventKind, NotificationsConfig, Notifier};
+use ring::{digest, hmac};
+use std::fs::File;
+use std::io::Read;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::Instant;
+
+// root of LSF-AGENT-MIB, under the Net-SNMP playpen
+fn default_enterprise_oid() -> String {
+    "1.3.6.1.4.1.8072.9999.9999.1".to_owned()
+}
+
+fn default_version() -> SnmpVersion {
+    SnmpVersion::V2c
+}
+
+fn default_community() -> String {
+    "public".to_owned()
+}
+
+fn default_auth_protocol() -> SnmpAuthProtocol {
+    SnmpAuthProtocol::Sha
+}
+
+const SYS_UP_TIME: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 3, 0];
+const SNMP_TRAP_OID: &[u32] = &[1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0];
+
+// largest message the receiver is told the agent accepts
+const MAX_MESSAGE_SIZE: i64 = 65507;
+
+// passwords are expanded to a megabyte before hashing them into keys
+const PASSWORD_EXPANSION: usize = 1_048_576;
+
+const USM_SECURITY_MODEL: i64 = 3;
+
+// BER tags
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+const TIME_TICKS: u8 = 0x43;
+const TRAP_PDU: u8 = 0xa7;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SnmpVersion {
+    V2c,
+    V3,
+}
+
+/// Authentication protocol of SNMPv3 users.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SnmpAuthProtocol {
+    /// HMAC-SHA-96
+    Sha,
+
+    /// HMAC-SHA-2 of RFC 7860
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl SnmpAuthProtocol {
+    fn digest(&self) -> &'static digest::Algorithm {
+        match *self {
+            SnmpAuthProtocol::Sha => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            SnmpAuthProtocol::Sha256 => &digest::SHA256,
+            SnmpAuthProtocol::Sha384 => &digest::SHA384,
+            SnmpAuthProtocol::Sha512 => &digest::SHA512,
+        }
+    }
+
+    fn hmac(&self) -> hmac::Algorithm {
+        match *self {
+            SnmpAuthProtocol::Sha => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            SnmpAuthProtocol::Sha256 => hmac::HMAC_SHA256,
+            SnmpAuthProtocol::Sha384 => hmac::HMAC_SHA384,
+            SnmpAuthProtocol::Sha512 => hmac::HMAC_SHA512,
+        }
+    }
+
+    /// Length the HMAC is truncated to in messages.
+    fn mac_len(&self) -> usize {
+        match *self {
+            SnmpAuthProtocol::Sha => 12,
+            SnmpAuthProtocol::Sha256 => 24,
+            SnmpAuthProtocol::Sha384 => 32,
+            SnmpAuthProtocol::Sha512 => 48,
+        }
+    }
+}
+
+/// Receiver of the traps. SNMPv3 traps are sent without privacy, as
+/// encrypted traps are not supported.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SnmpConfig {
+    /// Trap receiver as `host:port`, usually on port 162
+    pub target: String,
+
+    /// `v2c` or `v3`
+    #[serde(default = "default_version")]
+    pub version: SnmpVersion,
+
+    #[serde(default = "default_community")]
+    pub community: String,
+
+    /// SNMPv3 user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// SNMPv3 engine ID of the agent in hex, which the receiver needs to know
+    /// the user by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engine_id: Option<String>,
+
+    #[serde(default = "default_auth_protocol")]
+    pub auth_protocol: SnmpAuthProtocol,
+
+    /// Authenticates the SNMPv3 traps, which are sent unauthenticated when
+    /// not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_password: Option<String>,
+
+    /// File containing the password, so it does not need to live in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_password_file: Option<String>,
+
+    /// Root of LSF-AGENT-MIB, to move it under a private enterprise number
+    #[serde(default = "default_enterprise_oid")]
+    pub enterprise_oid: String,
+
+    /// Critical groups routed to this receiver, all when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<String>>,
+}
+
+struct Usm {
+    username: String,
+    engine_id: Vec<u8>,
+
+    /// Protocol and key localized to the engine ID, if authenticated
+    auth: Option<(SnmpAuthProtocol, Vec<u8>)>,
+}
+
+/// Sends a trap of LSF-AGENT-MIB for every event.
+pub struct SnmpNotifier {
+    config: SnmpConfig,
+    notifications: NotificationsConfig,
+    enterprise_oid: Vec<u32>,
+    usm: Option<Usm>,
+    started: Instant,
+    request_id: i64,
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let len = content.len();
+    let mut out = vec![tag];
+
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().iter().cloned().skip_while(|&b| b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend_from_slice(&bytes);
+    }
+
+    out.extend_from_slice(content);
+    out
+}
+
+/// Minimal two's complement big-endian bytes of the value.
+fn integer_bytes(value: i64) -> Vec<u8> {
+    let mut bytes = value.to_be_bytes().to_vec();
+
+    while bytes.len() > 1 && ((bytes[0] == 0 && bytes[1] & 0x80 == 0) || (bytes[0] == 0xff && bytes[1] & 0x80 != 0)) {
+        bytes.remove(0);
+    }
+
+    bytes
+}
+
+fn integer(value: i64) -> Vec<u8> {
+    tlv(INTEGER, &integer_bytes(value))
+}
+
+fn octet_string(value: &[u8]) -> Vec<u8> {
+    tlv(OCTET_STRING, value)
+}
+
+fn object_identifier(arcs: &[u32]) -> Vec<u8> {
+    let mut content = Vec::new();
+    let first = arcs.first().cloned().unwrap_or(0) * 40 + arcs.get(1).cloned().unwrap_or(0);
+
+    for &arc in Some(first).iter().chain(arcs.iter().skip(2)) {
+        let mut base128 = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+
+        while rest > 0 {
+            base128.insert(0, 0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+
+        content.extend_from_slice(&base128);
+    }
+
+    tlv(OBJECT_IDENTIFIER, &content)
+}
+
+fn sequence(parts: &[Vec<u8>]) -> Vec<u8> {
+    tlv(SEQUENCE, &parts.concat())
+}
+
+fn parse_oid(oid: &str) -> Result<Vec<u32>> {
+    let arcs = oid.trim_start_matches('.').split('.')
+        .map(|arc| arc.parse::<u32>().chain_err(|| format!("Invalid OID {}", oid)))
+        .collect::<Result<Vec<_>>>()?;
+
+    if arcs.len() < 2 {
+        bail!("Invalid OID {}", oid);
+    }
+
+    Ok(arcs)
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+    let hex = hex.trim_start_matches("0x");
+
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        bail!("Invalid hex {}", hex);
+    }
+
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).chain_err(|| format!("Invalid hex {}", hex)))
+        .collect()
+}
+
+/// Key of the password localized to the engine ID, as of RFC 3414.
+fn localized_key(protocol: SnmpAuthProtocol, password: &str, engine_id: &[u8]) -> Vec<u8> {
+    let expanded: Vec<u8> = password.bytes().cycle().take(PASSWORD_EXPANSION).collect();
+    let key = digest::digest(protocol.digest(), &expanded);
+
+    let mut context = digest::Context::new(protocol.digest());
+    context.update(key.as_ref());
+    context.update(engine_id);
+    context.update(key.as_ref());
+    context.finish().as_ref().to_vec()
+}
+
+impl SnmpNotifier {
+    pub fn new(config: SnmpConfig, notifications: NotificationsConfig) -> Result<SnmpNotifier> {
+        let enterprise_oid = parse_oid(&config.enterprise_oid)?;
+
+        let usm = match config.version {
+            SnmpVersion::V2c => None,
+
+            SnmpVersion::V3 => {
+                let username = config.username.clone()
+                    .ok_or_else(|| Error::from("SNMPv3 traps require a username"))?;
+
+                let engine_id = parse_hex(config.engine_id.as_deref()
+                    .ok_or_else(|| Error::from("SNMPv3 traps require an engineId"))?)?;
+
+                if engine_id.len() < 5 || engine_id.len() > 32 {
+                    bail!("SNMP engine ID must be 5 to 32 bytes long");
+                }
+
+                let password = match (&config.auth_password, &config.auth_password_file) {
+                    (Some(password), _) => Some(password.clone()),
+
+                    (None, Some(password_file)) => {
+                        let mut password = String::new();
+
+                        File::open(password_file)
+                            .and_then(|mut f| f.read_to_string(&mut password))
+                            .chain_err(|| format!("Unable to read SNMP password file at {}", password_file))?;
+
+                        Some(password.trim().to_owned())
+                    },
+
+                    (None, None) => None,
+                };
+
+                let auth = match password {
+                    Some(ref password) if password.len() < 8 => bail!("SNMP password must be at least 8 characters long"),
+
+                    Some(password) => Some((config.auth_protocol,
+                        localized_key(config.auth_protocol, &password, &engine_id))),
+
+                    None => None,
+                };
+
+                Some(Usm { username, engine_id, auth })
+            },
+        };
+
+        Ok(SnmpNotifier { config, notifications, enterprise_oid, usm, started: Instant::now(), request_id: 0 })
+    }
+
+    fn oid(&self, suffix: &[u32]) -> Vec<u8> {
+        object_identifier(&[self.enterprise_oid.as_slice(), suffix].concat())
+    }
+
+    fn varbind(&self, suffix: &[u32], value: Vec<u8>) -> Vec<u8> {
+        sequence(&[self.oid(suffix), value])
+    }
+
+    fn trap_pdu(&self, event: &Event, request_id: i64, up_time: u32) -> Vec<u8> {
+        let notification = match event.kind {
+            EventKind::Failed => 1,
+            EventKind::Reminder => 2,
+            EventKind::Escalated => 3,
+            EventKind::Cleared => 4,
+        };
+
+        let record = &event.record;
+
+        let mut varbinds = vec![
+            sequence(&[object_identifier(SYS_UP_TIME), tlv(TIME_TICKS, &integer_bytes(up_time as i64))]),
+            sequence(&[object_identifier(SNMP_TRAP_OID), self.oid(&[0, notification])]),
+            self.varbind(&[1, 1, 0], octet_string(record.name.as_bytes())),
+            self.varbind(&[1, 2, 0], integer(record.status as i64)),
+        ];
+
+        if let Some(previous_status) = event.previous_status {
+            varbinds.push(self.varbind(&[1, 3, 0], integer(previous_status as i64)));
+        }
+
+        varbinds.extend(vec![
+            self.varbind(&[1, 4, 0], octet_string(record.critical_group_name.as_deref().unwrap_or("").as_bytes())),
+            self.varbind(&[1, 5, 0], octet_string(record.remarks.as_deref().unwrap_or("").as_bytes())),
+            self.varbind(&[1, 6, 0], integer(event.escalation as i64)),
+            self.varbind(&[1, 7, 0], octet_string(event.render(&self.notifications).as_bytes())),
+        ]);
+
+        tlv(TRAP_PDU, &[integer(request_id), integer(0), integer(0), sequence(&varbinds)].concat())
+    }
+
+    fn message(&self, pdu: Vec<u8>, request_id: i64) -> Vec<u8> {
+        let usm = match self.usm {
+            Some(ref usm) => usm,
+            None => return sequence(&[integer(1), octet_string(self.config.community.as_bytes()), pdu]),
+        };
+
+        let mac_len = usm.auth.as_ref().map_or(0, |&(protocol, _)| protocol.mac_len());
+
+        // traps are not reportable, so only the authentication flag applies
+        let flags = if usm.auth.is_some() { 0x01 } else { 0x00 };
+
+        // the engine time counts from the Unix epoch, so that it keeps
+        // increasing across restarts of the agent within the same boot count
+        let engine_time = Utc::now().timestamp().min(i32::MAX as i64);
+
+        let header = [
+            integer(3),
+            sequence(&[integer(request_id), integer(MAX_MESSAGE_SIZE), octet_string(&[flags]), integer(USM_SECURITY_MODEL)]),
+        ].concat();
+
+        let usm_fields = [
+            octet_string(&usm.engine_id),
+            integer(1),
+            integer(engine_time),
+            octet_string(usm.username.as_bytes()),
+        ].concat();
+
+        let usm_parameters = sequence(&[usm_fields.clone(), octet_string(&vec![0; mac_len]), octet_string(&[])]);
+        let security_parameters = octet_string(&usm_parameters);
+        let scoped_pdu = sequence(&[octet_string(&usm.engine_id), octet_string(&[]), pdu]);
+
+        let body = [header.clone(), security_parameters.clone(), scoped_pdu].concat();
+        let mut message = tlv(SEQUENCE, &body);
+
+        if let Some((protocol, ref key)) = usm.auth {
+            // the MAC is computed over the message with its parameter zeroed,
+            // which follows the TLV headers enclosing it and the fields before it
+            let usm_offset = (usm_parameters.len() - (usm_fields.len() + 2 + mac_len + 2)) + usm_fields.len() + 2;
+
+            let offset = (message.len() - body.len()) + header.len()
+                + (security_parameters.len() - usm_parameters.len()) + usm_offset;
+
+            let mac = hmac::sign(&hmac::Key::new(protocol.hmac(), key), &message);
+            message[offset..offset + mac_len].copy_from_slice(&mac.as_ref()[..mac_len]);
+        }
+
+        message
+    }
+}
+
+impl Notifier for SnmpNotifier {
+    fn name(&self) -> &str {
+        "snmp"
+    }
+
+    fn groups(&self) -> Option<&[String]> {
+        self.config.groups.as_deref()
+    }
+
+    fn notify(&mut self, events: &[Event]) -> Result<()> {
+        let target = self.config.target.to_socket_addrs()
+            .chain_err(|| format!("Unable to resolve SNMP target {}", self.config.target))?
+            .next()
+            .ok_or_else(|| format!("No address found for SNMP target {}", self.config.target))?;
+
+        let socket = UdpSocket::bind(if target.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" })
+            .chain_err(|| "Unable to bind UDP socket for SNMP traps")?;
+
+        for event in events {
+            self.request_id = (self.request_id + 1) % i32::MAX as i64;
+            let up_time = (self.started.elapsed().as_millis() / 10) as u32;
+            let message = self.message(self.trap_pdu(event, self.request_id, up_time), self.request_id);
+
+            socket.send_to(&message, target)
+                .chain_err(|| format!("Unable to send SNMP trap to {}", self.config.target))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding;
+    use serde_json;
+    use {StatusStorageInfo, FAILED, PASSED};
+
+    fn notifier(config: serde_json::Value) -> SnmpNotifier {
+        let notifications = NotificationsConfig { failed_template: "{name} is down".to_owned(), ..Default::default() };
+        SnmpNotifier::new(serde_json::from_value(config).unwrap(), notifications).unwrap()
+    }
+
+    fn event() -> Event {
+        let record = StatusStorageInfo::new("host1".to_owned(), FAILED, None,
+            Some("hpc".to_owned()), Some("disk full".to_owned()));
+
+        Event { kind: EventKind::Failed, previous_status: Some(PASSED), record, escalation: 1 }
+    }
+
+    /// Tag and content bounds of the TLV at the index within the bounds.
+    fn child(input: &[u8], (start, end): (usize, usize), index: usize) -> (u8, usize, usize) {
+        let mut i = start;
+
+        for n in 0.. {
+            assert!(i < end);
+
+            let (len, header) = match input[i + 1] {
+                len if len < 0x80 => (len as usize, 2),
+                long => {
+                    let count = (long & 0x7f) as usize;
+                    (input[i + 2..i + 2 + count].iter().fold(0, |len, &b| len << 8 | b as usize), 2 + count)
+                },
+            };
+
+            if n == index {
+                return (input[i], i + header, i + header + len);
+            }
+
+            i += header + len;
+        }
+
+        unreachable!()
+    }
+
+    #[test]
+    fn encodes_integers() {
+        assert_eq!(integer(0), [0x02, 0x01, 0x00]);
+        assert_eq!(integer(127), [0x02, 0x01, 0x7f]);
+        assert_eq!(integer(128), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(integer(-1), [0x02, 0x01, 0xff]);
+        assert_eq!(integer(-129), [0x02, 0x02, 0xff, 0x7f]);
+        assert_eq!(integer(65507), [0x02, 0x03, 0x00, 0xff, 0xe3]);
+    }
+
+    #[test]
+    fn encodes_long_lengths() {
+        assert_eq!(&octet_string(&[0; 200])[..3], [0x04, 0x81, 0xc8]);
+        assert_eq!(&octet_string(&[0; 300])[..4], [0x04, 0x82, 0x01, 0x2c]);
+    }
+
+    #[test]
+    fn encodes_object_identifiers() {
+        assert_eq!(object_identifier(&parse_oid(".1.3.6.1.4.1.8072.9999").unwrap()),
+            [0x06, 0x09, 0x2b, 0x06, 0x01, 0x04, 0x01, 0xbf, 0x08, 0xce, 0x0f]);
+
+        assert!(parse_oid("1").is_err());
+        assert!(parse_oid("1.3.x").is_err());
+    }
+
+    #[test]
+    fn encodes_v2c_trap() {
+        let notifier = notifier(json!({ "target": "localhost:162" }));
+        let message = notifier.message(notifier.trap_pdu(&event(), 7, 4321), 7);
+
+        // encoded independently with pyasn1
+        assert_eq!(encoding::hex(&message), concat!(
+            "3081fc02010104067075626c6963a781ee0201070201000201003081e2300e06",
+            "082b06010201010300430210e1301c060a2b060106030101040100060e2b0601",
+            "0401bf08ce0fce0f0100013018060f2b06010401bf08ce0fce0f010101000405",
+            "686f7374313014060f2b06010401bf08ce0fce0f010102000201023014060f2b",
+            "06010401bf08ce0fce0f010103000201003016060f2b06010401bf08ce0fce0f",
+            "010104000403687063301c060f2b06010401bf08ce0fce0f0101050004096469",
+            "736b2066756c6c3014060f2b06010401bf08ce0fce0f01010600020101302006",
+            "0f2b06010401bf08ce0fce0f01010700040d686f73743120697320646f776e"));
+    }
+
+    #[test]
+    fn localizes_keys() {
+        // RFC 3414 A.3.2
+        let key = localized_key(SnmpAuthProtocol::Sha, "maplesyrup", &parse_hex("000000000000000000000002").unwrap());
+        assert_eq!(encoding::hex(&key), "6695febc9288e36282235fc7151f128497b38f3f");
+    }
+
+    #[test]
+    fn authenticates_v3_traps() {
+        let protocols = [
+            ("sha", SnmpAuthProtocol::Sha),
+            ("sha256", SnmpAuthProtocol::Sha256),
+            ("sha384", SnmpAuthProtocol::Sha384),
+            ("sha512", SnmpAuthProtocol::Sha512),
+        ];
+
+        // the long username moves the MAC behind a long form length
+        for &username in &["admin", &"u".repeat(100) as &str] {
+            for &(name, protocol) in &protocols {
+                let notifier = notifier(json!({
+                    "target": "localhost:162",
+                    "version": "v3",
+                    "username": username,
+                    "engineId": "0x80001f8880e3",
+                    "authProtocol": name,
+                    "authPassword": "maplesyrup",
+                }));
+
+                let mut message = notifier.message(notifier.trap_pdu(&event(), 7, 4321), 7);
+
+                // message, security parameters, USM parameters, then the MAC
+                let outer = child(&message, (0, message.len()), 0);
+                let security_parameters = child(&message, (outer.1, outer.2), 2);
+                let usm_parameters = child(&message, (security_parameters.1, security_parameters.2), 0);
+                let (tag, mac_start, mac_end) = child(&message, (usm_parameters.1, usm_parameters.2), 4);
+
+                assert_eq!(tag, OCTET_STRING);
+                assert_eq!(mac_end - mac_start, protocol.mac_len());
+
+                let mac = message[mac_start..mac_end].to_vec();
+                assert!(mac.iter().any(|&b| b != 0));
+
+                for b in &mut message[mac_start..mac_end] {
+                    *b = 0;
+                }
+
+                let key = localized_key(protocol, "maplesyrup", &parse_hex("80001f8880e3").unwrap());
+                let expected = hmac::sign(&hmac::Key::new(protocol.hmac(), &key), &message);
+                assert_eq!(mac, &expected.as_ref()[..protocol.mac_len()]);
+            }
+        }
+    }
+}
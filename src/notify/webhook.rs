@@ -0,0 +1,103 @@
+use errors::*;
+use notify::{Event, NotificationsConfig, Notifier};
+use output;
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use ureq;
+
+fn default_kind() -> String {
+    "generic".to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookConfig {
+    /// `slack`, `teams` or `generic`
+    #[serde(default = "default_kind")]
+    pub kind: String,
+
+    pub url: String,
+
+    /// Critical groups routed to this webhook, all when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Posts the lines of the events of a poll as a single message.
+pub struct WebhookNotifier {
+    config: WebhookConfig,
+    notifications: NotificationsConfig,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: WebhookConfig, notifications: NotificationsConfig) -> Result<WebhookNotifier> {
+        match config.kind.as_str() {
+            "slack" | "teams" | "generic" => (),
+            other => bail!("Unknown webhook kind {}, expected slack, teams or generic", other),
+        }
+
+        Ok(WebhookNotifier { config, notifications })
+    }
+
+    fn body(&self, events: &[Event]) -> Value {
+        let text = events.iter()
+            .map(|event| event.render(&self.notifications))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        match self.config.kind.as_str() {
+            // both incoming webhooks take plain markdown text
+            "slack" | "teams" => json!({ "text": text }),
+
+            _ => json!({
+                "text": text,
+                "events": events.iter()
+                    .map(|event| json!({
+                        "event": event.kind.as_str(),
+                        "previousStatus": event.previous_status.map(output::to_check_status_str),
+                        "escalation": event.escalation,
+                        "record": event.record,
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        &self.config.kind
+    }
+
+    fn groups(&self) -> Option<&[String]> {
+        self.config.groups.as_deref()
+    }
+
+    fn notify(&mut self, events: &[Event]) -> Result<()> {
+        let body = serde_json::to_string(&self.body(events))
+            .chain_err(|| "Unable to serialize notification into string!")?;
+
+        let mut request = ureq::post(&self.config.url)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .set("Content-Type", "application/json");
+
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value);
+        }
+
+        request.send_string(&body)
+            .map(|_| ())
+            .map_err(|e| format!("Unable to POST notification to {}: {}", self.config.url, e).into())
+    }
+}
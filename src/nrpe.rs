@@ -0,0 +1,267 @@
+use common::StatusStorageInfo;
+use errors::*;
+use flate2::Crc;
+use glob;
+use logging;
+use output::{self, OutputFormat, NAGIOS_OK, NAGIOS_UNKNOWN};
+use rustls::{ServerConnection, StreamOwned};
+use server::ServerState;
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpListener};
+use std::thread;
+use std::time::Duration;
+use tls::{self, TlsConfig};
+
+const QUERY_PACKET: u16 = 1;
+const RESPONSE_PACKET: u16 = 2;
+
+// buffer of version 2 packets, which have a fixed size
+const V2_BUFFER_SIZE: usize = 1024;
+
+// buffers of later versions are sized, within reason
+const MAX_BUFFER_SIZE: usize = 64 * 1024;
+
+const HEADER_SIZE: usize = 16;
+
+fn default_bind() -> String {
+    "0.0.0.0:5666".to_owned()
+}
+
+fn default_allowed_hosts() -> Vec<String> {
+    vec!["127.0.0.1".to_owned(), "::1".to_owned()]
+}
+
+/// NRPE listener answering the `check_lsf_*` commands of Nagios servers from
+/// the latest poll.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NrpeConfig {
+    #[serde(default = "default_bind")]
+    pub bind: String,
+
+    /// Addresses of the Nagios servers allowed to connect
+    #[serde(default = "default_allowed_hosts")]
+    pub allowed_hosts: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+}
+
+/// Runs the command of a query against the records of the latest poll, if
+/// any, returning the Nagios exit code and output. Arguments follow the
+/// command after `!`.
+pub fn run_command(query: &str, prefix: &str, records: Option<&[StatusStorageInfo]>) -> (i32, String) {
+    let mut args = query.split('!');
+    let command = args.next().unwrap_or("");
+    let args: Vec<&str> = args.collect();
+
+    if command == "_NRPE_CHECK" {
+        return (NAGIOS_OK, format!("lsf_agent NRPE v{}", env!("CARGO_PKG_VERSION")));
+    }
+
+    let records = match records {
+        Some(records) => records,
+        None => return (NAGIOS_UNKNOWN, "LSF UNKNOWN - No poll has completed yet".to_owned()),
+    };
+
+    let matched: Vec<StatusStorageInfo> = match (command, args.as_slice()) {
+        ("check_lsf_hosts", []) => records.to_vec(),
+
+        ("check_lsf_hosts", patterns) => records.iter()
+            .filter(|record| patterns.iter().any(|pattern| glob::matches(pattern, &record.name)))
+            .cloned()
+            .collect(),
+
+        ("check_lsf_host", [name]) => records.iter()
+            .filter(|record| record.name == *name || record.name == format!("{}{}", prefix, name))
+            .cloned()
+            .collect(),
+
+        ("check_lsf_queue", [name]) => records.iter()
+            .filter(|record| record.name == format!("{}queue:{}", prefix, name))
+            .cloned()
+            .collect(),
+
+        ("check_lsf_host", _) | ("check_lsf_queue", _) =>
+            return (NAGIOS_UNKNOWN, format!("LSF UNKNOWN - {} takes a single name", command)),
+
+        _ => return (NAGIOS_UNKNOWN, format!("LSF UNKNOWN - Unknown command {}", command)),
+    };
+
+    if matched.is_empty() {
+        return (NAGIOS_UNKNOWN, format!("LSF UNKNOWN - No records match {}", query));
+    }
+
+    match output::render(OutputFormat::Nagios, &matched) {
+        Ok(check_output) => (output::nagios_exit_code(&matched), check_output),
+        Err(e) => (NAGIOS_UNKNOWN, format!("LSF UNKNOWN - {}", e)),
+    }
+}
+
+fn crc32(packet: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(packet);
+    crc.sum()
+}
+
+/// Reads a query packet, returning its version and command. Version 2
+/// packets have a fixed buffer, while versions 3 and 4 carry its length,
+/// where version 3 pads the buffer by 3 bytes.
+fn read_query<S: Read>(stream: &mut S) -> Result<(u16, String)> {
+    let mut packet = vec![0u8; HEADER_SIZE];
+    stream.read_exact(&mut packet).chain_err(|| "Unable to read NRPE packet header")?;
+
+    let version = u16::from_be_bytes([packet[0], packet[1]]);
+    let packet_type = u16::from_be_bytes([packet[2], packet[3]]);
+
+    let rest = match version {
+        // result code, buffer and padding after the first 8 bytes
+        2 => 8 + 2 + V2_BUFFER_SIZE + 2 - HEADER_SIZE,
+
+        3 | 4 => {
+            let buffer_len = u32::from_be_bytes([packet[12], packet[13], packet[14], packet[15]]) as usize;
+
+            if buffer_len > MAX_BUFFER_SIZE {
+                bail!("NRPE buffer of {} bytes exceeds {} bytes", buffer_len, MAX_BUFFER_SIZE);
+            }
+
+            if version == 3 { buffer_len + 3 } else { buffer_len }
+        },
+
+        _ => bail!("Unsupported NRPE packet version {}", version),
+    };
+
+    if packet_type != QUERY_PACKET {
+        bail!("Expected NRPE query packet, got type {}", packet_type);
+    }
+
+    let start = packet.len();
+    packet.resize(start + rest, 0);
+    stream.read_exact(&mut packet[start..]).chain_err(|| "Unable to read NRPE packet")?;
+
+    let expected_crc = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+    packet[4..8].copy_from_slice(&[0; 4]);
+
+    if crc32(&packet) != expected_crc {
+        bail!("NRPE packet has an invalid CRC");
+    }
+
+    let buffer = if version == 2 { &packet[10..10 + V2_BUFFER_SIZE] } else { &packet[HEADER_SIZE..] };
+    let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+
+    Ok((version, String::from_utf8_lossy(&buffer[..end]).into_owned()))
+}
+
+/// Builds the response packet of the version, truncating the output to fit
+/// version 2 packets.
+fn response(version: u16, result_code: i32, check_output: &str) -> Vec<u8> {
+    let mut buffer = check_output.as_bytes().to_vec();
+
+    let mut packet = Vec::new();
+    packet.extend_from_slice(&version.to_be_bytes());
+    packet.extend_from_slice(&RESPONSE_PACKET.to_be_bytes());
+    packet.extend_from_slice(&[0; 4]);
+    packet.extend_from_slice(&(result_code as i16).to_be_bytes());
+
+    if version == 2 {
+        buffer.truncate(V2_BUFFER_SIZE - 1);
+        buffer.resize(V2_BUFFER_SIZE, 0);
+        packet.extend_from_slice(&buffer);
+        packet.extend_from_slice(&[0; 2]);
+    } else {
+        buffer.truncate(MAX_BUFFER_SIZE - 1);
+        buffer.push(0);
+
+        packet.extend_from_slice(&[0; 2]);
+        packet.extend_from_slice(&(buffer.len() as u32).to_be_bytes());
+        packet.extend_from_slice(&buffer);
+
+        if version == 3 {
+            packet.extend_from_slice(&[0; 3]);
+        }
+    }
+
+    let crc = crc32(&packet);
+    packet[4..8].copy_from_slice(&crc.to_be_bytes());
+    packet
+}
+
+fn handle<S: Read + Write>(mut stream: S, prefix: &str, state: &ServerState) -> Result<()> {
+    let (version, query) = read_query(&mut stream)?;
+    debug!(query = query.as_str(), "Answering NRPE query");
+
+    let records = state.records();
+    let (result_code, check_output) = run_command(&query, prefix, records.as_deref());
+
+    stream.write_all(&response(version, result_code, &check_output))
+        .and_then(|_| stream.flush())
+        .chain_err(|| "Unable to write NRPE response")
+}
+
+/// Binds the NRPE listener and answers queries on background threads.
+pub fn spawn(config: &NrpeConfig, prefix: &str, state: ServerState) -> Result<()> {
+    let tls_config = match config.tls {
+        Some(ref tls) => Some(tls::server_config(tls)?),
+        None => None,
+    };
+
+    let allowed_hosts = config.allowed_hosts.iter()
+        .map(|host| host.parse::<IpAddr>().chain_err(|| format!("Invalid NRPE allowed host {}", host)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let listener = TcpListener::bind(&config.bind)
+        .chain_err(|| format!("Unable to bind NRPE listener to {}", config.bind))?;
+
+    let prefix = prefix.to_owned();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!(error = %e, "Unable to accept NRPE connection");
+                    continue;
+                },
+            };
+
+            match stream.peer_addr() {
+                Ok(peer) if allowed_hosts.contains(&peer.ip()) => (),
+
+                Ok(peer) => {
+                    warn!(peer = %peer.ip(), "Refusing NRPE connection from a host that is not allowed");
+                    continue;
+                },
+
+                Err(e) => {
+                    warn!(error = %e, "Unable to get address of NRPE connection");
+                    continue;
+                },
+            }
+
+            if let Err(e) = stream.set_read_timeout(Some(Duration::from_secs(10))) {
+                warn!(error = %e, "Unable to set read timeout on NRPE connection");
+                continue;
+            }
+
+            let state = state.clone();
+            let tls_config = tls_config.clone();
+            let prefix = prefix.clone();
+
+            thread::spawn(move || {
+                let res = match tls_config {
+                    Some(tls_config) => ServerConnection::new(tls_config)
+                        .chain_err(|| "Unable to start TLS session")
+                        .and_then(|conn| handle(StreamOwned::new(conn, stream), &prefix, &state)),
+
+                    None => handle(stream, &prefix, &state),
+                };
+
+                if let Err(e) = res {
+                    warn!(error = %logging::chain(&e), "Unable to answer NRPE query");
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use chrono::Utc;
+use compression::Compression;
+use encryption::Encryptor;
+use errors::*;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+// sorts in the order the copies were written
+const COPY_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// File the payload of every poll replaces atomically, so that readers never
+/// see a half-written payload, optionally keeping timestamped copies of the
+/// previous payloads next to it.
+#[derive(Clone, Debug)]
+pub struct OutputFile {
+    path: String,
+    keep: usize,
+    compression: Option<Compression>,
+    encryptor: Option<Encryptor>,
+}
+
+impl OutputFile {
+    pub fn new(path: &str, keep: usize, compression: Option<Compression>, encryptor: Option<Encryptor>) -> OutputFile {
+        OutputFile { path: path.to_owned(), keep, compression, encryptor }
+    }
+
+    /// Writes the payload, compressed and then encrypted if configured, into a
+    /// temporary file next to the output file and renames it over the output
+    /// file.
+    pub fn write(&self, payload: &str) -> Result<()> {
+        let tmp_path = format!("{}.tmp", self.path);
+        let line = format!("{}\n", payload);
+
+        let content = match self.compression {
+            Some(compression) => compression.compress(line.as_bytes())?,
+            None => line.into_bytes(),
+        };
+
+        let content = match self.encryptor {
+            Some(ref encryptor) => encryptor.encrypt(&content)?,
+            None => content,
+        };
+
+        File::create(&tmp_path)
+            .and_then(|mut file| file.write_all(&content).and_then(|_| file.sync_all()))
+            .chain_err(|| format!("Unable to write output file at {}", tmp_path))?;
+
+        fs::rename(&tmp_path, &self.path)
+            .chain_err(|| format!("Unable to replace output file at {}", self.path))?;
+
+        if self.keep > 0 {
+            self.keep_copy()?;
+        }
+
+        Ok(())
+    }
+
+    fn keep_copy(&self) -> Result<()> {
+        let copy_path = format!("{}.{}", self.path, Utc::now().format(COPY_TIMESTAMP_FORMAT));
+
+        // the output file is only ever replaced, never written in place
+        fs::hard_link(&self.path, &copy_path)
+            .or_else(|_| fs::copy(&self.path, &copy_path).map(|_| ()))
+            .chain_err(|| format!("Unable to keep copy of output file at {}", copy_path))?;
+
+        let path = Path::new(&self.path);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let copy_prefix = format!("{}.", path.file_name().and_then(|name| name.to_str()).unwrap_or_default());
+
+        let mut copies: Vec<_> = fs::read_dir(dir)
+            .chain_err(|| format!("Unable to list copies of output file in {}", dir.display()))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_str()
+                .and_then(|name| name.strip_prefix(&copy_prefix))
+                .is_some_and(|suffix| suffix.len() == 20 && suffix.ends_with('Z') && suffix.as_bytes()[8] == b'T'))
+            .map(|entry| entry.path())
+            .collect();
+
+        copies.sort();
+
+        let excess = copies.len().saturating_sub(self.keep);
+
+        for copy in &copies[..excess] {
+            fs::remove_file(copy)
+                .chain_err(|| format!("Unable to remove old copy of output file at {}", copy.display()))?;
+        }
+
+        Ok(())
+    }
+}
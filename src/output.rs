@@ -0,0 +1,842 @@
+use chrono::{SecondsFormat, Utc};
+use ack;
+use common::StatusStorageInfo;
+use errors::*;
+use lsf;
+use libc;
+use serde::Serialize;
+use serde_json::{self, Value};
+use signing::Signer;
+use sinks;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::env;
+use std::slice;
+use std::str::FromStr;
+use {ALERT, DOWNTIME, FAILED, PASSED};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    Ndjson,
+    Yaml,
+    Csv,
+    Table,
+    Nagios,
+    Influx,
+    Sensu,
+    Collectd,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<OutputFormat> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "csv" => Ok(OutputFormat::Csv),
+            "table" => Ok(OutputFormat::Table),
+            "nagios" => Ok(OutputFormat::Nagios),
+            "influx" => Ok(OutputFormat::Influx),
+            "sensu" => Ok(OutputFormat::Sensu),
+            "collectd" => Ok(OutputFormat::Collectd),
+            _ => bail!("Unknown output format {}, expected json, ndjson, yaml, csv, table, nagios, influx, sensu or collectd", s),
+        }
+    }
+}
+
+/// Shape of the printed records. Version 1 is the original shape, without the
+/// fields added since, for consumers that have not yet been updated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SchemaVersion {
+    V1,
+    V2,
+}
+
+impl FromStr for SchemaVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SchemaVersion> {
+        match s {
+            "1" => Ok(SchemaVersion::V1),
+            "2" => Ok(SchemaVersion::V2),
+            _ => bail!("Unknown schema version {}, expected 1 or 2", s),
+        }
+    }
+}
+
+/// Converts the records into the given schema version. Version 1 drops the
+/// fields it did not have, and reports DOWNTIME, which it did not know, as
+/// ALERT.
+pub fn to_schema_version(version: SchemaVersion, records: &[StatusStorageInfo]) -> Vec<StatusStorageInfo> {
+    match version {
+        SchemaVersion::V2 => records.to_vec(),
+
+        SchemaVersion::V1 => records.iter()
+            .map(|record| StatusStorageInfo::new(
+                record.name.clone(),
+                if record.status == DOWNTIME { ALERT } else { record.status },
+                record.storage.clone(),
+                record.critical_group_name.clone(),
+                record.remarks.clone()))
+            .collect(),
+    }
+}
+
+/// Order of the printed records, as `name`, `status` or a load index name
+/// such as `r1m`, optionally followed by `:asc` (default) or `:desc`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+    pub key: String,
+    pub descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SortKey> {
+        let (key, descending) = match s.rfind(':') {
+            Some(i) => match &s[i + 1..] {
+                "asc" => (&s[..i], false),
+                "desc" => (&s[..i], true),
+                order => bail!("Unknown sort order {}, expected asc or desc", order),
+            },
+
+            None => (s, false),
+        };
+
+        if key.is_empty() {
+            bail!("Sort key must not be empty");
+        }
+
+        Ok(SortKey { key: key.to_owned(), descending })
+    }
+}
+
+/// Least severe first, so that descending order puts the worst first.
+fn status_rank(status: i32) -> u8 {
+    match status {
+        PASSED => 0,
+        DOWNTIME => 1,
+        ALERT => 2,
+        FAILED => 3,
+        _ => 4,
+    }
+}
+
+/// Sorts the records by the key, with ties broken by name. Records without the
+/// load index go last in either order.
+pub fn sort(records: &mut [StatusStorageInfo], sort_key: &SortKey) {
+    let directed = |ordering: Ordering| if sort_key.descending { ordering.reverse() } else { ordering };
+
+    records.sort_by(|a, b| {
+        let ordering = match sort_key.key.as_str() {
+            "name" => directed(a.name.cmp(&b.name)),
+            "status" => directed(status_rank(a.status).cmp(&status_rank(b.status))),
+
+            index => {
+                let value = |record: &StatusStorageInfo| record.load_indices.as_ref()
+                    .and_then(|load_indices| load_indices.get(index).cloned());
+
+                match (value(a), value(b)) {
+                    (Some(a), Some(b)) => directed(a.partial_cmp(&b).unwrap_or(Ordering::Equal)),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                }
+            },
+        };
+
+        ordering.then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+/// Sorts the records by the key, if any, and converts them into the schema
+/// version. Sorting comes first, as version 1 drops the load indices.
+pub fn arrange(version: SchemaVersion, sort_key: Option<&SortKey>, mut records: Vec<StatusStorageInfo>) -> Vec<StatusStorageInfo> {
+    if let Some(sort_key) = sort_key {
+        sort(&mut records, sort_key);
+    }
+
+    to_schema_version(version, &records)
+}
+
+/// Metadata of the poll that output may be wrapped in, with the records
+/// under `hosts`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub timestamp: String,
+    pub agent_host: String,
+    pub agent_version: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster_name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lsf_version: Option<String>,
+
+    pub poll_duration_ms: u64,
+
+    /// Number of hosts queried, which may be more than the records output
+    pub host_count: usize,
+}
+
+impl OutputFormat {
+    /// Whether the format is a document that records can be enveloped in.
+    pub fn supports_envelope(&self) -> bool {
+        matches!(*self, OutputFormat::Json | OutputFormat::Ndjson | OutputFormat::Yaml)
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EnvelopedRecords<'a> {
+    timestamp: &'a str,
+    agent_host: &'a str,
+    agent_version: &'a str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster_name: &'a Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lsf_version: &'a Option<String>,
+
+    poll_duration_ms: u64,
+    host_count: usize,
+
+    /// Signature of the `hosts` as compact JSON at the `timestamp`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature_key_id: Option<&'a str>,
+
+    hosts: &'a [StatusStorageInfo],
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<&'a Summary>,
+}
+
+/// Headline counts of a poll, for consumers that do not need every record.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub total: usize,
+    pub passed: usize,
+    pub alert: usize,
+    pub failed: usize,
+    pub downtime: usize,
+
+    /// Names of the records that are not OK
+    pub failing: Vec<String>,
+}
+
+pub fn summarize(records: &[StatusStorageInfo]) -> Summary {
+    let count = |status| records.iter().filter(|record| record.status == status).count();
+
+    Summary {
+        total: records.len(),
+        passed: count(PASSED),
+        alert: count(ALERT),
+        failed: count(FAILED),
+        downtime: count(DOWNTIME),
+        failing: records.iter()
+            .filter(|record| !ack::is_ok(record))
+            .map(|record| record.name.clone())
+            .collect(),
+    }
+}
+
+/// Single line document emitted per poll in NDJSON format.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NdjsonLine<'a> {
+    timestamp: String,
+    hosts: &'a [StatusStorageInfo],
+}
+
+// Nagios plugin exit codes
+pub const NAGIOS_OK: i32 = 0;
+pub const NAGIOS_WARNING: i32 = 1;
+pub const NAGIOS_CRITICAL: i32 = 2;
+pub const NAGIOS_UNKNOWN: i32 = 3;
+
+pub fn to_check_status_str(status: i32) -> &'static str {
+    match status {
+        PASSED => "PASSED",
+        ALERT => "ALERT",
+        FAILED => "FAILED",
+        DOWNTIME => "DOWNTIME",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Options of `render_with` beyond the format.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderOptions<'a> {
+    /// Wraps the records of formats that support it
+    pub envelope: Option<&'a Envelope>,
+
+    /// Included alongside the records in the envelope, or rendered instead of
+    /// the records without one
+    pub summary: Option<&'a Summary>,
+
+    /// Colors the statuses of tables with ANSI escapes
+    pub color: bool,
+
+    /// Signs the records in the envelope
+    pub signer: Option<&'a Signer>,
+
+    /// Interval of the collectd values, when polling continuously
+    pub interval_secs: Option<u64>,
+}
+
+/// Renders the list of records in the given output format.
+pub fn render(format: OutputFormat, records: &[StatusStorageInfo]) -> Result<String> {
+    render_with(format, records, &RenderOptions::default())
+}
+
+/// Renders the list of records like `render`, with the given options.
+pub fn render_with(format: OutputFormat, records: &[StatusStorageInfo], options: &RenderOptions) -> Result<String> {
+    match (options.envelope, options.summary) {
+        (Some(envelope), summary) if format.supports_envelope() => {
+            let signature = match options.signer {
+                Some(signer) => Some(signer.sign(&envelope.timestamp, serde_json::to_string(records)
+                    .chain_err(|| "Unable to serialize list of status storage into string!")?.as_bytes())),
+                None => None,
+            };
+
+            let enveloped = EnvelopedRecords {
+                timestamp: &envelope.timestamp,
+                agent_host: &envelope.agent_host,
+                agent_version: &envelope.agent_version,
+                cluster_name: &envelope.cluster_name,
+                lsf_version: &envelope.lsf_version,
+                poll_duration_ms: envelope.poll_duration_ms,
+                host_count: envelope.host_count,
+                signature,
+                signature_key_id: options.signer.and_then(|signer| signer.key_id.as_deref()),
+                hosts: records,
+                summary,
+            };
+
+            return render_document(format, &enveloped);
+        },
+
+        (None, Some(summary)) if format.supports_envelope() => return render_document(format, summary),
+        _ => (),
+    }
+
+    match format {
+        OutputFormat::Json => serde_json::to_string(records)
+            .chain_err(|| "Unable to serialize list of status storage into string!"),
+
+        OutputFormat::Ndjson => {
+            let line = NdjsonLine {
+                timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                hosts: records,
+            };
+
+            serde_json::to_string(&line)
+                .chain_err(|| "Unable to serialize NDJSON line into string!")
+        },
+
+        OutputFormat::Yaml => {
+            let value = serde_json::to_value(records)
+                .chain_err(|| "Unable to convert list of status storage into value!")?;
+
+            let mut buf = String::new();
+            write_yaml(&mut buf, &value, 0);
+            Ok(buf.trim_end().to_owned())
+        },
+
+        OutputFormat::Csv => {
+            let (columns, rows) = to_rows(records)?;
+            let mut lines = vec![columns.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(",")];
+
+            for row in &rows {
+                lines.push(row.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+            }
+
+            Ok(lines.join("\n"))
+        },
+
+        OutputFormat::Table => {
+            let (columns, rows) = to_rows(records)?;
+            Ok(render_table_with(&columns, &rows, options.color))
+        },
+
+        OutputFormat::Nagios => Ok(render_nagios(records)),
+        OutputFormat::Influx => Ok(render_influx(records)),
+
+        OutputFormat::Sensu => {
+            let events: Vec<Value> = records.iter()
+                .map(|record| sensu_event(record, DEFAULT_SENSU_CHECK_NAME, &[]))
+                .collect();
+
+            serde_json::to_string(&events)
+                .chain_err(|| "Unable to serialize Sensu events into string!")
+        },
+
+        OutputFormat::Collectd => Ok(render_collectd(records, options.interval_secs.or_else(collectd_interval))),
+    }
+}
+
+/// Renders a single document as YAML, or as JSON on a single line otherwise.
+fn render_document<T: Serialize>(format: OutputFormat, document: &T) -> Result<String> {
+    match format {
+        OutputFormat::Yaml => {
+            let value = serde_json::to_value(document)
+                .chain_err(|| "Unable to convert document into value!")?;
+
+            let mut buf = String::new();
+            write_yaml(&mut buf, &value, 0);
+            Ok(buf.trim_end().to_owned())
+        },
+
+        _ => serde_json::to_string(document)
+            .chain_err(|| "Unable to serialize document into string!"),
+    }
+}
+
+/// Maps the records into the Nagios plugin exit code convention.
+pub fn nagios_exit_code(records: &[StatusStorageInfo]) -> i32 {
+    let counted = || records.iter().filter(|record| !record.acknowledged);
+
+    if counted().any(|record| record.status == FAILED) {
+        NAGIOS_CRITICAL
+    } else if counted().any(|record| record.status == ALERT) {
+        NAGIOS_WARNING
+    } else if counted().all(|record| record.status == PASSED || record.status == DOWNTIME) {
+        NAGIOS_OK
+    } else {
+        NAGIOS_UNKNOWN
+    }
+}
+
+pub fn nagios_state_str(exit_code: i32) -> &'static str {
+    match exit_code {
+        NAGIOS_OK => "OK",
+        NAGIOS_WARNING => "WARNING",
+        NAGIOS_CRITICAL => "CRITICAL",
+        _ => "UNKNOWN",
+    }
+}
+
+fn render_nagios(records: &[StatusStorageInfo]) -> String {
+    let count = |status| records.iter().filter(|record| record.status == status).count();
+    let (passed, alert, failed, downtime) = (count(PASSED), count(ALERT), count(FAILED), count(DOWNTIME));
+
+    let not_passed: Vec<&str> = records.iter()
+        .filter(|record| !ack::is_ok(record))
+        .map(|record| record.name.as_str())
+        .collect();
+
+    let summary = if not_passed.is_empty() {
+        format!("{} hosts OK", records.len())
+    } else {
+        format!("{} of {} hosts not OK: {}", not_passed.len(), records.len(), not_passed.join(", "))
+    };
+
+    let mut perfdata = vec![
+        format!("hosts={};;;0", records.len()),
+        format!("passed={};;;0", passed),
+        format!("alert={};;;0", alert),
+        format!("failed={};;;0", failed),
+        format!("downtime={};;;0", downtime),
+    ];
+
+    for record in records {
+        if let Some(ref load_indices) = record.load_indices {
+            for (index, value) in load_indices {
+                // single quotes in labels are escaped by doubling them
+                perfdata.push(format!("'{}_{}'={}", record.name.replace('\'', "''"), index, value));
+            }
+        }
+    }
+
+    format!("LSF {} - {} | {}",
+        nagios_state_str(nagios_exit_code(records)), summary, perfdata.join(" "))
+}
+
+/// Flattens the records into a header row and value rows, where nested
+/// objects become dotted column names. Columns appear in first-seen order.
+fn to_rows(records: &[StatusStorageInfo]) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+    let mut columns: Vec<String> = Vec::new();
+    let mut flat_records = Vec::new();
+
+    for record in records {
+        let value = serde_json::to_value(record)
+            .chain_err(|| "Unable to convert status storage into value!")?;
+
+        let mut fields = Vec::new();
+        flatten("", &value, &mut fields);
+
+        for (key, _) in &fields {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+
+        flat_records.push(fields);
+    }
+
+    // identifying columns go first, the rest keep their serialized order
+    columns.sort_by_key(|column| match column.as_str() {
+        "name" => 0,
+        "status" => 1,
+        _ => 2,
+    });
+
+    let rows = flat_records.into_iter()
+        .map(|fields| {
+            columns.iter()
+                .map(|column| {
+                    fields.iter()
+                        .find(|&(key, _)| key == column)
+                        .map(|(_, value)| value.clone())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok((columns, rows))
+}
+
+fn flatten(prefix: &str, value: &Value, fields: &mut Vec<(String, String)>) {
+    match *value {
+        Value::Object(ref map) => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(&key, value, fields);
+            }
+        },
+
+        Value::Null => fields.push((prefix.to_owned(), String::new())),
+        Value::String(ref s) => fields.push((prefix.to_owned(), s.clone())),
+        ref other => fields.push((prefix.to_owned(), other.to_string())),
+    }
+}
+
+/// Quotes the field for CSV when needed.
+pub fn csv_field(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') || field.contains('\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Whether color was not disabled through `NO_COLOR` and stdout is a
+/// terminal.
+pub fn color_supported() -> bool {
+    env::var_os("NO_COLOR").map(|no_color| no_color.is_empty()).unwrap_or(true)
+        && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1
+}
+
+fn status_color(status: i32) -> &'static str {
+    match status {
+        PASSED => "\x1b[32m",
+        ALERT => "\x1b[33m",
+        FAILED => "\x1b[31m",
+        DOWNTIME => "\x1b[34m",
+        _ => "",
+    }
+}
+
+/// Renders rows as aligned columns with uppercase headers, where cells of a
+/// `status` column are shown by name.
+pub fn render_table(columns: &[String], rows: &[Vec<String>]) -> String {
+    render_table_with(columns, rows, false)
+}
+
+/// Renders rows like `render_table`, with the statuses colored green, yellow
+/// and red if enabled.
+pub fn render_table_with(columns: &[String], rows: &[Vec<String>], color: bool) -> String {
+    let status_idx = columns.iter().position(|c| c == "status");
+
+    let statuses: Vec<Option<i32>> = rows.iter()
+        .map(|row| status_idx.and_then(|i| row[i].parse::<i32>().ok()))
+        .collect();
+
+    let rows: Vec<Vec<String>> = rows.iter()
+        .zip(&statuses)
+        .map(|(row, status)| {
+            row.iter()
+                .enumerate()
+                .map(|(i, cell)| match (Some(i) == status_idx, *status) {
+                    (true, Some(status)) => to_check_status_str(status).to_owned(),
+                    _ => cell.clone(),
+                })
+                .collect()
+        })
+        .collect();
+
+    let headers: Vec<String> = columns.iter().map(|c| c.to_uppercase()).collect();
+
+    let widths: Vec<usize> = headers.iter()
+        .enumerate()
+        .map(|(i, header)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(Some(header.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    // escapes are added after padding, so that they do not count as width
+    let format_row = |row: &[String], status: Option<i32>| {
+        row.iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (cell, &width))| match (color && Some(i) == status_idx, status) {
+                (true, Some(status)) => format!("{}{}\x1b[0m{}",
+                    status_color(status), cell, " ".repeat(width - cell.chars().count())),
+                _ => format!("{:width$}", cell, width = width),
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+
+    let mut lines = vec![format_row(&headers, None)];
+    lines.extend(rows.iter().zip(&statuses).map(|(row, &status)| format_row(row, status)));
+    lines.join("\n")
+}
+
+fn write_yaml(buf: &mut String, value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+
+    match *value {
+        Value::Array(ref items) if items.is_empty() => buf.push_str(&format!("{}[]\n", pad)),
+
+        Value::Array(ref items) => {
+            for item in items {
+                match *item {
+                    Value::Object(ref map) if !map.is_empty() => {
+                        // first key shares the line with the sequence dash
+                        let mut nested = String::new();
+                        write_yaml(&mut nested, item, indent + 2);
+                        buf.push_str(&format!("{}- {}", pad, &nested[indent + 2..]));
+                    },
+
+                    _ => buf.push_str(&format!("{}- {}\n", pad, yaml_scalar(item))),
+                }
+            }
+        },
+
+        Value::Object(ref map) if map.is_empty() => buf.push_str(&format!("{}{{}}\n", pad)),
+
+        Value::Object(ref map) => {
+            for (key, value) in map {
+                match *value {
+                    Value::Object(ref m) if !m.is_empty() => {
+                        buf.push_str(&format!("{}{}:\n", pad, yaml_key(key)));
+                        write_yaml(buf, value, indent + 2);
+                    },
+
+                    Value::Array(ref a) if !a.is_empty() => {
+                        buf.push_str(&format!("{}{}:\n", pad, yaml_key(key)));
+                        write_yaml(buf, value, indent + 2);
+                    },
+
+                    _ => buf.push_str(&format!("{}{}: {}\n", pad, yaml_key(key), yaml_scalar(value))),
+                }
+            }
+        },
+
+        _ => buf.push_str(&format!("{}{}\n", pad, yaml_scalar(value))),
+    }
+}
+
+// keys such as `on` or `a: b` would otherwise not read back as strings
+fn yaml_key(key: &str) -> String {
+    Value::String(key.to_owned()).to_string()
+}
+
+fn yaml_scalar(value: &Value) -> String {
+    match *value {
+        Value::Null => "null".to_owned(),
+        Value::Array(ref a) if a.is_empty() => "[]".to_owned(),
+        Value::Object(ref m) if m.is_empty() => "{}".to_owned(),
+
+        // JSON strings are valid YAML double-quoted scalars
+        ref other => other.to_string(),
+    }
+}
+
+/// Renders one InfluxDB line protocol measurement per record, suitable for the
+/// Telegraf `inputs.exec` plugin.
+fn render_influx(records: &[StatusStorageInfo]) -> String {
+    let timestamp = Utc::now().timestamp_nanos_opt().unwrap_or(0);
+
+    records.iter()
+        .map(|record| {
+            let mut tags = format!("lsf_host,host={}", influx_escape(&record.name));
+
+            if let Some(lim_status) = record.lim_status {
+                tags.push_str(&format!(",status={}", lsf::to_status_str(lim_status)));
+            }
+
+            // the line protocol rejects empty tag values
+            if let Some(critical_group_name) = record.critical_group_name.as_ref().filter(|name| !name.is_empty()) {
+                tags.push_str(&format!(",critical_group={}", influx_escape(critical_group_name)));
+            }
+
+            // the tags of the agent itself take precedence
+            for (key, value) in record.labels.iter().flatten()
+                .filter(|&(key, value)| !value.is_empty() && key != "host" && key != "status" && key != "critical_group") {
+
+                tags.push_str(&format!(",{}={}", influx_escape(key), influx_escape(value)));
+            }
+
+            let mut fields = vec![format!("check_status={}i", record.status)];
+
+            if let Some(lim_status) = record.lim_status {
+                fields.push(format!("lim_status={}i", lim_status));
+            }
+
+            if let Some(ref load_indices) = record.load_indices {
+                fields.extend(load_indices.iter()
+                    .map(|(index, value)| format!("{}={}", influx_escape(index), value)));
+            }
+
+            format!("{} {} {}", tags, fields.join(","), timestamp)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Interval collectd runs the exec plugin with, from `COLLECTD_INTERVAL`.
+pub fn collectd_interval() -> Option<u64> {
+    env::var("COLLECTD_INTERVAL").ok()
+        .and_then(|interval| interval.parse::<f64>().ok())
+        .filter(|&interval| interval >= 1.0)
+        .map(|interval| interval.round() as u64)
+}
+
+/// Replaces the characters that separate or quote the parts of collectd
+/// identifiers.
+fn collectd_component(name: &str) -> String {
+    name.chars()
+        .map(|c| if c == '/' || c == '"' || c == '\\' { '_' } else { c })
+        .collect()
+}
+
+/// Renders the metrics of every record as collectd exec plugin `PUTVAL`
+/// lines, with the record as the host and the metric as the instance of a
+/// `gauge` of the `lsf` plugin.
+fn render_collectd(records: &[StatusStorageInfo], interval_secs: Option<u64>) -> String {
+    let interval = interval_secs.map(|interval| format!(" interval={}", interval)).unwrap_or_default();
+
+    records.iter()
+        .flat_map(|record| {
+            let host = collectd_component(&record.name);
+            let interval = &interval;
+
+            sinks::record_metrics(record).into_iter()
+                .map(move |(metric, value)| format!("PUTVAL \"{}/lsf/gauge-{}\"{} N:{}",
+                    host, sinks::metric_component(&metric), interval, value))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub const DEFAULT_SENSU_CHECK_NAME: &str = "lsf_host";
+
+/// Replaces the characters Sensu does not allow in resource names.
+fn sensu_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// Builds the event of the record for the Sensu Go agent events API, as the
+/// result of the check on the proxy entity of the host, with the Nagios state
+/// of the record as its status.
+pub fn sensu_event(record: &StatusStorageInfo, check_name: &str, handlers: &[String]) -> Value {
+    let mut labels: BTreeMap<&str, &str> = record.labels.iter().flatten()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+
+    if let Some(ref critical_group_name) = record.critical_group_name {
+        labels.insert("critical_group", critical_group_name);
+    }
+
+    let check_output = match record.remarks {
+        Some(ref remarks) => format!("{}: {}", to_check_status_str(record.status), remarks),
+        None => to_check_status_str(record.status).to_owned(),
+    };
+
+    let mut check = json!({
+        "metadata": { "name": check_name, "labels": labels },
+        "status": nagios_exit_code(slice::from_ref(record)),
+        "output": check_output,
+        "proxy_entity_name": sensu_name(&record.name),
+    });
+
+    if !handlers.is_empty() {
+        check["handlers"] = json!(handlers);
+    }
+
+    json!({ "check": check })
+}
+
+fn influx_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_keys_are_quoted() {
+        let mut buf = String::new();
+        write_yaml(&mut buf, &json!({ "on": "x", "a: b": { "yes": [1] } }), 0);
+        assert_eq!(buf, "\"a: b\":\n  \"yes\":\n    - 1\n\"on\": \"x\"\n");
+    }
+
+    #[test]
+    fn csv_quotes_carriage_returns() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a\rb"), "\"a\rb\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn influx_skips_empty_tags() {
+        let mut record = StatusStorageInfo::new("h1".to_owned(), PASSED, None, Some(String::new()), None);
+        record.labels = Some([("rack", ""), ("site", "eu")].iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect());
+
+        let line = render_influx(&[record]);
+        assert!(line.starts_with("lsf_host,host=h1,site=eu check_status=0i "), "{}", line);
+    }
+
+    #[test]
+    fn sorts_by_load_index_before_dropping_it() {
+        let record = |name: &str, r1m: f32| {
+            let mut record = StatusStorageInfo::new(name.to_owned(), PASSED, None, None, None);
+            record.load_indices = Some(vec![("r1m".to_owned(), r1m)].into_iter().collect());
+            record
+        };
+
+        let sort_key = SortKey::from_str("r1m:desc").unwrap();
+        let records = vec![record("a", 1.0), record("b", 3.0), record("c", 2.0)];
+
+        let names: Vec<_> = arrange(SchemaVersion::V1, Some(&sort_key), records).into_iter()
+            .map(|record| record.name)
+            .collect();
+
+        assert_eq!(names, vec!["b", "c", "a"]);
+    }
+}
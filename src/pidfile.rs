@@ -0,0 +1,46 @@
+use errors::*;
+use libc;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::process;
+
+/// Exclusively locked PID file, held for as long as the value lives.
+pub struct PidFile {
+    _file: File,
+}
+
+impl PidFile {
+    /// Locks the PID file and writes the current PID into it. Returns `None`
+    /// if another instance holds the lock and `wait` is not set.
+    pub fn acquire(path: &str, wait: bool) -> Result<Option<PidFile>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .chain_err(|| format!("Unable to open PID file at {}", path))?;
+
+        let operation = if wait { libc::LOCK_EX } else { libc::LOCK_EX | libc::LOCK_NB };
+
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } != 0 {
+            let e = io::Error::last_os_error();
+
+            if e.kind() == io::ErrorKind::WouldBlock {
+                return Ok(None);
+            }
+
+            return Err(e).chain_err(|| format!("Unable to lock PID file at {}", path));
+        }
+
+        // only truncate once the lock is ours, the file is not removed on exit
+        // so that waiting instances keep contending on the same inode
+        file.set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| writeln!(file, "{}", process::id()))
+            .chain_err(|| format!("Unable to write PID into PID file at {}", path))?;
+
+        Ok(Some(PidFile { _file: file }))
+    }
+}
@@ -0,0 +1,191 @@
+use errors::*;
+use lsf::{self, HostLoad};
+use retry::RetryConfig;
+use serde_json;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use worker;
+
+/// How LIM gets queried on every poll.
+#[derive(Clone, Debug)]
+pub struct QuerySettings {
+    pub options: i32,
+    pub from_host: Option<String>,
+
+    /// External (ELIM) load indices queried along with the built-in ones
+    pub indices: Vec<String>,
+
+    /// Additional views of the run queue lengths, as index name suffix and
+    /// the ls_load option flag queried for it
+    pub views: Vec<(String, i32)>,
+
+    pub timeout: Option<Duration>,
+    pub isolate: bool,
+    pub retry: RetryConfig,
+
+    /// File to write the decoded results of the queries of every poll into
+    pub record: Option<String>,
+
+    /// Results of recorded queries to use instead of querying LIM
+    pub replay: Option<Vec<RecordedQuery>>,
+}
+
+/// Decoded result of a query, as written by `--record`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedQuery {
+    pub options: i32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_loads: Option<Vec<HostLoad>>,
+
+    #[serde(default)]
+    pub duration_ms: u64,
+
+    /// Reason of a failed query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RecordedQuery {
+    pub fn new(options: i32, cluster_query: &ClusterQuery) -> RecordedQuery {
+        let (host_loads, error) = match cluster_query.host_loads {
+            Ok(ref host_loads) => (Some(host_loads.clone()), None),
+            Err(ref e) => (None, Some(e.to_string())),
+        };
+
+        RecordedQuery {
+            options,
+            cluster: cluster_query.cluster.clone(),
+            host_loads,
+            duration_ms: cluster_query.duration.as_millis() as u64,
+            error,
+        }
+    }
+}
+
+/// Writes the recorded queries of a poll into the file.
+pub fn save_recording(path: &str, queries: &[RecordedQuery]) -> Result<()> {
+    let content = serde_json::to_string_pretty(queries)
+        .chain_err(|| "Unable to serialize recorded queries into string!")?;
+
+    File::create(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .chain_err(|| format!("Unable to write recording at {}", path))
+}
+
+/// Reads the recorded queries written by `save_recording`.
+pub fn load_recording(path: &str) -> Result<Vec<RecordedQuery>> {
+    let mut buf = String::new();
+
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut buf))
+        .chain_err(|| format!("Unable to read recording at {}", path))?;
+
+    serde_json::from_str(&buf)
+        .chain_err(|| format!("Unable to parse recording at {}", path))
+}
+
+fn replay(queries: &[RecordedQuery], options: i32, cluster: Option<&str>) -> ClusterQuery {
+    let recorded = queries.iter()
+        .find(|query| query.options == options && query.cluster.as_deref() == cluster);
+
+    let duration = Duration::from_millis(recorded.map_or(0, |query| query.duration_ms));
+
+    let host_loads = match recorded {
+        Some(&RecordedQuery { host_loads: Some(ref host_loads), .. }) => Ok(host_loads.clone()),
+        Some(&RecordedQuery { error: Some(ref error), .. }) => Err(ErrorKind::QueryFailed(error.clone()).into()),
+        _ => Err(ErrorKind::QueryFailed(format!("no query with options {} in the recording", options)).into()),
+    };
+
+    ClusterQuery { cluster: cluster.map(|cluster| cluster.to_owned()), host_loads, duration }
+}
+
+/// Hosts returned for one cluster, or for all clusters at once if `cluster`
+/// is `None`.
+pub struct ClusterQuery {
+    pub cluster: Option<String>,
+    pub host_loads: Result<Vec<HostLoad>>,
+
+    /// Time taken by the query, retries included
+    pub duration: Duration,
+}
+
+fn timed_query(settings: &QuerySettings, cluster: Option<&str>) -> ClusterQuery {
+    let started = Instant::now();
+    let host_loads = query(settings, cluster);
+
+    ClusterQuery { cluster: cluster.map(|cluster| cluster.to_owned()), host_loads, duration: started.elapsed() }
+}
+
+fn query_once(settings: &QuerySettings, cluster: Option<&str>) -> Result<Vec<HostLoad>> {
+    if settings.isolate || cluster.is_some() {
+        worker::load_isolated(settings.options, settings.from_host.as_deref(), cluster, &settings.indices, settings.timeout)
+    } else {
+        lsf::load_with_timeout(settings.options, settings.from_host.clone(), settings.indices.clone(), settings.timeout)
+    }
+}
+
+/// Queries LIM, retrying with backoff while no hosts are returned.
+pub fn query(settings: &QuerySettings, cluster: Option<&str>) -> Result<Vec<HostLoad>> {
+    let mut host_loads = query_once(settings, cluster);
+
+    // LIM may be briefly unreachable, e.g. during master election
+    for attempt in 0..settings.retry.attempts {
+        match host_loads {
+            Ok(ref host_loads) if !host_loads.is_empty() => break,
+            Ok(_) | Err(Error(ErrorKind::QueryFailed(_), _)) => (),
+            Err(_) => break,
+        }
+
+        let delay = settings.retry.delay(attempt);
+
+        warn!(cluster = ?cluster, delay_millis = delay.as_millis() as u64, attempt = attempt + 1,
+            attempts = settings.retry.attempts, "No hosts returned by LIM, retrying");
+
+        thread::sleep(delay);
+        host_loads = query_once(settings, cluster);
+    }
+
+    host_loads
+}
+
+/// Queries every cluster in its own thread and worker process, since liblsf
+/// cannot be entered concurrently within a process. Without explicit
+/// clusters, a single `ls_load` call covers all of them.
+pub fn query_all(settings: &QuerySettings, clusters: &[String]) -> Vec<ClusterQuery> {
+    if let Some(ref queries) = settings.replay {
+        return if clusters.is_empty() {
+            vec![replay(queries, settings.options, None)]
+        } else {
+            clusters.iter().map(|cluster| replay(queries, settings.options, Some(cluster))).collect()
+        };
+    }
+
+    if clusters.is_empty() {
+        return vec![timed_query(settings, None)];
+    }
+
+    let handles: Vec<_> = clusters.iter()
+        .map(|cluster| {
+            let settings = settings.clone();
+            let cluster = cluster.clone();
+
+            thread::spawn(move || timed_query(&settings, Some(&cluster)))
+        })
+        .collect();
+
+    handles.into_iter()
+        .zip(clusters)
+        .map(|(handle, cluster)| handle.join().unwrap_or_else(|_| ClusterQuery {
+            cluster: Some(cluster.clone()),
+            host_loads: Err(ErrorKind::QueryFailed("poller thread panicked".to_owned()).into()),
+            duration: Duration::from_secs(0),
+        }))
+        .collect()
+}
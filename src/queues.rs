@@ -0,0 +1,94 @@
+use common::StatusStorageInfo;
+use lsf::{self, CUR_JOB, JOB_STAT_PEND, JOB_STAT_PSUSP, JOB_STAT_RUN};
+use std::collections::BTreeMap;
+use {ALERT, FAILED, PASSED};
+
+/// Backlog levels of a queue, reached once its pending jobs or its ratio of
+/// pending to running jobs are above them.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueThreshold {
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_pending: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_pending: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_pending_ratio: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_pending_ratio: Option<f64>,
+}
+
+fn above_ratio(pending: usize, running: usize, ratio: Option<f64>) -> bool {
+    match ratio {
+        // pending jobs with none running at all is the worst backlog
+        Some(_) if running == 0 => pending > 0,
+        Some(ratio) => pending as f64 / running as f64 > ratio,
+        None => false,
+    }
+}
+
+fn to_record(threshold: &QueueThreshold, pending: usize, running: usize, name: String, critical_group_name: &str) -> StatusStorageInfo {
+    let above_pending = |level: Option<usize>| level.is_some_and(|level| pending > level);
+
+    let status = if above_pending(threshold.failed_pending) || above_ratio(pending, running, threshold.failed_pending_ratio) {
+        FAILED
+    } else if above_pending(threshold.alert_pending) || above_ratio(pending, running, threshold.alert_pending_ratio) {
+        ALERT
+    } else {
+        PASSED
+    };
+
+    let mut record = StatusStorageInfo::new(name, status, None, Some(critical_group_name.to_owned()),
+        Some(format!("Queue {}: {} pending, {} running", threshold.name, pending, running)));
+
+    let mut load_indices = BTreeMap::new();
+    load_indices.insert("pending".to_owned(), pending as f32);
+    load_indices.insert("running".to_owned(), running as f32);
+    record.load_indices = Some(load_indices);
+
+    record
+}
+
+/// Builds a `{prefix}queue:{name}` record per configured queue from the jobs
+/// known to mbatchd. All of them are FAILED if mbatchd cannot be queried.
+pub fn check(thresholds: &[QueueThreshold], prefix: &str, critical_group_name: &str) -> Vec<StatusStorageInfo> {
+    let name = |threshold: &QueueThreshold| format!("{}queue:{}", prefix, threshold.name);
+
+    let jobs = match lsf::jobs(CUR_JOB) {
+        Ok(jobs) => jobs,
+
+        Err(e) => return thresholds.iter()
+            .map(|threshold| StatusStorageInfo::new(
+                name(threshold),
+                FAILED,
+                None,
+                Some(critical_group_name.to_owned()),
+                Some(format!("Unable to query the jobs of queue {}: {}", threshold.name, e))))
+            .collect(),
+    };
+
+    // pending and running jobs by queue
+    let mut counts: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+
+    for job in &jobs {
+        let count = counts.entry(job.queue.as_str()).or_insert((0, 0));
+
+        if job.status & (JOB_STAT_PEND | JOB_STAT_PSUSP) != 0 {
+            count.0 += 1;
+        } else if job.status & JOB_STAT_RUN != 0 {
+            count.1 += 1;
+        }
+    }
+
+    thresholds.iter()
+        .map(|threshold| {
+            let (pending, running) = counts.get(threshold.name.as_str()).cloned().unwrap_or((0, 0));
+            to_record(threshold, pending, running, name(threshold), critical_group_name)
+        })
+        .collect()
+}
@@ -0,0 +1,55 @@
+use common::StatusStorageInfo;
+use errors::*;
+use lsf::LIM_OK;
+use {FAILED, PASSED};
+
+fn default_name() -> String {
+    "cluster".to_owned()
+}
+
+/// Minimum number or percentage of hosts that LIM reports as OK for the
+/// cluster to count as usable.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QuorumConfig {
+    /// Record name without the prefix
+    #[serde(default = "default_name")]
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_hosts: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_percent: Option<f64>,
+}
+
+/// Builds the cluster health record from the records of the hosts queried
+/// from LIM, PASSED while the quorum of hosts is LIM_OK and FAILED below it.
+pub fn check(
+    records: &[StatusStorageInfo],
+    quorum: &QuorumConfig,
+    prefix: &str,
+    critical_group_name: &str) -> Result<StatusStorageInfo> {
+
+    let hosts = records.iter().filter(|record| record.lim_status.is_some()).count();
+    let ok_hosts = records.iter().filter(|record| record.lim_status == Some(LIM_OK)).count();
+
+    let required = match (quorum.min_hosts, quorum.min_percent) {
+        (Some(min_hosts), None) => min_hosts,
+        (None, Some(min_percent)) => (hosts as f64 * min_percent / 100.0).ceil() as usize,
+        _ => bail!("Quorum {} must have exactly one of minHosts and minPercent", quorum.name),
+    };
+
+    // a cluster without any hosts is never usable
+    let status = if ok_hosts >= required && ok_hosts > 0 { PASSED } else { FAILED };
+
+    let mut record = StatusStorageInfo::new(
+        format!("{}{}", prefix, quorum.name),
+        status,
+        None,
+        Some(critical_group_name.to_owned()),
+        Some(format!("{} of {} hosts LIM_OK, quorum of {}", ok_hosts, hosts, required)));
+
+    record.synthetic = true;
+    Ok(record)
+}
@@ -0,0 +1,362 @@
+use chrono::{DateTime, TimeZone, Utc};
+use errors::*;
+use history::HistoryEntry;
+use output;
+use serde_json;
+use std::collections::BTreeMap;
+use {ALERT, DOWNTIME, FAILED, PASSED};
+
+/// Load index drawn as the sparkline of each host in the HTML report,
+/// falling back to the first recorded index.
+const SPARKLINE_INDEX: &str = "r1m";
+const SPARKLINE_WIDTH: f32 = 120.0;
+const SPARKLINE_HEIGHT: f32 = 24.0;
+
+/// Availability of a host or a group of hosts over the report period.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Availability {
+    pub name: String,
+    pub availability_percent: f64,
+    pub incidents: u32,
+
+    /// Mean time to recovery, over the incidents that recovered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mttr_secs: Option<i64>,
+
+    pub samples: usize,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub since: i64,
+    pub until: i64,
+    pub hosts: Vec<Availability>,
+    pub groups: Vec<Availability>,
+}
+
+#[derive(Default)]
+struct Tally {
+    up_secs: i64,
+    total_secs: i64,
+    incidents: u32,
+    recovery_secs: Vec<i64>,
+    samples: usize,
+}
+
+impl Tally {
+    fn add(&mut self, other: &Tally) {
+        self.up_secs += other.up_secs;
+        self.total_secs += other.total_secs;
+        self.incidents += other.incidents;
+        self.recovery_secs.extend(&other.recovery_secs);
+        self.samples += other.samples;
+    }
+
+    fn to_availability(&self, name: &str) -> Availability {
+        let availability_percent = if self.total_secs > 0 {
+            self.up_secs as f64 * 100.0 / self.total_secs as f64
+        } else {
+            100.0
+        };
+
+        let mttr_secs = if self.recovery_secs.is_empty() {
+            None
+        } else {
+            Some(self.recovery_secs.iter().sum::<i64>() / self.recovery_secs.len() as i64)
+        };
+
+        Availability {
+            name: name.to_owned(),
+            availability_percent,
+            incidents: self.incidents,
+            mttr_secs,
+            samples: self.samples,
+        }
+    }
+}
+
+/// Parses durations such as `30d`, `12h`, `90m` or `3600s` into seconds.
+pub fn parse_duration(s: &str) -> Result<i64> {
+    let (value, unit) = s.split_at(s.len() - s.chars().last().map(|c| c.len_utf8()).unwrap_or(0));
+
+    let multiplier = match unit {
+        "d" => 24 * 60 * 60,
+        "h" => 60 * 60,
+        "m" => 60,
+        "s" => 1,
+        _ => bail!("Invalid duration {}, expected e.g. 30d, 12h, 90m or 3600s", s),
+    };
+
+    value.parse::<i64>()
+        .map(|value| value * multiplier)
+        .chain_err(|| format!("Invalid duration {}, expected e.g. 30d, 12h, 90m or 3600s", s))
+}
+
+/// Parses either a RFC 3339 time or a duration before `now` into Unix time.
+pub fn parse_time(s: &str, now: i64) -> Result<i64> {
+    match DateTime::parse_from_rfc3339(s) {
+        Ok(time) => Ok(time.timestamp()),
+        Err(_) => parse_duration(s)
+            .map(|duration| now - duration)
+            .chain_err(|| format!("Invalid time {}, expected a RFC 3339 time or a duration", s)),
+    }
+}
+
+/// Each poll counts from its own time until the next poll of the host, hosts
+/// only count as unavailable while FAILED.
+fn tally(entries: &[&HistoryEntry], until: i64) -> Tally {
+    let mut tally = Tally { samples: entries.len(), ..Tally::default() };
+    let mut incident_start = None;
+
+    for (i, entry) in entries.iter().enumerate() {
+        let end = entries.get(i + 1).map(|next| next.unix_time).unwrap_or(until);
+        let secs = (end - entry.unix_time).max(0);
+        let failed = entry.status == FAILED;
+
+        tally.total_secs += secs;
+
+        if failed {
+            if incident_start.is_none() {
+                incident_start = Some(entry.unix_time);
+                tally.incidents += 1;
+            }
+        } else {
+            tally.up_secs += secs;
+
+            if let Some(start) = incident_start.take() {
+                tally.recovery_secs.push(entry.unix_time - start);
+            }
+        }
+    }
+
+    tally
+}
+
+/// Computes the availability of every host and critical group from the
+/// history entries, given by host and oldest first.
+pub fn build(entries: &[HistoryEntry], since: i64, until: i64) -> Report {
+    let mut by_host: BTreeMap<&str, Vec<&HistoryEntry>> = BTreeMap::new();
+
+    for entry in entries {
+        by_host.entry(entry.name.as_str()).or_default().push(entry);
+    }
+
+    let mut groups: BTreeMap<String, Tally> = BTreeMap::new();
+    let mut hosts = Vec::new();
+
+    for (name, host_entries) in &by_host {
+        let host_tally = tally(host_entries, until);
+
+        // the latest group wins, should the host have moved
+        let group = host_entries.iter()
+            .rev()
+            .filter_map(|entry| entry.critical_group_name.clone())
+            .next()
+            .unwrap_or_default();
+
+        groups.entry(group).or_default().add(&host_tally);
+        hosts.push(host_tally.to_availability(name));
+    }
+
+    Report {
+        since,
+        until,
+        hosts,
+        groups: groups.iter().map(|(group, tally)| tally.to_availability(group)).collect(),
+    }
+}
+
+/// Renders the report as `json`, `table` or `html`, where the history entries
+/// the report was built from provide the details of the HTML page.
+pub fn render(report: &Report, entries: &[HistoryEntry], format: &str) -> Result<String> {
+    match format {
+        "json" => serde_json::to_string(report)
+            .chain_err(|| "Unable to serialize report into string!"),
+
+        "table" => {
+            let columns: Vec<String> = ["kind", "name", "availability", "incidents", "mttr", "samples"].iter()
+                .map(|column| column.to_string())
+                .collect();
+
+            let rows: Vec<Vec<String>> = report.hosts.iter().map(|host| ("host", host))
+                .chain(report.groups.iter().map(|group| ("group", group)))
+                .map(|(kind, availability)| vec![
+                    kind.to_owned(),
+                    availability.name.clone(),
+                    format!("{:.3}%", availability.availability_percent),
+                    availability.incidents.to_string(),
+                    availability.mttr_secs.map(|secs| format!("{}s", secs)).unwrap_or_default(),
+                    availability.samples.to_string(),
+                ])
+                .collect();
+
+            Ok(output::render_table(&columns, &rows))
+        },
+
+        "html" => Ok(render_html(report, entries)),
+
+        _ => bail!("Unknown report format {}, expected json, table or html", format),
+    }
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn format_time(unix_time: i64) -> String {
+    Utc.timestamp_opt(unix_time, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+fn status_class(status: i32) -> &'static str {
+    match status {
+        PASSED => "passed",
+        ALERT => "alert",
+        FAILED => "failed",
+        DOWNTIME => "downtime",
+        _ => "unknown",
+    }
+}
+
+/// Draws the load index over time as an inline SVG polyline, scaled to the
+/// range of the values.
+fn sparkline(entries: &[&HistoryEntry]) -> String {
+    let index = entries.iter()
+        .filter_map(|entry| entry.load_indices.as_ref())
+        .find_map(|load_indices| if load_indices.contains_key(SPARKLINE_INDEX) {
+            Some(SPARKLINE_INDEX.to_owned())
+        } else {
+            load_indices.keys().next().cloned()
+        });
+
+    let index = match index {
+        Some(index) => index,
+        None => return String::new(),
+    };
+
+    let values: Vec<f32> = entries.iter()
+        .filter_map(|entry| entry.load_indices.as_ref().and_then(|load_indices| load_indices.get(&index)))
+        .cloned()
+        .collect();
+
+    let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let step = if values.len() > 1 { SPARKLINE_WIDTH / (values.len() - 1) as f32 } else { 0.0 };
+
+    let points: Vec<String> = values.iter()
+        .enumerate()
+        .map(|(i, value)| {
+            // flat lines are drawn through the middle
+            let y = if max > min { SPARKLINE_HEIGHT - (value - min) / (max - min) * SPARKLINE_HEIGHT } else { SPARKLINE_HEIGHT / 2.0 };
+            format!("{:.1},{:.1}", i as f32 * step, y)
+        })
+        .collect();
+
+    format!(
+        "<svg width=\"{w}\" height=\"{h}\" viewBox=\"-1 -1 {vw} {vh}\"><title>{index} {min:.2} to {max:.2}</title>\
+         <polyline fill=\"none\" stroke=\"#3465a4\" stroke-width=\"1.5\" points=\"{points}\"/></svg> \
+         <span class=\"index\">{index}</span>",
+        w = SPARKLINE_WIDTH, h = SPARKLINE_HEIGHT, vw = SPARKLINE_WIDTH + 2.0, vh = SPARKLINE_HEIGHT + 2.0,
+        index = html_escape(&index), min = min, max = max, points = points.join(" "))
+}
+
+/// Renders a standalone HTML page with a status grid of the latest poll of
+/// every host, the remarks of failures and the load history of each host.
+fn render_html(report: &Report, entries: &[HistoryEntry]) -> String {
+    let mut by_host: BTreeMap<&str, Vec<&HistoryEntry>> = BTreeMap::new();
+
+    for entry in entries {
+        by_host.entry(entry.name.as_str()).or_default().push(entry);
+    }
+
+    let grid: Vec<String> = by_host.iter()
+        .filter_map(|(name, host_entries)| host_entries.last().map(|latest| (name, latest)))
+        .map(|(name, latest)| format!("<div class=\"cell {}\" title=\"{}\">{}</div>",
+            status_class(latest.status), output::to_check_status_str(latest.status), html_escape(name)))
+        .collect();
+
+    let hosts: Vec<String> = report.hosts.iter()
+        .map(|host| {
+            let host_entries = by_host.get(host.name.as_str()).map(|e| e.as_slice()).unwrap_or(&[]);
+            let status = host_entries.last().map(|latest| latest.status).unwrap_or(-1);
+
+            format!("<tr><td>{}</td><td class=\"{}\">{}</td><td>{:.3}%</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                html_escape(&host.name), status_class(status), output::to_check_status_str(status),
+                host.availability_percent, host.incidents,
+                host.mttr_secs.map(|secs| format!("{}s", secs)).unwrap_or_default(),
+                sparkline(host_entries))
+        })
+        .collect();
+
+    let groups: Vec<String> = report.groups.iter()
+        .map(|group| format!("<tr><td>{}</td><td>{:.3}%</td><td>{}</td><td>{}</td></tr>",
+            html_escape(&group.name), group.availability_percent, group.incidents,
+            group.mttr_secs.map(|secs| format!("{}s", secs)).unwrap_or_default()))
+        .collect();
+
+    // only the first poll of each run of failures with the same remarks is listed
+    let mut failures = Vec::new();
+
+    for host_entries in by_host.values() {
+        let mut previous: Option<&HistoryEntry> = None;
+
+        for entry in host_entries {
+            let repeated = previous.map(|p| p.status == FAILED && p.remarks == entry.remarks).unwrap_or(false);
+
+            if entry.status == FAILED && !repeated {
+                failures.push(format!("<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    html_escape(&entry.timestamp), html_escape(&entry.name),
+                    html_escape(entry.remarks.as_deref().unwrap_or(""))));
+            }
+
+            previous = Some(entry);
+        }
+    }
+
+    let failures = if failures.is_empty() {
+        "<p>No failures in this period.</p>".to_owned()
+    } else {
+        format!("<table><tr><th>Time</th><th>Host</th><th>Remarks</th></tr>{}</table>", failures.join(""))
+    };
+
+    format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>LSF status report</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; color: #222; }}
+table {{ border-collapse: collapse; margin-bottom: 2em; }}
+th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; vertical-align: middle; }}
+.grid {{ display: flex; flex-wrap: wrap; gap: 4px; margin-bottom: 2em; }}
+.cell {{ padding: 6px 10px; border-radius: 3px; color: #fff; font-size: 0.9em; }}
+.cell.passed {{ background: #4e9a06; }} .cell.alert {{ background: #c4a000; }}
+.cell.failed {{ background: #cc0000; }} .cell.downtime, .cell.unknown {{ background: #75507b; }}
+td.passed {{ color: #4e9a06; }} td.alert {{ color: #c4a000; }} td.failed {{ color: #cc0000; }} td.downtime {{ color: #75507b; }}
+.index {{ color: #888; font-size: 0.8em; }}
+</style>
+</head>
+<body>
+<h1>LSF status report</h1>
+<p>{since} to {until}</p>
+<h2>Current status</h2>
+<div class="grid">{grid}</div>
+<h2>Hosts</h2>
+<table><tr><th>Host</th><th>Status</th><th>Availability</th><th>Incidents</th><th>MTTR</th><th>Load</th></tr>{hosts}</table>
+<h2>Groups</h2>
+<table><tr><th>Group</th><th>Availability</th><th>Incidents</th><th>MTTR</th></tr>{groups}</table>
+<h2>Failures</h2>
+{failures}
+</body>
+</html>"#,
+        since = format_time(report.since), until = format_time(report.until),
+        grid = grid.join(""), hosts = hosts.join(""), groups = groups.join(""), failures = failures)
+}
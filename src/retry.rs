@@ -0,0 +1,69 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+fn default_initial_backoff_millis() -> u64 {
+    500
+}
+
+fn default_max_backoff_millis() -> u64 {
+    10000
+}
+
+fn default_jitter_percent() -> u64 {
+    20
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryConfig {
+    /// Number of retries after the first attempt
+    #[serde(default)]
+    pub attempts: u32,
+
+    #[serde(default = "default_initial_backoff_millis")]
+    pub initial_backoff_millis: u64,
+
+    #[serde(default = "default_max_backoff_millis")]
+    pub max_backoff_millis: u64,
+
+    /// Maximum random deviation from the backoff, in percent of it
+    #[serde(default = "default_jitter_percent")]
+    pub jitter_percent: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> RetryConfig {
+        RetryConfig {
+            attempts: 0,
+            initial_backoff_millis: default_initial_backoff_millis(),
+            max_backoff_millis: default_max_backoff_millis(),
+            jitter_percent: default_jitter_percent(),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Delay before the given retry, starting from 0, with exponential growth
+    /// capped at the maximum backoff and randomized by the jitter.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff_millis
+            .saturating_mul(1u64.checked_shl(retry).unwrap_or(u64::MAX))
+            .min(self.max_backoff_millis);
+
+        let spread = backoff * self.jitter_percent.min(100) / 100;
+
+        let jittered = if spread > 0 {
+            backoff - spread + random() % (2 * spread + 1)
+        } else {
+            backoff
+        };
+
+        Duration::from_millis(jittered)
+    }
+}
+
+/// Randomly keyed hasher output, good enough for spreading out retries.
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
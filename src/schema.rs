@@ -0,0 +1,163 @@
+use errors::*;
+use output::{OutputFormat, SchemaVersion};
+use serde_json::Value;
+
+const DRAFT: &str = "http://json-schema.org/draft-07/schema#";
+
+fn storage_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "used": { "type": "integer", "minimum": 0 },
+            "total": { "type": "integer", "minimum": 0 }
+        },
+        "required": ["used", "total"]
+    })
+}
+
+/// Schema of a single record, following the serde attributes of
+/// `StatusStorageInfo`.
+fn record_schema(version: SchemaVersion) -> Value {
+    let statuses = match version {
+        SchemaVersion::V1 => json!([0, 1, 2]),
+        SchemaVersion::V2 => json!([0, 1, 2, 3]),
+    };
+
+    let mut schema = json!({
+        "type": "object",
+        "properties": {
+            "name": { "type": "string" },
+            "status": {
+                "type": "integer",
+                "enum": statuses,
+                "description": "0 PASSED, 1 ALERT, 2 FAILED, 3 DOWNTIME"
+            },
+            "storage": storage_schema(),
+            "criticalGroupName": { "type": "string" },
+            "remarks": { "type": "string" }
+        },
+        "required": ["name", "status"]
+    });
+
+    if version == SchemaVersion::V2 {
+        if let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) {
+            properties.insert("limStatus".to_owned(), json!({
+                "type": "integer",
+                "description": "Status flags reported by LIM"
+            }));
+
+            properties.insert("loadIndices".to_owned(), json!({
+                "type": "object",
+                "additionalProperties": { "type": "number" }
+            }));
+
+            properties.insert("acknowledged".to_owned(), json!({
+                "type": "boolean",
+                "description": "Failure is a known issue, only present when true"
+            }));
+
+            properties.insert("labels".to_owned(), json!({
+                "type": "object",
+                "additionalProperties": { "type": "string" }
+            }));
+
+            properties.insert("batch".to_owned(), json!({
+                "type": "object",
+                "properties": {
+                    "status": { "type": "string" },
+                    "maxJobs": { "type": "integer" },
+                    "numJobs": { "type": "integer" },
+                    "numRun": { "type": "integer" },
+                    "numSsusp": { "type": "integer" },
+                    "numUsusp": { "type": "integer" },
+                    "closed": { "type": "boolean" },
+                    "full": { "type": "boolean" },
+                    "controlMessage": { "type": "string" }
+                },
+                "required": ["status", "maxJobs", "numJobs", "numRun", "numSsusp", "numUsusp", "closed", "full"]
+            }));
+        }
+    }
+
+    schema
+}
+
+fn summary_schema() -> Value {
+    let count = json!({ "type": "integer", "minimum": 0 });
+
+    json!({
+        "type": "object",
+        "properties": {
+            "total": count,
+            "passed": count,
+            "alert": count,
+            "failed": count,
+            "downtime": count,
+            "failing": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": ["total", "passed", "alert", "failed", "downtime", "failing"]
+    })
+}
+
+fn envelope_schema(hosts: Value, summary: bool) -> Value {
+    let mut schema = json!({
+        "type": "object",
+        "properties": {
+            "timestamp": { "type": "string", "format": "date-time" },
+            "agentHost": { "type": "string" },
+            "agentVersion": { "type": "string" },
+            "clusterName": { "type": "string" },
+            "lsfVersion": { "type": "string" },
+            "pollDurationMs": { "type": "integer", "minimum": 0 },
+            "hostCount": { "type": "integer", "minimum": 0 },
+            "signature": { "type": "string", "pattern": "^sha256=[0-9a-f]{64}$" },
+            "signatureKeyId": { "type": "string" },
+            "hosts": hosts
+        },
+        "required": ["timestamp", "agentHost", "agentVersion", "pollDurationMs", "hostCount", "hosts"]
+    });
+
+    if summary {
+        if let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) {
+            properties.insert("summary".to_owned(), summary_schema());
+        }
+
+        if let Some(required) = schema.get_mut("required").and_then(Value::as_array_mut) {
+            required.push(json!("summary"));
+        }
+    }
+
+    schema
+}
+
+/// JSON Schema of the document printed per poll in the given output format,
+/// or of every line for `ndjson`.
+pub fn output_schema(format: OutputFormat, version: SchemaVersion, envelope: bool, summary: bool) -> Result<Value> {
+    if !format.supports_envelope() {
+        bail!("Only the json, ndjson and yaml output formats have a schema");
+    }
+
+    let hosts = json!({ "type": "array", "items": record_schema(version) });
+
+    let mut schema = match (format, envelope, summary) {
+        (_, true, _) => envelope_schema(hosts, summary),
+        (_, false, true) => summary_schema(),
+        (OutputFormat::Ndjson, false, false) => json!({
+            "type": "object",
+            "properties": {
+                "timestamp": { "type": "string", "format": "date-time" },
+                "hosts": hosts
+            },
+            "required": ["timestamp", "hosts"]
+        }),
+
+        _ => hosts,
+    };
+
+    if let Some(schema) = schema.as_object_mut() {
+        schema.insert("$schema".to_owned(), json!(DRAFT));
+        schema.insert("title".to_owned(), json!("lsf_agent output"));
+    }
+
+    Ok(schema)
+}
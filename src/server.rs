@@ -0,0 +1,535 @@
+use chrono::{SecondsFormat, Utc};
+use common::StatusStorageInfo;
+use consul::ConsulConfig;
+use logging;
+use nrpe::NrpeConfig;
+use encoding;
+use errors::*;
+use rustls::{ServerConnection, StreamOwned};
+use serde::Serialize;
+use serde_json;
+use sinks::http::BasicAuth;
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+use tls::{self, TlsConfig};
+
+const MAX_REQUEST_HEAD_BYTES: usize = 8192;
+
+// connections served at once, each on a thread of its own
+const MAX_CONNECTIONS: usize = 64;
+
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+// keeps idle streams from being closed by proxies
+const STREAM_KEEPALIVE: Duration = Duration::from_secs(15);
+
+// events queued for a stream client before it is dropped as stalled
+const STREAM_QUEUE_LEN: usize = 16;
+
+const DEFAULT_READY_INTERVALS: u32 = 3;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthConfig>,
+
+    /// Poll intervals within which a poll reaching LIM has to have completed
+    /// for `/readyz`, 3 when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ready_intervals: Option<u32>,
+
+    /// Also answers NRPE queries of Nagios servers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nrpe: Option<NrpeConfig>,
+
+    /// Registers the server in Consul with the health of the cluster
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consul: Option<ConsulConfig>,
+}
+
+/// Credentials accepted by the API server. Any of the configured credentials
+/// grants access.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token_file: Option<String>,
+
+    /// Environment variable holding the bearer token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token_env: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basic_auth: Option<BasicAuth>,
+
+    /// File containing `username:password`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basic_auth_file: Option<String>,
+
+    /// Environment variable holding `username:password`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basic_auth_env: Option<String>,
+}
+
+/// Authorization header values that grant access, resolved once at startup.
+struct Authenticator {
+    accepted: Vec<String>,
+    challenge: &'static str,
+}
+
+fn read_secret_file(path: &str) -> Result<String> {
+    let mut secret = String::new();
+
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut secret))
+        .chain_err(|| format!("Unable to read secret file at {}", path))?;
+
+    Ok(secret.trim().to_owned())
+}
+
+fn read_secret_env(name: &str) -> Result<String> {
+    env::var(name)
+        .map(|secret| secret.trim().to_owned())
+        .chain_err(|| format!("Unable to read secret from environment variable {}", name))
+}
+
+impl Authenticator {
+    fn from_config(auth: &AuthConfig) -> Result<Authenticator> {
+        let mut bearer_tokens = auth.bearer_token.iter().cloned().collect::<Vec<_>>();
+
+        if let Some(ref path) = auth.bearer_token_file {
+            bearer_tokens.push(read_secret_file(path)?);
+        }
+
+        if let Some(ref name) = auth.bearer_token_env {
+            bearer_tokens.push(read_secret_env(name)?);
+        }
+
+        let mut credentials = auth.basic_auth.iter()
+            .map(|basic_auth| format!("{}:{}", basic_auth.username, basic_auth.password))
+            .collect::<Vec<_>>();
+
+        if let Some(ref path) = auth.basic_auth_file {
+            credentials.push(read_secret_file(path)?);
+        }
+
+        if let Some(ref name) = auth.basic_auth_env {
+            credentials.push(read_secret_env(name)?);
+        }
+
+        if bearer_tokens.iter().chain(&credentials).any(|secret| secret.is_empty()) {
+            bail!("Empty API server credentials are not allowed");
+        }
+
+        if credentials.iter().any(|credentials| !credentials.contains(':')) {
+            bail!("Basic auth credentials must be in the form username:password");
+        }
+
+        let challenge = if credentials.is_empty() { "Bearer" } else { "Basic realm=\"lsf_agent\"" };
+
+        let accepted = bearer_tokens.iter()
+            .map(|token| format!("Bearer {}", token))
+            .chain(credentials.iter().map(|credentials| format!("Basic {}", encoding::base64(credentials.as_bytes()))))
+            .collect();
+
+        Ok(Authenticator { accepted, challenge })
+    }
+
+    fn allows(&self, authorization: Option<&str>) -> bool {
+        let authorization = match authorization {
+            Some(authorization) => authorization.as_bytes(),
+            None => return false,
+        };
+
+        // compare without short-circuiting, so timing does not leak the secret
+        self.accepted.iter().fold(false, |allowed, accepted| {
+            let accepted = accepted.as_bytes();
+
+            let equal = accepted.len() == authorization.len() && accepted.iter()
+                .zip(authorization)
+                .fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+
+            allowed | equal
+        })
+    }
+}
+
+struct Snapshot {
+    records: Vec<StatusStorageInfo>,
+    updated_at: String,
+
+    /// Whether LIM could be queried in the poll
+    lim_reachable: bool,
+
+    /// Latest poll in which LIM could be queried
+    reachable_at: Option<Instant>,
+}
+
+/// Latest poll results shared between the poller and the request handlers.
+#[derive(Clone)]
+pub struct ServerState {
+    snapshot: Arc<RwLock<Option<Snapshot>>>,
+    subscribers: Arc<Mutex<Vec<SyncSender<String>>>>,
+
+    /// Age beyond which the latest poll reaching LIM makes the server unready
+    max_poll_age: Duration,
+}
+
+/// Event pushed on `/stream`, with the records whose status changed.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct StatusEvent<'a> {
+    changed: Vec<&'a StatusStorageInfo>,
+    updated_at: &'a str,
+}
+
+impl ServerState {
+    /// Creates the state of a server polling at the interval.
+    pub fn new(config: &ServerConfig, interval: Duration) -> ServerState {
+        ServerState {
+            snapshot: Arc::default(),
+            subscribers: Arc::default(),
+            max_poll_age: interval * config.ready_intervals.unwrap_or(DEFAULT_READY_INTERVALS),
+        }
+    }
+
+    pub fn update(&self, records: &[StatusStorageInfo], lim_reachable: bool) {
+        let mut snapshot = self.snapshot.write().unwrap_or_else(|e| e.into_inner());
+        let updated_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+
+        let changed: Vec<&StatusStorageInfo> = records.iter()
+            .filter(|record| match *snapshot {
+                Some(ref snapshot) => !snapshot.records.iter()
+                    .any(|prev| prev.name == record.name && prev.status == record.status),
+                None => true,
+            })
+            .collect();
+
+        if !changed.is_empty() {
+            let event = StatusEvent { changed, updated_at: &updated_at };
+
+            if let Ok(event) = serde_json::to_string(&event) {
+                // stalled clients are dropped rather than queued for, their
+                // stream ends once the queued events are written
+                let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+                subscribers.retain(|subscriber| subscriber.try_send(event.clone()).is_ok());
+            }
+        }
+
+        let reachable_at = if lim_reachable {
+            Some(Instant::now())
+        } else {
+            snapshot.as_ref().and_then(|snapshot| snapshot.reachable_at)
+        };
+
+        *snapshot = Some(Snapshot { records: records.to_vec(), updated_at, lim_reachable, reachable_at });
+    }
+
+    /// Records of the latest poll, if any.
+    pub fn records(&self) -> Option<Vec<StatusStorageInfo>> {
+        let snapshot = self.snapshot.read().unwrap_or_else(|e| e.into_inner());
+        snapshot.as_ref().map(|snapshot| snapshot.records.clone())
+    }
+
+    /// Subscribes to status change events, starting with an event of all the
+    /// records of the latest poll, if any.
+    fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::sync_channel(STREAM_QUEUE_LEN);
+        let snapshot = self.snapshot.read().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(ref snapshot) = *snapshot {
+            let event = StatusEvent {
+                changed: snapshot.records.iter().collect(),
+                updated_at: &snapshot.updated_at,
+            };
+
+            if let Ok(event) = serde_json::to_string(&event) {
+                let _ = sender.try_send(event);
+            }
+        }
+
+        // registered under the snapshot lock, so that no update is missed
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner()).push(sender);
+        receiver
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Health<'a> {
+    status: &'a str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated_at: Option<&'a str>,
+
+    /// Why the server is not ready
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+struct Response {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn json<T: Serialize>(status: &'static str, value: &T) -> Response {
+        let body = serde_json::to_string(value)
+            .unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e));
+
+        Response { status, headers: Vec::new(), body }
+    }
+
+    fn error(status: &'static str, message: &str) -> Response {
+        Response::json(status, &ErrorBody { error: message })
+    }
+}
+
+/// Slot of an open connection, released when the connection is done.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Binds the API server and serves requests on background threads.
+pub fn spawn(bind: &str, config: &ServerConfig, state: ServerState) -> Result<()> {
+    let tls_config = match config.tls {
+        Some(ref tls) => Some(tls::server_config(tls)?),
+        None => None,
+    };
+
+    let authenticator = match config.auth {
+        Some(ref auth) => Some(Arc::new(Authenticator::from_config(auth)?)),
+        None => None,
+    };
+
+    let listener = TcpListener::bind(bind)
+        .chain_err(|| format!("Unable to bind API server to {}", bind))?;
+
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!(error = %e, "Unable to accept API connection");
+                    continue;
+                },
+            };
+
+            if let Err(e) = stream.set_read_timeout(Some(IO_TIMEOUT)).and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT))) {
+                warn!(error = %e, "Unable to set timeouts on API connection");
+                continue;
+            }
+
+            if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                warn!(max_connections = MAX_CONNECTIONS, "Dropping API connection, too many connections are open already");
+                continue;
+            }
+
+            let slot = ConnectionSlot(connections.clone());
+            let state = state.clone();
+            let tls_config = tls_config.clone();
+            let authenticator = authenticator.clone();
+
+            thread::spawn(move || {
+                let _slot = slot;
+                let authenticator = authenticator.as_ref().map(|authenticator| authenticator.as_ref());
+
+                let res = match tls_config {
+                    Some(tls_config) => ServerConnection::new(tls_config)
+                        .chain_err(|| "Unable to start TLS session")
+                        .and_then(|conn| handle(StreamOwned::new(conn, stream), &state, authenticator)),
+
+                    None => handle(stream, &state, authenticator),
+                };
+
+                if let Err(e) = res {
+                    warn!(error = %logging::chain(&e), "Unable to serve API request");
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn handle<S: Read + Write>(stream: S, state: &ServerState, authenticator: Option<&Authenticator>) -> Result<()> {
+    // bounds what a client sending a line without end can make us buffer
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_HEAD_BYTES as u64));
+    let mut request_line = String::new();
+
+    reader.read_line(&mut request_line)
+        .chain_err(|| "Unable to read request line")?;
+
+    // drain the headers, only the credentials are needed
+    let mut head_len = request_line.len();
+    let mut authorization = None;
+
+    loop {
+        let mut line = String::new();
+        let n = reader.read_line(&mut line).chain_err(|| "Unable to read request headers")?;
+        head_len += n;
+
+        if n == 0 && head_len >= MAX_REQUEST_HEAD_BYTES {
+            bail!("Request head exceeds {} bytes", MAX_REQUEST_HEAD_BYTES);
+        }
+
+        if n == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+
+        if let Some(colon) = line.find(':') {
+            if line[..colon].eq_ignore_ascii_case("authorization") {
+                authorization = Some(line[colon + 1..].trim().to_owned());
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("");
+    let path = target.split('?').next().unwrap_or("");
+
+    let mut stream = reader.into_inner().into_inner();
+
+    // health checks stay open to probes, as they reveal no host details
+    let probe = path == "/healthz" || path == "/livez" || path == "/readyz";
+
+    let authorized = probe || authenticator
+        .map(|authenticator| authenticator.allows(authorization.as_deref()))
+        .unwrap_or(true);
+
+    let response = match authenticator {
+        Some(authenticator) if !authorized => {
+            let mut response = Response::error("401 Unauthorized", "Missing or invalid credentials");
+            response.headers.push(("WWW-Authenticate", authenticator.challenge.to_owned()));
+            response
+        },
+
+        _ if method == "GET" && path == "/stream" => return serve_stream(stream, state),
+        _ => route(method, path, state),
+    };
+
+    let headers: String = response.headers.iter()
+        .map(|&(name, ref value)| format!("{}: {}\r\n", name, value))
+        .collect();
+
+    write!(stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        response.status, response.body.len(), headers, response.body)
+        .and_then(|_| stream.flush())
+        .chain_err(|| "Unable to write response")
+}
+
+fn route(method: &str, path: &str, state: &ServerState) -> Response {
+    if method != "GET" {
+        return Response::error("405 Method Not Allowed", "Only GET is supported");
+    }
+
+    let snapshot = state.snapshot.read().unwrap_or_else(|e| e.into_inner());
+
+    match path.trim_end_matches('/') {
+        "/healthz" => match *snapshot {
+            Some(ref snapshot) => Response::json("200 OK",
+                &Health { status: "ok", updated_at: Some(&snapshot.updated_at), reason: None }),
+
+            None => Response::json("503 Service Unavailable",
+                &Health { status: "starting", updated_at: None, reason: None }),
+        },
+
+        // answering at all means the process is alive
+        "/livez" => Response::json("200 OK", &Health { status: "ok", updated_at: None, reason: None }),
+
+        "/readyz" => match *snapshot {
+            Some(ref snapshot) => {
+                let reason = if !snapshot.lim_reachable {
+                    Some("LIM could not be queried in the latest poll".to_owned())
+                } else if snapshot.reachable_at.is_none_or(|reachable_at| reachable_at.elapsed() > state.max_poll_age) {
+                    Some(format!("No poll has completed within the last {}s", state.max_poll_age.as_secs()))
+                } else {
+                    None
+                };
+
+                match reason {
+                    Some(reason) => Response::json("503 Service Unavailable",
+                        &Health { status: "unready", updated_at: Some(&snapshot.updated_at), reason: Some(reason) }),
+
+                    None => Response::json("200 OK",
+                        &Health { status: "ready", updated_at: Some(&snapshot.updated_at), reason: None }),
+                }
+            },
+
+            None => Response::json("503 Service Unavailable",
+                &Health { status: "starting", updated_at: None, reason: Some("No poll has completed yet".to_owned()) }),
+        },
+
+        "/api/hosts" => match *snapshot {
+            Some(ref snapshot) => Response::json("200 OK", &snapshot.records),
+            None => Response::error("503 Service Unavailable", "No poll has completed yet"),
+        },
+
+        p if p.starts_with("/api/hosts/") => {
+            let name = encoding::percent_decode(&p["/api/hosts/".len()..]);
+
+            match *snapshot {
+                Some(ref snapshot) => match snapshot.records.iter().find(|record| record.name == name) {
+                    Some(record) => Response::json("200 OK", record),
+                    None => Response::error("404 Not Found", &format!("Unknown host {}", name)),
+                },
+
+                None => Response::error("503 Service Unavailable", "No poll has completed yet"),
+            }
+        },
+
+        _ => Response::error("404 Not Found", "Unknown endpoint"),
+    }
+}
+
+/// Pushes status change events as Server-Sent Events until the client leaves,
+/// or stalls past the write timeout of the connection or the event queue.
+fn serve_stream<S: Write>(mut stream: S, state: &ServerState) -> Result<()> {
+    write!(stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n")
+        .chain_err(|| "Unable to write stream response head")?;
+
+    let events = state.subscribe();
+
+    loop {
+        let message = match events.recv_timeout(STREAM_KEEPALIVE) {
+            Ok(event) => format!("event: status\ndata: {}\n\n", event),
+            Err(RecvTimeoutError::Timeout) => ": keepalive\n\n".to_owned(),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+
+        // a failed write means the client went away
+        if stream.write_all(message.as_bytes()).and_then(|_| stream.flush()).is_err() {
+            return Ok(());
+        }
+    }
+}
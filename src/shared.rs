@@ -0,0 +1,108 @@
+use common::StatusStorageInfo;
+use lsf::{self, SharedResource};
+use std::collections::BTreeMap;
+use {ALERT, FAILED, PASSED};
+
+/// Shared resource checked on every poll, ALERT or FAILED once the value of
+/// any of its instances drops below the levels.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedResourceConfig {
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_below: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_below: Option<f64>,
+}
+
+fn to_record(
+    config: &SharedResourceConfig,
+    resource: Option<&SharedResource>,
+    name: String,
+    critical_group_name: &str) -> StatusStorageInfo {
+
+    let resource = match resource {
+        Some(resource) if !resource.instances.is_empty() => resource,
+        _ => return StatusStorageInfo::new(name, FAILED, None, Some(critical_group_name.to_owned()),
+            Some(format!("Shared resource {} is not defined", config.name))),
+    };
+
+    let mut status = PASSED;
+    let mut lowest: Option<f64> = None;
+
+    let remarks = resource.instances.iter()
+        .map(|instance| {
+            let value: Option<f64> = instance.value.trim().parse().ok();
+
+            let instance_status = match value {
+                Some(value) if config.failed_below.is_some_and(|level| value < level) => FAILED,
+                Some(value) if config.alert_below.is_some_and(|level| value < level) => ALERT,
+                Some(_) => PASSED,
+
+                // levels cannot be checked against non-numeric values
+                None if config.alert_below.is_some() || config.failed_below.is_some() => FAILED,
+                None => PASSED,
+            };
+
+            if instance_status == FAILED || (instance_status == ALERT && status == PASSED) {
+                status = instance_status;
+            }
+
+            if let Some(value) = value {
+                lowest = Some(lowest.map_or(value, |lowest| lowest.min(value)));
+            }
+
+            format!("{} on {} hosts", instance.value, instance.hosts.len())
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let level = match status {
+        FAILED => config.failed_below.or(config.alert_below),
+        ALERT => config.alert_below,
+        _ => None,
+    };
+
+    let remarks = match level {
+        Some(level) => format!("{}: {} (below {})", config.name, remarks, level),
+        None => format!("{}: {}", config.name, remarks),
+    };
+
+    let mut record = StatusStorageInfo::new(name, status, None, Some(critical_group_name.to_owned()), Some(remarks));
+
+    // the scarcest instance, for the sinks to graph
+    record.load_indices = lowest.map(|lowest| {
+        let mut load_indices = BTreeMap::new();
+        load_indices.insert(config.name.clone(), lowest as f32);
+        load_indices
+    });
+
+    record
+}
+
+/// Queries the configured shared resources into a record each, named by the
+/// prefixed resource name. All of them are FAILED if LIM cannot be queried.
+pub fn check(configs: &[SharedResourceConfig], prefix: &str, critical_group_name: &str) -> Vec<StatusStorageInfo> {
+    let names: Vec<String> = configs.iter().map(|config| config.name.clone()).collect();
+
+    match lsf::shared_resources(&names) {
+        Ok(resources) => configs.iter()
+            .map(|config| to_record(
+                config,
+                resources.iter().find(|resource| resource.name == config.name),
+                format!("{}{}", prefix, config.name),
+                critical_group_name))
+            .collect(),
+
+        Err(e) => configs.iter()
+            .map(|config| StatusStorageInfo::new(
+                format!("{}{}", prefix, config.name),
+                FAILED,
+                None,
+                Some(critical_group_name.to_owned()),
+                Some(format!("Unable to query shared resource {}: {}", config.name, e))))
+            .collect(),
+    }
+}
@@ -0,0 +1,86 @@
+use encoding;
+use errors::*;
+use ring::hmac;
+use std::fs::File;
+use std::io::Read;
+
+/// Shared secret the payloads are signed with, so that the collector can
+/// reject spoofed or tampered reports.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SigningConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+
+    /// File containing the key, so it does not need to live in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<String>,
+
+    /// Sent along with the signature, for the collector to pick the key with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+}
+
+/// Signs payloads with HMAC-SHA256.
+#[derive(Clone, Debug)]
+pub struct Signer {
+    key: hmac::Key,
+    pub key_id: Option<String>,
+}
+
+impl Signer {
+    pub fn from_config(config: &SigningConfig) -> Result<Signer> {
+        let key = match (config.key.as_ref(), config.key_file.as_ref()) {
+            (Some(key), None) => key.clone(),
+
+            (None, Some(key_file)) => {
+                let mut key = String::new();
+
+                File::open(key_file)
+                    .and_then(|mut f| f.read_to_string(&mut key))
+                    .chain_err(|| format!("Unable to read signing key file at {}", key_file))?;
+
+                key.trim().to_owned()
+            },
+
+            _ => bail!("Exactly one of key and keyFile has to be given for signing"),
+        };
+
+        if key.is_empty() {
+            bail!("Signing key is empty");
+        }
+
+        Ok(Signer {
+            key: hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes()),
+            key_id: config.key_id.clone(),
+        })
+    }
+
+    /// Signature of the payload at the time, as `sha256=<hex>` of the HMAC of
+    /// `<timestamp>.<payload>`, the timestamp keeping old payloads from being
+    /// replayed.
+    pub fn sign(&self, timestamp: &str, payload: &[u8]) -> String {
+        let mut context = hmac::Context::with_key(&self.key);
+        context.update(timestamp.as_bytes());
+        context.update(b".");
+        context.update(payload);
+
+        format!("sha256={}", encoding::hex(context.sign().as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_timestamp_and_payload() {
+        let config = SigningConfig { key: Some("secret".to_owned()), key_file: None, key_id: None };
+        let signer = Signer::from_config(&config).unwrap();
+
+        // python3 -c 'import hmac, hashlib; print(hmac.new(b"secret", b"2024-01-02T03:04:05.000Z." +
+        //     b"[{\"name\":\"lsf.h1\",\"status\":0}]", hashlib.sha256).hexdigest())'
+        assert_eq!(signer.sign("2024-01-02T03:04:05.000Z", br#"[{"name":"lsf.h1","status":0}]"#),
+            "sha256=9be7642ec0a50a1ee9bc3e1ebf2b0a4ae5aee5df837dcbf8e3dd56660fcb61a0");
+    }
+}
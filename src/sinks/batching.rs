@@ -0,0 +1,89 @@
+use common::StatusStorageInfo;
+use errors::*;
+use sinks::Sink;
+use std::time::{Duration, Instant};
+
+fn default_polls() -> usize {
+    1
+}
+
+/// Sending of the records of several polls at once, and at most this often.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchingConfig {
+    /// Sends once this many polls have accumulated
+    #[serde(default = "default_polls")]
+    pub polls: usize,
+
+    /// Sends once this many records have accumulated, even if fewer polls have
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub records: Option<usize>,
+
+    /// Keeps accumulating polls until this long has passed since the last send
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_interval_secs: Option<u64>,
+}
+
+/// Wraps a sink so that it receives the records of the accumulated polls in
+/// one send, in poll order.
+pub struct BatchingSink {
+    inner: Box<dyn Sink>,
+    config: BatchingConfig,
+    pending: Vec<StatusStorageInfo>,
+    pending_polls: usize,
+    last_sent: Option<Instant>,
+}
+
+impl BatchingSink {
+    pub fn new(inner: Box<dyn Sink>, config: BatchingConfig) -> BatchingSink {
+        BatchingSink { inner, config, pending: Vec::new(), pending_polls: 0, last_sent: None }
+    }
+
+    fn due(&self) -> bool {
+        let batch_full = self.pending_polls >= self.config.polls
+            || self.config.records.is_some_and(|records| self.pending.len() >= records);
+
+        let interval_passed = match (self.config.min_interval_secs, self.last_sent) {
+            (Some(secs), Some(last_sent)) => last_sent.elapsed() >= Duration::from_secs(secs),
+            _ => true,
+        };
+
+        batch_full && interval_passed
+    }
+
+    fn send_pending(&mut self) -> Result<()> {
+        if self.pending_polls == 0 {
+            return Ok(());
+        }
+
+        // a failed batch is not retried, like a failed poll
+        let pending = ::std::mem::take(&mut self.pending);
+        self.pending_polls = 0;
+        self.last_sent = Some(Instant::now());
+
+        self.inner.send(&pending)
+    }
+}
+
+impl Sink for BatchingSink {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        self.pending.extend_from_slice(records);
+        self.pending_polls += 1;
+
+        if !self.due() {
+            debug!(sink = self.inner.name(), polls = self.pending_polls, records = self.pending.len(), "Batching records");
+            return Ok(());
+        }
+
+        self.send_pending()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send_pending()?;
+        self.inner.flush()
+    }
+}
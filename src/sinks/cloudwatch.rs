@@ -0,0 +1,356 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use common::StatusStorageInfo;
+use encoding;
+use errors::*;
+use ring::{digest, hmac};
+use serde_json::{self, Value};
+use sinks::{self, Sink};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+use ureq;
+
+const API_VERSION: &str = "2010-08-01";
+
+// limit of PutMetricData on the number of datums per request
+const MAX_DATUMS: usize = 1000;
+
+const IMDS_URL: &str = "http://169.254.169.254/latest";
+
+fn default_namespace() -> String {
+    "LSF".to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudWatchConfig {
+    /// AWS region, `AWS_REGION` or `AWS_DEFAULT_REGION` when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+
+    /// Endpoint of the CloudWatch API, e.g. of a VPC endpoint, the regional
+    /// endpoint when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+
+    /// `Cluster` dimension of records without a `cluster` label
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+
+    /// Dimensions of every metric, in addition to `Cluster` and `Host`
+    #[serde(default)]
+    pub dimensions: BTreeMap<String, String>,
+
+    /// Static credentials, taken from the environment or the instance
+    /// profile of the EC2 instance when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_key_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_access_key: Option<String>,
+
+    /// File containing the secret access key, so it does not need to live in
+    /// the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_access_key_file: Option<String>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Clone)]
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+
+    /// Expiration of temporary credentials of the instance profile
+    expiration: Option<DateTime<Utc>>,
+}
+
+/// Publishes the metrics of every record with PutMetricData, with the
+/// `Cluster` and `Host` dimensions, signing the requests with AWS Signature
+/// Version 4.
+pub struct CloudWatchSink {
+    config: CloudWatchConfig,
+    region: String,
+    endpoint: String,
+    static_credentials: Option<Credentials>,
+    instance_credentials: Option<Credentials>,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    encoding::hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes()).as_ref().to_vec()
+}
+
+/// Host of the endpoint URL, including any port, which the request is signed
+/// with.
+fn endpoint_host(endpoint: &str) -> &str {
+    let rest = endpoint.split("://").nth(1).unwrap_or(endpoint);
+    rest.split('/').next().unwrap_or(rest)
+}
+
+/// Gets the temporary credentials of the instance profile from the instance
+/// metadata service, with an IMDSv2 session token.
+fn instance_profile_credentials(timeout: Duration) -> Result<Credentials> {
+    let token = ureq::put(&format!("{}/api/token", IMDS_URL))
+        .timeout(timeout)
+        .set("X-aws-ec2-metadata-token-ttl-seconds", "21600")
+        .call()
+        .map_err(|e| Error::from(format!("Unable to get instance metadata token: {}", e)))?
+        .into_string()
+        .chain_err(|| "Unable to read instance metadata token")?;
+
+    let get = |path: &str| -> Result<String> {
+        ureq::get(&format!("{}/meta-data/iam/security-credentials/{}", IMDS_URL, path))
+            .timeout(timeout)
+            .set("X-aws-ec2-metadata-token", &token)
+            .call()
+            .map_err(|e| Error::from(format!("Unable to get instance profile credentials: {}", e)))?
+            .into_string()
+            .chain_err(|| "Unable to read instance profile credentials")
+    };
+
+    let roles = get("")?;
+
+    let role = roles.lines().next()
+        .ok_or_else(|| Error::from("EC2 instance has no instance profile"))?;
+
+    let response: Value = serde_json::from_str(&get(role)?)
+        .chain_err(|| "Unable to parse instance profile credentials")?;
+
+    let field = |name: &str| -> Result<String> {
+        response[name].as_str()
+            .map(|value| value.to_owned())
+            .ok_or_else(|| format!("Instance profile credentials have no {}", name).into())
+    };
+
+    let expiration = DateTime::parse_from_rfc3339(&field("Expiration")?)
+        .chain_err(|| "Invalid expiration of instance profile credentials")?
+        .with_timezone(&Utc);
+
+    Ok(Credentials {
+        access_key_id: field("AccessKeyId")?,
+        secret_access_key: field("SecretAccessKey")?,
+        session_token: Some(field("Token")?),
+        expiration: Some(expiration),
+    })
+}
+
+impl CloudWatchSink {
+    pub fn new(config: CloudWatchConfig) -> Result<CloudWatchSink> {
+        let region = config.region.clone()
+            .or_else(|| env::var("AWS_REGION").ok())
+            .or_else(|| env::var("AWS_DEFAULT_REGION").ok())
+            .ok_or_else(|| Error::from("CloudWatch sink requires a region, AWS_REGION or AWS_DEFAULT_REGION"))?;
+
+        let endpoint = config.endpoint.clone()
+            .unwrap_or_else(|| format!("https://monitoring.{}.amazonaws.com", region));
+
+        let secret_access_key = match (&config.secret_access_key, &config.secret_access_key_file) {
+            (Some(secret_access_key), _) => Some(secret_access_key.clone()),
+
+            (None, Some(secret_access_key_file)) => {
+                let mut secret_access_key = String::new();
+
+                File::open(secret_access_key_file)
+                    .and_then(|mut f| f.read_to_string(&mut secret_access_key))
+                    .chain_err(|| format!("Unable to read AWS secret access key file at {}", secret_access_key_file))?;
+
+                Some(secret_access_key.trim().to_owned())
+            },
+
+            (None, None) => None,
+        };
+
+        let static_credentials = match (config.access_key_id.clone(), secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) =>
+                Some(Credentials { access_key_id, secret_access_key, session_token: None, expiration: None }),
+
+            (None, None) => match (env::var("AWS_ACCESS_KEY_ID"), env::var("AWS_SECRET_ACCESS_KEY")) {
+                (Ok(access_key_id), Ok(secret_access_key)) => Some(Credentials {
+                    access_key_id,
+                    secret_access_key,
+                    session_token: env::var("AWS_SESSION_TOKEN").ok(),
+                    expiration: None,
+                }),
+
+                _ => None,
+            },
+
+            _ => bail!("CloudWatch sink requires both an accessKeyId and a secretAccessKey or secretAccessKeyFile"),
+        };
+
+        Ok(CloudWatchSink { config, region, endpoint, static_credentials, instance_credentials: None })
+    }
+
+    /// Static credentials if any, or those of the instance profile, fetched
+    /// again shortly before they expire.
+    fn credentials(&mut self) -> Result<Credentials> {
+        if let Some(ref credentials) = self.static_credentials {
+            return Ok(credentials.clone());
+        }
+
+        let refresh_at = Utc::now() + ChronoDuration::minutes(5);
+
+        let expired = self.instance_credentials.as_ref()
+            .and_then(|credentials| credentials.expiration)
+            .is_none_or(|expiration| expiration <= refresh_at);
+
+        if expired {
+            let timeout = Duration::from_secs(self.config.timeout_secs);
+            self.instance_credentials = Some(instance_profile_credentials(timeout)?);
+        }
+
+        Ok(self.instance_credentials.clone().unwrap())
+    }
+
+    fn dimensions(&self, record: &StatusStorageInfo) -> BTreeMap<String, String> {
+        let mut dimensions = self.config.dimensions.clone();
+
+        let cluster = record.labels.as_ref()
+            .and_then(|labels| labels.get("cluster"))
+            .or(self.config.cluster.as_ref());
+
+        if let Some(cluster) = cluster {
+            dimensions.insert("Cluster".to_owned(), cluster.clone());
+        }
+
+        dimensions.insert("Host".to_owned(), record.name.clone());
+        dimensions
+    }
+
+    /// Form parameters of the PutMetricData requests, in batches within the
+    /// limit on datums.
+    fn requests(&self, records: &[StatusStorageInfo], timestamp: &str) -> Vec<Vec<(String, String)>> {
+        let datums: Vec<(String, f64, BTreeMap<String, String>)> = records.iter()
+            .flat_map(|record| {
+                let dimensions = self.dimensions(record);
+
+                sinks::record_metrics(record).into_iter()
+                    .map(move |(metric, value)| (metric, value, dimensions.clone()))
+            })
+            .collect();
+
+        datums.chunks(MAX_DATUMS)
+            .map(|batch| {
+                let mut params = vec![
+                    ("Action".to_owned(), "PutMetricData".to_owned()),
+                    ("Version".to_owned(), API_VERSION.to_owned()),
+                    ("Namespace".to_owned(), self.config.namespace.clone()),
+                ];
+
+                for (i, (metric, value, dimensions)) in batch.iter().enumerate() {
+                    let member = format!("MetricData.member.{}", i + 1);
+                    params.push((format!("{}.MetricName", member), metric.clone()));
+                    params.push((format!("{}.Value", member), value.to_string()));
+                    params.push((format!("{}.Timestamp", member), timestamp.to_owned()));
+
+                    for (j, (name, value)) in dimensions.iter().enumerate() {
+                        params.push((format!("{}.Dimensions.member.{}.Name", member, j + 1), name.clone()));
+                        params.push((format!("{}.Dimensions.member.{}.Value", member, j + 1), value.clone()));
+                    }
+                }
+
+                params
+            })
+            .collect()
+    }
+
+    fn put(&self, credentials: &Credentials, params: &[(String, String)]) -> Result<()> {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let body = params.iter()
+            .map(|(key, value)| format!("{}={}", encoding::percent_encode(key), encoding::percent_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let content_type = "application/x-www-form-urlencoded; charset=utf-8";
+        let host = endpoint_host(&self.endpoint);
+
+        let mut headers = vec![
+            ("content-type", content_type.to_owned()),
+            ("host", host.to_owned()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+
+        if let Some(ref session_token) = credentials.session_token {
+            headers.push(("x-amz-security-token", session_token.clone()));
+        }
+
+        let canonical_headers: String = headers.iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+
+        let signed_headers = headers.iter().map(|&(name, _)| name).collect::<Vec<_>>().join(";");
+
+        let canonical_request = format!("POST\n/\n\n{}\n{}\n{}",
+            canonical_headers, signed_headers, sha256_hex(body.as_bytes()));
+
+        let scope = format!("{}/{}/monitoring/aws4_request", date, self.region);
+
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date, scope, sha256_hex(canonical_request.as_bytes()));
+
+        // the key is derived from the secret for the date, region and service
+        let signing_key = [date.as_str(), self.region.as_str(), "monitoring", "aws4_request"].iter()
+            .fold(format!("AWS4{}", credentials.secret_access_key).into_bytes(), |key, part| hmac_sha256(&key, part));
+
+        let signature = encoding::hex(&hmac_sha256(&signing_key, &string_to_sign));
+
+        let authorization = format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature);
+
+        let mut request = ureq::post(&self.endpoint)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .set("Content-Type", content_type)
+            .set("X-Amz-Date", &amz_date)
+            .set("Authorization", &authorization);
+
+        if let Some(ref session_token) = credentials.session_token {
+            request = request.set("X-Amz-Security-Token", session_token);
+        }
+
+        match request.send_string(&body) {
+            Ok(_) => Ok(()),
+
+            // the error response explains what was rejected
+            Err(ureq::Error::Status(code, response)) => bail!("CloudWatch PutMetricData returned {}: {}",
+                code, response.into_string().unwrap_or_default()),
+
+            Err(e) => bail!("Unable to POST {}: {}", self.endpoint, e),
+        }
+    }
+}
+
+impl Sink for CloudWatchSink {
+    fn name(&self) -> &str {
+        "cloudwatch"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let credentials = self.credentials()?;
+        let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+        for params in self.requests(records, timestamp.as_str()) {
+            self.put(&credentials, &params)?;
+        }
+
+        Ok(())
+    }
+}
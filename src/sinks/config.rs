@@ -0,0 +1,251 @@
+use common::StatusStorageInfo;
+use encryption::Encryptor;
+use errors::*;
+use glob;
+use output::OutputFormat;
+use signing::Signer;
+use sinks::Sink;
+use sinks::cloudwatch::{CloudWatchConfig, CloudWatchSink};
+use sinks::datadog::{DatadogConfig, DatadogSink};
+use sinks::etcd::{EtcdConfig, EtcdSink};
+use sinks::exec::{ExecConfig, ExecSink};
+use sinks::file::{FileConfig, FileSink};
+use sinks::graphite::{GraphiteConfig, GraphiteSink};
+use sinks::http::{HttpConfig, HttpSink};
+use sinks::icinga::{IcingaConfig, IcingaSink};
+use sinks::kafka::{KafkaConfig, KafkaSink};
+use sinks::nsca::{NscaConfig, NscaSink};
+use sinks::otlp::{OtlpConfig, OtlpSink};
+use sinks::pushgateway::{PushgatewayConfig, PushgatewaySink};
+use sinks::redis::{RedisConfig, RedisSink};
+use sinks::sensu::{SensuConfig, SensuSink};
+use sinks::statsd::{StatsdConfig, StatsdSink};
+use sinks::stdout::StdoutSink;
+use sinks::syslog::{SyslogConfig, SyslogSink};
+use sinks::zabbix::{ZabbixConfig, ZabbixSink};
+use PASSED;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StdoutConfig {}
+
+/// Entry of the `sinks` section, setting exactly one of the sink kinds along
+/// with the records it receives.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SinkConfig {
+    /// Name in logs and in `sinkDedup`, `sinkSpool` and `sinkBatching`,
+    /// defaults to the kind of the sink
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Output format of the `stdout`, `file` and `exec` sinks, `json` when not
+    /// set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+
+    /// Only sends the records that did not pass
+    #[serde(default)]
+    pub only_failed: bool,
+
+    /// Only sends the records whose name matches any of the glob patterns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosts: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<StdoutConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<FileConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exec: Option<ExecConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kafka: Option<KafkaConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redis: Option<RedisConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etcd: Option<EtcdConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub syslog: Option<SyslogConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub graphite: Option<GraphiteConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statsd: Option<StatsdConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zabbix: Option<ZabbixConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nsca: Option<NscaConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensu: Option<SensuConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icinga: Option<IcingaConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datadog: Option<DatadogConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cloudwatch: Option<CloudWatchConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otlp: Option<OtlpConfig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pushgateway: Option<PushgatewayConfig>,
+}
+
+impl SinkConfig {
+    /// Creates the sink of the entry, signing and encrypting its payloads
+    /// where the kind supports it.
+    pub fn to_sink(&self, signer: Option<Signer>, encryptor: Option<Encryptor>) -> Result<Box<dyn Sink>> {
+        let format = match self.format {
+            Some(ref format) => format.parse()?,
+            None => OutputFormat::Json,
+        };
+
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+
+        if self.stdout.is_some() {
+            sinks.push(Box::new(StdoutSink::new(format)));
+        }
+
+        if let Some(ref file) = self.file {
+            sinks.push(Box::new(FileSink::new(file.clone(), format, encryptor.clone())));
+        }
+
+        if let Some(ref exec) = self.exec {
+            sinks.push(Box::new(ExecSink::new(exec.clone(), format)?));
+        }
+
+        if let Some(ref http) = self.http {
+            sinks.push(Box::new(HttpSink::new(http.clone(), signer, encryptor)));
+        }
+
+        if let Some(ref kafka) = self.kafka {
+            sinks.push(Box::new(KafkaSink::new(kafka.clone())?));
+        }
+
+        if let Some(ref redis) = self.redis {
+            sinks.push(Box::new(RedisSink::new(redis.clone())));
+        }
+
+        if let Some(ref etcd) = self.etcd {
+            sinks.push(Box::new(EtcdSink::new(etcd.clone())));
+        }
+
+        if let Some(ref syslog) = self.syslog {
+            sinks.push(Box::new(SyslogSink::new(syslog.clone())?));
+        }
+
+        if let Some(ref graphite) = self.graphite {
+            sinks.push(Box::new(GraphiteSink::new(graphite.clone())));
+        }
+
+        if let Some(ref statsd) = self.statsd {
+            sinks.push(Box::new(StatsdSink::new(statsd.clone())));
+        }
+
+        if let Some(ref zabbix) = self.zabbix {
+            sinks.push(Box::new(ZabbixSink::new(zabbix.clone())));
+        }
+
+        if let Some(ref nsca) = self.nsca {
+            sinks.push(Box::new(NscaSink::new(nsca.clone())?));
+        }
+
+        if let Some(ref sensu) = self.sensu {
+            sinks.push(Box::new(SensuSink::new(sensu.clone())));
+        }
+
+        if let Some(ref icinga) = self.icinga {
+            sinks.push(Box::new(IcingaSink::new(icinga.clone())?));
+        }
+
+        if let Some(ref datadog) = self.datadog {
+            sinks.push(Box::new(DatadogSink::new(datadog.clone())?));
+        }
+
+        if let Some(ref cloudwatch) = self.cloudwatch {
+            sinks.push(Box::new(CloudWatchSink::new(cloudwatch.clone())?));
+        }
+
+        if let Some(ref otlp) = self.otlp {
+            sinks.push(Box::new(OtlpSink::new(otlp.clone())));
+        }
+
+        if let Some(ref pushgateway) = self.pushgateway {
+            sinks.push(Box::new(PushgatewaySink::new(pushgateway.clone())));
+        }
+
+        let label = self.name.as_deref().unwrap_or("without name");
+
+        let sink = match sinks.len() {
+            1 => sinks.remove(0),
+            0 => bail!("Sink {} has no kind, expected one of stdout, file, exec, http, kafka, redis, etcd, syslog, graphite, statsd, zabbix, nsca, sensu, icinga, datadog, cloudwatch, otlp or pushgateway", label),
+            _ => bail!("Sink {} has more than one kind, expected a separate entry for each", label),
+        };
+
+        if self.format.is_some() && self.stdout.is_none() && self.file.is_none() && self.exec.is_none() {
+            bail!("Sink {} sets a format, which only applies to stdout, file and exec sinks", label);
+        }
+
+        Ok(Box::new(ConfiguredSink {
+            inner: sink,
+            name: self.name.clone(),
+            only_failed: self.only_failed,
+            hosts: self.hosts.clone(),
+        }))
+    }
+}
+
+/// Names the sink of an entry, and filters the records it receives.
+struct ConfiguredSink {
+    inner: Box<dyn Sink>,
+    name: Option<String>,
+    only_failed: bool,
+    hosts: Option<Vec<String>>,
+}
+
+impl ConfiguredSink {
+    fn filter(&self, records: &[StatusStorageInfo]) -> Vec<StatusStorageInfo> {
+        records.iter()
+            .filter(|record| !self.only_failed || record.status != PASSED)
+            .filter(|record| self.hosts.as_ref()
+                .is_none_or(|hosts| hosts.iter().any(|pattern| glob::matches(pattern, &record.name))))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Sink for ConfiguredSink {
+    fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or_else(|| self.inner.name())
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let records = self.filter(records);
+        self.inner.send(&records)
+    }
+
+    fn send_spooled(&mut self, records: &[StatusStorageInfo], polled_at: &str) -> Result<()> {
+        let records = self.filter(records);
+        self.inner.send_spooled(&records, polled_at)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,230 @@
+use chrono::Utc;
+use common::StatusStorageInfo;
+use errors::*;
+use output;
+use serde_json::{self, Value};
+use sinks::{self, Sink};
+use std::fs::File;
+use std::io::Read;
+use std::net::UdpSocket;
+use std::slice;
+use std::time::Duration;
+use ureq;
+
+// keeps each datagram within a typical Ethernet MTU
+const MAX_PACKET_BYTES: usize = 1432;
+
+fn default_site() -> String {
+    "datadoghq.com".to_owned()
+}
+
+fn default_prefix() -> String {
+    "lsf".to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DatadogConfig {
+    /// Sends to the DogStatsD endpoint at `host:port` instead of the API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dogstatsd: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    /// File containing the API key, so it does not need to live in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_file: Option<String>,
+
+    /// Datadog site of the API, e.g. `datadoghq.eu`
+    #[serde(default = "default_site")]
+    pub site: String,
+
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+
+    /// Tags of every metric and service check, in addition to the labels
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Submits the metrics of every record as gauges of its host, and its Nagios
+/// state as the `<prefix>.host` service check, through the Datadog API or
+/// DogStatsD.
+pub struct DatadogSink {
+    config: DatadogConfig,
+    api_key: Option<String>,
+}
+
+/// Replaces the characters that separate tags and fields in DogStatsD.
+fn tag_component(s: &str) -> String {
+    s.chars()
+        .map(|c| if c == ',' || c == '|' || c == '#' || c.is_whitespace() { '_' } else { c })
+        .collect()
+}
+
+impl DatadogSink {
+    pub fn new(config: DatadogConfig) -> Result<DatadogSink> {
+        let api_key = match (&config.api_key, &config.api_key_file) {
+            (Some(api_key), _) => Some(api_key.clone()),
+
+            (None, Some(api_key_file)) => {
+                let mut api_key = String::new();
+
+                File::open(api_key_file)
+                    .and_then(|mut f| f.read_to_string(&mut api_key))
+                    .chain_err(|| format!("Unable to read Datadog API key file at {}", api_key_file))?;
+
+                Some(api_key.trim().to_owned())
+            },
+
+            (None, None) => None,
+        };
+
+        if config.dogstatsd.is_none() && api_key.is_none() {
+            bail!("Datadog sink requires an apiKey, apiKeyFile or dogstatsd address");
+        }
+
+        Ok(DatadogSink { config, api_key })
+    }
+
+    fn tags(&self, record: &StatusStorageInfo) -> Vec<String> {
+        let mut tags: Vec<String> = self.config.tags.iter().map(|tag| tag_component(tag)).collect();
+
+        if let Some(ref critical_group_name) = record.critical_group_name {
+            tags.push(format!("critical_group:{}", tag_component(critical_group_name)));
+        }
+
+        tags.extend(record.labels.iter().flatten()
+            .map(|(key, value)| format!("{}:{}", tag_component(key), tag_component(value))));
+
+        tags
+    }
+
+    fn metric_name(&self, metric: &str) -> String {
+        format!("{}.host.{}", self.config.prefix, sinks::metric_component(metric))
+    }
+
+    fn check_message(record: &StatusStorageInfo) -> String {
+        record.remarks.clone().unwrap_or_else(|| output::to_check_status_str(record.status).to_owned())
+    }
+
+    fn post(&self, path: &str, body: &Value) -> Result<()> {
+        let url = format!("https://api.{}{}", self.config.site, path);
+
+        let body = serde_json::to_string(body)
+            .chain_err(|| "Unable to serialize Datadog request into string!")?;
+
+        ureq::post(&url)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .set("Content-Type", "application/json")
+            .set("DD-API-KEY", self.api_key.as_deref().unwrap_or(""))
+            .send_string(&body)
+            .map(|_| ())
+            .map_err(|e| format!("Unable to POST {}: {}", url, e).into())
+    }
+
+    fn send_api(&self, records: &[StatusStorageInfo]) -> Result<()> {
+        let timestamp = Utc::now().timestamp();
+        let mut series = Vec::new();
+
+        for record in records {
+            let tags = self.tags(record);
+
+            for (metric, value) in sinks::record_metrics(record) {
+                series.push(json!({
+                    "metric": self.metric_name(&metric),
+                    "type": "gauge",
+                    "points": [[timestamp, value]],
+                    "host": record.name,
+                    "tags": tags,
+                }));
+            }
+        }
+
+        self.post("/api/v1/series", &json!({ "series": series }))?;
+
+        // the endpoint takes a single service check per request
+        for record in records {
+            self.post("/api/v1/check_run", &json!({
+                "check": format!("{}.host", self.config.prefix),
+                "host_name": record.name,
+                "status": output::nagios_exit_code(slice::from_ref(record)),
+                "timestamp": timestamp,
+                "message": Self::check_message(record),
+                "tags": self.tags(record),
+            }))?;
+        }
+
+        Ok(())
+    }
+
+    fn render_dogstatsd(&self, records: &[StatusStorageInfo]) -> Vec<String> {
+        let mut packets = Vec::new();
+        let mut packet = String::new();
+
+        for record in records {
+            let mut tags = self.tags(record);
+            tags.push(format!("host:{}", tag_component(&record.name)));
+            let tags = tags.join(",");
+
+            let mut lines: Vec<String> = sinks::record_metrics(record).iter()
+                .map(|&(ref metric, value)| format!("{}:{}|g|#{}", self.metric_name(metric), value, tags))
+                .collect();
+
+            // messages end the service check, so they must not contain newlines
+            lines.push(format!("_sc|{}.host|{}|h:{}|#{}|m:{}",
+                self.config.prefix, output::nagios_exit_code(slice::from_ref(record)), record.name, tags,
+                Self::check_message(record).replace('\n', "\\n")));
+
+            for line in lines {
+                if !packet.is_empty() && packet.len() + line.len() + 1 > MAX_PACKET_BYTES {
+                    packets.push(packet);
+                    packet = String::new();
+                }
+
+                if !packet.is_empty() {
+                    packet.push('\n');
+                }
+
+                packet.push_str(&line);
+            }
+        }
+
+        if !packet.is_empty() {
+            packets.push(packet);
+        }
+
+        packets
+    }
+}
+
+impl Sink for DatadogSink {
+    fn name(&self) -> &str {
+        "datadog"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let address = match self.config.dogstatsd {
+            Some(ref address) => address,
+            None => return self.send_api(records),
+        };
+
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .chain_err(|| "Unable to bind UDP socket for DogStatsD")?;
+
+        for packet in self.render_dogstatsd(records) {
+            socket.send_to(packet.as_bytes(), address.as_str())
+                .chain_err(|| format!("Unable to send metrics to DogStatsD at {}", address))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use common::StatusStorageInfo;
+use errors::*;
+use serde_json;
+use sinks::Sink;
+use std::time::{Duration, Instant};
+
+/// Skipping of polls whose records are identical to the previously sent ones.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DedupConfig {
+    /// Sends identical records anyway once this long has passed since the last
+    /// send, so that receivers can tell a stable cluster from a dead agent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_heartbeat_secs: Option<u64>,
+}
+
+/// Wraps a sink so that it only receives records that differ from those of
+/// the last successful send, or once the heartbeat is due.
+pub struct DedupSink {
+    inner: Box<dyn Sink>,
+    config: DedupConfig,
+    last_sent: Option<(String, Instant)>,
+}
+
+impl DedupSink {
+    pub fn new(inner: Box<dyn Sink>, config: DedupConfig) -> DedupSink {
+        DedupSink { inner, config, last_sent: None }
+    }
+}
+
+impl Sink for DedupSink {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        // records carry no poll time, sinks add it when sending
+        let payload = serde_json::to_string(records)
+            .chain_err(|| "Unable to serialize list of status storage into string!")?;
+
+        if let Some((ref last_payload, sent_at)) = self.last_sent {
+            let heartbeat_due = self.config.max_heartbeat_secs
+                .is_some_and(|secs| sent_at.elapsed() >= Duration::from_secs(secs));
+
+            if *last_payload == payload && !heartbeat_due {
+                debug!(sink = self.inner.name(), "Not sending records identical to the last sent ones");
+                return Ok(());
+            }
+        }
+
+        self.inner.send(records)?;
+        self.last_sent = Some((payload, Instant::now()));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
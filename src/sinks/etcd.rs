@@ -0,0 +1,207 @@
+use common::StatusStorageInfo;
+use encoding;
+use errors::*;
+use serde_json::{self, Value};
+use sinks::Sink;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+use ureq;
+
+fn default_key_prefix() -> String {
+    "/lsf_agent/hosts/".to_owned()
+}
+
+fn default_lease_ttl_secs() -> u64 {
+    300
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EtcdConfig {
+    /// Base URL of the etcd v3 HTTP gateway, e.g. `http://etcd:2379`
+    pub url: String,
+
+    /// Prefix of the keys holding the latest status of each host
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+
+    /// TTL of the lease the keys are attached to, kept alive with every poll
+    #[serde(default = "default_lease_ttl_secs")]
+    pub lease_ttl_secs: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    /// File containing the password, so it does not need to live in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<String>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Writes the latest record of every host under the key prefix, attached to
+/// a lease so that the keys expire once the agent stops. Only changed records
+/// are written, so that watchers see a revision per status change, and keys
+/// of hosts gone from the poll are deleted.
+pub struct EtcdSink {
+    config: EtcdConfig,
+    token: Option<String>,
+    lease: Option<String>,
+
+    /// Values last written by key, under the current lease
+    written: BTreeMap<String, String>,
+}
+
+impl EtcdSink {
+    pub fn new(config: EtcdConfig) -> EtcdSink {
+        EtcdSink { config, token: None, lease: None, written: BTreeMap::new() }
+    }
+
+    fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        let url = format!("{}{}", self.config.url.trim_end_matches('/'), path);
+
+        let mut request = ureq::post(&url)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .set("Content-Type", "application/json");
+
+        if let Some(ref token) = self.token {
+            request = request.set("Authorization", token);
+        }
+
+        let body = serde_json::to_string(body)
+            .chain_err(|| "Unable to serialize etcd request into string!")?;
+
+        let response = request.send_string(&body)
+            .map_err(|e| Error::from(format!("Unable to POST {}: {}", url, e)))?
+            .into_string()
+            .chain_err(|| format!("Unable to read response of {}", url))?;
+
+        serde_json::from_str(&response)
+            .chain_err(|| format!("Unable to parse response of {}", url))
+    }
+
+    fn authenticate(&mut self) -> Result<()> {
+        let username = match self.config.username {
+            Some(ref username) => username.clone(),
+            None => return Ok(()),
+        };
+
+        let password = match (&self.config.password, &self.config.password_file) {
+            (Some(password), _) => password.clone(),
+
+            (None, Some(password_file)) => {
+                let mut password = String::new();
+
+                File::open(password_file)
+                    .and_then(|mut f| f.read_to_string(&mut password))
+                    .chain_err(|| format!("Unable to read etcd password file at {}", password_file))?;
+
+                password.trim().to_owned()
+            },
+
+            (None, None) => bail!("etcd authentication requires a password"),
+        };
+
+        let response = self.post("/v3/auth/authenticate", &json!({ "name": username, "password": password }))?;
+
+        let token = response["token"].as_str()
+            .ok_or_else(|| Error::from("etcd authentication returned no token"))?;
+
+        self.token = Some(token.to_owned());
+        Ok(())
+    }
+
+    /// Keeps the current lease alive, or grants a new one if it expired, in
+    /// which case its keys are gone and have to be written again.
+    fn lease(&mut self) -> Result<String> {
+        if let Some(lease) = self.lease.clone() {
+            let response = self.post("/v3/lease/keepalive", &json!({ "ID": lease }))?;
+
+            // int64 fields are strings in the JSON of the gateway
+            let ttl = response["result"]["TTL"].as_str().and_then(|ttl| ttl.parse::<i64>().ok());
+
+            if ttl.is_some_and(|ttl| ttl > 0) {
+                return Ok(lease);
+            }
+        }
+
+        let response = self.post("/v3/lease/grant", &json!({ "TTL": self.config.lease_ttl_secs.to_string() }))?;
+
+        let lease = response["ID"].as_str()
+            .ok_or_else(|| Error::from("etcd lease grant returned no ID"))?
+            .to_owned();
+
+        self.lease = Some(lease.clone());
+        self.written.clear();
+        Ok(lease)
+    }
+
+    fn write(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        if self.token.is_none() {
+            self.authenticate()?;
+        }
+
+        let lease = self.lease()?;
+        let mut current = BTreeMap::new();
+
+        for record in records {
+            let value = serde_json::to_string(record)
+                .chain_err(|| "Unable to serialize status storage record into string!")?;
+
+            current.insert(format!("{}{}", self.config.key_prefix, record.name), value);
+        }
+
+        for (key, value) in &current {
+            if self.written.get(key) == Some(value) {
+                continue;
+            }
+
+            self.post("/v3/kv/put", &json!({
+                "key": encoding::base64(key.as_bytes()),
+                "value": encoding::base64(value.as_bytes()),
+                "lease": lease,
+            }))?;
+
+            self.written.insert(key.clone(), value.clone());
+        }
+
+        let gone: Vec<String> = self.written.keys()
+            .filter(|key| !current.contains_key(*key))
+            .cloned()
+            .collect();
+
+        for key in gone {
+            self.post("/v3/kv/deleterange", &json!({ "key": encoding::base64(key.as_bytes()) }))?;
+            self.written.remove(&key);
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for EtcdSink {
+    fn name(&self) -> &str {
+        "etcd"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let res = self.write(records);
+
+        // the token may have expired, so authenticate again with the next poll
+        if res.is_err() {
+            self.token = None;
+        }
+
+        res
+    }
+}
@@ -0,0 +1,124 @@
+use common::StatusStorageInfo;
+use errors::*;
+use output::{self, OutputFormat};
+use sinks::Sink;
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+// how often the command is checked for having exited
+const WAIT_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecConfig {
+    /// Program and its arguments, run without a shell
+    pub command: Vec<String>,
+
+    /// Extra environment variables of the command
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    /// Kills the command once it has run this long
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Pipes the payload of every poll into the stdin of a command, with the
+/// summary of the poll in `LSF_AGENT_*` environment variables.
+pub struct ExecSink {
+    config: ExecConfig,
+    format: OutputFormat,
+}
+
+impl ExecSink {
+    pub fn new(config: ExecConfig, format: OutputFormat) -> Result<ExecSink> {
+        if config.command.is_empty() {
+            bail!("Exec sink requires a command");
+        }
+
+        Ok(ExecSink { config, format })
+    }
+
+    fn summary_env(records: &[StatusStorageInfo]) -> Vec<(&'static str, String)> {
+        let summary = output::summarize(records);
+
+        vec![
+            ("LSF_AGENT_TOTAL", summary.total.to_string()),
+            ("LSF_AGENT_PASSED", summary.passed.to_string()),
+            ("LSF_AGENT_ALERT", summary.alert.to_string()),
+            ("LSF_AGENT_FAILED", summary.failed.to_string()),
+            ("LSF_AGENT_DOWNTIME", summary.downtime.to_string()),
+            ("LSF_AGENT_FAILING", summary.failing.join(",")),
+        ]
+    }
+}
+
+impl Sink for ExecSink {
+    fn name(&self) -> &str {
+        "exec"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let payload = format!("{}\n", output::render(self.format, records)?);
+
+        let mut env = Self::summary_env(records);
+        env.extend(self.config.env.iter().map(|(name, value)| (name.as_str(), value.clone())));
+
+        run(&self.config.command, &env, payload.into_bytes(), Duration::from_secs(self.config.timeout_secs))
+    }
+}
+
+/// Runs the program and its arguments with the input on its stdin, failing
+/// unless it exits successfully within the timeout.
+pub fn run(command: &[String], env: &[(&str, String)], input: Vec<u8>, timeout: Duration) -> Result<()> {
+    let program = command.first().ok_or_else(|| Error::from("Command to run is empty"))?;
+
+    // stdout of the agent carries the payload, so the command gets none
+    let mut child = Command::new(program)
+        .args(&command[1..])
+        .envs(env.iter().map(|&(name, ref value)| (name, value)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .chain_err(|| format!("Unable to run command {}", program))?;
+
+    let mut stdin = child.stdin.take()
+        .ok_or_else(|| Error::from(format!("Unable to open stdin of command {}", program)))?;
+
+    // the command may exit without reading all of it, which is fine
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+
+    let deadline = Instant::now() + timeout;
+
+    let status = loop {
+        match child.try_wait().chain_err(|| format!("Unable to wait for command {}", program))? {
+            Some(status) => break status,
+
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                bail!("Command {} killed after {}s timeout", program, timeout.as_secs());
+            },
+
+            None => thread::sleep(WAIT_INTERVAL),
+        }
+    };
+
+    let _ = writer.join();
+
+    if !status.success() {
+        bail!("Command {} exited with {}", program, status);
+    }
+
+    Ok(())
+}
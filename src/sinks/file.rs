@@ -0,0 +1,45 @@
+use common::StatusStorageInfo;
+use compression::Compression;
+use encryption::Encryptor;
+use errors::*;
+use outfile::OutputFile;
+use output::{self, OutputFormat};
+use sinks::Sink;
+
+/// File replaced atomically with the records of every poll, like `--output`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileConfig {
+    pub path: String,
+
+    /// Number of timestamped copies of previous payloads to keep
+    #[serde(default)]
+    pub keep: usize,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+}
+
+pub struct FileSink {
+    output_file: OutputFile,
+    format: OutputFormat,
+}
+
+impl FileSink {
+    pub fn new(config: FileConfig, format: OutputFormat, encryptor: Option<Encryptor>) -> FileSink {
+        FileSink {
+            output_file: OutputFile::new(&config.path, config.keep, config.compression, encryptor),
+            format,
+        }
+    }
+}
+
+impl Sink for FileSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        self.output_file.write(&output::render(self.format, records)?)
+    }
+}
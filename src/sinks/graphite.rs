@@ -0,0 +1,87 @@
+use chrono::Utc;
+use common::StatusStorageInfo;
+use errors::*;
+use sinks::{self, Sink};
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+fn default_path_template() -> String {
+    "lsf.{host}.{metric}".to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphiteConfig {
+    /// Carbon plaintext endpoint as `host:port`
+    pub address: String,
+
+    /// Metric path, where `{host}`, `{group}` and `{metric}` are substituted
+    #[serde(default = "default_path_template")]
+    pub path_template: String,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+pub struct GraphiteSink {
+    config: GraphiteConfig,
+}
+
+impl GraphiteSink {
+    pub fn new(config: GraphiteConfig) -> GraphiteSink {
+        GraphiteSink { config }
+    }
+
+    fn render(&self, records: &[StatusStorageInfo], timestamp: i64) -> String {
+        let mut lines = String::new();
+
+        for record in records {
+            let host = sinks::metric_component(&record.name);
+
+            let group = record.critical_group_name.as_ref()
+                .map(|group| sinks::metric_component(group))
+                .unwrap_or_default();
+
+            for (metric, value) in sinks::record_metrics(record) {
+                let path = self.config.path_template
+                    .replace("{host}", &host)
+                    .replace("{group}", &group)
+                    .replace("{metric}", &sinks::metric_component(&metric));
+
+                lines.push_str(&format!("{} {} {}\n", path, value, timestamp));
+            }
+        }
+
+        lines
+    }
+}
+
+impl Sink for GraphiteSink {
+    fn name(&self) -> &str {
+        "graphite"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let lines = self.render(records, Utc::now().timestamp());
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+
+        let addr = self.config.address.to_socket_addrs()
+            .chain_err(|| format!("Unable to resolve Graphite address {}", self.config.address))?
+            .next()
+            .ok_or_else(|| format!("No address found for Graphite address {}", self.config.address))?;
+
+        let mut stream = TcpStream::connect_timeout(&addr, timeout)
+            .chain_err(|| format!("Unable to connect to Graphite at {}", self.config.address))?;
+
+        stream.set_write_timeout(Some(timeout))
+            .chain_err(|| "Unable to set Graphite write timeout")?;
+
+        stream.write_all(lines.as_bytes())
+            .chain_err(|| format!("Unable to write metrics to Graphite at {}", self.config.address))
+    }
+}
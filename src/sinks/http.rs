@@ -0,0 +1,233 @@
+use chrono::{SecondsFormat, Utc};
+use common::StatusStorageInfo;
+use compression::{self, Compression};
+use encoding;
+use encryption::Encryptor;
+use errors::*;
+use serde_json;
+use signing::Signer;
+use sinks::Sink;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+use ureq;
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_retry_backoff_millis() -> u64 {
+    500
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpConfig {
+    pub url: String,
+
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token: Option<String>,
+
+    /// File containing the bearer token, so it does not need to live in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer_token_file: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basic_auth: Option<BasicAuth>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// Number of retries after the first failed attempt
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+
+    /// Delay before the first retry, doubled on every subsequent retry
+    #[serde(default = "default_retry_backoff_millis")]
+    pub retry_backoff_millis: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+}
+
+pub struct HttpSink {
+    config: HttpConfig,
+    signer: Option<Signer>,
+    encryptor: Option<Encryptor>,
+}
+
+/// Headers carrying the signature of the body, and the time it was signed at.
+const SIGNATURE_HEADER: &str = "X-Lsf-Agent-Signature";
+const SIGNATURE_TIMESTAMP_HEADER: &str = "X-Lsf-Agent-Timestamp";
+const SIGNATURE_KEY_ID_HEADER: &str = "X-Lsf-Agent-Key-Id";
+
+/// Headers of encrypted bodies, with the encoding of the decrypted body
+const ENCRYPTION_HEADER: &str = "X-Lsf-Agent-Encryption";
+const ENCRYPTED_CONTENT_ENCODING_HEADER: &str = "X-Lsf-Agent-Content-Encoding";
+
+/// Header of resent bodies, with the time of the poll they are of
+const POLLED_AT_HEADER: &str = "X-Lsf-Agent-Polled-At";
+
+type Headers = Vec<(&'static str, String)>;
+
+impl HttpSink {
+    pub fn new(config: HttpConfig, signer: Option<Signer>, encryptor: Option<Encryptor>) -> HttpSink {
+        HttpSink { config, signer, encryptor }
+    }
+
+    /// Body as sent, compressed and then encrypted as configured, along with
+    /// the headers describing it.
+    fn encode(&self, body: &str) -> Result<(Vec<u8>, Headers)> {
+        let (body, content_encoding) = compression::encode_body(self.config.compression, body)?;
+
+        let encryptor = match self.encryptor {
+            Some(ref encryptor) => encryptor,
+            None => {
+                let mut headers = vec![("Content-Type", "application/json".to_owned())];
+                headers.extend(content_encoding.map(|content_encoding| ("Content-Encoding", content_encoding.to_owned())));
+                return Ok((body, headers));
+            },
+        };
+
+        // a Content-Encoding would have the receiver decode the encrypted body
+        let mut headers = vec![
+            ("Content-Type", "application/octet-stream".to_owned()),
+            (ENCRYPTION_HEADER, "age".to_owned()),
+        ];
+
+        headers.extend(content_encoding.map(|content_encoding| (ENCRYPTED_CONTENT_ENCODING_HEADER, content_encoding.to_owned())));
+        Ok((encryptor.encrypt(&body)?, headers))
+    }
+
+    /// Signature headers of the uncompressed body.
+    fn signature_headers(&self, body: &str) -> Vec<(&'static str, String)> {
+        let signer = match self.signer {
+            Some(ref signer) => signer,
+            None => return vec![],
+        };
+
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let mut headers = vec![(SIGNATURE_HEADER, signer.sign(&timestamp, body.as_bytes()))];
+        headers.push((SIGNATURE_TIMESTAMP_HEADER, timestamp));
+
+        if let Some(ref key_id) = signer.key_id {
+            headers.push((SIGNATURE_KEY_ID_HEADER, key_id.clone()));
+        }
+
+        headers
+    }
+
+    fn authorization(&self) -> Result<Option<String>> {
+        if let Some(ref bearer_token) = self.config.bearer_token {
+            return Ok(Some(format!("Bearer {}", bearer_token)));
+        }
+
+        if let Some(ref bearer_token_file) = self.config.bearer_token_file {
+            let mut bearer_token = String::new();
+
+            File::open(bearer_token_file)
+                .and_then(|mut f| f.read_to_string(&mut bearer_token))
+                .chain_err(|| format!("Unable to read bearer token file at {}", bearer_token_file))?;
+
+            return Ok(Some(format!("Bearer {}", bearer_token.trim())));
+        }
+
+        Ok(self.config.basic_auth.as_ref().map(|basic_auth| {
+            let credentials = format!("{}:{}", basic_auth.username, basic_auth.password);
+            format!("Basic {}", encoding::base64(credentials.as_bytes()))
+        }))
+    }
+
+    fn post(&self, body: &[u8], body_headers: &[(&str, String)], authorization: &Option<String>)
+        -> ::std::result::Result<(), Box<ureq::Error>> {
+
+        let mut request = ureq::post(&self.config.url)
+            .timeout(Duration::from_secs(self.config.timeout_secs));
+
+        for &(name, ref value) in body_headers {
+            request = request.set(name, value);
+        }
+
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value);
+        }
+
+        if let Some(ref authorization) = *authorization {
+            request = request.set("Authorization", authorization);
+        }
+
+        request.send_bytes(body).map(|_| ()).map_err(Box::new)
+    }
+}
+
+/// Client errors other than throttling will not go away by retrying.
+fn is_retryable(e: &ureq::Error) -> bool {
+    match *e {
+        ureq::Error::Status(code, _) => code == 429 || code >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+impl Sink for HttpSink {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        self.send_with(records, vec![])
+    }
+
+    fn send_spooled(&mut self, records: &[StatusStorageInfo], polled_at: &str) -> Result<()> {
+        self.send_with(records, vec![(POLLED_AT_HEADER, polled_at.to_owned())])
+    }
+}
+
+impl HttpSink {
+    fn send_with(&self, records: &[StatusStorageInfo], extra_headers: Headers) -> Result<()> {
+        let body = serde_json::to_string(records)
+            .chain_err(|| "Unable to serialize list of status storage into string!")?;
+
+        let signature_headers = self.signature_headers(&body);
+        let (body, mut body_headers) = self.encode(&body)?;
+        body_headers.extend(signature_headers);
+        body_headers.extend(extra_headers);
+
+        let authorization = self.authorization()?;
+        let mut backoff = Duration::from_millis(self.config.retry_backoff_millis);
+        let mut attempt = 0;
+
+        loop {
+            match self.post(&body, &body_headers, &authorization) {
+                Ok(()) => return Ok(()),
+
+                Err(ref e) if attempt < self.config.retries && is_retryable(e) => {
+                    warn!(url = self.config.url.as_str(), attempt = attempt + 1, attempts = self.config.retries + 1,
+                        error = %e, backoff_millis = backoff.as_millis() as u64, "HTTP POST failed, retrying");
+
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                },
+
+                Err(e) => bail!("Unable to POST payload to {} after {} attempt(s): {}",
+                    self.config.url, attempt + 1, e),
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use common::StatusStorageInfo;
+use encoding;
+use errors::*;
+use output;
+use serde_json::{self, Value};
+use sinks::Sink;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Read;
+use std::slice;
+use std::time::Duration;
+use template;
+use tls;
+use ureq::{self, Agent, AgentBuilder};
+
+fn default_host_template() -> String {
+    "{name}".to_owned()
+}
+
+fn default_service_name() -> String {
+    "lsf".to_owned()
+}
+
+fn default_create_objects() -> bool {
+    true
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct IcingaConfig {
+    /// Base URL of the Icinga2 API, e.g. `https://icinga:5665`
+    pub url: String,
+
+    /// API user with permission for the objects and check results
+    pub username: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    /// File containing the password, so it does not need to live in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<String>,
+
+    /// CA bundle the API certificate must chain to, the Mozilla root
+    /// certificates when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_path: Option<String>,
+
+    /// Icinga host of the check, with the `{name}` and `{group}` placeholders
+    #[serde(default = "default_host_template")]
+    pub host_template: String,
+
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+
+    /// Creates the hosts and services that do not exist yet
+    #[serde(default = "default_create_objects")]
+    pub create_objects: bool,
+
+    /// Templates imported by the created hosts
+    #[serde(default)]
+    pub host_imports: Vec<String>,
+
+    /// Templates imported by the created services
+    #[serde(default)]
+    pub service_imports: Vec<String>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Pushes every record as the passive check result of a service of its host
+/// through the Icinga2 API, creating the host and service first if needed.
+pub struct IcingaSink {
+    config: IcingaConfig,
+    agent: Agent,
+    authorization: String,
+
+    /// Hosts whose objects were found or created
+    known: BTreeSet<String>,
+}
+
+impl IcingaSink {
+    pub fn new(config: IcingaConfig) -> Result<IcingaSink> {
+        let password = match (&config.password, &config.password_file) {
+            (Some(password), _) => password.clone(),
+
+            (None, Some(password_file)) => {
+                let mut password = String::new();
+
+                File::open(password_file)
+                    .and_then(|mut f| f.read_to_string(&mut password))
+                    .chain_err(|| format!("Unable to read Icinga password file at {}", password_file))?;
+
+                password.trim().to_owned()
+            },
+
+            (None, None) => bail!("Icinga sink requires a password or passwordFile"),
+        };
+
+        let agent = AgentBuilder::new()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .tls_config(tls::client_config(config.ca_path.as_deref())?)
+            .build();
+
+        let credentials = format!("{}:{}", config.username, password);
+        let authorization = format!("Basic {}", encoding::base64(credentials.as_bytes()));
+
+        Ok(IcingaSink { config, agent, authorization, known: BTreeSet::new() })
+    }
+
+    fn host(&self, record: &StatusStorageInfo) -> String {
+        template::render(&self.config.host_template, |placeholder| match placeholder {
+            "name" => Some(record.name.clone()),
+            "group" => Some(record.critical_group_name.clone().unwrap_or_default()),
+            _ => None,
+        })
+    }
+
+    /// Sends the request, returning the status code of the response, or
+    /// failing on anything but a 404.
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<u16> {
+        let url = format!("{}{}", self.config.url.trim_end_matches('/'), path);
+
+        let request = self.agent.request(method, &url)
+            .set("Accept", "application/json")
+            .set("Authorization", &self.authorization);
+
+        let res = match body {
+            Some(body) => {
+                let body = serde_json::to_string(body)
+                    .chain_err(|| "Unable to serialize Icinga request into string!")?;
+
+                request.set("Content-Type", "application/json").send_string(&body)
+            },
+
+            None => request.call(),
+        };
+
+        match res {
+            Ok(response) => Ok(response.status()),
+            Err(ureq::Error::Status(404, _)) => Ok(404),
+            Err(e) => bail!("Unable to {} {}: {}", method, url, e),
+        }
+    }
+
+    fn vars(record: &StatusStorageInfo) -> BTreeMap<String, String> {
+        let mut vars: BTreeMap<String, String> = record.labels.iter().flatten()
+            .map(|(key, value)| (format!("lsf_{}", key), value.clone()))
+            .collect();
+
+        if let Some(ref critical_group_name) = record.critical_group_name {
+            vars.insert("lsf_critical_group".to_owned(), critical_group_name.clone());
+        }
+
+        vars
+    }
+
+    /// Creates the host and the service of the record unless they exist, and
+    /// updates the variables of existing hosts.
+    fn ensure_objects(&self, host: &str, record: &StatusStorageInfo) -> Result<()> {
+        let host_path = format!("/v1/objects/hosts/{}", encoding::percent_encode(host));
+        let vars = Self::vars(record);
+
+        if self.request("GET", &host_path, None)? == 404 {
+            debug!(host, "Creating Icinga host");
+
+            let object = json!({
+                "templates": self.config.host_imports,
+                "attrs": { "check_command": "dummy", "enable_active_checks": false, "vars": vars },
+            });
+
+            self.request("PUT", &host_path, Some(&object))?;
+        } else if !vars.is_empty() {
+            let attrs: BTreeMap<String, &String> = vars.iter()
+                .map(|(key, value)| (format!("vars.{}", key), value))
+                .collect();
+
+            self.request("POST", &host_path, Some(&json!({ "attrs": attrs })))?;
+        }
+
+        let service_path = format!("/v1/objects/services/{}!{}",
+            encoding::percent_encode(host), encoding::percent_encode(&self.config.service_name));
+
+        if self.request("GET", &service_path, None)? == 404 {
+            debug!(host, service = self.config.service_name.as_str(), "Creating Icinga service");
+
+            let object = json!({
+                "templates": self.config.service_imports,
+                "attrs": { "check_command": "dummy", "enable_active_checks": false },
+            });
+
+            self.request("PUT", &service_path, Some(&object))?;
+        }
+
+        Ok(())
+    }
+
+    fn process_check_result(&self, host: &str, record: &StatusStorageInfo) -> Result<()> {
+        let plugin_output = record.remarks.clone()
+            .unwrap_or_else(|| output::to_check_status_str(record.status).to_owned());
+
+        let performance_data: Vec<String> = record.load_indices.iter().flatten()
+            .map(|(index, value)| format!("{}={}", index, value))
+            .collect();
+
+        let result = json!({
+            "type": "Service",
+            "filter": "host.name==h && service.name==s",
+            "filter_vars": { "h": host, "s": self.config.service_name },
+            "exit_status": output::nagios_exit_code(slice::from_ref(record)),
+            "plugin_output": plugin_output,
+            "performance_data": performance_data,
+        });
+
+        match self.request("POST", "/v1/actions/process-check-result", Some(&result))? {
+            404 => bail!("Icinga has no service {}!{}", host, self.config.service_name),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Sink for IcingaSink {
+    fn name(&self) -> &str {
+        "icinga"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        for record in records {
+            let host = self.host(record);
+
+            if self.config.create_objects && !self.known.contains(&host) {
+                self.ensure_objects(&host, record)?;
+                self.known.insert(host.clone());
+            }
+
+            if let Err(e) = self.process_check_result(&host, record) {
+                // the objects may have been deleted since, so check them again next time
+                self.known.remove(&host);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+}
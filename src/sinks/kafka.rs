@@ -0,0 +1,140 @@
+use common::StatusStorageInfo;
+use errors::*;
+use sinks::Sink;
+
+#[cfg(feature = "kafka")]
+use kafka::producer::{Producer, Record, RequiredAcks};
+#[cfg(feature = "kafka")]
+use serde_json;
+#[cfg(feature = "kafka")]
+use std::time::Duration;
+
+fn default_ack_timeout_secs() -> u64 {
+    10
+}
+
+fn default_required_acks() -> String {
+    "one".to_owned()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KafkaConfig {
+    /// Bootstrap brokers as `host:port`
+    pub brokers: Vec<String>,
+    pub topic: String,
+
+    /// Publish one message per host keyed by the mapped host name, instead of
+    /// one message per poll with the whole payload
+    #[serde(default)]
+    pub per_host: bool,
+
+    /// `none`, `one` or `all`
+    #[serde(default = "default_required_acks")]
+    pub required_acks: String,
+
+    #[serde(default = "default_ack_timeout_secs")]
+    pub ack_timeout_secs: u64,
+}
+
+#[cfg(feature = "kafka")]
+pub struct KafkaSink {
+    config: KafkaConfig,
+    producer: Option<Producer>,
+}
+
+#[cfg(feature = "kafka")]
+impl KafkaSink {
+    pub fn new(config: KafkaConfig) -> Result<KafkaSink> {
+        match config.required_acks.as_str() {
+            "none" | "one" | "all" => (),
+            other => bail!("Unknown Kafka required acks {}, expected none, one or all", other),
+        }
+
+        Ok(KafkaSink { config, producer: None })
+    }
+
+    fn create_producer(&self) -> Result<Producer> {
+        let required_acks = match self.config.required_acks.as_str() {
+            "none" => RequiredAcks::None,
+            "all" => RequiredAcks::All,
+            _ => RequiredAcks::One,
+        };
+
+        Producer::from_hosts(self.config.brokers.clone())
+            .with_ack_timeout(Duration::from_secs(self.config.ack_timeout_secs))
+            .with_required_acks(required_acks)
+            .create()
+            .chain_err(|| format!("Unable to connect to Kafka brokers {}", self.config.brokers.join(",")))
+    }
+
+    fn publish(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        if self.producer.is_none() {
+            self.producer = Some(self.create_producer()?);
+        }
+
+        let topic = self.config.topic.as_str();
+        let producer = self.producer.as_mut().expect("Kafka producer must have been created");
+
+        if self.config.per_host {
+            let messages = records.iter()
+                .map(|record| serde_json::to_string(record).map(|value| (record.name.as_str(), value)))
+                .collect::<::std::result::Result<Vec<_>, _>>()
+                .chain_err(|| "Unable to serialize status storage record into string!")?;
+
+            let kafka_records: Vec<_> = messages.iter()
+                .map(|&(key, ref value)| Record::from_key_value(topic, key, value.as_str()))
+                .collect();
+
+            producer.send_all(&kafka_records)
+                .chain_err(|| format!("Unable to publish host records to Kafka topic {}", topic))?;
+        } else {
+            let payload = serde_json::to_string(records)
+                .chain_err(|| "Unable to serialize list of status storage into string!")?;
+
+            producer.send(&Record::from_value(topic, payload.as_str()))
+                .chain_err(|| format!("Unable to publish payload to Kafka topic {}", topic))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "kafka")]
+impl Sink for KafkaSink {
+    fn name(&self) -> &str {
+        "kafka"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let res = self.publish(records);
+
+        // reconnect on the next poll, the brokers may have moved
+        if res.is_err() {
+            self.producer = None;
+        }
+
+        res
+    }
+}
+
+#[cfg(not(feature = "kafka"))]
+pub struct KafkaSink;
+
+#[cfg(not(feature = "kafka"))]
+impl KafkaSink {
+    pub fn new(_: KafkaConfig) -> Result<KafkaSink> {
+        bail!("Kafka sink requires lsf_agent to be built with the kafka feature")
+    }
+}
+
+#[cfg(not(feature = "kafka"))]
+impl Sink for KafkaSink {
+    fn name(&self) -> &str {
+        "kafka"
+    }
+
+    fn send(&mut self, _: &[StatusStorageInfo]) -> Result<()> {
+        unreachable!("Kafka sink cannot be created without the kafka feature")
+    }
+}
@@ -0,0 +1,170 @@
+use common::StatusStorageInfo;
+use errors::*;
+use logging;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+
+pub mod batching;
+pub mod cloudwatch;
+pub mod config;
+pub mod datadog;
+pub mod dedup;
+pub mod etcd;
+pub mod exec;
+pub mod file;
+pub mod graphite;
+pub mod http;
+pub mod icinga;
+pub mod kafka;
+pub mod nsca;
+pub mod otlp;
+pub mod pushgateway;
+pub mod redis;
+pub mod sensu;
+pub mod spool;
+pub mod statsd;
+pub mod stdout;
+pub mod syslog;
+pub mod zabbix;
+
+/// Destination that receives the records of every poll, in addition to the
+/// payload printed on stdout.
+pub trait Sink: Send {
+    fn name(&self) -> &str;
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()>;
+
+    /// Sends the records of an earlier poll taken at the RFC 3339 time, for
+    /// sinks that can tell the receiver about it.
+    fn send_spooled(&mut self, records: &[StatusStorageInfo], _polled_at: &str) -> Result<()> {
+        self.send(records)
+    }
+
+    /// Sends whatever the sink still holds back, before the agent exits.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Sanitizes a name into a single metric path component.
+pub fn metric_component(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Numeric metrics of a record, i.e. the check status, the raw LIM status and
+/// every load index.
+pub fn record_metrics(record: &StatusStorageInfo) -> Vec<(String, f64)> {
+    let mut metrics = vec![("status".to_owned(), record.status as f64)];
+
+    if let Some(lim_status) = record.lim_status {
+        metrics.push(("lim_status".to_owned(), lim_status as f64));
+    }
+
+    if let Some(ref load_indices) = record.load_indices {
+        metrics.extend(load_indices.iter().map(|(index, &value)| (index.clone(), value as f64)));
+    }
+
+    metrics
+}
+
+fn report_send_error(sink: &dyn Sink, e: &Error) {
+    error!(sink = sink.name(), error = %logging::chain(e), "Unable to send records");
+}
+
+struct SinkWorker {
+    name: String,
+    sender: SyncSender<Arc<Vec<StatusStorageInfo>>>,
+    handle: JoinHandle<()>,
+}
+
+/// Runs every sink on its own thread behind a bounded queue, so that a slow
+/// sink neither stalls polling nor the other sinks.
+///
+/// Threads stand in for the async runtime (tokio) the sinks were once meant
+/// to move to: the sinks and their HTTP, Kafka and socket clients are all
+/// blocking, and with a handful of sinks a thread each isolates them just as
+/// well, without an executor and async rewrites of every sink.
+pub struct Dispatcher {
+    workers: Vec<SinkWorker>,
+
+    /// Failed sends of all sinks, counted by their threads
+    errors: Arc<AtomicUsize>,
+
+    drops: AtomicUsize,
+}
+
+impl Dispatcher {
+    pub fn spawn(sinks: Vec<Box<dyn Sink>>, queue_size: usize) -> Dispatcher {
+        let errors = Arc::new(AtomicUsize::new(0));
+
+        let workers = sinks.into_iter()
+            .map(|mut sink| {
+                let name = sink.name().to_owned();
+                let (sender, receiver) = mpsc::sync_channel::<Arc<Vec<StatusStorageInfo>>>(queue_size);
+                let errors = errors.clone();
+
+                let handle = thread::spawn(move || {
+                    for records in receiver {
+                        match sink.send(&records) {
+                            Ok(()) => debug!(sink = sink.name(), records = records.len(), "Passed records to sink"),
+
+                            Err(e) => {
+                                errors.fetch_add(1, Ordering::Relaxed);
+                                report_send_error(sink.as_ref(), &e);
+                            },
+                        }
+                    }
+
+                    if let Err(e) = sink.flush() {
+                        errors.fetch_add(1, Ordering::Relaxed);
+                        report_send_error(sink.as_ref(), &e);
+                    }
+                });
+
+                SinkWorker { name, sender, handle }
+            })
+            .collect();
+
+        Dispatcher { workers, errors, drops: AtomicUsize::new(0) }
+    }
+
+    /// Sends that failed since the dispatcher was spawned, of all sinks.
+    pub fn error_count(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Polls whose records were dropped for a sink, of all sinks.
+    pub fn drop_count(&self) -> usize {
+        self.drops.load(Ordering::Relaxed)
+    }
+
+    /// Queues the records for every sink, dropping them for sinks whose queue
+    /// is still full from earlier polls.
+    pub fn dispatch(&self, records: &[StatusStorageInfo]) {
+        let records = Arc::new(records.to_vec());
+
+        for worker in &self.workers {
+            let dropped = match worker.sender.try_send(records.clone()) {
+                Ok(()) => None,
+                Err(TrySendError::Full(_)) => Some("is falling behind"),
+                Err(TrySendError::Disconnected(_)) => Some("has stopped"),
+            };
+
+            if let Some(reason) = dropped {
+                self.drops.fetch_add(1, Ordering::Relaxed);
+                warn!(sink = worker.name.as_str(), reason, "Dropping records of this poll");
+            }
+        }
+    }
+
+    /// Waits for every sink to drain its queue.
+    pub fn finish(self) {
+        for worker in self.workers {
+            drop(worker.sender);
+            let _ = worker.handle.join();
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use common::StatusStorageInfo;
+use errors::*;
+use flate2::Crc;
+use output;
+use sinks::Sink;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::slice;
+use std::time::Duration;
+use template;
+
+const PACKET_VERSION: i16 = 3;
+
+// sizes of the fields of the packet, including their terminating NUL
+const HOST_NAME_SIZE: usize = 64;
+const SERVICE_SIZE: usize = 128;
+const PLUGIN_OUTPUT_SIZE: usize = 512;
+
+// IV followed by the timestamp the packets have to carry
+const INIT_PACKET_SIZE: usize = 132;
+const IV_SIZE: usize = 128;
+
+fn default_encryption() -> NscaEncryption {
+    NscaEncryption::Xor
+}
+
+fn default_host_template() -> String {
+    "{name}".to_owned()
+}
+
+fn default_service_template() -> String {
+    "LSF".to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+/// `decryption_method` of the NSCA daemon. The methods of the daemon
+/// needing mcrypt are not supported.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NscaEncryption {
+    /// Method 0
+    None,
+
+    /// Method 1
+    Xor,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NscaConfig {
+    /// NSCA daemon as `host:port`
+    pub address: String,
+
+    #[serde(default = "default_encryption")]
+    pub encryption: NscaEncryption,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    /// File containing the password, so it does not need to live in the config
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<String>,
+
+    /// Nagios host of the check, with the `{name}` and `{group}` placeholders
+    #[serde(default = "default_host_template")]
+    pub host_template: String,
+
+    /// Nagios service of the check, with the placeholders of the host
+    #[serde(default = "default_service_template")]
+    pub service_template: String,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Submits every record as the passive check result of a Nagios service,
+/// with the status as its return code and the remarks as its output.
+pub struct NscaSink {
+    config: NscaConfig,
+    password: Vec<u8>,
+}
+
+impl NscaSink {
+    pub fn new(config: NscaConfig) -> Result<NscaSink> {
+        let password = match (&config.password, &config.password_file) {
+            (Some(password), _) => password.clone(),
+
+            (None, Some(password_file)) => {
+                let mut password = String::new();
+
+                File::open(password_file)
+                    .and_then(|mut f| f.read_to_string(&mut password))
+                    .chain_err(|| format!("Unable to read NSCA password file at {}", password_file))?;
+
+                password.trim().to_owned()
+            },
+
+            (None, None) => String::new(),
+        };
+
+        Ok(NscaSink { config, password: password.into_bytes() })
+    }
+
+    fn render(&self, template: &str, record: &StatusStorageInfo) -> String {
+        template::render(template, |placeholder| match placeholder {
+            "name" => Some(record.name.clone()),
+            "group" => Some(record.critical_group_name.clone().unwrap_or_default()),
+            _ => None,
+        })
+    }
+
+    fn packet(&self, record: &StatusStorageInfo, timestamp: &[u8], iv: &[u8]) -> Vec<u8> {
+        let return_code = output::nagios_exit_code(slice::from_ref(record));
+
+        let plugin_output = record.remarks.clone()
+            .unwrap_or_else(|| output::to_check_status_str(record.status).to_owned());
+
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&PACKET_VERSION.to_be_bytes());
+        packet.extend_from_slice(&[0; 2]);
+        packet.extend_from_slice(&[0; 4]);
+        packet.extend_from_slice(timestamp);
+        packet.extend_from_slice(&(return_code as i16).to_be_bytes());
+        push_field(&mut packet, &self.render(&self.config.host_template, record), HOST_NAME_SIZE);
+        push_field(&mut packet, &self.render(&self.config.service_template, record), SERVICE_SIZE);
+        push_field(&mut packet, &plugin_output, PLUGIN_OUTPUT_SIZE);
+        packet.extend_from_slice(&[0; 2]);
+
+        let mut crc = Crc::new();
+        crc.update(&packet);
+        packet[4..8].copy_from_slice(&crc.sum().to_be_bytes());
+
+        if self.config.encryption == NscaEncryption::Xor {
+            for (i, b) in packet.iter_mut().enumerate() {
+                *b ^= iv[i % iv.len()];
+
+                if !self.password.is_empty() {
+                    *b ^= self.password[i % self.password.len()];
+                }
+            }
+        }
+
+        packet
+    }
+}
+
+/// Appends the value truncated to leave room for its NUL, padded with NULs
+/// to the size of the field.
+fn push_field(packet: &mut Vec<u8>, value: &str, size: usize) {
+    let mut field = value.as_bytes().to_vec();
+    field.truncate(size - 1);
+    field.resize(size, 0);
+    packet.extend_from_slice(&field);
+}
+
+impl Sink for NscaSink {
+    fn name(&self) -> &str {
+        "nsca"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+
+        let addr = self.config.address.to_socket_addrs()
+            .chain_err(|| format!("Unable to resolve NSCA address {}", self.config.address))?
+            .next()
+            .ok_or_else(|| format!("No address found for NSCA address {}", self.config.address))?;
+
+        let mut stream = TcpStream::connect_timeout(&addr, timeout)
+            .chain_err(|| format!("Unable to connect to NSCA at {}", self.config.address))?;
+
+        stream.set_read_timeout(Some(timeout))
+            .and_then(|_| stream.set_write_timeout(Some(timeout)))
+            .chain_err(|| "Unable to set NSCA timeouts")?;
+
+        let mut init = [0u8; INIT_PACKET_SIZE];
+
+        stream.read_exact(&mut init)
+            .chain_err(|| format!("Unable to read NSCA initialization packet from {}", self.config.address))?;
+
+        let (iv, timestamp) = init.split_at(IV_SIZE);
+
+        let packets: Vec<u8> = records.iter()
+            .flat_map(|record| self.packet(record, timestamp, iv))
+            .collect();
+
+        stream.write_all(&packets)
+            .chain_err(|| format!("Unable to submit passive checks to NSCA at {}", self.config.address))
+    }
+}
@@ -0,0 +1,193 @@
+use chrono::Utc;
+use common::StatusStorageInfo;
+use errors::*;
+use compression::{self, Compression};
+use serde_json;
+use sinks::{self, Sink};
+use std::collections::HashMap;
+use std::time::Duration;
+use ureq;
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OtlpConfig {
+    /// OTLP/HTTP metrics endpoint, e.g. `http://collector:4318/v1/metrics`
+    pub endpoint: String,
+
+    /// Extra request headers, e.g. for collector authentication
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    /// Resource attributes attached to every host, e.g. `lsf.cluster`
+    #[serde(default)]
+    pub resource_attributes: HashMap<String, String>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+}
+
+// OTLP protobuf JSON encoding, limited to what gauges need
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AnyValue {
+    string_value: String,
+}
+
+#[derive(Serialize, Debug)]
+struct KeyValue {
+    key: String,
+    value: AnyValue,
+}
+
+impl KeyValue {
+    fn new(key: &str, value: &str) -> KeyValue {
+        KeyValue { key: key.to_owned(), value: AnyValue { string_value: value.to_owned() } }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NumberDataPoint {
+    attributes: Vec<KeyValue>,
+    time_unix_nano: String,
+    as_double: f64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Gauge {
+    data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Serialize, Debug)]
+struct Metric {
+    name: String,
+    gauge: Gauge,
+}
+
+#[derive(Serialize, Debug)]
+struct Scope {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ScopeMetrics {
+    scope: Scope,
+    metrics: Vec<Metric>,
+}
+
+#[derive(Serialize, Debug)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ResourceMetrics {
+    resource: Resource,
+    scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ExportMetricsServiceRequest {
+    resource_metrics: Vec<ResourceMetrics>,
+}
+
+pub struct OtlpSink {
+    config: OtlpConfig,
+}
+
+impl OtlpSink {
+    pub fn new(config: OtlpConfig) -> OtlpSink {
+        OtlpSink { config }
+    }
+
+    fn request(&self, records: &[StatusStorageInfo]) -> ExportMetricsServiceRequest {
+        let time_unix_nano = Utc::now().timestamp_nanos_opt().unwrap_or(0).to_string();
+
+        let resource_metrics = records.iter()
+            .map(|record| {
+                let mut attributes = vec![
+                    KeyValue::new("service.name", "lsf_agent"),
+                    KeyValue::new("host.name", &record.name),
+                ];
+
+                attributes.extend(self.config.resource_attributes.iter()
+                    .map(|(key, value)| KeyValue::new(key, value)));
+
+                attributes.extend(record.labels.iter()
+                    .flatten()
+                    .map(|(key, value)| KeyValue::new(key, value)));
+
+                let gauge = |name: &str, attributes: Vec<KeyValue>, value: f64| Metric {
+                    name: name.to_owned(),
+                    gauge: Gauge {
+                        data_points: vec![NumberDataPoint {
+                            attributes,
+                            time_unix_nano: time_unix_nano.clone(),
+                            as_double: value,
+                        }],
+                    },
+                };
+
+                let metrics = sinks::record_metrics(record).into_iter()
+                    .map(|(metric, value)| match metric.as_str() {
+                        "status" | "lim_status" => gauge(&format!("lsf.host.{}", metric), vec![], value),
+                        _ => gauge("lsf.host.load", vec![KeyValue::new("lsf.index", &metric)], value),
+                    })
+                    .collect();
+
+                ResourceMetrics {
+                    resource: Resource { attributes },
+                    scope_metrics: vec![ScopeMetrics {
+                        scope: Scope { name: "lsf_agent", version: env!("CARGO_PKG_VERSION") },
+                        metrics,
+                    }],
+                }
+            })
+            .collect();
+
+        ExportMetricsServiceRequest { resource_metrics }
+    }
+}
+
+impl Sink for OtlpSink {
+    fn name(&self) -> &str {
+        "otlp"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let body = serde_json::to_string(&self.request(records))
+            .chain_err(|| "Unable to serialize OTLP metrics request")?;
+
+        let (body, content_encoding) = compression::encode_body(self.config.compression, &body)?;
+
+        let mut request = ureq::post(&self.config.endpoint)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .set("Content-Type", "application/json");
+
+        for (name, value) in &self.config.headers {
+            request = request.set(name, value);
+        }
+
+        if let Some(content_encoding) = content_encoding {
+            request = request.set("Content-Encoding", content_encoding);
+        }
+
+        request.send_bytes(&body)
+            .chain_err(|| format!("Unable to export metrics to OTLP endpoint {}", self.config.endpoint))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use common::StatusStorageInfo;
+use compression::{self, Compression};
+use encoding;
+use errors::*;
+use sinks::{self, Sink};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use ureq;
+
+fn default_job() -> String {
+    "lsf_agent".to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PushgatewayConfig {
+    /// Base URL of the Pushgateway, e.g. `http://pushgateway:9091`
+    pub url: String,
+
+    #[serde(default = "default_job")]
+    pub job: String,
+
+    #[serde(default)]
+    pub grouping_labels: BTreeMap<String, String>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+}
+
+pub struct PushgatewaySink {
+    config: PushgatewayConfig,
+}
+
+impl PushgatewaySink {
+    pub fn new(config: PushgatewayConfig) -> PushgatewaySink {
+        PushgatewaySink { config }
+    }
+
+    fn push_url(&self) -> String {
+        let mut url = format!("{}/metrics", self.config.url.trim_end_matches('/'));
+        url.push_str(&path_label("job", &self.config.job));
+
+        for (name, value) in &self.config.grouping_labels {
+            url.push_str(&path_label(name, value));
+        }
+
+        url
+    }
+}
+
+impl Sink for PushgatewaySink {
+    fn name(&self) -> &str {
+        "pushgateway"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let url = self.push_url();
+
+        let (body, content_encoding) = compression::encode_body(self.config.compression, &render_exposition(records))?;
+
+        // PUT replaces the whole group, so hosts gone from LIM disappear as well
+        let mut request = ureq::put(&url)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .set("Content-Type", "text/plain; version=0.0.4");
+
+        if let Some(content_encoding) = content_encoding {
+            request = request.set("Content-Encoding", content_encoding);
+        }
+
+        request.send_bytes(&body)
+            .chain_err(|| format!("Unable to push metrics to Pushgateway at {}", url))?;
+
+        Ok(())
+    }
+}
+
+/// Renders the records in the Prometheus text exposition format.
+pub fn render_exposition(records: &[StatusStorageInfo]) -> String {
+    let mut families: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for record in records {
+        let mut labels = vec![format!("host=\"{}\"", escape_label_value(&record.name))];
+
+        if let Some(ref critical_group_name) = record.critical_group_name {
+            labels.push(format!("critical_group=\"{}\"", escape_label_value(critical_group_name)));
+        }
+
+        for (key, value) in record.labels.iter().flatten() {
+            let key = label_name(key);
+
+            // the labels of the agent itself take precedence
+            if key != "host" && key != "critical_group" && key != "index" {
+                labels.push(format!("{}=\"{}\"", key, escape_label_value(value)));
+            }
+        }
+
+        for (metric, value) in sinks::record_metrics(record) {
+            let (family, sample_labels) = match metric.as_str() {
+                "status" | "lim_status" => (format!("lsf_host_{}", metric), labels.clone()),
+                _ => {
+                    let mut sample_labels = labels.clone();
+                    sample_labels.push(format!("index=\"{}\"", escape_label_value(&metric)));
+                    ("lsf_host_load".to_owned(), sample_labels)
+                },
+            };
+
+            let sample = format!("{}{{{}}} {}", family, sample_labels.join(","), value);
+            families.entry(family).or_default().push(sample);
+        }
+    }
+
+    let mut buf = String::new();
+
+    for (family, samples) in families {
+        buf.push_str(&format!("# TYPE {} gauge\n", family));
+
+        for sample in samples {
+            buf.push_str(&sample);
+            buf.push('\n');
+        }
+    }
+
+    buf
+}
+
+/// Replaces the characters not allowed in Prometheus label names.
+fn label_name(key: &str) -> String {
+    key.chars()
+        .enumerate()
+        .map(|(i, c)| if c.is_ascii_alphabetic() || c == '_' || (i > 0 && c.is_ascii_digit()) { c } else { '_' })
+        .collect()
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Encodes a grouping label as URL path segments, falling back to the base64
+/// form for values that cannot appear in a path segment.
+fn path_label(name: &str, value: &str) -> String {
+    if value.is_empty() || value.contains('/') {
+        format!("/{}@base64/{}", name, encoding::base64_url_safe(value.as_bytes()))
+    } else {
+        format!("/{}/{}", name, encoding::percent_encode(value))
+    }
+}
@@ -0,0 +1,173 @@
+use common::StatusStorageInfo;
+use errors::*;
+use serde_json;
+use sinks::Sink;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+fn default_channel() -> String {
+    "lsf.status".to_owned()
+}
+
+fn default_key_prefix() -> String {
+    "lsf:host:".to_owned()
+}
+
+fn default_ttl_secs() -> u64 {
+    300
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RedisConfig {
+    /// Redis endpoint as `host:port`
+    pub address: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+
+    #[serde(default)]
+    pub database: u32,
+
+    /// Channel on which every payload is published
+    #[serde(default = "default_channel")]
+    pub channel: String,
+
+    /// Prefix of the keys holding the latest status of each host
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+pub struct RedisSink {
+    config: RedisConfig,
+}
+
+impl RedisSink {
+    pub fn new(config: RedisConfig) -> RedisSink {
+        RedisSink { config }
+    }
+
+    fn commands(&self, records: &[StatusStorageInfo]) -> Result<Vec<Vec<String>>> {
+        let mut commands = Vec::new();
+
+        if let Some(ref password) = self.config.password {
+            commands.push(vec!["AUTH".to_owned(), password.clone()]);
+        }
+
+        if self.config.database != 0 {
+            commands.push(vec!["SELECT".to_owned(), self.config.database.to_string()]);
+        }
+
+        let payload = serde_json::to_string(records)
+            .chain_err(|| "Unable to serialize list of status storage into string!")?;
+
+        commands.push(vec!["PUBLISH".to_owned(), self.config.channel.clone(), payload]);
+
+        for record in records {
+            let value = serde_json::to_string(record)
+                .chain_err(|| "Unable to serialize status storage record into string!")?;
+
+            commands.push(vec![
+                "SET".to_owned(),
+                format!("{}{}", self.config.key_prefix, record.name),
+                value,
+                "EX".to_owned(),
+                self.config.ttl_secs.to_string(),
+            ]);
+        }
+
+        Ok(commands)
+    }
+}
+
+fn encode_command(args: &[String]) -> Vec<u8> {
+    let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+
+    for arg in args {
+        buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        buf.extend_from_slice(arg.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+
+    buf
+}
+
+/// Reads a single non-array reply, failing on error replies.
+fn read_reply<R: BufRead>(reader: &mut R) -> Result<()> {
+    let mut line = String::new();
+
+    reader.read_line(&mut line)
+        .chain_err(|| "Unable to read Redis reply")?;
+
+    let line = line.trim_end();
+
+    match line.chars().next() {
+        Some('+') | Some(':') => Ok(()),
+        Some('-') => bail!("Redis replied with error: {}", &line[1..]),
+
+        Some('$') => {
+            let len: i64 = line[1..].parse()
+                .chain_err(|| format!("Invalid Redis bulk reply length {}", line))?;
+
+            if len >= 0 {
+                // bulk string followed by CRLF
+                let mut buf = vec![0u8; len as usize + 2];
+                reader.read_exact(&mut buf)
+                    .chain_err(|| "Unable to read Redis bulk reply")?;
+            }
+
+            Ok(())
+        },
+
+        _ => bail!("Unexpected Redis reply {}", line),
+    }
+}
+
+impl Sink for RedisSink {
+    fn name(&self) -> &str {
+        "redis"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let commands = self.commands(records)?;
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+
+        let addr = self.config.address.to_socket_addrs()
+            .chain_err(|| format!("Unable to resolve Redis address {}", self.config.address))?
+            .next()
+            .ok_or_else(|| format!("No address found for Redis address {}", self.config.address))?;
+
+        let mut stream = TcpStream::connect_timeout(&addr, timeout)
+            .chain_err(|| format!("Unable to connect to Redis at {}", self.config.address))?;
+
+        stream.set_write_timeout(Some(timeout))
+            .and_then(|_| stream.set_read_timeout(Some(timeout)))
+            .chain_err(|| "Unable to set Redis socket timeouts")?;
+
+        // pipeline all commands, then collect the replies in order
+        let buf: Vec<u8> = commands.iter().flat_map(|command| encode_command(command)).collect();
+
+        stream.write_all(&buf)
+            .chain_err(|| format!("Unable to send commands to Redis at {}", self.config.address))?;
+
+        let mut reader = BufReader::new(stream);
+
+        for command in &commands {
+            read_reply(&mut reader)
+                .chain_err(|| format!("Redis {} command failed", command[0]))?;
+        }
+
+        Ok(())
+    }
+}
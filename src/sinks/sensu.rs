@@ -0,0 +1,73 @@
+use common::StatusStorageInfo;
+use errors::*;
+use output;
+use serde_json;
+use sinks::Sink;
+use std::time::Duration;
+use ureq;
+
+fn default_url() -> String {
+    "http://127.0.0.1:3031/events".to_owned()
+}
+
+fn default_check_name() -> String {
+    output::DEFAULT_SENSU_CHECK_NAME.to_owned()
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SensuConfig {
+    /// Events API of the local Sensu Go agent
+    #[serde(default = "default_url")]
+    pub url: String,
+
+    #[serde(default = "default_check_name")]
+    pub check_name: String,
+
+    /// Sensu handlers of the events, those of the backend when empty
+    #[serde(default)]
+    pub handlers: Vec<String>,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// Posts the event of every record to the Sensu Go agent, which forwards it
+/// to its backend.
+pub struct SensuSink {
+    config: SensuConfig,
+}
+
+impl SensuSink {
+    pub fn new(config: SensuConfig) -> SensuSink {
+        SensuSink { config }
+    }
+}
+
+impl Sink for SensuSink {
+    fn name(&self) -> &str {
+        "sensu"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        // the agent takes a single event per request
+        for record in records {
+            let event = output::sensu_event(record, &self.config.check_name, &self.config.handlers);
+
+            let body = serde_json::to_string(&event)
+                .chain_err(|| "Unable to serialize Sensu event into string!")?;
+
+            ureq::post(&self.config.url)
+                .timeout(Duration::from_secs(self.config.timeout_secs))
+                .set("Content-Type", "application/json")
+                .send_string(&body)
+                .map_err(|e| Error::from(format!("Unable to POST event of {} to Sensu agent at {}: {}", record.name, self.config.url, e)))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+use chrono::{SecondsFormat, Utc};
+use common::StatusStorageInfo;
+use errors::*;
+use serde_json;
+use sinks::Sink;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+fn default_max_entries() -> usize {
+    1000
+}
+
+/// On-disk queue of the polls a sink failed to send, resent in order once the
+/// sink succeeds again.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpoolConfig {
+    /// Directory of the queue, one file per poll, created if missing
+    pub dir: String,
+
+    /// Spooled polls kept at most, dropping the oldest beyond
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SpooledPoll {
+    polled_at: String,
+    records: Vec<StatusStorageInfo>,
+}
+
+/// Wraps a sink so that the polls it fails to send are spooled, and resent
+/// before any newer poll.
+pub struct SpoolSink {
+    inner: Box<dyn Sink>,
+    config: SpoolConfig,
+    next_seq: u64,
+}
+
+/// Sequence number of a spool file, which sorts in spooling order.
+fn spool_seq(path: &Path) -> Option<u64> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".json"))
+        .filter(|seq| seq.len() == 20)
+        .and_then(|seq| seq.parse().ok())
+}
+
+impl SpoolSink {
+    pub fn open(inner: Box<dyn Sink>, config: SpoolConfig) -> Result<SpoolSink> {
+        fs::create_dir_all(&config.dir)
+            .chain_err(|| format!("Unable to create spool directory at {}", config.dir))?;
+
+        let mut sink = SpoolSink { inner, config, next_seq: 0 };
+        sink.next_seq = sink.spooled()?.last().and_then(|path| spool_seq(path)).map_or(0, |seq| seq + 1);
+        Ok(sink)
+    }
+
+    /// Spool files, oldest first.
+    fn spooled(&self) -> Result<Vec<PathBuf>> {
+        let mut paths: Vec<_> = fs::read_dir(&self.config.dir)
+            .chain_err(|| format!("Unable to list spool directory at {}", self.config.dir))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| spool_seq(path).is_some())
+            .collect();
+
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn spool(&mut self, poll: &SpooledPoll) -> Result<()> {
+        let path = Path::new(&self.config.dir).join(format!("{:020}.json", self.next_seq));
+        let tmp_path = path.with_extension("tmp");
+
+        let content = serde_json::to_vec(poll)
+            .chain_err(|| "Unable to serialize spooled poll into string!")?;
+
+        File::create(&tmp_path)
+            .and_then(|mut file| file.write_all(&content).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&tmp_path, &path))
+            .chain_err(|| format!("Unable to spool poll at {}", path.display()))?;
+
+        self.next_seq += 1;
+
+        let spooled = self.spooled()?;
+        let excess = spooled.len().saturating_sub(self.config.max_entries);
+
+        if excess > 0 {
+            warn!(sink = self.inner.name(), excess, "Spool is full, dropping the oldest polls");
+        }
+
+        for path in &spooled[..excess] {
+            fs::remove_file(path)
+                .chain_err(|| format!("Unable to remove spooled poll at {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Resends the spooled polls in order, stopping at the first failure.
+    fn resend(&mut self) -> Result<()> {
+        for path in self.spooled()? {
+            let mut buf = String::new();
+
+            File::open(&path)
+                .and_then(|mut file| file.read_to_string(&mut buf))
+                .chain_err(|| format!("Unable to read spooled poll at {}", path.display()))?;
+
+            match serde_json::from_str::<SpooledPoll>(&buf) {
+                Ok(poll) => {
+                    self.inner.send_spooled(&poll.records, &poll.polled_at)?;
+                    debug!(sink = self.inner.name(), polled_at = poll.polled_at.as_str(), "Resent spooled poll");
+                },
+
+                // would otherwise block the spool forever
+                Err(e) => warn!(sink = self.inner.name(), path = %path.display(), error = %e, "Dropping unreadable spooled poll"),
+            }
+
+            fs::remove_file(&path)
+                .chain_err(|| format!("Unable to remove spooled poll at {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for SpoolSink {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let polled_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+
+        let res = self.resend().and_then(|_| self.inner.send(records));
+
+        if let Err(e) = res {
+            self.spool(&SpooledPoll { polled_at, records: records.to_vec() })?;
+            return Err(e).chain_err(|| format!("Spooled poll to {} for resending", self.config.dir));
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use std::sync::{Arc, Mutex};
+    use PASSED;
+
+    /// Names of the records of every poll sent, and whether sends fail.
+    #[derive(Default)]
+    struct Received {
+        failing: bool,
+        polls: Vec<Vec<String>>,
+    }
+
+    struct FlakySink(Arc<Mutex<Received>>);
+
+    impl Sink for FlakySink {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+            let mut received = self.0.lock().unwrap();
+
+            if received.failing {
+                bail!("Sink is down");
+            }
+
+            received.polls.push(records.iter().map(|record| record.name.clone()).collect());
+            Ok(())
+        }
+    }
+
+    fn records(name: &str) -> Vec<StatusStorageInfo> {
+        vec![StatusStorageInfo::new(name.to_owned(), PASSED, None, None, None)]
+    }
+
+    fn open(name: &str, max_entries: usize) -> (SpoolSink, Arc<Mutex<Received>>) {
+        let dir = env::temp_dir().join(format!("lsf_agent-spool-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        reopen(&dir, max_entries)
+    }
+
+    fn reopen(dir: &Path, max_entries: usize) -> (SpoolSink, Arc<Mutex<Received>>) {
+        let received = Arc::new(Mutex::new(Received::default()));
+        let config = SpoolConfig { dir: dir.to_str().unwrap().to_owned(), max_entries };
+        (SpoolSink::open(Box::new(FlakySink(received.clone())), config).unwrap(), received)
+    }
+
+    fn spooled_seqs(sink: &SpoolSink) -> Vec<u64> {
+        sink.spooled().unwrap().iter().filter_map(|path| spool_seq(path)).collect()
+    }
+
+    #[test]
+    fn spools_failed_polls_and_resends_them_in_order() {
+        let (mut sink, received) = open("resend", 10);
+
+        received.lock().unwrap().failing = true;
+        assert!(sink.send(&records("p1")).is_err());
+        assert!(sink.send(&records("p2")).is_err());
+        assert_eq!(spooled_seqs(&sink), vec![0, 1]);
+
+        received.lock().unwrap().failing = false;
+        sink.send(&records("p3")).unwrap();
+
+        assert_eq!(received.lock().unwrap().polls, vec![vec!["p1"], vec!["p2"], vec!["p3"]]);
+        assert!(spooled_seqs(&sink).is_empty());
+
+        fs::remove_dir_all(&sink.config.dir).unwrap();
+    }
+
+    #[test]
+    fn drops_the_oldest_polls_beyond_max_entries() {
+        let (mut sink, received) = open("max_entries", 2);
+
+        received.lock().unwrap().failing = true;
+
+        for name in &["p1", "p2", "p3"] {
+            assert!(sink.send(&records(name)).is_err());
+        }
+
+        assert_eq!(spooled_seqs(&sink), vec![1, 2]);
+
+        received.lock().unwrap().failing = false;
+        sink.send(&records("p4")).unwrap();
+
+        assert_eq!(received.lock().unwrap().polls, vec![vec!["p2"], vec!["p3"], vec!["p4"]]);
+
+        fs::remove_dir_all(&sink.config.dir).unwrap();
+    }
+
+    #[test]
+    fn reopening_continues_the_sequence() {
+        let (mut sink, received) = open("reopen", 10);
+
+        received.lock().unwrap().failing = true;
+        assert!(sink.send(&records("p1")).is_err());
+        assert!(sink.send(&records("p2")).is_err());
+
+        let dir = PathBuf::from(&sink.config.dir);
+        drop(sink);
+
+        let (mut sink, received) = reopen(&dir, 10);
+        assert_eq!(sink.next_seq, 2);
+
+        received.lock().unwrap().failing = true;
+        assert!(sink.send(&records("p3")).is_err());
+        assert_eq!(spooled_seqs(&sink), vec![0, 1, 2]);
+
+        received.lock().unwrap().failing = false;
+        sink.send(&records("p4")).unwrap();
+
+        assert_eq!(received.lock().unwrap().polls, vec![vec!["p1"], vec!["p2"], vec!["p3"], vec!["p4"]]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
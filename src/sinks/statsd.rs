@@ -0,0 +1,80 @@
+use common::StatusStorageInfo;
+use errors::*;
+use sinks::{self, Sink};
+use std::net::UdpSocket;
+
+// keeps each datagram within a typical Ethernet MTU
+const MAX_PACKET_BYTES: usize = 1432;
+
+fn default_prefix() -> String {
+    "lsf".to_owned()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsdConfig {
+    /// StatsD endpoint as `host:port`
+    pub address: String,
+
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+}
+
+pub struct StatsdSink {
+    config: StatsdConfig,
+}
+
+impl StatsdSink {
+    pub fn new(config: StatsdConfig) -> StatsdSink {
+        StatsdSink { config }
+    }
+
+    fn render(&self, records: &[StatusStorageInfo]) -> Vec<String> {
+        let mut packets = Vec::new();
+        let mut packet = String::new();
+
+        for record in records {
+            let host = sinks::metric_component(&record.name);
+
+            for (metric, value) in sinks::record_metrics(record) {
+                let line = format!("{}.{}.{}:{}|g",
+                    self.config.prefix, host, sinks::metric_component(&metric), value);
+
+                if !packet.is_empty() && packet.len() + line.len() + 1 > MAX_PACKET_BYTES {
+                    packets.push(packet);
+                    packet = String::new();
+                }
+
+                if !packet.is_empty() {
+                    packet.push('\n');
+                }
+
+                packet.push_str(&line);
+            }
+        }
+
+        if !packet.is_empty() {
+            packets.push(packet);
+        }
+
+        packets
+    }
+}
+
+impl Sink for StatsdSink {
+    fn name(&self) -> &str {
+        "statsd"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .chain_err(|| "Unable to bind UDP socket for StatsD")?;
+
+        for packet in self.render(records) {
+            socket.send_to(packet.as_bytes(), self.config.address.as_str())
+                .chain_err(|| format!("Unable to send gauges to StatsD at {}", self.config.address))?;
+        }
+
+        Ok(())
+    }
+}
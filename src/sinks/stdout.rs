@@ -0,0 +1,34 @@
+use common::StatusStorageInfo;
+use errors::*;
+use output::{self, OutputFormat};
+use sinks::Sink;
+use std::io::{self, Write};
+
+/// Prints the records of every poll on stdout in the format, after the
+/// payload of the poll.
+pub struct StdoutSink {
+    format: OutputFormat,
+}
+
+impl StdoutSink {
+    pub fn new(format: OutputFormat) -> StdoutSink {
+        StdoutSink { format }
+    }
+}
+
+impl Sink for StdoutSink {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let payload = output::render(self.format, records)?;
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+
+        writeln!(stdout, "{}", payload)
+            .and_then(|_| stdout.flush())
+            .chain_err(|| "Unable to print records on stdout")
+    }
+}
@@ -0,0 +1,257 @@
+use chrono::{SecondsFormat, Utc};
+use common::StatusStorageInfo;
+use errors::*;
+use libc;
+use sinks::Sink;
+use std::ffi::CStr;
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+use std::os::raw::c_char;
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use {ALERT, DOWNTIME, PASSED};
+
+fn default_address() -> String {
+    "unix:/dev/log".to_owned()
+}
+
+fn default_facility() -> String {
+    "daemon".to_owned()
+}
+
+fn default_app_name() -> String {
+    "lsf_agent".to_owned()
+}
+
+fn default_sd_id() -> String {
+    "lsf@32473".to_owned()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SeverityMapping {
+    pub passed: String,
+    pub alert: String,
+    pub failed: String,
+}
+
+impl Default for SeverityMapping {
+    fn default() -> SeverityMapping {
+        SeverityMapping {
+            passed: "info".to_owned(),
+            alert: "warning".to_owned(),
+            failed: "err".to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SyslogConfig {
+    /// `unix:<path>`, `udp:<host:port>` or `tcp:<host:port>`
+    #[serde(default = "default_address")]
+    pub address: String,
+
+    #[serde(default = "default_facility")]
+    pub facility: String,
+
+    /// Severity names of the PASSED, ALERT and FAILED check statuses
+    #[serde(default)]
+    pub severities: SeverityMapping,
+
+    #[serde(default = "default_app_name")]
+    pub app_name: String,
+
+    /// RFC 5424 structured data ID of the host record element
+    #[serde(default = "default_sd_id")]
+    pub sd_id: String,
+}
+
+fn to_facility(name: &str) -> Option<u8> {
+    let facility = match name {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "lpr" => 6,
+        "news" => 7,
+        "uucp" => 8,
+        "cron" => 9,
+        "authpriv" => 10,
+        "ftp" => 11,
+        "local0" => 16,
+        "local1" => 17,
+        "local2" => 18,
+        "local3" => 19,
+        "local4" => 20,
+        "local5" => 21,
+        "local6" => 22,
+        "local7" => 23,
+        _ => return None,
+    };
+
+    Some(facility)
+}
+
+fn to_severity(name: &str) -> Option<u8> {
+    let severity = match name {
+        "emerg" => 0,
+        "alert" => 1,
+        "crit" => 2,
+        "err" => 3,
+        "warning" => 4,
+        "notice" => 5,
+        "info" => 6,
+        "debug" => 7,
+        _ => return None,
+    };
+
+    Some(severity)
+}
+
+/// Hostname of the machine the agent runs on.
+pub fn local_hostname() -> String {
+    let mut buf = [0 as c_char; 256];
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) };
+
+    if rc != 0 {
+        return "-".to_owned();
+    }
+
+    // gethostname does not guarantee termination on truncation
+    buf[buf.len() - 1] = 0;
+    unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+fn sd_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+enum Transport {
+    Unix(String),
+    Udp(String),
+    Tcp(String),
+}
+
+pub struct SyslogSink {
+    config: SyslogConfig,
+    transport: Transport,
+    facility: u8,
+    severities: [u8; 3],
+    hostname: String,
+}
+
+impl SyslogSink {
+    pub fn new(config: SyslogConfig) -> Result<SyslogSink> {
+        let transport = if config.address.starts_with("unix:") {
+            Transport::Unix(config.address["unix:".len()..].to_owned())
+        } else if config.address.starts_with("udp:") {
+            Transport::Udp(config.address["udp:".len()..].to_owned())
+        } else if config.address.starts_with("tcp:") {
+            Transport::Tcp(config.address["tcp:".len()..].to_owned())
+        } else {
+            bail!("Unknown syslog address {}, expected unix:, udp: or tcp: prefix", config.address);
+        };
+
+        let facility = to_facility(&config.facility)
+            .ok_or_else(|| format!("Unknown syslog facility {}", config.facility))?;
+
+        let severity = |name: &str| to_severity(name)
+            .ok_or_else(|| Error::from(format!("Unknown syslog severity {}", name)));
+
+        let severities = [
+            severity(&config.severities.passed)?,
+            severity(&config.severities.alert)?,
+            severity(&config.severities.failed)?,
+        ];
+
+        Ok(SyslogSink {
+            config,
+            transport,
+            facility,
+            severities,
+            hostname: local_hostname(),
+        })
+    }
+
+    fn message(&self, record: &StatusStorageInfo) -> String {
+        let severity = match record.status {
+            PASSED => self.severities[0],
+            ALERT | DOWNTIME => self.severities[1],
+            _ => self.severities[2],
+        };
+
+        let mut params = vec![
+            format!("host=\"{}\"", sd_escape(&record.name)),
+            format!("status=\"{}\"", record.status),
+        ];
+
+        if let Some(lim_status) = record.lim_status {
+            params.push(format!("limStatus=\"{}\"", lim_status));
+        }
+
+        if let Some(ref critical_group_name) = record.critical_group_name {
+            params.push(format!("criticalGroupName=\"{}\"", sd_escape(critical_group_name)));
+        }
+
+        format!("<{}>1 {} {} {} {} {} [{} {}] {}",
+            self.facility * 8 + severity,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.hostname,
+            self.config.app_name,
+            process::id(),
+            "status",
+            self.config.sd_id,
+            params.join(" "),
+            record.remarks.as_deref().unwrap_or(""))
+    }
+}
+
+impl Sink for SyslogSink {
+    fn name(&self) -> &str {
+        "syslog"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let messages: Vec<String> = records.iter().map(|record| self.message(record)).collect();
+
+        match self.transport {
+            Transport::Unix(ref path) => {
+                let socket = UnixDatagram::unbound()
+                    .chain_err(|| "Unable to create syslog unix socket")?;
+
+                for message in &messages {
+                    socket.send_to(message.as_bytes(), path)
+                        .chain_err(|| format!("Unable to send syslog message to {}", path))?;
+                }
+            },
+
+            Transport::Udp(ref addr) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")
+                    .chain_err(|| "Unable to bind UDP socket for syslog")?;
+
+                for message in &messages {
+                    socket.send_to(message.as_bytes(), addr.as_str())
+                        .chain_err(|| format!("Unable to send syslog message to {}", addr))?;
+                }
+            },
+
+            Transport::Tcp(ref addr) => {
+                let mut stream = TcpStream::connect(addr.as_str())
+                    .chain_err(|| format!("Unable to connect to syslog at {}", addr))?;
+
+                // RFC 6587 octet counting framing
+                let framed: String = messages.iter()
+                    .map(|message| format!("{} {}", message.len(), message))
+                    .collect();
+
+                stream.write_all(framed.as_bytes())
+                    .chain_err(|| format!("Unable to send syslog messages to {}", addr))?;
+            },
+        }
+
+        Ok(())
+    }
+}
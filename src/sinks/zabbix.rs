@@ -0,0 +1,196 @@
+use chrono::Utc;
+use common::StatusStorageInfo;
+use errors::*;
+use serde_json;
+use sinks::{self, Sink};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const ZBX_HEADER: &[u8] = b"ZBXD\x01";
+
+/// Largest response body read, as the server only answers with a short
+/// summary.
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+fn default_key_prefix() -> String {
+    "lsf".to_owned()
+}
+
+fn default_discovery() -> bool {
+    true
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ZabbixConfig {
+    /// Zabbix server or proxy trapper endpoint as `host:port`
+    pub server: String,
+
+    /// Name of the Zabbix host that owns the trapper items
+    pub host: String,
+
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+
+    /// Whether to also push the low-level discovery payload of the host list
+    #[serde(default = "default_discovery")]
+    pub discovery: bool,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct SenderItem {
+    host: String,
+    key: String,
+    value: String,
+    clock: i64,
+}
+
+#[derive(Serialize, Debug)]
+struct SenderRequest {
+    request: &'static str,
+    data: Vec<SenderItem>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SenderResponse {
+    response: String,
+
+    #[serde(default)]
+    info: String,
+}
+
+#[derive(Serialize, Debug)]
+struct DiscoveryEntry {
+    #[serde(rename = "{#LSFHOST}")]
+    host: String,
+}
+
+#[derive(Serialize, Debug)]
+struct Discovery {
+    data: Vec<DiscoveryEntry>,
+}
+
+pub struct ZabbixSink {
+    config: ZabbixConfig,
+}
+
+impl ZabbixSink {
+    pub fn new(config: ZabbixConfig) -> ZabbixSink {
+        ZabbixSink { config }
+    }
+
+    fn items(&self, records: &[StatusStorageInfo]) -> Result<Vec<SenderItem>> {
+        let clock = Utc::now().timestamp();
+        let mut items = Vec::new();
+
+        let item = |key: String, value: String| SenderItem {
+            host: self.config.host.clone(),
+            key,
+            value,
+            clock,
+        };
+
+        if self.config.discovery {
+            let discovery = Discovery {
+                data: records.iter()
+                    .map(|record| DiscoveryEntry { host: record.name.clone() })
+                    .collect(),
+            };
+
+            let discovery = serde_json::to_string(&discovery)
+                .chain_err(|| "Unable to serialize Zabbix discovery payload")?;
+
+            items.push(item(format!("{}.hosts.discovery", self.config.key_prefix), discovery));
+        }
+
+        for record in records {
+            for (metric, value) in sinks::record_metrics(record) {
+                let key = match metric.as_str() {
+                    "status" | "lim_status" => format!("{}.{}[{}]", self.config.key_prefix, metric, record.name),
+                    _ => format!("{}.load[{},{}]", self.config.key_prefix, record.name, metric),
+                };
+
+                items.push(item(key, value.to_string()));
+            }
+        }
+
+        Ok(items)
+    }
+}
+
+impl Sink for ZabbixSink {
+    fn name(&self) -> &str {
+        "zabbix"
+    }
+
+    fn send(&mut self, records: &[StatusStorageInfo]) -> Result<()> {
+        let request = SenderRequest {
+            request: "sender data",
+            data: self.items(records)?,
+        };
+
+        let body = serde_json::to_vec(&request)
+            .chain_err(|| "Unable to serialize Zabbix sender request")?;
+
+        let mut packet = ZBX_HEADER.to_vec();
+        packet.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        packet.extend_from_slice(&body);
+
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+
+        let addr = self.config.server.to_socket_addrs()
+            .chain_err(|| format!("Unable to resolve Zabbix server address {}", self.config.server))?
+            .next()
+            .ok_or_else(|| format!("No address found for Zabbix server {}", self.config.server))?;
+
+        let mut stream = TcpStream::connect_timeout(&addr, timeout)
+            .chain_err(|| format!("Unable to connect to Zabbix server at {}", self.config.server))?;
+
+        stream.set_write_timeout(Some(timeout))
+            .and_then(|_| stream.set_read_timeout(Some(timeout)))
+            .chain_err(|| "Unable to set Zabbix socket timeouts")?;
+
+        stream.write_all(&packet)
+            .chain_err(|| format!("Unable to send items to Zabbix server at {}", self.config.server))?;
+
+        let mut header = [0u8; 13];
+
+        stream.read_exact(&mut header)
+            .chain_err(|| "Unable to read Zabbix server response header")?;
+
+        if &header[..5] != ZBX_HEADER {
+            bail!("Invalid Zabbix server response header");
+        }
+
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&header[5..]);
+
+        let len = u64::from_le_bytes(len);
+
+        if len > MAX_RESPONSE_BYTES {
+            bail!("Zabbix server response of {} bytes exceeds the limit of {} bytes", len, MAX_RESPONSE_BYTES);
+        }
+
+        let mut body = vec![0u8; len as usize];
+
+        stream.read_exact(&mut body)
+            .chain_err(|| "Unable to read Zabbix server response body")?;
+
+        let response: SenderResponse = serde_json::from_slice(&body)
+            .chain_err(|| "Unable to parse Zabbix server response")?;
+
+        if response.response != "success" {
+            bail!("Zabbix server rejected items: {}", response.info);
+        }
+
+        Ok(())
+    }
+}
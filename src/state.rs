@@ -0,0 +1,367 @@
+use common::StatusStorageInfo;
+use errors::*;
+use output;
+use serde_json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use {DOWNTIME, PASSED};
+
+fn default_threshold() -> u32 {
+    1
+}
+
+/// Number of consecutive polls a new status has to persist for before it gets
+/// reported, so that briefly toggling hosts do not cause alert storms.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FlapConfig {
+    #[serde(default = "default_threshold")]
+    pub fail_threshold: u32,
+
+    #[serde(default = "default_threshold")]
+    pub pass_threshold: u32,
+}
+
+impl FlapConfig {
+    /// Polls needed before the given status gets reported. Downtimes are
+    /// scheduled, so they apply right away.
+    fn threshold(&self, status: i32) -> u32 {
+        match status {
+            PASSED => self.pass_threshold,
+            DOWNTIME => 1,
+            _ => self.fail_threshold,
+        }
+    }
+}
+
+impl Default for FlapConfig {
+    fn default() -> FlapConfig {
+        FlapConfig {
+            fail_threshold: default_threshold(),
+            pass_threshold: default_threshold(),
+        }
+    }
+}
+
+/// Last reported status of a host.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HostState {
+    pub status: i32,
+
+    /// Unix time since when the host has had this status
+    pub since: i64,
+
+    /// Differing status observed, but not reported yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_status: Option<i32>,
+
+    /// Consecutive polls the pending status has been observed in
+    #[serde(default)]
+    pub pending_polls: u32,
+
+    /// Load indices, and storage as `storage:<what>`, above their alert
+    /// threshold in the previous poll
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub load_alerts: BTreeSet<String>,
+}
+
+/// Failure of a host that has been notified and not cleared yet.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenAlert {
+    /// Unix time of the first notification
+    pub opened_at: i64,
+
+    /// Unix time of the latest notification
+    pub notified_at: i64,
+
+    /// Escalations notified so far
+    #[serde(default)]
+    pub escalation: usize,
+}
+
+/// Statuses of the previous poll, persisted across runs in the state file.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct State {
+    #[serde(default)]
+    pub hosts: BTreeMap<String, HostState>,
+
+    /// Unix time of the previous poll
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polled_at: Option<i64>,
+
+    /// Consecutive polls in which LIM could not be queried
+    #[serde(default)]
+    pub failed_polls: u32,
+
+    /// Open alerts by record name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub alerts: BTreeMap<String, OpenAlert>,
+}
+
+impl State {
+    /// Reads the state file, starting afresh if it does not exist yet.
+    pub fn load(path: &str) -> Result<State> {
+        let mut buf = String::new();
+
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut buf)
+                .chain_err(|| format!("Unable to read state file at {}", path))?,
+
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(State::default()),
+            Err(e) => return Err(e).chain_err(|| format!("Unable to open state file at {}", path)),
+        };
+
+        serde_json::from_str(&buf)
+            .chain_err(|| format!("Unable to parse state file at {}", path))
+    }
+
+    /// Writes the state file atomically, so that an interrupted write never
+    /// loses the previous state.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let tmp_path = format!("{}.tmp", path);
+
+        let content = serde_json::to_string(self)
+            .chain_err(|| "Unable to serialize state into string!")?;
+
+        File::create(&tmp_path)
+            .and_then(|mut file| file.write_all(content.as_bytes()).and_then(|_| file.sync_all()))
+            .chain_err(|| format!("Unable to write state file at {}", tmp_path))?;
+
+        fs::rename(&tmp_path, path)
+            .chain_err(|| format!("Unable to replace state file at {}", path))
+    }
+
+    /// Records whose status differs from the previous poll, including hosts
+    /// that were not seen before.
+    pub fn changed(&self, records: &[StatusStorageInfo]) -> Vec<StatusStorageInfo> {
+        records.iter()
+            .filter(|record| match self.hosts.get(&record.name) {
+                Some(host_state) => host_state.status != record.status,
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Replaces the state with the statuses of the given poll. Records whose
+    /// status has not persisted for long enough keep their previously
+    /// reported status.
+    pub fn observe(&mut self, records: &mut [StatusStorageInfo], flap: &FlapConfig, now: i64) {
+        let mut hosts = BTreeMap::new();
+
+        for record in records.iter_mut() {
+            let observed = record.status;
+
+            let host_state = match self.hosts.get(&record.name) {
+                Some(prev) if prev.status == observed => HostState {
+                    pending_status: None,
+                    pending_polls: 0,
+                    load_alerts: record.load_alerts.clone(),
+                    ..prev.clone()
+                },
+
+                Some(prev) => {
+                    let pending_polls = if prev.pending_status == Some(observed) { prev.pending_polls + 1 } else { 1 };
+                    let threshold = flap.threshold(observed);
+
+                    if pending_polls >= threshold {
+                        HostState {
+                            status: observed,
+                            since: now,
+                            pending_status: None,
+                            pending_polls: 0,
+                            load_alerts: record.load_alerts.clone(),
+                        }
+                    } else {
+                        HostState {
+                            pending_status: Some(observed),
+                            pending_polls,
+                            load_alerts: record.load_alerts.clone(),
+                            ..prev.clone()
+                        }
+                    }
+                },
+
+                None => HostState {
+                    status: observed,
+                    since: now,
+                    pending_status: None,
+                    pending_polls: 0,
+                    load_alerts: record.load_alerts.clone(),
+                },
+            };
+
+            if host_state.status != observed {
+                let pending = format!("{} pending for {} of {} polls",
+                    output::to_check_status_str(observed),
+                    host_state.pending_polls,
+                    flap.threshold(observed));
+
+                record.status = host_state.status;
+
+                record.remarks = Some(match record.remarks {
+                    Some(ref remarks) => format!("{} ({})", remarks, pending),
+                    None => pending,
+                });
+            }
+
+            hosts.insert(record.name.clone(), host_state);
+        }
+
+        self.hosts = hosts;
+        self.polled_at = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use {ALERT, FAILED};
+
+    fn record(name: &str, status: i32) -> StatusStorageInfo {
+        StatusStorageInfo::new(name.to_owned(), status, None, None, None)
+    }
+
+    fn flap(fail_threshold: u32) -> FlapConfig {
+        FlapConfig { fail_threshold, pass_threshold: 1 }
+    }
+
+    /// Observes a single record of the given status, returning it as reported.
+    fn poll(state: &mut State, flap: &FlapConfig, status: i32, now: i64) -> StatusStorageInfo {
+        let mut records = vec![record("h1", status)];
+        state.observe(&mut records, flap, now);
+        records.remove(0)
+    }
+
+    #[test]
+    fn failure_is_reported_after_the_threshold() {
+        let flap = flap(3);
+        let mut state = State::default();
+
+        assert_eq!(poll(&mut state, &flap, PASSED, 0).status, PASSED);
+        assert_eq!(poll(&mut state, &flap, FAILED, 1).status, PASSED);
+        assert_eq!(poll(&mut state, &flap, FAILED, 2).status, PASSED);
+
+        let reported = poll(&mut state, &flap, FAILED, 3);
+        assert_eq!(reported.status, FAILED);
+        assert_eq!(reported.remarks, None);
+        assert_eq!(state.hosts["h1"].since, 3);
+        assert_eq!(state.hosts["h1"].pending_status, None);
+    }
+
+    #[test]
+    fn pending_status_is_remarked() {
+        let flap = flap(3);
+        let mut state = State::default();
+
+        poll(&mut state, &flap, PASSED, 0);
+
+        let reported = poll(&mut state, &flap, FAILED, 1);
+        assert_eq!(reported.remarks.as_deref(), Some("FAILED pending for 1 of 3 polls"));
+
+        let mut records = vec![StatusStorageInfo::new("h1".to_owned(), FAILED, None, None, Some("Down".to_owned()))];
+        state.observe(&mut records, &flap, 2);
+        assert_eq!(records[0].remarks.as_deref(), Some("Down (FAILED pending for 2 of 3 polls)"));
+    }
+
+    #[test]
+    fn another_status_restarts_the_pending_count() {
+        let flap = flap(3);
+        let mut state = State::default();
+
+        poll(&mut state, &flap, PASSED, 0);
+        poll(&mut state, &flap, FAILED, 1);
+        poll(&mut state, &flap, FAILED, 2);
+
+        let reported = poll(&mut state, &flap, ALERT, 3);
+        assert_eq!(reported.status, PASSED);
+        assert_eq!(reported.remarks.as_deref(), Some("ALERT pending for 1 of 3 polls"));
+        assert_eq!(state.hosts["h1"].pending_status, Some(ALERT));
+
+        assert_eq!(poll(&mut state, &flap, FAILED, 4).remarks.as_deref(), Some("FAILED pending for 1 of 3 polls"));
+    }
+
+    #[test]
+    fn reported_status_clears_the_pending_one() {
+        let flap = flap(3);
+        let mut state = State::default();
+
+        poll(&mut state, &flap, PASSED, 0);
+        poll(&mut state, &flap, FAILED, 1);
+
+        let reported = poll(&mut state, &flap, PASSED, 2);
+        assert_eq!(reported.remarks, None);
+        assert_eq!(state.hosts["h1"].pending_status, None);
+        assert_eq!(state.hosts["h1"].pending_polls, 0);
+        assert_eq!(state.hosts["h1"].since, 0);
+    }
+
+    #[test]
+    fn downtime_applies_right_away() {
+        let flap = flap(3);
+        let mut state = State::default();
+
+        poll(&mut state, &flap, PASSED, 0);
+        assert_eq!(poll(&mut state, &flap, DOWNTIME, 1).status, DOWNTIME);
+        assert_eq!(state.hosts["h1"].since, 1);
+    }
+
+    #[test]
+    fn new_hosts_are_reported_right_away() {
+        let mut state = State::default();
+        assert_eq!(poll(&mut state, &flap(3), FAILED, 0).status, FAILED);
+    }
+
+    fn state_path(name: &str) -> String {
+        env::temp_dir().join(format!("lsf_agent-state-{}-{}.json", name, process::id())).to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn changed_includes_new_hosts() {
+        let mut state = State::default();
+        state.observe(&mut [record("h1", PASSED), record("h2", FAILED)], &FlapConfig::default(), 0);
+
+        let names: Vec<_> = state.changed(&[record("h1", PASSED), record("h2", PASSED), record("h3", PASSED)]).into_iter()
+            .map(|record| record.name)
+            .collect();
+
+        assert_eq!(names, vec!["h2", "h3"]);
+    }
+
+    #[test]
+    fn missing_file_loads_empty_state() {
+        let path = state_path("missing");
+        let _ = fs::remove_file(&path);
+
+        let state = State::load(&path).unwrap();
+        assert!(state.hosts.is_empty());
+        assert_eq!(state.polled_at, None);
+    }
+
+    #[test]
+    fn save_replaces_the_file() {
+        let path = state_path("save");
+        let _ = fs::remove_file(&path);
+
+        let mut state = State::default();
+        state.observe(&mut [record("h1", FAILED)], &FlapConfig::default(), 7);
+        state.save(&path).unwrap();
+
+        state.observe(&mut [record("h1", PASSED)], &FlapConfig::default(), 8);
+        state.save(&path).unwrap();
+
+        assert!(fs::metadata(format!("{}.tmp", path)).is_err());
+
+        let loaded = State::load(&path).unwrap();
+        assert_eq!(loaded.polled_at, Some(8));
+        assert_eq!(loaded.hosts["h1"].status, PASSED);
+
+        let _ = fs::remove_file(&path);
+    }
+}
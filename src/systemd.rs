@@ -0,0 +1,80 @@
+use std::env;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Client of the systemd notification protocol, a no-op when the agent is not
+/// started by systemd with `Type=notify` or `WatchdogSec=`.
+pub struct Notifier {
+    socket: Option<(UnixDatagram, SocketAddr)>,
+    watchdog_interval: Option<Duration>,
+    ready: bool,
+}
+
+impl Notifier {
+    pub fn from_env() -> Notifier {
+        let socket = env::var("NOTIFY_SOCKET").ok().and_then(|path| {
+            // a leading @ denotes the abstract socket namespace
+            let addr = if let Some(name) = path.strip_prefix('@') {
+                SocketAddr::from_abstract_name(name.as_bytes()).ok()
+            } else {
+                SocketAddr::from_pathname(&path).ok()
+            };
+
+            addr.and_then(|addr| UnixDatagram::unbound().ok().map(|socket| (socket, addr)))
+        });
+
+        let watchdog_for_us = env::var("WATCHDOG_PID").ok()
+            .map(|pid| pid.parse::<u32>().ok() == Some(process::id()))
+            .unwrap_or(true);
+
+        // ping twice per watchdog period, as recommended by sd_watchdog_enabled(3)
+        let watchdog_interval = env::var("WATCHDOG_USEC").ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|&usec| usec > 0 && watchdog_for_us)
+            .map(|usec| Duration::from_micros(usec / 2));
+
+        Notifier { socket, watchdog_interval, ready: false }
+    }
+
+    fn notify(&self, state: &str) {
+        if let Some((ref socket, ref addr)) = self.socket {
+            // systemd not listening must never take the agent down
+            let _ = socket.send_to_addr(state.as_bytes(), addr);
+        }
+    }
+
+    /// Signals readiness once, along with a human-readable status line.
+    pub fn poll_done(&mut self, status: &str) {
+        if self.ready {
+            self.notify(&format!("WATCHDOG=1\nSTATUS={}", status));
+        } else {
+            self.notify(&format!("READY=1\nSTATUS={}", status));
+            self.ready = true;
+        }
+    }
+
+    /// Sleeps for the given duration while keeping the watchdog fed. Pings are
+    /// only sent from the poll loop, so a wedged LSF call stops them.
+    pub fn sleep(&self, duration: Duration) {
+        let watchdog_interval = match self.watchdog_interval {
+            Some(watchdog_interval) => watchdog_interval,
+            None => return thread::sleep(duration),
+        };
+
+        let deadline = Instant::now() + duration;
+
+        loop {
+            let now = Instant::now();
+
+            if now >= deadline {
+                break;
+            }
+
+            thread::sleep(watchdog_interval.min(deadline - now));
+            self.notify("WATCHDOG=1");
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use common::StatusStorageInfo;
+use libc;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+use {ALERT, FAILED, PASSED};
+
+/// Record about the agent itself, so that a broken agent does not go
+/// unnoticed.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TelemetryConfig {
+    /// Record name without the prefix, `agent` when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// ALERT once LIM could not be queried in this many consecutive polls
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_failed_polls: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert_memory_bytes: Option<u64>,
+}
+
+/// Measurements of the agent at the end of a poll.
+#[derive(Clone, Debug)]
+pub struct AgentStats {
+    pub poll_duration: Duration,
+    pub failed_polls: u32,
+
+    /// Failed sends and dropped polls of all sinks since the agent started
+    pub sink_errors: usize,
+    pub sink_drops: usize,
+
+    pub memory_bytes: Option<u64>,
+}
+
+/// Resident memory of the agent process.
+pub fn resident_bytes() -> Option<u64> {
+    let mut buf = String::new();
+
+    File::open("/proc/self/statm")
+        .and_then(|mut file| file.read_to_string(&mut buf))
+        .ok()?;
+
+    let pages: u64 = buf.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+    if page_size > 0 { Some(pages * page_size as u64) } else { None }
+}
+
+/// Whether LIM could not be queried in the poll, for all clusters or any of
+/// them, as told by the cluster-wide FAILED records.
+pub fn poll_failed(records: &[StatusStorageInfo], prefix: &str) -> bool {
+    records.iter().any(|record| record.status == FAILED
+        && record.lim_status.is_none()
+        && record.name.starts_with(prefix)
+        && record.name.ends_with('*'))
+}
+
+/// Builds the agent record of the poll, with the measurements as load
+/// indices.
+pub fn to_record(config: &TelemetryConfig, stats: &AgentStats, prefix: &str, critical_group_name: &str) -> StatusStorageInfo {
+    let poll_duration_ms = stats.poll_duration.as_millis() as u64;
+    let mut reasons = Vec::new();
+
+    if config.alert_failed_polls.is_some_and(|level| stats.failed_polls >= level) {
+        reasons.push(format!("LIM unreachable for {} consecutive polls", stats.failed_polls));
+    }
+
+    if let (Some(level), Some(memory_bytes)) = (config.alert_memory_bytes, stats.memory_bytes) {
+        if memory_bytes >= level {
+            reasons.push(format!("using {} bytes of memory", memory_bytes));
+        }
+    }
+
+    let status = if reasons.is_empty() { PASSED } else { ALERT };
+
+    let mut remarks = format!("Poll took {}ms, {} consecutive failed polls, {} sink errors, {} dropped sink polls",
+        poll_duration_ms, stats.failed_polls, stats.sink_errors, stats.sink_drops);
+
+    if !reasons.is_empty() {
+        remarks = format!("{}: {}", reasons.join(", "), remarks);
+    }
+
+    let name = format!("{}{}", prefix, config.name.as_deref().unwrap_or("agent"));
+    let mut record = StatusStorageInfo::new(name, status, None, Some(critical_group_name.to_owned()), Some(remarks));
+
+    let mut load_indices = BTreeMap::new();
+    load_indices.insert("pollDurationMs".to_owned(), poll_duration_ms as f32);
+    load_indices.insert("failedPolls".to_owned(), stats.failed_polls as f32);
+    load_indices.insert("sinkErrors".to_owned(), stats.sink_errors as f32);
+    load_indices.insert("sinkDrops".to_owned(), stats.sink_drops as f32);
+
+    if let Some(memory_bytes) = stats.memory_bytes {
+        load_indices.insert("memoryBytes".to_owned(), memory_bytes as f32);
+    }
+
+    record.load_indices = Some(load_indices);
+    record.synthetic = true;
+    record
+}
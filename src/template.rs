@@ -0,0 +1,33 @@
+/// Replaces every `{name}` placeholder in the template with the value looked
+/// up by name. Unknown placeholders are kept as they are.
+pub fn render<F>(template: &str, lookup: F) -> String
+    where F: Fn(&str) -> Option<String> {
+
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+
+        match rest[start..].find('}') {
+            Some(len) => {
+                let placeholder = &rest[start..start + len + 1];
+
+                match lookup(&placeholder[1..len]) {
+                    Some(value) => out.push_str(&value),
+                    None => out.push_str(placeholder),
+                }
+
+                rest = &rest[start + len + 1..];
+            },
+
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            },
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,286 @@
+use common::StatusStorageInfo;
+use glob;
+use state::State;
+use std::collections::BTreeMap;
+use {ALERT, FAILED, PASSED};
+
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+/// Alert threshold of a load index. Once above `alert`, the index only clears
+/// when it drops below `clear`, so that hosts hovering around the threshold do
+/// not oscillate between PASSED and ALERT.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadThreshold {
+    pub alert: f32,
+
+    /// Defaults to the alert threshold, i.e. no hysteresis
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clear: Option<f32>,
+}
+
+/// Raises passing records to ALERT while any of their load indices is above
+/// its threshold, given the alerts of the previous poll in the state.
+pub fn apply(records: &mut [StatusStorageInfo], thresholds: &BTreeMap<String, LoadThreshold>, state: &State) {
+    for record in records.iter_mut() {
+        let prev_alerts = state.hosts.get(&record.name).map(|host_state| &host_state.load_alerts);
+        let mut reasons = Vec::new();
+
+        if let Some(ref load_indices) = record.load_indices {
+            for (index, threshold) in thresholds {
+                let value = match load_indices.get(index) {
+                    Some(&value) => value,
+                    None => continue,
+                };
+
+                let was_alerted = prev_alerts.map(|alerts| alerts.contains(index)).unwrap_or(false);
+                let clear = threshold.clear.unwrap_or(threshold.alert);
+
+                if value > threshold.alert {
+                    reasons.push(format!("{} {} above {}", index, value, threshold.alert));
+                } else if was_alerted && value >= clear {
+                    reasons.push(format!("{} {} not yet below {}", index, value, clear));
+                } else {
+                    continue;
+                }
+
+                record.load_alerts.insert(index.clone());
+            }
+        }
+
+        if reasons.is_empty() {
+            continue;
+        }
+
+        if record.status == PASSED {
+            record.status = ALERT;
+        }
+
+        let reasons = reasons.join(", ");
+
+        record.remarks = Some(match record.remarks {
+            Some(ref remarks) => format!("{} ({})", remarks, reasons),
+            None => reasons,
+        });
+    }
+}
+
+/// Storage level, reached once the used percentage is at `maxUsedPercent` or
+/// the free bytes drop below `minFreeBytes`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageLevel {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_used_percent: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_free_bytes: Option<u64>,
+}
+
+/// ALERT and FAILED storage levels of the records matched by name with `*`
+/// and `?` wildcards. Once at the ALERT level, the record only clears when it
+/// is no longer at the `clear` level, if given.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageThreshold {
+    pub hosts: Vec<String>,
+
+    /// Load index in MB, such as `tmp` or `swp`, checked instead of `storage`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert: Option<StorageLevel>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<StorageLevel>,
+
+    /// Defaults to the alert level, i.e. no hysteresis
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clear: Option<StorageLevel>,
+}
+
+/// Free bytes and used percentage, when known, of what the threshold checks.
+fn measure(record: &StatusStorageInfo, threshold: &StorageThreshold) -> Option<(String, u64, Option<f64>)> {
+    match threshold.index {
+        Some(ref index) => record.load_indices.as_ref()
+            .and_then(|load_indices| load_indices.get(index))
+            .map(|&value| (index.clone(), (value as f64 * BYTES_PER_MB) as u64, None)),
+
+        None => record.storage.as_ref().map(|storage| {
+            let used_percent = if storage.total == 0 { 0.0 } else { storage.used as f64 * 100.0 / storage.total as f64 };
+            ("storage".to_owned(), storage.total.saturating_sub(storage.used), Some(used_percent))
+        }),
+    }
+}
+
+fn reached(level: &StorageLevel, what: &str, free_bytes: u64, used_percent: Option<f64>) -> Option<String> {
+    match (level.max_used_percent, used_percent) {
+        (Some(max_used_percent), Some(used_percent)) if used_percent >= max_used_percent =>
+            return Some(format!("{} {:.1}% used, at least {}%", what, used_percent, max_used_percent)),
+        _ => (),
+    }
+
+    match level.min_free_bytes {
+        Some(min_free_bytes) if free_bytes < min_free_bytes =>
+            Some(format!("{} {} bytes free, below {}", what, free_bytes, min_free_bytes)),
+        _ => None,
+    }
+}
+
+fn not_cleared(level: &StorageLevel, what: &str, free_bytes: u64, used_percent: Option<f64>) -> Option<String> {
+    match (level.max_used_percent, used_percent) {
+        (Some(max_used_percent), Some(used_percent)) if used_percent >= max_used_percent =>
+            return Some(format!("{} {:.1}% used, not yet below {}%", what, used_percent, max_used_percent)),
+        _ => (),
+    }
+
+    match level.min_free_bytes {
+        Some(min_free_bytes) if free_bytes < min_free_bytes =>
+            Some(format!("{} {} bytes free, not yet {} or more", what, free_bytes, min_free_bytes)),
+        _ => None,
+    }
+}
+
+/// Raises records to ALERT or FAILED while the storage or load index of any
+/// matching threshold reaches its level, given the alerts of the previous
+/// poll in the state. The used percentage is only known for `storage`, load
+/// indices only report the free space.
+pub fn apply_storage(records: &mut [StatusStorageInfo], thresholds: &[StorageThreshold], state: &State) {
+    for record in records.iter_mut() {
+        let prev_alerts = state.hosts.get(&record.name).map(|host_state| &host_state.load_alerts);
+        let mut status = record.status;
+        let mut reasons = Vec::new();
+        let mut alerts = Vec::new();
+
+        for threshold in thresholds.iter()
+            .filter(|threshold| threshold.hosts.iter().any(|pattern| glob::matches(pattern, &record.name))) {
+
+            let (what, free_bytes, used_percent) = match measure(record, threshold) {
+                Some(measured) => measured,
+                None => continue,
+            };
+
+            // kept apart from the load index alerts of the same name
+            let alert_key = format!("storage:{}", what);
+            let was_alerted = prev_alerts.map(|alerts| alerts.contains(&alert_key)).unwrap_or(false);
+
+            let failed = threshold.failed.as_ref().and_then(|level| reached(level, &what, free_bytes, used_percent));
+            let alert = threshold.alert.as_ref().and_then(|level| reached(level, &what, free_bytes, used_percent));
+
+            let clear = threshold.clear.as_ref()
+                .filter(|_| was_alerted)
+                .and_then(|level| not_cleared(level, &what, free_bytes, used_percent));
+
+            if let Some(reason) = failed {
+                status = FAILED;
+                reasons.push(reason);
+                alerts.push(alert_key);
+            } else if let Some(reason) = alert.or(clear) {
+                if status == PASSED {
+                    status = ALERT;
+                }
+
+                reasons.push(reason);
+                alerts.push(alert_key);
+            }
+        }
+
+        record.load_alerts.extend(alerts);
+
+        if reasons.is_empty() {
+            continue;
+        }
+
+        record.status = status;
+        let reasons = reasons.join(", ");
+
+        record.remarks = Some(match record.remarks {
+            Some(ref remarks) => format!("{} ({})", remarks, reasons),
+            None => reasons,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::StorageInfo;
+    use state::HostState;
+    use std::slice;
+
+    fn level(max_used_percent: f64) -> Option<StorageLevel> {
+        Some(StorageLevel { max_used_percent: Some(max_used_percent), min_free_bytes: None })
+    }
+
+    /// Polls a host with the used percentage, carrying its alerts over in the
+    /// state as the agent does.
+    fn poll(used: u64, threshold: &StorageThreshold, state: &mut State) -> StatusStorageInfo {
+        let mut records = vec![StatusStorageInfo::new(
+            "p.host".to_owned(), PASSED, Some(StorageInfo::new(used, 100)), None, None)];
+
+        apply_storage(&mut records, slice::from_ref(threshold), state);
+
+        state.hosts.insert("p.host".to_owned(), HostState {
+            status: records[0].status,
+            since: 0,
+            pending_status: None,
+            pending_polls: 0,
+            load_alerts: records[0].load_alerts.clone(),
+        });
+
+        records.remove(0)
+    }
+
+    #[test]
+    fn storage_hovering_at_alert_level_stays_alerted() {
+        let threshold = StorageThreshold {
+            hosts: vec!["p.*".to_owned()],
+            index: None,
+            alert: level(80.0),
+            failed: level(95.0),
+            clear: level(70.0),
+        };
+
+        let mut state = State::default();
+        let statuses: Vec<_> = [79, 81, 79, 81, 75, 69, 79].iter()
+            .map(|&used| poll(used, &threshold, &mut state).status)
+            .collect();
+
+        assert_eq!(statuses, vec![PASSED, ALERT, ALERT, ALERT, ALERT, PASSED, PASSED]);
+    }
+
+    #[test]
+    fn storage_without_clear_level_follows_alert_level() {
+        let threshold = StorageThreshold {
+            hosts: vec!["p.*".to_owned()],
+            index: None,
+            alert: level(80.0),
+            failed: None,
+            clear: None,
+        };
+
+        let mut state = State::default();
+        let statuses: Vec<_> = [81, 79].iter()
+            .map(|&used| poll(used, &threshold, &mut state).status)
+            .collect();
+
+        assert_eq!(statuses, vec![ALERT, PASSED]);
+    }
+
+    #[test]
+    fn storage_remarks_tell_why_not_cleared() {
+        let threshold = StorageThreshold {
+            hosts: vec!["p.*".to_owned()],
+            index: None,
+            alert: level(80.0),
+            failed: None,
+            clear: level(70.0),
+        };
+
+        let mut state = State::default();
+        poll(81, &threshold, &mut state);
+
+        assert_eq!(poll(75, &threshold, &mut state).remarks.as_deref(), Some("storage 75.0% used, not yet below 70%"));
+    }
+}
@@ -0,0 +1,98 @@
+use errors::*;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use rustls::crypto::ring;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::pem::PemObject;
+use rustls::server::WebPkiClientVerifier;
+use std::sync::Arc;
+use webpki_roots;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+
+    /// CA bundle that client certificates must chain to, if clients are to be
+    /// verified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ca_path: Option<String>,
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .chain_err(|| format!("Unable to open certificates at {}", path))?
+        .collect::<::std::result::Result<Vec<_>, _>>()
+        .chain_err(|| format!("Unable to parse certificates at {}", path))?;
+
+    if certs.is_empty() {
+        bail!("No certificates found at {}", path);
+    }
+
+    Ok(certs)
+}
+
+/// Builds the TLS configuration of the API server.
+pub fn server_config(tls: &TlsConfig) -> Result<Arc<ServerConfig>> {
+    let provider = Arc::new(ring::default_provider());
+
+    let certs = load_certs(&tls.cert_path)?;
+
+    let key = PrivateKeyDer::from_pem_file(&tls.key_path)
+        .chain_err(|| format!("Unable to load private key at {}", tls.key_path))?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .chain_err(|| "Unable to select TLS protocol versions")?;
+
+    let builder = match tls.client_ca_path {
+        Some(ref client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+
+            for cert in load_certs(client_ca_path)? {
+                roots.add(cert)
+                    .chain_err(|| format!("Invalid client CA certificate in {}", client_ca_path))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .chain_err(|| "Unable to build client certificate verifier")?;
+
+            builder.with_client_cert_verifier(verifier)
+        },
+
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder.with_single_cert(certs, key)
+        .chain_err(|| "Certificate and private key do not form a valid pair")?;
+
+    Ok(Arc::new(config))
+}
+
+/// Builds the TLS configuration of outgoing connections, trusting the CA
+/// bundle if given or the Mozilla root certificates otherwise.
+pub fn client_config(ca_path: Option<&str>) -> Result<Arc<ClientConfig>> {
+    let roots = match ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+
+            for cert in load_certs(ca_path)? {
+                roots.add(cert)
+                    .chain_err(|| format!("Invalid CA certificate in {}", ca_path))?;
+            }
+
+            roots
+        },
+
+        None => RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() },
+    };
+
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .chain_err(|| "Unable to select TLS protocol versions")?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
@@ -0,0 +1,249 @@
+use chrono::Local;
+use common::StatusStorageInfo;
+use errors::*;
+use libc;
+use output;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::mem;
+use std::time::{Duration, Instant};
+use {ALERT, DOWNTIME, FAILED, PASSED};
+
+const CTRL_C: u8 = 3;
+const BACKSPACE: u8 = 8;
+const ENTER: u8 = 13;
+const ESCAPE: u8 = 27;
+const DELETE: u8 = 127;
+
+/// Puts the terminal into raw mode on the alternate screen, restoring it when
+/// dropped.
+struct Terminal {
+    original: libc::termios,
+}
+
+impl Terminal {
+    fn enter() -> Result<Terminal> {
+        let is_tty = unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 };
+
+        if !is_tty {
+            bail!("top requires stdin and stdout to be a terminal");
+        }
+
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            bail!("Unable to get the terminal attributes");
+        }
+
+        // Ctrl-C is read as a key, so that the terminal is always restored
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_iflag &= !libc::ICRNL;
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            bail!("Unable to put the terminal into raw mode");
+        }
+
+        print!("\x1b[?1049h\x1b[?25l");
+        Ok(Terminal { original })
+    }
+
+    fn size(&self) -> (usize, usize) {
+        let mut winsize: libc::winsize = unsafe { mem::zeroed() };
+
+        match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut winsize) } {
+            0 if winsize.ws_row > 0 && winsize.ws_col > 0 => (winsize.ws_row as usize, winsize.ws_col as usize),
+            _ => (24, 80),
+        }
+    }
+
+    /// Reads the pending keys, waiting up to the timeout for the first one.
+    fn read_keys(&self, timeout: Duration) -> Vec<u8> {
+        let mut fds = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+
+        if unsafe { libc::poll(&mut fds, 1, timeout_ms) } <= 0 {
+            return vec![];
+        }
+
+        let mut buf = [0u8; 64];
+        let len = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+
+        if len > 0 { buf[..len as usize].to_vec() } else { vec![] }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Worst statuses first.
+fn severity(status: i32) -> u8 {
+    match status {
+        FAILED => 0,
+        ALERT => 1,
+        DOWNTIME => 2,
+        PASSED => 3,
+        _ => 4,
+    }
+}
+
+fn load_index(record: &StatusStorageInfo, index: &str) -> Option<f32> {
+    record.load_indices.as_ref().and_then(|load_indices| load_indices.get(index).cloned())
+}
+
+/// Sorts by status, worst first, then by `r1m` load, highest first.
+fn compare(a: &StatusStorageInfo, b: &StatusStorageInfo) -> Ordering {
+    severity(a.status).cmp(&severity(b.status))
+        .then_with(|| load_index(b, "r1m").partial_cmp(&load_index(a, "r1m")).unwrap_or(Ordering::Equal))
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+fn truncate(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}
+
+struct View {
+    records: Vec<StatusStorageInfo>,
+
+    /// Previous status of the hosts whose status changed in the latest poll
+    transitions: BTreeMap<String, i32>,
+
+    error: Option<String>,
+    polled_at: String,
+    filter: String,
+    editing: bool,
+}
+
+impl View {
+    fn draw(&self, (rows, cols): (usize, usize)) -> String {
+        let summary = output::summarize(&self.records);
+        let mut lines = Vec::new();
+
+        lines.push(truncate(&format!("lsf_agent top - {} - {} hosts: {} passed, {} alert, {} failed, {} downtime",
+            self.polled_at, summary.total, summary.passed, summary.alert, summary.failed, summary.downtime), cols));
+
+        lines.push(truncate(&match (self.editing, self.error.as_ref()) {
+            (true, _) => format!("Filter: {}_", self.filter),
+            (false, Some(error)) => format!("\x1b[31mPoll failed: {}\x1b[0m", error),
+            (false, None) if !self.filter.is_empty() => format!("Filter: {} (Esc to clear, / to edit, q to quit)", self.filter),
+            (false, None) => "/ to filter, q to quit".to_owned(),
+        }, cols));
+
+        lines.push(String::new());
+        lines.push(truncate(&format!("{:<32} {:<18} {:>7} {:>7} {:>9}  REMARKS", "NAME", "STATUS", "R1M", "UT", "MEM"), cols));
+
+        let mut records: Vec<&StatusStorageInfo> = self.records.iter()
+            .filter(|record| record.name.contains(&self.filter))
+            .collect();
+
+        records.sort_by(|a, b| compare(a, b));
+
+        for record in records.iter().take(rows.saturating_sub(lines.len())) {
+            let status = match self.transitions.get(&record.name) {
+                Some(&from) => format!("{}>{}", output::to_check_status_str(from), output::to_check_status_str(record.status)),
+                None => output::to_check_status_str(record.status).to_owned(),
+            };
+
+            let index = |name| load_index(record, name).map(|value| format!("{:.2}", value)).unwrap_or_else(|| "-".to_owned());
+
+            let line = truncate(&format!("{:<32} {:<18} {:>7} {:>7} {:>9}  {}",
+                truncate(&record.name, 32), status, index("r1m"), index("ut"), index("mem"),
+                record.remarks.as_deref().unwrap_or("")), cols);
+
+            let color = match record.status {
+                PASSED => "\x1b[32m",
+                ALERT => "\x1b[33m",
+                FAILED => "\x1b[31m",
+                _ => "\x1b[34m",
+            };
+
+            // transitions of the latest poll are highlighted in reverse video
+            let highlight = if self.transitions.contains_key(&record.name) { "\x1b[7m" } else { "" };
+            lines.push(format!("{}{}{}\x1b[0m", color, highlight, line));
+        }
+
+        format!("\x1b[H\x1b[2J{}", lines.join("\n"))
+    }
+}
+
+/// Runs the interactive dashboard, refreshing with the records of `poll`
+/// every interval until `q` or Ctrl-C is pressed.
+pub fn run<F>(interval: Duration, mut poll: F) -> Result<()>
+    where F: FnMut() -> Result<Vec<StatusStorageInfo>> {
+
+    let terminal = Terminal::enter()?;
+
+    let mut view = View {
+        records: vec![],
+        transitions: BTreeMap::new(),
+        error: None,
+        polled_at: String::new(),
+        filter: String::new(),
+        editing: false,
+    };
+
+    loop {
+        match poll() {
+            Ok(records) => {
+                let previous: BTreeMap<&str, i32> = view.records.iter()
+                    .map(|record| (record.name.as_str(), record.status))
+                    .collect();
+
+                view.transitions = records.iter()
+                    .filter_map(|record| match previous.get(record.name.as_str()) {
+                        Some(&status) if status != record.status => Some((record.name.clone(), status)),
+                        _ => None,
+                    })
+                    .collect();
+
+                view.records = records;
+                view.error = None;
+            },
+
+            Err(e) => view.error = Some(e.to_string()),
+        }
+
+        view.polled_at = Local::now().format("%H:%M:%S").to_string();
+        let next_poll = Instant::now() + interval;
+
+        loop {
+            print!("{}", view.draw(terminal.size()));
+            io::stdout().flush().chain_err(|| "Unable to write into the terminal")?;
+
+            let now = Instant::now();
+
+            if now >= next_poll {
+                break;
+            }
+
+            for key in terminal.read_keys(next_poll - now) {
+                match (view.editing, key) {
+                    (_, CTRL_C) | (false, b'q') => return Ok(()),
+                    (false, b'/') => view.editing = true,
+                    (false, ESCAPE) => view.filter.clear(),
+                    (true, ENTER) => view.editing = false,
+
+                    (true, ESCAPE) => {
+                        view.filter.clear();
+                        view.editing = false;
+                    },
+
+                    (true, BACKSPACE) | (true, DELETE) => {
+                        view.filter.pop();
+                    },
+
+                    (true, key) if key.is_ascii_graphic() || key == b' ' => view.filter.push(key as char),
+                    _ => (),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use chrono::Local;
+use common::StatusStorageInfo;
+use errors::*;
+use output;
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+use {ALERT, FAILED, PASSED};
+
+/// Difference of a host between two polls.
+#[derive(Debug)]
+pub enum Change<'a> {
+    Appeared(&'a StatusStorageInfo),
+    Disappeared(&'a StatusStorageInfo),
+    Changed { from: i32, to: &'a StatusStorageInfo },
+}
+
+/// Hosts that appeared, disappeared or changed status since the previous
+/// poll, by name.
+pub fn diff<'a>(previous: &'a [StatusStorageInfo], current: &'a [StatusStorageInfo]) -> Vec<Change<'a>> {
+    let previous_by_name: BTreeMap<&str, &StatusStorageInfo> = previous.iter()
+        .map(|record| (record.name.as_str(), record))
+        .collect();
+
+    let current_by_name: BTreeMap<&str, &StatusStorageInfo> = current.iter()
+        .map(|record| (record.name.as_str(), record))
+        .collect();
+
+    let mut changes: Vec<(&str, Change)> = current_by_name.iter()
+        .filter_map(|(&name, &record)| match previous_by_name.get(name) {
+            None => Some((name, Change::Appeared(record))),
+            Some(previous) if previous.status != record.status =>
+                Some((name, Change::Changed { from: previous.status, to: record })),
+            _ => None,
+        })
+        .collect();
+
+    changes.extend(previous_by_name.iter()
+        .filter(|&(name, _)| !current_by_name.contains_key(name))
+        .map(|(&name, &record)| (name, Change::Disappeared(record))));
+
+    changes.sort_by(|a, b| a.0.cmp(b.0));
+    changes.into_iter().map(|(_, change)| change).collect()
+}
+
+fn paint(s: &str, code: &str, color: bool) -> String {
+    if color { format!("\x1b[{}m{}\x1b[0m", code, s) } else { s.to_owned() }
+}
+
+fn status_str(status: i32, color: bool) -> String {
+    let code = match status {
+        PASSED => "32",
+        ALERT => "33",
+        FAILED => "31",
+        _ => "34",
+    };
+
+    paint(output::to_check_status_str(status), code, color)
+}
+
+/// Renders a line per change, prefixed by the time of the poll and `+`, `-`
+/// or `~`.
+pub fn render(changes: &[Change], time: &str, color: bool) -> String {
+    changes.iter()
+        .map(|change| match *change {
+            Change::Appeared(record) => format!("{} {} {} {}", time, paint("+", "32", color),
+                record.name, status_str(record.status, color)),
+
+            Change::Disappeared(record) => format!("{} {} {} (was {})", time, paint("-", "31", color),
+                record.name, status_str(record.status, color)),
+
+            Change::Changed { from, to } => format!("{} {} {} {} -> {}{}", time, paint("~", "33", color),
+                to.name, status_str(from, color), status_str(to.status, color),
+                to.remarks.as_ref().map(|remarks| format!(": {}", remarks)).unwrap_or_default()),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Polls every interval and prints the changes since the previous poll,
+/// starting with a count of the hosts of the first poll.
+pub fn run<F>(interval: Duration, color: bool, mut poll: F) -> Result<()>
+    where F: FnMut() -> Result<Vec<StatusStorageInfo>> {
+
+    let mut previous: Option<Vec<StatusStorageInfo>> = None;
+
+    loop {
+        let records = poll()?;
+        let time = Local::now().format("%H:%M:%S").to_string();
+
+        match previous {
+            Some(ref previous) => {
+                let changes = diff(previous, &records);
+
+                if !changes.is_empty() {
+                    println!("{}", render(&changes, &time, color));
+                }
+            },
+
+            None => {
+                let summary = output::summarize(&records);
+
+                println!("{} watching {} hosts: {} passed, {} alert, {} failed, {} downtime",
+                    time, summary.total, summary.passed, summary.alert, summary.failed, summary.downtime);
+            },
+        }
+
+        previous = Some(records);
+        thread::sleep(interval);
+    }
+}
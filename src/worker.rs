@@ -0,0 +1,142 @@
+use errors::*;
+use lsf::{self, HostLoad};
+use serde_json;
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Set in the environment of the worker process, holding the ls_load options.
+pub const WORKER_ENV: &str = "LSF_AGENT_QUERY_WORKER";
+const WORKER_FROM_HOST_ENV: &str = "LSF_AGENT_QUERY_WORKER_FROM_HOST";
+const WORKER_CLUSTER_ENV: &str = "LSF_AGENT_QUERY_WORKER_CLUSTER";
+const WORKER_INDICES_ENV: &str = "LSF_AGENT_QUERY_WORKER_INDICES";
+
+/// Entry point of the worker process: performs the query and writes the
+/// decoded hosts as JSON on stdout. Returns the process exit code.
+pub fn run_worker() -> i32 {
+    let options = env::var(WORKER_ENV).ok().and_then(|options| options.parse().ok());
+
+    let options = match options {
+        Some(options) => options,
+        None => {
+            eprintln!("Error: Invalid ls_load options in {}", WORKER_ENV);
+            return 1;
+        },
+    };
+
+    let from_host = env::var(WORKER_FROM_HOST_ENV).ok();
+    let cluster = env::var(WORKER_CLUSTER_ENV).ok();
+
+    let indices: Vec<String> = env::var(WORKER_INDICES_ENV).ok().iter()
+        .flat_map(|indices| indices.split(','))
+        .filter(|index| !index.is_empty())
+        .map(|index| index.to_owned())
+        .collect();
+
+    let res = lsf::load(options, from_host.as_deref(), cluster.as_deref(), &indices)
+        .and_then(|host_loads| serde_json::to_string(&host_loads)
+            .chain_err(|| "Unable to serialize host loads into string!"));
+
+    match res {
+        Ok(host_loads) => {
+            let stdout = &mut io::stdout();
+
+            match writeln!(stdout, "{}", host_loads) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            }
+        },
+
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        },
+    }
+}
+
+/// Runs the query in a child process of the current executable, so that a
+/// crashing or hanging liblsf cannot take the agent down with it. Worker
+/// failures are reported as `ErrorKind::QueryFailed`.
+pub fn load_isolated(
+    options: i32,
+    from_host: Option<&str>,
+    cluster: Option<&str>,
+    indices: &[String],
+    timeout: Option<Duration>) -> Result<Vec<HostLoad>> {
+
+    let exe = env::current_exe()
+        .chain_err(|| "Unable to locate the agent executable for the query worker")?;
+
+    let mut command = Command::new(exe);
+
+    command.env(WORKER_ENV, options.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+
+    if let Some(from_host) = from_host {
+        command.env(WORKER_FROM_HOST_ENV, from_host);
+    }
+
+    if let Some(cluster) = cluster {
+        command.env(WORKER_CLUSTER_ENV, cluster);
+    }
+
+    if !indices.is_empty() {
+        command.env(WORKER_INDICES_ENV, indices.join(","));
+    }
+
+    let mut child = command.spawn()
+        .chain_err(|| "Unable to spawn the query worker process")?;
+
+    let mut stdout = child.stdout.take()
+        .ok_or_else(|| Error::from("Unable to capture the query worker output"))?;
+
+    // output reaches EOF once the worker exits, so reading doubles as waiting
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut buf = String::new();
+        let res = stdout.read_to_string(&mut buf).map(|_| buf);
+        let _ = tx.send(res);
+    });
+
+    let output = match timeout {
+        Some(timeout) => match rx.recv_timeout(timeout) {
+            Ok(output) => output,
+            Err(_) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                bail!(ErrorKind::QueryFailed(format!("worker killed after {}s timeout", timeout.as_secs())));
+            },
+        },
+
+        None => rx.recv().chain_err(|| "Query worker output reader disconnected")?,
+    };
+
+    let status = child.wait()
+        .chain_err(|| "Unable to wait for the query worker process")?;
+
+    if let Some(signal) = status.signal() {
+        bail!(ErrorKind::QueryFailed(format!("worker crashed with signal {}", signal)));
+    }
+
+    if !status.success() {
+        bail!(ErrorKind::QueryFailed(format!("worker exited with {}", status)));
+    }
+
+    let output = output.chain_err(|| "Unable to read the query worker output")?;
+
+    let mut host_loads: Vec<HostLoad> = serde_json::from_str(&output)
+        .chain_err(|| ErrorKind::QueryFailed("worker returned malformed output".to_owned()))?;
+
+    for host_load in &mut host_loads {
+        host_load.cluster = cluster.map(|cluster| cluster.to_owned());
+    }
+
+    Ok(host_loads)
+}
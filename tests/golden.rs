@@ -0,0 +1,105 @@
+//! Feeds the recordings under `tests/golden/<case>/` through the full polling
+//! pipeline, evaluated against the previous poll in `state.json` if the case
+//! has one, and compares the rendered JSON against `expected.json` of the
+//! case. Set `UPDATE_GOLDEN=1` to rewrite the expected files instead.
+
+extern crate lsf_agent;
+extern crate serde_json;
+
+use lsf_agent::Config;
+use lsf_agent::output::{self, OutputFormat};
+use lsf_agent::poll;
+use lsf_agent::state::State;
+use serde_json::Value;
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+fn case_path(case: &str, file: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "golden", case, file].iter().collect()
+}
+
+fn poll_case(case: &str) -> Value {
+    let config = Config::from_file(case_path(case, "config.json").to_str().unwrap())
+        .expect("Unable to read config of case");
+
+    let mut settings = config.query_settings().expect("Unable to resolve query settings of case");
+
+    settings.replay = Some(poll::load_recording(case_path(case, "recording.json").to_str().unwrap())
+        .expect("Unable to read recording of case"));
+
+    let state = State::load(case_path(case, "state.json").to_str().unwrap())
+        .expect("Unable to read state of case");
+
+    let downtimes = config.downtimes().expect("Unable to parse downtimes of case");
+
+    let mut records = lsf_agent::poll_hosts_with(&config, &settings).expect("Unable to poll case");
+    lsf_agent::evaluate(&config, &mut records, &state, &downtimes).expect("Unable to evaluate case");
+
+    let rendered = output::render(OutputFormat::Json, &records).expect("Unable to render records of case");
+
+    serde_json::from_str(&rendered).unwrap()
+}
+
+fn check(case: &str) {
+    let actual = poll_case(case);
+    let expected_path = case_path(case, "expected.json");
+
+    if env::var("UPDATE_GOLDEN").is_ok() {
+        let content = serde_json::to_string_pretty(&actual).unwrap() + "\n";
+        File::create(&expected_path).unwrap().write_all(content.as_bytes()).unwrap();
+        return;
+    }
+
+    let mut buf = String::new();
+
+    File::open(&expected_path)
+        .and_then(|mut file| file.read_to_string(&mut buf))
+        .unwrap_or_else(|e| panic!("Unable to read {}: {}", expected_path.display(), e));
+
+    let expected: Value = serde_json::from_str(&buf).unwrap();
+
+    assert!(actual == expected, "Output of case {} differs from {}:\n{}",
+        case, expected_path.display(), serde_json::to_string_pretty(&actual).unwrap());
+}
+
+#[test]
+fn name_mapping() {
+    check("name_mapping");
+}
+
+#[test]
+fn non_utf8_host_names() {
+    check("non_utf8_host_names");
+}
+
+#[test]
+fn empty_clusters() {
+    check("empty_clusters");
+}
+
+#[test]
+fn status_bits() {
+    check("status_bits");
+}
+
+#[test]
+fn query_latency() {
+    check("query_latency");
+}
+
+#[test]
+fn thresholds() {
+    check("thresholds");
+}
+
+#[test]
+fn downtime_and_acks() {
+    check("downtime_and_acks");
+}
+
+#[test]
+fn groups_and_quorum() {
+    check("groups_and_quorum");
+}